@@ -0,0 +1,11 @@
+// compile the gRPC interface with the protoc that comes with protoc-bin-vendored
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/pepmatch.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("a protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::compile_protos("proto/pepmatch.proto").expect("proto/pepmatch.proto compiles");
+    }
+}
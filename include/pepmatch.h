@@ -0,0 +1,42 @@
+#ifndef PEPMATCH_H
+#define PEPMATCH_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit it by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Preprocess a proteome FASTA file into a DB of its k-mers at db_path, or at
+// <proteome>_k<k>.db in the current directory when db_path is NULL.
+//
+// Returns 0 on success and -1 on failure, with the reason from pepmatch_last_error.
+//
+// # Safety
+//
+// proteome and db_path must be NULL or NUL-terminated UTF-8 strings.
+int32_t pepmatch_preprocess(const char *proteome, size_t k, const char *db_path);
+
+// Search peptides against a preprocessed DB or binary index. request is a JSON object
+// of the "peptides" and optional search settings like "max_mismatches", the same as
+// the body of a POST /match to pepmatch serve.
+//
+// Returns the hits as a JSON array, to be freed with pepmatch_free_string, or NULL on
+// failure with the reason from pepmatch_last_error.
+//
+// # Safety
+//
+// db_path and request must be NUL-terminated UTF-8 strings.
+char *pepmatch_match_json(const char *db_path, const char *request);
+
+// The message of the last error on this thread, or NULL if the last call succeeded.
+// The string belongs to the library and is only valid until the next call on the thread.
+const char *pepmatch_last_error(void);
+
+// Free a string returned by the library, NULL is ignored.
+//
+// # Safety
+//
+// s must be NULL or a string returned by the library that hasn't been freed yet.
+void pepmatch_free_string(char *s);
+
+#endif  /* PEPMATCH_H */
@@ -0,0 +1,116 @@
+use crate::error::{PepMatchError, Result};
+use std::collections::{HashMap, HashSet};
+
+// restricts hits to the proteins with some accessions or leaves out those with others, an
+// accession without an isoform suffix stands for its isoforms too, e.g. P04637 for P04637-2,
+// and an empty filter passes everything
+#[derive(Debug, Clone, Default)]
+pub struct AccessionFilter {
+    include: Option<HashSet<String>>,
+    exclude: HashSet<String>,
+}
+
+impl AccessionFilter {
+    pub fn new() -> AccessionFilter {
+        AccessionFilter::default()
+    }
+
+    // only pass proteins with one of these accessions, given more than once a protein can
+    // have any of them
+    pub fn include(mut self, accessions: &[String]) -> AccessionFilter {
+        self.include.get_or_insert_with(HashSet::new).extend(accessions.iter().map(|id| id.trim().to_string()));
+        self
+    }
+
+    pub fn exclude(mut self, accessions: &[String]) -> AccessionFilter {
+        self.exclude.extend(accessions.iter().map(|id| id.trim().to_string()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_empty()
+    }
+
+    // the accessions in a fixed order, the same for the same filter
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn key(&self) -> String {
+        let sorted = |ids: &HashSet<String>| {
+            let mut ids: Vec<&String> = ids.iter().collect();
+            ids.sort();
+            format!("{:?}", ids)
+        };
+        format!("{} {}", self.include.as_ref().map(sorted).unwrap_or_default(), sorted(&self.exclude))
+    }
+
+    pub fn allows(&self, protein_id: &str) -> bool {
+        let listed = |ids: &HashSet<String>| {
+            ids.contains(protein_id) || protein_id.split_once('-').is_some_and(|(canonical, _)| ids.contains(canonical))
+        };
+        self.include.as_ref().is_none_or(listed) && !listed(&self.exclude)
+    }
+}
+
+// the accessions in a file, one per line, blank lines and lines starting with # are skipped
+pub fn read_accessions(path: &str) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path).map_err(PepMatchError::io(path))?;
+    let accessions: Vec<String> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if accessions.is_empty() {
+        return Err(PepMatchError::invalid(format!("{} has no accessions", path)));
+    }
+    Ok(accessions)
+}
+
+// a filter for each peptide leaving out the proteins it comes from, by the accessions of its
+// source in each row it's in, ;-separated or as UniProt IRIs like http://purl.uniprot.org/uniprot/P04637
+pub fn source_filters(peptides: &[String], sources: &[String]) -> HashMap<String, AccessionFilter> {
+    let mut filters: HashMap<String, AccessionFilter> = HashMap::new();
+    for (peptide, source) in peptides.iter().zip(sources) {
+        let accessions: Vec<String> = source
+            .split(';')
+            .map(|accession| accession.trim().rsplit('/').next().unwrap_or_default().to_string())
+            .filter(|accession| !accession.is_empty())
+            .collect();
+        let filter = filters.entry(peptide.clone()).or_default();
+        filter.exclude.extend(accessions);
+    }
+    filters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessions_cover_their_isoforms() {
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<String>>();
+        let filter = AccessionFilter::new().exclude(&ids(&["P04637", "Q00001-2"]));
+        assert!(!filter.allows("P04637"));
+        assert!(!filter.allows("P04637-2"));
+        assert!(!filter.allows("Q00001-2"));
+        assert!(filter.allows("Q00001"));
+        let filter = AccessionFilter::new().include(&ids(&["P04637", "P00001"])).exclude(&ids(&["P00001"]));
+        assert!(filter.allows("P04637-3"));
+        assert!(!filter.allows("P00001"));
+        assert!(!filter.allows("P00002"));
+        assert!(AccessionFilter::new().allows("P00002"));
+    }
+
+    #[test]
+    fn peptides_leave_out_the_sources_of_their_rows() {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect::<Vec<String>>();
+        let peptides = strings(&["STAAQW", "DEQRRPLN", "STAAQW"]);
+        let sources = strings(&["P00001", "", "http://purl.uniprot.org/uniprot/P00002; Q00003"]);
+        let filters = source_filters(&peptides, &sources);
+        let allowed = |peptide: &str, protein_id| filters[peptide].allows(protein_id);
+        assert!(!allowed("STAAQW", "P00001"));
+        assert!(!allowed("STAAQW", "P00002"));
+        assert!(!allowed("STAAQW", "Q00003"));
+        assert!(allowed("STAAQW", "P00004"));
+        assert!(allowed("DEQRRPLN", "P00001"));
+    }
+}
@@ -0,0 +1,236 @@
+use crate::error::{PepMatchError, Result};
+use crate::{Match, Matcher, Preprocessor};
+use std::collections::HashSet;
+use std::time::Instant;
+
+// the searches of the Python PEPMatch's benchmarking suite, run the same way on the same
+// proteome and queries to compare the two
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dataset {
+    MhcLigands,  // exact matches of MHC ligands eluted from cells
+    Neoepitopes, // tumor neoepitopes with up to 3 mismatches to the reference proteome
+    Coronavirus, // the best match of coronavirus epitopes in the human proteome
+}
+
+impl std::str::FromStr for Dataset {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Dataset, String> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "mhc-ligands" => Ok(Dataset::MhcLigands),
+            "neoepitopes" => Ok(Dataset::Neoepitopes),
+            "coronavirus" => Ok(Dataset::Coronavirus),
+            _ => Err(format!("unknown benchmark dataset: {}", s)),
+        }
+    }
+}
+
+impl Dataset {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dataset::MhcLigands => "mhc-ligands",
+            Dataset::Neoepitopes => "neoepitopes",
+            Dataset::Coronavirus => "coronavirus",
+        }
+    }
+
+    // k the proteome is indexed with for the dataset
+    pub fn k(self) -> usize {
+        match self {
+            Dataset::MhcLigands => 9,
+            Dataset::Neoepitopes | Dataset::Coronavirus => 3,
+        }
+    }
+
+    pub fn max_mismatches(self) -> usize {
+        match self {
+            Dataset::Neoepitopes => 3,
+            Dataset::MhcLigands | Dataset::Coronavirus => 0,
+        }
+    }
+
+    pub fn best_match(self) -> bool {
+        self == Dataset::Coronavirus
+    }
+}
+
+// what a benchmark run took and found
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub dataset: Dataset,
+    pub k: usize,
+    pub peptides: usize,
+    pub hits: usize,
+    pub index_seconds: f64, // building the index in memory, 0 for a preprocessed one
+    pub search_seconds: f64,
+    pub peak_rss: Option<u64>, // bytes, None where the OS doesn't say
+    pub recall: Option<Recall>,
+}
+
+// how many of the expected hits were found
+#[derive(Debug, Clone, Copy)]
+pub struct Recall {
+    pub expected: usize,
+    pub found: usize,
+}
+
+impl Recall {
+    pub fn fraction(&self) -> f64 {
+        if self.expected == 0 { 1.0 } else { self.found as f64 / self.expected as f64 }
+    }
+}
+
+// runs a dataset's search against a proteome and times it
+pub struct Benchmark {
+    dataset: Dataset,
+    k: Option<usize>, // None for the dataset's
+    proteome: Option<String>,
+    db: Option<String>,
+    threads: usize,
+    progress: bool,
+}
+
+impl Benchmark {
+    pub fn new(dataset: Dataset) -> Benchmark {
+        Benchmark { dataset, k: None, proteome: None, db: None, threads: 0, progress: false }
+    }
+
+    // index the proteome with another k than the dataset's, a DB searched instead has to
+    // have been preprocessed with it
+    pub fn k(mut self, k: usize) -> Benchmark {
+        self.k = Some(k);
+        self
+    }
+
+    // proteome FASTA file to index in memory and search
+    pub fn proteome(mut self, proteome: &str) -> Benchmark {
+        self.proteome = Some(proteome.to_string());
+        self
+    }
+
+    // search a preprocessed DB or binary index instead of indexing a proteome in memory
+    pub fn db(mut self, db_path: &str) -> Benchmark {
+        self.db = Some(db_path.to_string());
+        self
+    }
+
+    // number of threads to search with, 0 uses all cores
+    pub fn threads(mut self, threads: usize) -> Benchmark {
+        self.threads = threads;
+        self
+    }
+
+    pub fn progress(mut self, progress: bool) -> Benchmark {
+        self.progress = progress;
+        self
+    }
+
+    // search the peptides against the proteome, comparing the hits against the expected
+    // results of the Python tool if there are any
+    pub fn run(&self, peptides: &[String], expected: Option<&str>) -> Result<BenchResult> {
+        let expected = expected.map(read_expected).transpose()?;
+
+        let start = Instant::now();
+        let (matcher, index_seconds) = match (&self.db, &self.proteome) {
+            (Some(db_path), _) => {
+                let matcher = Matcher::new(db_path)?;
+                match self.k {
+                    Some(k) if k != matcher.k() => {
+                        return Err(PepMatchError::invalid(format!(
+                            "{}: preprocessed with k = {}, not {}",
+                            db_path,
+                            matcher.k(),
+                            k
+                        )));
+                    }
+                    None if matcher.k() != self.dataset.k() => tracing::warn!(
+                        "{} was preprocessed with k = {}, the {} benchmark's is {}",
+                        db_path,
+                        matcher.k(),
+                        self.dataset.name(),
+                        self.dataset.k()
+                    ),
+                    _ => {}
+                }
+                (matcher, 0.0)
+            }
+            (None, Some(proteome)) => {
+                let k = self.k.unwrap_or(self.dataset.k());
+                let preprocessor = Preprocessor::new(proteome, k).progress(self.progress);
+                let matcher = Matcher::in_memory(&preprocessor)?;
+                (matcher, start.elapsed().as_secs_f64())
+            }
+            (None, None) => return Err(PepMatchError::invalid("a benchmark needs a proteome or a DB to search")),
+        };
+        let matcher = matcher
+            .max_mismatches(self.dataset.max_mismatches())
+            .best_match(self.dataset.best_match())
+            .threads(self.threads)
+            .progress(self.progress);
+
+        let start = Instant::now();
+        let hits = matcher.match_peptides(peptides)?;
+        let search_seconds = start.elapsed().as_secs_f64();
+
+        Ok(BenchResult {
+            dataset: self.dataset,
+            k: matcher.k(),
+            peptides: peptides.len(),
+            hits: hits.len(),
+            index_seconds,
+            search_seconds,
+            peak_rss: peak_rss(),
+            recall: expected.map(|expected| recall(&hits, &expected)),
+        })
+    }
+}
+
+// a hit by its peptide, protein and where it starts, which is all the two tools agree on
+type HitKey = (String, String, usize);
+
+// the hits in a results file of the Python tool, a CSV or TSV with its column names
+fn read_expected(path: &str) -> Result<HashSet<HitKey>> {
+    let invalid = |message: String| PepMatchError::invalid(format!("{}: {}", path, message));
+    let contents = std::fs::read_to_string(path).map_err(PepMatchError::io(path))?;
+    let header = contents.lines().next().unwrap_or_default();
+    let delimiter = if header.contains('\t') { b'\t' } else { b',' };
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(contents.as_bytes());
+    let headers = reader.headers().map_err(|e| invalid(e.to_string()))?.clone();
+    let column = |name: &str| {
+        headers.iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+            .ok_or_else(|| invalid(format!("no column named {}", name)))
+    };
+    let (peptide, protein, start) = (column("Query Sequence")?, column("Protein ID")?, column("Index start")?);
+
+    let mut expected = HashSet::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| invalid(e.to_string()))?;
+        let field = |i: usize| record.get(i).unwrap_or_default().trim();
+        // peptides without hits get a row with no protein
+        if field(protein).is_empty() {
+            continue;
+        }
+        // the Python tool writes its positions as floats when a column has missing values
+        let index_start = field(start).parse::<f64>().map_err(|_| invalid(format!("invalid index start: {}", field(start))))?;
+        expected.insert((field(peptide).to_string(), field(protein).to_string(), index_start as usize));
+    }
+    Ok(expected)
+}
+
+fn recall(hits: &[Match], expected: &HashSet<HitKey>) -> Recall {
+    let found: HashSet<HitKey> = hits
+        .iter()
+        .map(|hit| (hit.peptide.clone(), hit.protein_id.clone(), hit.index_start))
+        .filter(|key| expected.contains(key))
+        .collect();
+    Recall { expected: expected.len(), found: found.len() }
+}
+
+// the most memory the process has had resident, from Linux's /proc
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
@@ -0,0 +1,246 @@
+use crate::equivalence::EquivalenceClasses;
+use crate::error::{PepMatchError, Result};
+use crate::index::open_read_only;
+use crate::preprocessor::{Compression, DbLayout, Preprocessor};
+use rusqlite::OptionalExtension;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+
+// what a DB was preprocessed from and with, read back from its meta and build_info
+// tables, DBs written before those existed or by the Python PEPMatch have none of it
+#[derive(Debug, Clone, Default)]
+pub struct BuildInfo {
+    pub k: Option<usize>,
+    pub extra_k: Vec<usize>, // other k the DB has k-mers of
+    pub i_l_equivalent: Option<bool>,
+    pub equivalence_classes: EquivalenceClasses, // classes of residues the k-mers don't tell apart
+    pub translate: Option<bool>,
+    pub dedupe_sequences: bool, // identical sequences were indexed once, with the other proteins kept as synonyms
+    pub layout: Option<DbLayout>,
+    pub shards: usize, // files the k-mers are split across next to the DB, 0 if they're in it
+    pub compression: Compression,
+    pub gene_priority: Option<String>, // gene priority proteome FASTA the DB was built with
+    pub version: Option<String>,
+    pub built_at: Option<u64>, // seconds since the Unix epoch
+    pub sources: Vec<Source>,
+}
+
+// a proteome FASTA file a DB was preprocessed from
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub proteome: String,
+    pub path: String,
+    pub checksum: String, // SHA-256 of the file as it was read, compressed or not
+    pub modified: Option<u64>, // when the file was last changed, in nanoseconds since the Unix epoch
+}
+
+impl BuildInfo {
+    pub fn read(db_path: &str) -> Result<BuildInfo> {
+        let conn = open_read_only(db_path)?;
+        BuildInfo::from_connection(&conn).map_err(PepMatchError::database(db_path))
+    }
+
+    pub(crate) fn from_connection(conn: &rusqlite::Connection) -> rusqlite::Result<BuildInfo> {
+        let mut info = BuildInfo {
+            k: meta(conn, "k")?.and_then(|k| k.parse().ok()),
+            extra_k: meta(conn, "extra_k")?
+                .map(|extra_k| extra_k.split(',').filter_map(|k| k.parse().ok()).collect())
+                .unwrap_or_default(),
+            i_l_equivalent: meta(conn, "i_l_equivalent")?.and_then(|i_l| i_l.parse().ok()),
+            equivalence_classes: meta(conn, "equivalence_classes")?.and_then(|classes| classes.parse().ok()).unwrap_or_default(),
+            translate: meta(conn, "translate")?.and_then(|translate| translate.parse().ok()),
+            dedupe_sequences: meta(conn, "dedupe_sequences")?.and_then(|dedupe| dedupe.parse().ok()).unwrap_or_default(),
+            layout: meta(conn, "layout")?.and_then(|layout| layout.parse().ok()),
+            shards: meta(conn, "shards")?.and_then(|shards| shards.parse().ok()).unwrap_or_default(),
+            compression: meta(conn, "compression")?.and_then(|compression| compression.parse().ok()).unwrap_or_default(),
+            gene_priority: meta(conn, "gene_priority")?,
+            version: meta(conn, "version")?,
+            built_at: meta(conn, "built_at")?.and_then(|built_at| built_at.parse().ok()),
+            sources: Vec::new(),
+        };
+        if has_table(conn, "build_info")? {
+            // DBs written before modification times were kept have no modified column
+            let modified = if has_column(conn, "build_info", "modified")? { "modified" } else { "NULL" };
+            let mut stmt =
+                conn.prepare(&format!("SELECT proteome, source, checksum, {} FROM build_info ORDER BY rowid", modified))?;
+            let sources = stmt.query_map(rusqlite::params![], |row| {
+                Ok(Source {
+                    proteome: row.get(0)?,
+                    path: row.get(1)?,
+                    checksum: row.get(2)?,
+                    modified: row.get::<_, Option<i64>>(3)?.map(|modified| modified as u64),
+                })
+            })?;
+            info.sources = sources.collect::<rusqlite::Result<_>>()?;
+        }
+        Ok(info)
+    }
+
+    // check a search's settings against what the DB was built with, a k given for the
+    // search has to be one of the DB's, the equivalence classes the DB's, and (name, path)
+    // proteome files given have to be unchanged, anything the DB doesn't record passes
+    pub fn validate(
+        &self,
+        k: Option<usize>,
+        i_l_equivalent: bool,
+        equivalence_classes: &EquivalenceClasses,
+        proteomes: &[(String, String)],
+    ) -> Result<()> {
+        if let (Some(k), Some(built_k)) = (k, self.k) {
+            if k != built_k && !self.extra_k.contains(&k) {
+                let mut built: Vec<String> = vec![built_k.to_string()];
+                built.extend(self.extra_k.iter().map(usize::to_string));
+                return Err(incompatible(format!("the DB was preprocessed with k = {}, not {}", built.join(", "), k)));
+            }
+        }
+        if let Some(built_i_l) = self.i_l_equivalent {
+            if built_i_l != i_l_equivalent {
+                let treated = if built_i_l { "with" } else { "without" };
+                return Err(incompatible(format!("the DB was preprocessed {} --treat-i-l-equivalent", treated)));
+            }
+        }
+        // DBs that record their settings record the classes they were built with, if any
+        if self.k.is_some() && self.equivalence_classes != *equivalence_classes {
+            return Err(incompatible(if self.equivalence_classes.is_empty() {
+                "the DB was preprocessed without --equivalence-classes".to_string()
+            } else {
+                format!("the DB was preprocessed with --equivalence-classes \"{}\"", self.equivalence_classes)
+            }));
+        }
+        for (name, path) in proteomes {
+            let Some(source) = self.sources.iter().find(|source| source.proteome == *name) else {
+                continue;
+            };
+            if checksum(path)? != source.checksum {
+                return Err(incompatible(format!(
+                    "{} doesn't match the {} proteome the DB was preprocessed from ({})",
+                    path, name, source.path
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // the proteome files the DB was built from that changed since, a file is only hashed
+    // again when its modification time isn't the one recorded, files that are gone pass
+    pub fn changed_sources(&self) -> Result<Vec<&Source>> {
+        let mut changed = Vec::new();
+        for source in &self.sources {
+            if !std::path::Path::new(&source.path).is_file() {
+                continue;
+            }
+            if source.modified.is_some() && modified(&source.path) == source.modified {
+                continue;
+            }
+            if checksum(&source.path)? != source.checksum {
+                changed.push(source);
+            }
+        }
+        Ok(changed)
+    }
+
+    // a preprocessor that builds the DB at db_path again from the files and with the
+    // settings it was built with
+    pub fn preprocessor(&self, db_path: &str) -> Result<Preprocessor> {
+        let (Some(k), false) = (self.k, self.sources.is_empty()) else {
+            return Err(PepMatchError::invalid(format!("{}: doesn't record how it was built, preprocess it again by hand", db_path)));
+        };
+        let proteomes: Vec<(String, String)> =
+            self.sources.iter().map(|source| (source.proteome.clone(), source.path.clone())).collect();
+        let preprocessor = Preprocessor::with_proteomes(&proteomes, k)
+            .db_path(db_path)
+            .extra_k(&self.extra_k)
+            .treat_i_l_equivalent(self.i_l_equivalent.unwrap_or_default())
+            .equivalence_classes(self.equivalence_classes.clone())
+            .translate(self.translate.unwrap_or_default())
+            .dedupe_sequences(self.dedupe_sequences)
+            .layout(self.layout.unwrap_or_default())
+            .shards(self.shards)
+            .compression(self.compression)
+            .overwrite(true);
+        Ok(match &self.gene_priority {
+            Some(gene_priority) => preprocessor.gene_priority(gene_priority),
+            None => preprocessor,
+        })
+    }
+}
+
+fn incompatible(reason: String) -> PepMatchError {
+    PepMatchError::invalid(format!("{}, preprocess it again or use --force to search it anyway", reason))
+}
+
+// SHA-256 of a file as a hex string
+pub fn checksum(path: &str) -> Result<String> {
+    let mut file = std::fs::File::open(path).map_err(PepMatchError::io(path))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buffer).map_err(PepMatchError::io(path))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// when a file was last changed, in nanoseconds since the Unix epoch
+pub(crate) fn modified(path: &str) -> Option<u64> {
+    let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()?;
+    modified.duration_since(std::time::UNIX_EPOCH).map(|elapsed| elapsed.as_nanos() as u64).ok()
+}
+
+// a value from the meta table of build parameters
+fn meta(conn: &rusqlite::Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    if !has_table(conn, "meta")? {
+        return Ok(None);
+    }
+    conn.query_row("SELECT value FROM meta WHERE key = ?1", rusqlite::params![key], |row| row.get(0)).optional()
+}
+
+pub(crate) fn has_column(conn: &rusqlite::Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    let name: Option<String> = conn
+        .query_row(
+            "SELECT name FROM pragma_table_info(?1) WHERE name = ?2",
+            rusqlite::params![table, column],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(name.is_some())
+}
+
+pub(crate) fn has_table(conn: &rusqlite::Connection, table: &str) -> rusqlite::Result<bool> {
+    let name: Option<String> = conn
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            rusqlite::params![table],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(name.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_proteomes_are_found_and_rebuilt() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-build-info-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        Preprocessor::new(fasta, 3).db_path(db).extra_k(&[4]).preprocess().unwrap();
+        let info = BuildInfo::read(db).unwrap();
+        assert!(info.changed_sources().unwrap().is_empty());
+
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQRR\n").unwrap();
+        assert_eq!(info.changed_sources().unwrap().len(), 1);
+        info.preprocessor(db).unwrap().preprocess().unwrap();
+        let rebuilt = BuildInfo::read(db).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(rebuilt.changed_sources().unwrap().is_empty());
+        assert_eq!((rebuilt.k, rebuilt.extra_k), (Some(3), vec![4]));
+    }
+}
@@ -0,0 +1,228 @@
+use crate::build_info::BuildInfo;
+use crate::error::{PepMatchError, Result};
+use crate::index::is_sqlite_db;
+use crate::Match;
+use rusqlite::OptionalExtension;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+// hits of peptides already searched, kept in a SQLite DB keyed by the peptide and a
+// digest of the search: the index searched, what it was built from and every setting
+// that changes the hits, so a repeated search of a peptide is looked up rather than redone
+pub struct ResultCache {
+    conn: rusqlite::Connection,
+    path: String,
+}
+
+impl ResultCache {
+    // open the cache at a path, creating it if it doesn't exist yet
+    pub fn open(path: &str) -> Result<ResultCache> {
+        if let Some(parent) = std::path::Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(PepMatchError::io(path))?;
+        }
+        let conn = rusqlite::Connection::open(path).map_err(PepMatchError::database(path))?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS results (
+                search           TEXT NOT NULL,
+                peptide          TEXT NOT NULL,
+                hits             TEXT NOT NULL,
+                PRIMARY KEY (search, peptide)
+            ) WITHOUT ROWID",
+            rusqlite::params![],
+        )
+        .map_err(PepMatchError::database(path))?;
+        // searches running at once write to the cache in turn
+        conn.busy_timeout(std::time::Duration::from_secs(60)).map_err(PepMatchError::database(path))?;
+        Ok(ResultCache { conn, path: path.to_string() })
+    }
+
+    // another connection to the cache, for another thread
+    pub(crate) fn reopen(&self) -> Result<ResultCache> {
+        ResultCache::open(&self.path)
+    }
+
+    // $XDG_CACHE_HOME/pepmatch/results.db, or under ~/.cache if that isn't set
+    pub fn default_path() -> Result<String> {
+        let dir = std::env::var("XDG_CACHE_HOME")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .or_else(|| std::env::var("HOME").ok().map(|home| format!("{}/.cache", home)))
+            .ok_or_else(|| PepMatchError::invalid("neither XDG_CACHE_HOME nor HOME is set for the result cache"))?;
+        Ok(format!("{}/pepmatch/results.db", dir))
+    }
+
+    // delete the cache at a path, if there is one
+    pub fn clear(path: &str) -> Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(PepMatchError::io(path)(e)),
+            _ => Ok(()),
+        }
+    }
+
+    // the cached hits of a peptide in a search, if it's been searched before
+    pub(crate) fn get(&self, search: &str, peptide: &str) -> Result<Option<Vec<Match>>> {
+        let hits: Option<String> = self.conn
+            .prepare_cached("SELECT hits FROM results WHERE search = ?1 AND peptide = ?2")
+            .and_then(|mut stmt| stmt.query_row(rusqlite::params![search, peptide], |row| row.get(0)).optional())
+            .map_err(PepMatchError::database(&self.path))?;
+        // an entry that can't be read is searched again and replaced
+        Ok(hits.and_then(|hits| decode_hits(peptide, &hits)))
+    }
+
+    // cache the hits of peptides in a search, in one transaction
+    pub(crate) fn put(&self, search: &str, results: &[(&str, &[Match])]) -> Result<()> {
+        let tx = self.conn.unchecked_transaction().map_err(PepMatchError::database(&self.path))?;
+        for (peptide, hits) in results {
+            tx.prepare_cached("INSERT OR REPLACE INTO results (search, peptide, hits) VALUES (?1, ?2, ?3)")
+                .and_then(|mut stmt| stmt.execute(rusqlite::params![search, peptide, encode_hits(hits)]))
+                .map_err(PepMatchError::database(&self.path))?;
+        }
+        tx.commit().map_err(PepMatchError::database(&self.path))
+    }
+}
+
+// what a DB or binary index was built from, the checksums of its proteomes and when
+// it was built when it records them, otherwise the size and modification time of the file,
+// or of the directory of a store, which is made anew when it's rebuilt
+pub(crate) fn index_fingerprint(db_path: &str) -> Result<String> {
+    if is_sqlite_db(db_path) {
+        let info = BuildInfo::read(db_path)?;
+        if !info.sources.is_empty() {
+            let checksums: Vec<&str> = info.sources.iter().map(|source| source.checksum.as_str()).collect();
+            return Ok(format!("{} built at {}", checksums.join(","), info.built_at.unwrap_or_default()));
+        }
+    }
+    let metadata = std::fs::metadata(db_path).map_err(PepMatchError::io(db_path))?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos())
+        .unwrap_or_default();
+    Ok(format!("{} bytes modified at {}", metadata.len(), modified))
+}
+
+// SHA-256 of a description of a search as a hex string
+pub(crate) fn digest(description: &str) -> String {
+    Sha256::digest(description.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// a peptide's hits as a JSON array with an array of fields per hit, the peptide is the key
+fn encode_hits(hits: &[Match]) -> String {
+    let hits: Vec<Value> = hits
+        .iter()
+        .map(|hit| {
+            json!([
+                hit.matched_sequence, hit.protein_number, hit.protein_id, hit.protein_name, hit.species,
+                hit.taxon_id, hit.gene, hit.mismatches, hit.mutated_positions, hit.index_start, hit.index_end,
+                hit.pe_level, hit.gene_priority, hit.db, hit.isoform, hit.discontinuous, hit.frame,
+                hit.genomic_start, hit.genomic_end, hit.left_context, hit.right_context, hit.score,
+                hit.n_terminus, hit.query_start, hit.query_end, hit.protein_length, hit.molecular_weight
+            ])
+        })
+        .collect();
+    Value::Array(hits).to_string()
+}
+
+fn decode_hits(peptide: &str, hits: &str) -> Option<Vec<Match>> {
+    let hits: Value = serde_json::from_str(hits).ok()?;
+    hits.as_array()?.iter().map(|hit| decode_hit(peptide, hit)).collect()
+}
+
+fn decode_hit(peptide: &str, hit: &Value) -> Option<Match> {
+    let text = |i: usize| hit[i].as_str().map(str::to_string);
+    let number = |i: usize| hit[i].as_u64().map(|n| n as usize);
+    Some(Match {
+        peptide: peptide.to_string(),
+        matched_sequence: text(0)?,
+        protein_number: number(1)?,
+        protein_id: text(2)?,
+        protein_name: text(3)?,
+        species: text(4)?,
+        taxon_id: text(5)?,
+        gene: text(6)?,
+        mismatches: number(7)?,
+        mutated_positions: hit[8].as_array()?.iter().map(|n| n.as_u64().map(|n| n as usize)).collect::<Option<_>>()?,
+        index_start: number(9)?,
+        index_end: number(10)?,
+        pe_level: number(11)?,
+        gene_priority: hit[12].as_bool()?,
+        db: text(13)?,
+        isoform: number(14)?,
+        protein_length: number(25)?,
+        molecular_weight: number(26)?,
+        discontinuous: hit[15].as_bool()?,
+        frame: hit[16].as_i64()? as i8,
+        genomic_start: number(17)?,
+        genomic_end: number(18)?,
+        left_context: text(19)?,
+        right_context: text(20)?,
+        score: hit[21].as_i64()? as i32,
+        n_terminus: text(22)?,
+        mature_start: 0,
+        mature_end: 0,
+        query_start: number(23)?,
+        query_end: number(24)?,
+        e_value: 0.0,
+        ensembl: String::new(),
+        refseq: String::new(),
+        hgnc: String::new(),
+        kept: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits_round_trip() {
+        let hit = Match {
+            peptide: "SIINFEKL".to_string(),
+            matched_sequence: "SIINFEKV".to_string(),
+            protein_number: 12,
+            protein_id: "P01012".to_string(),
+            protein_name: "Ovalbumin".to_string(),
+            species: "Gallus gallus".to_string(),
+            taxon_id: "9031".to_string(),
+            gene: "SERPINB14".to_string(),
+            mismatches: 1,
+            mutated_positions: vec![8],
+            index_start: 258,
+            index_end: 265,
+            pe_level: 1,
+            gene_priority: true,
+            db: "sp".to_string(),
+            isoform: 2,
+            protein_length: 393,
+            molecular_weight: 43653,
+            discontinuous: false,
+            frame: -2,
+            genomic_start: 100,
+            genomic_end: 123,
+            left_context: "EVS".to_string(),
+            right_context: "TEW".to_string(),
+            score: -3,
+            n_terminus: "mature".to_string(),
+            mature_start: 0,
+            mature_end: 0,
+            query_start: 0,
+            query_end: 0,
+            e_value: 0.0,
+            ensembl: String::new(),
+            refseq: String::new(),
+            hgnc: String::new(),
+            kept: Vec::new(),
+        };
+        let decoded = decode_hits("SIINFEKL", &encode_hits(std::slice::from_ref(&hit))).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(format!("{:?}", decoded[0]), format!("{:?}", hit));
+    }
+
+    #[test]
+    fn broken_entries_are_misses() {
+        assert!(decode_hits("SIINFEKL", "not json").is_none());
+        assert!(decode_hits("SIINFEKL", "[[\"SIINFEKL\", 1]]").is_none());
+        assert_eq!(decode_hits("SIINFEKL", "[]").unwrap().len(), 0);
+    }
+}
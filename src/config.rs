@@ -0,0 +1,131 @@
+use crate::error::{PepMatchError, Result};
+use std::collections::HashMap;
+
+// the config file read from the working directory when none is given
+pub const CONFIG_FILE: &str = "pepmatch.toml";
+
+// the value of an arg in a config file, a flag that's on or off or the values it's given
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Flag(bool),
+    Values(Vec<String>),
+}
+
+// defaults for the args of the command line from a TOML file, top-level keys are used by
+// every subcommand with an arg of that name and the keys of a [subcommand] table by that
+// one only, over the top-level ones, keys are long flags like db, k-value or threads
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    path: String,
+    shared: Vec<(String, Setting)>,
+    commands: HashMap<String, Vec<(String, Setting)>>,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config> {
+        let contents = std::fs::read_to_string(path).map_err(PepMatchError::io(path))?;
+        Config::parse(path, &contents)
+    }
+
+    pub fn parse(path: &str, contents: &str) -> Result<Config> {
+        let table: toml::Table = contents.parse().map_err(|e| PepMatchError::invalid(format!("{}: {}", path, e)))?;
+        let mut config = Config { path: path.to_string(), ..Config::default() };
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(table) => {
+                    let settings = table
+                        .into_iter()
+                        .map(|(key, value)| Ok((arg_name(&key), setting(path, &key, value)?)))
+                        .collect::<Result<Vec<_>>>()?;
+                    config.commands.insert(key, settings);
+                }
+                value => config.shared.push((arg_name(&key), setting(path, &key, value)?)),
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // the [subcommand] tables of the file
+    pub fn commands(&self) -> impl Iterator<Item = &str> {
+        self.commands.keys().map(String::as_str)
+    }
+
+    // the settings of a subcommand, its table's and then the top-level ones it doesn't
+    // override, each with whether it's from its table
+    pub fn settings(&self, command: &str) -> Vec<(&str, &Setting, bool)> {
+        let own = self.commands.get(command).map(Vec::as_slice).unwrap_or_default();
+        let mut settings: Vec<(&str, &Setting, bool)> =
+            own.iter().map(|(name, setting)| (name.as_str(), setting, true)).collect();
+        for (name, setting) in &self.shared {
+            if !own.iter().any(|(own, _)| own == name) {
+                settings.push((name, setting, false));
+            }
+        }
+        settings
+    }
+}
+
+// args are named by their long flag, with dashes or underscores between words
+fn arg_name(key: &str) -> String {
+    key.replace('_', "-")
+}
+
+fn setting(path: &str, key: &str, value: toml::Value) -> Result<Setting> {
+    let invalid = || PepMatchError::invalid(format!("{}: {} must be a string, number, boolean or array of them", path, key));
+    let text = |value: toml::Value| match value {
+        toml::Value::String(s) => Some(s),
+        toml::Value::Integer(n) => Some(n.to_string()),
+        toml::Value::Float(x) => Some(x.to_string()),
+        _ => None,
+    };
+    match value {
+        toml::Value::Boolean(on) => Ok(Setting::Flag(on)),
+        toml::Value::Array(values) => {
+            Ok(Setting::Values(values.into_iter().map(text).collect::<Option<_>>().ok_or_else(invalid)?))
+        }
+        value => Ok(Setting::Values(vec![text(value).ok_or_else(invalid)?])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+db = "human_k5.db"
+threads = 8
+quiet = true
+
+[match]
+output_format = "csv"
+threads = 4
+taxon-id = [9606, 10090]
+"#;
+
+    #[test]
+    fn tables_override_top_level_keys() {
+        let config = Config::parse("pepmatch.toml", CONFIG).unwrap();
+        let values = |s: &[&str]| Setting::Values(s.iter().map(|v| v.to_string()).collect());
+        let settings = config.settings("match");
+        assert_eq!(settings, vec![
+            ("output-format", &values(&["csv"]), true),
+            ("taxon-id", &values(&["9606", "10090"]), true),
+            ("threads", &values(&["4"]), true),
+            ("db", &values(&["human_k5.db"]), false),
+            ("quiet", &Setting::Flag(true), false),
+        ]);
+        assert_eq!(config.settings("inspect").len(), 3);
+        assert_eq!(config.commands().collect::<Vec<_>>(), vec!["match"]);
+    }
+
+    #[test]
+    fn nested_values_are_rejected() {
+        assert!(Config::parse("pepmatch.toml", "[match]\ndb = { path = \"x.db\" }").is_err());
+        assert!(Config::parse("pepmatch.toml", "[match]\ndb = [[\"x.db\"]]").is_err());
+        assert!(Config::parse("pepmatch.toml", "db = ").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::error::Result;
+use crate::Match;
+use std::collections::{BTreeMap, HashSet};
+
+// the hits in one protein aggregated for protein inference
+#[derive(Debug, Clone)]
+pub struct ProteinCoverage {
+    pub protein_id: String,
+    pub protein_name: String,
+    pub species: String,
+    pub gene: String,
+    pub protein_length: usize,
+    pub peptides: usize, // distinct query peptides with a hit in the protein
+    pub covered_residues: usize,
+    pub intervals: Vec<(usize, usize)>, // 1-based inclusive ranges of residues covered by hits, merged
+    pub coverage_sequence: String, // the protein with covered residues upper case and the rest lower case
+}
+
+impl ProteinCoverage {
+    // fraction of the protein's residues covered by hits
+    pub fn coverage(&self) -> f64 {
+        if self.protein_length == 0 { 0.0 } else { self.covered_residues as f64 / self.protein_length as f64 }
+    }
+
+    // the intervals as start-end, e.g. 1-10;22-31
+    pub fn intervals_string(&self) -> String {
+        let intervals: Vec<String> = self.intervals.iter().map(|(start, end)| format!("{}-{}", start, end)).collect();
+        intervals.join(";")
+    }
+}
+
+// aggregate hits per protein with the sequence of each protein by its number, proteins
+// come out with the most peptides first and then in the order of the index
+pub(crate) fn aggregate(hits: &[Match], mut sequence: impl FnMut(usize) -> Result<String>) -> Result<Vec<ProteinCoverage>> {
+    let mut by_protein: BTreeMap<usize, Vec<&Match>> = BTreeMap::new();
+    for hit in hits {
+        by_protein.entry(hit.protein_number).or_default().push(hit);
+    }
+
+    let mut coverages = Vec::new();
+    for (protein_number, hits) in by_protein {
+        let sequence = sequence(protein_number)?;
+        let mut ranges: Vec<(usize, usize)> = hits.iter().map(|hit| (hit.index_start, hit.index_end)).collect();
+        ranges.sort();
+        let mut intervals: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in ranges {
+            match intervals.last_mut() {
+                Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+                _ => intervals.push((start, end)),
+            }
+        }
+
+        let coverage_sequence: String = sequence
+            .chars()
+            .enumerate()
+            .map(|(i, residue)| {
+                let covered = intervals.iter().any(|(start, end)| (*start..=*end).contains(&(i + 1)));
+                if covered { residue.to_ascii_uppercase() } else { residue.to_ascii_lowercase() }
+            })
+            .collect();
+        let first = hits[0];
+        coverages.push(ProteinCoverage {
+            protein_id: first.protein_id.clone(),
+            protein_name: first.protein_name.clone(),
+            species: first.species.clone(),
+            gene: first.gene.clone(),
+            protein_length: sequence.len(),
+            peptides: hits.iter().map(|hit| &hit.peptide).collect::<HashSet<_>>().len(),
+            covered_residues: intervals.iter().map(|(start, end)| end + 1 - start).sum(),
+            intervals,
+            coverage_sequence,
+        });
+    }
+
+    // stable, so ties stay in protein number order
+    coverages.sort_by_key(|coverage| std::cmp::Reverse(coverage.peptides));
+    Ok(coverages)
+}
@@ -0,0 +1,123 @@
+use crate::error::{PepMatchError, Result};
+use crate::proteome::open_proteome;
+use bio::io::fasta;
+
+// prefix of the IDs of decoy proteins
+pub const DECOY_PREFIX: &str = "DECOY_";
+
+// how decoy sequences are made from the target ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DecoyMode {
+    #[default]
+    Reverse,
+    Shuffle,
+}
+
+impl std::str::FromStr for DecoyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<DecoyMode, String> {
+        match s.to_lowercase().as_str() {
+            "reverse" => Ok(DecoyMode::Reverse),
+            "shuffle" => Ok(DecoyMode::Shuffle),
+            _ => Err(format!("unknown decoy mode: {}", s)),
+        }
+    }
+}
+
+// write a decoy of every protein in a proteome FASTA file to another FASTA file, with
+// its sequence reversed or shuffled and DECOY_ before its accession, shuffles are the
+// same for the same seed, returns the number of decoys written
+pub fn write_decoys(input: &str, output: &str, mode: DecoyMode, seed: u64) -> Result<usize> {
+    let reader = fasta::Reader::new(open_proteome(input)?);
+    let file = std::fs::File::create(output).map_err(PepMatchError::io(output))?;
+    let mut writer = fasta::Writer::new(std::io::BufWriter::new(file));
+    let mut rng = SplitMix64(seed);
+
+    let mut count = 0;
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| PepMatchError::Fasta {
+            path: input.to_string(),
+            record: i + 1,
+            message: e.to_string(),
+        })?;
+        let mut sequence = record.seq().to_vec();
+        match mode {
+            DecoyMode::Reverse => sequence.reverse(),
+            DecoyMode::Shuffle => rng.shuffle(&mut sequence),
+        }
+        writer
+            .write(&decoy_id(record.id()), record.desc(), &sequence)
+            .map_err(PepMatchError::io(output))?;
+        count += 1;
+    }
+    writer.flush().map_err(PepMatchError::io(output))?;
+    Ok(count)
+}
+
+// the ID of a decoy, the prefix goes on the accession of UniProt IDs so their
+// headers still parse, e.g. sp|P04637|P53_HUMAN --> sp|DECOY_P04637|P53_HUMAN
+fn decoy_id(id: &str) -> String {
+    match id.split_once('|') {
+        Some((db @ ("sp" | "tr"), rest)) => format!("{}|{}{}", db, DECOY_PREFIX, rest),
+        _ => format!("{}{}", DECOY_PREFIX, id),
+    }
+}
+
+// small seedable generator so shuffles are reproducible without another dependency
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Fisher-Yates shuffle
+    fn shuffle(&mut self, residues: &mut [u8]) {
+        for i in (1..residues.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            residues.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoys_are_reversed_or_shuffled_targets() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-decoy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("proteome.fasta");
+        let input = input.to_str().unwrap();
+        std::fs::write(input, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606\nMKLVSTAAQW\n>custom\nPESTAV\n").unwrap();
+        let read = |mode, seed| {
+            let output = dir.join(format!("{:?}-{}.fasta", mode, seed));
+            let output = output.to_str().unwrap();
+            assert_eq!(write_decoys(input, output, mode, seed).unwrap(), 2);
+            let records: Vec<_> = fasta::Reader::from_file(output).unwrap().records().map(|record| record.unwrap()).collect();
+            records.iter().map(|record| (record.id().to_string(), String::from_utf8(record.seq().to_vec()).unwrap())).collect::<Vec<_>>()
+        };
+        let reversed = read(DecoyMode::Reverse, 0);
+        let (shuffled, again, other) = (read(DecoyMode::Shuffle, 7), read(DecoyMode::Shuffle, 7), read(DecoyMode::Shuffle, 8));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            reversed,
+            [("sp|DECOY_P00001|ONE_HUMAN".to_string(), "WQAATSVLKM".to_string()), ("DECOY_custom".to_string(), "VATSEP".to_string())]
+        );
+        assert_eq!(shuffled, again);
+        assert_ne!(shuffled, other);
+        let sorted = |sequence: &str| {
+            let mut residues = sequence.as_bytes().to_vec();
+            residues.sort_unstable();
+            residues
+        };
+        assert_eq!(sorted(&shuffled[0].1), sorted("MKLVSTAAQW"));
+    }
+}
@@ -0,0 +1,197 @@
+use crate::error::{PepMatchError, Result};
+use crate::header::{self, HeaderFormat};
+use crate::output::Coordinates;
+use crate::preprocessor::split_sequence;
+use crate::proteome::ProteomeReader;
+use std::io::Write;
+
+// columns of a digest, a row per peptide
+const COLUMNS: [&str; 4] = ["Peptide", "Protein ID", "Start", "End"];
+
+// proteases and the residues they cleave after, or before for Asp-N
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protease {
+    Trypsin,      // after K or R, not before P
+    TrypsinP,     // after K or R, before P too
+    Chymotrypsin, // after F, W or Y, not before P
+    LysC,         // after K
+    ArgC,         // after R, not before P
+    GluC,         // after E
+    AspN,         // before D
+}
+
+impl std::str::FromStr for Protease {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Protease, String> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "trypsin" => Ok(Protease::Trypsin),
+            "trypsin/p" => Ok(Protease::TrypsinP),
+            "chymotrypsin" => Ok(Protease::Chymotrypsin),
+            "lysc" => Ok(Protease::LysC),
+            "argc" => Ok(Protease::ArgC),
+            "gluc" => Ok(Protease::GluC),
+            "aspn" => Ok(Protease::AspN),
+            _ => Err(format!(
+                "unknown protease: {}, expected trypsin, trypsin/p, chymotrypsin, lys-c, arg-c, glu-c or asp-n",
+                s
+            )),
+        }
+    }
+}
+
+impl Protease {
+    // whether the protease cuts between two residues
+    fn cleaves(&self, before: u8, after: u8) -> bool {
+        match self {
+            Protease::Trypsin => matches!(before, b'K' | b'R') && after != b'P',
+            Protease::TrypsinP => matches!(before, b'K' | b'R'),
+            Protease::Chymotrypsin => matches!(before, b'F' | b'W' | b'Y') && after != b'P',
+            Protease::LysC => before == b'K',
+            Protease::ArgC => before == b'R' && after != b'P',
+            Protease::GluC => before == b'E',
+            Protease::AspN => after == b'D',
+        }
+    }
+}
+
+// how proteins are cut into peptides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Digestion {
+    // overlapping windows of the same length
+    Windows { length: usize, overlap: usize },
+    // the peptides a protease leaves, spanning up to `missed_cleavages` of its sites, with
+    // lengths between the bounds
+    Protease { protease: Protease, missed_cleavages: usize, min_length: usize, max_length: usize },
+}
+
+impl Digestion {
+    // the 0-based (start, end) of the peptides of a sequence
+    pub fn peptides(&self, sequence: &str) -> Vec<(usize, usize)> {
+        match *self {
+            Digestion::Windows { length, overlap } => windows(sequence, length, overlap),
+            Digestion::Protease { protease, missed_cleavages, min_length, max_length } => {
+                cleave(sequence, protease, missed_cleavages)
+                    .into_iter()
+                    .filter(|(start, end)| (min_length..=max_length).contains(&(end - start)))
+                    .collect()
+            }
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        match *self {
+            Digestion::Windows { length, overlap } if length == 0 || overlap >= length => {
+                Err(PepMatchError::invalid(format!(
+                    "the overlap of peptides must be less than their length, got {} and {}",
+                    overlap, length
+                )))
+            }
+            Digestion::Protease { min_length, max_length, .. } if min_length == 0 || min_length > max_length => {
+                Err(PepMatchError::invalid(format!(
+                    "the length bounds of peptides must be at least 1 and in order, got {} and {}",
+                    min_length, max_length
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// the 0-based (start, end) of the peptides between a protease's sites in a sequence and of
+// those spanning up to `missed_cleavages` sites
+pub fn cleave(sequence: &str, protease: Protease, missed_cleavages: usize) -> Vec<(usize, usize)> {
+    let residues = sequence.as_bytes();
+    let mut sites = vec![0];
+    sites.extend((1..residues.len()).filter(|&i| protease.cleaves(residues[i - 1], residues[i])));
+    sites.push(residues.len());
+    let mut peptides = Vec::new();
+    for (i, start) in sites.iter().enumerate().take(sites.len() - 1) {
+        for end in sites.iter().skip(i + 1).take(missed_cleavages + 1) {
+            peptides.push((*start, *end));
+        }
+    }
+    peptides
+}
+
+// the 0-based (start, end) of overlapping windows of `length` residues along a sequence, each
+// starting `length - overlap` residues after the one before, with a last one flush with the
+// sequence's end so its tail is covered too, a sequence shorter than `length` is one window
+pub fn windows(sequence: &str, length: usize, overlap: usize) -> Vec<(usize, usize)> {
+    if sequence.len() <= length {
+        return vec![(0, sequence.len())];
+    }
+    let mut windows: Vec<(usize, usize)> = split_sequence(sequence, length)
+        .into_iter()
+        .step_by(length - overlap)
+        .map(|(_, start)| (start, start + length))
+        .collect();
+    if windows.last().is_some_and(|(_, end)| *end < sequence.len()) {
+        windows.push((sequence.len() - length, sequence.len()));
+    }
+    windows
+}
+
+// write the peptides every protein of a proteome FASTA file is digested into as a TSV with
+// the protein each is from and its positions in the given coordinates, to a file or stdout,
+// returns the number of peptides written
+pub fn digest_proteome(
+    proteome: &str,
+    digestion: &Digestion,
+    header_format: &HeaderFormat,
+    coordinates: Coordinates,
+    output: Option<&str>,
+) -> Result<usize> {
+    digestion.check()?;
+    let path = output.unwrap_or("stdout");
+    let out: Box<dyn Write> = match output {
+        Some(output) => Box::new(std::fs::File::create(output).map_err(PepMatchError::io(output))?),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut writer = csv::WriterBuilder::new().delimiter(b'\t').from_writer(std::io::BufWriter::new(out));
+    let error = |e: csv::Error| PepMatchError::io(path)(e.into());
+    writer.write_record(COLUMNS).map_err(error)?;
+
+    let mut count = 0;
+    let proteomes = [(String::new(), proteome.to_string())];
+    for protein in ProteomeReader::open(&proteomes, header::parser(header_format)) {
+        let protein = protein?;
+        for (start, end) in digestion.peptides(&protein.sequence) {
+            // windows are 0-based with exclusive ends, hits' positions are 1-based with inclusive ones
+            let peptide = &protein.sequence[start..end];
+            let (start, end) = coordinates.interval(start + 1, end);
+            writer
+                .write_record([peptide, &protein.metadata.protein_id, &start.to_string(), &end.to_string()])
+                .map_err(error)?;
+            count += 1;
+        }
+    }
+    writer.flush().map_err(PepMatchError::io(path))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_cover_the_whole_sequence() {
+        assert_eq!(windows("ABCDEFGHIJ", 4, 2), [(0, 4), (2, 6), (4, 8), (6, 10)]);
+        // the last window is flush with the end even if it overlaps the one before more
+        assert_eq!(windows("ABCDEFGHIJK", 4, 2), [(0, 4), (2, 6), (4, 8), (6, 10), (7, 11)]);
+        assert_eq!(windows("ABCDEFGH", 4, 0), [(0, 4), (4, 8)]);
+        assert_eq!(windows("ABC", 4, 2), [(0, 3)]);
+    }
+
+    #[test]
+    fn proteases_cut_at_their_sites() {
+        let sequence = "MKPLRAKDEFPGR";
+        assert_eq!(cleave(sequence, Protease::Trypsin, 0), [(0, 5), (5, 7), (7, 13)]);
+        assert_eq!(cleave(sequence, Protease::TrypsinP, 0), [(0, 2), (2, 5), (5, 7), (7, 13)]);
+        assert_eq!(cleave(sequence, Protease::Trypsin, 1), [(0, 5), (0, 7), (5, 7), (5, 13), (7, 13)]);
+        assert_eq!(cleave(sequence, Protease::AspN, 0), [(0, 7), (7, 13)]);
+        let digestion = Digestion::Protease { protease: Protease::Trypsin, missed_cleavages: 1, min_length: 3, max_length: 7 };
+        assert_eq!(digestion.peptides(sequence), [(0, 5), (0, 7), (7, 13)]);
+        assert_eq!("Lys-C".parse::<Protease>(), Ok(Protease::LysC));
+    }
+}
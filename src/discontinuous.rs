@@ -0,0 +1,161 @@
+use crate::error::{PepMatchError, Result};
+use crate::progress;
+use crate::proteome::Protein;
+use crate::ranking;
+use crate::translate::genomic_range;
+use crate::{Match, Preprocessor};
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+// a discontinuous (conformational) epitope in IEDB notation, residues and their
+// 1-based positions in the protein, e.g. A12, K15, R18
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscontinuousEpitope {
+    pub residues: Vec<(u8, usize)>,
+}
+
+impl DiscontinuousEpitope {
+    // whether a query looks like a discontinuous epitope rather than a peptide
+    pub fn is_discontinuous(query: &str) -> bool {
+        Regex::new(r"^\s*[A-Za-z]\d+(\s*,\s*[A-Za-z]\d+)*\s*$").unwrap().is_match(query)
+    }
+}
+
+impl FromStr for DiscontinuousEpitope {
+    type Err = PepMatchError;
+
+    fn from_str(s: &str) -> Result<DiscontinuousEpitope> {
+        let invalid = || PepMatchError::invalid(format!("{} isn't a discontinuous epitope like A12, K15, R18", s));
+        let residues = s
+            .split(',')
+            .map(|residue| {
+                let residue = residue.trim();
+                let (letter, position) = residue.split_at(residue.chars().next().map_or(0, char::len_utf8));
+                match (letter.as_bytes(), position.parse::<usize>()) {
+                    ([letter], Ok(position)) if letter.is_ascii_alphabetic() && position > 0 => {
+                        Ok((letter.to_ascii_uppercase(), position))
+                    }
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DiscontinuousEpitope { residues })
+    }
+}
+
+impl fmt::Display for DiscontinuousEpitope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let residues: Vec<String> =
+            self.residues.iter().map(|(residue, position)| format!("{}{}", *residue as char, position)).collect();
+        write!(f, "{}", residues.join(", "))
+    }
+}
+
+// find the proteins with every residue of each epitope at its position, or within
+// tolerance positions of it to allow for numbering differences between a structure
+// and the sequence, up to max_mismatches residues may disagree, hits come out in
+// the order of the epitopes with the protein's residue for each epitope residue
+// as the matched sequence, e.g. A12, K16, G18
+pub fn match_discontinuous(
+    preprocessor: &Preprocessor,
+    epitopes: &[DiscontinuousEpitope],
+    max_mismatches: usize,
+    tolerance: usize,
+) -> Result<Vec<Match>> {
+    let mut hits: Vec<Vec<Match>> = vec![Vec::new(); epitopes.len()];
+    let bar = progress::proteins(preprocessor.progress, preprocessor.count_proteins()?);
+    for protein in preprocessor.read_proteome()? {
+        let protein = protein?;
+        bar.inc(1);
+        for (epitope, hits) in epitopes.iter().zip(&mut hits) {
+            if let Some(hit) = match_epitope(epitope, &protein, max_mismatches, tolerance) {
+                hits.push(hit);
+            }
+        }
+    }
+    bar.finish_and_clear();
+    for hits in &mut hits {
+        ranking::sort_matches(hits);
+    }
+    Ok(hits.into_iter().flatten().collect())
+}
+
+fn match_epitope(epitope: &DiscontinuousEpitope, protein: &Protein, max_mismatches: usize, tolerance: usize) -> Option<Match> {
+    let sequence = protein.sequence.as_bytes();
+    // residues past the protein's end, tolerance aside, can't be in it
+    if epitope.residues.iter().any(|&(_, position)| position > sequence.len() + tolerance) {
+        return None;
+    }
+    let mut matched = Vec::new();
+    let mut mutated_positions = Vec::new();
+    for (i, &(residue, position)) in epitope.residues.iter().enumerate() {
+        match find_residue(sequence, residue, position, tolerance) {
+            Some(found) => matched.push((residue, found)),
+            None => {
+                // report what the protein has at the position instead, - past its end within tolerance
+                let actual = sequence.get(position - 1).copied().unwrap_or(b'-');
+                matched.push((actual, position));
+                mutated_positions.push(i + 1);
+                if mutated_positions.len() > max_mismatches {
+                    return None;
+                }
+            }
+        }
+    }
+
+    let metadata = &protein.metadata;
+    let index_start = matched.iter().map(|(_, position)| *position).min().unwrap_or_default();
+    let index_end = matched.iter().map(|(_, position)| *position).max().unwrap_or_default();
+    let (genomic_start, genomic_end) = match metadata.frame {
+        0 => (0, 0),
+        frame => genomic_range(frame, metadata.source_length, index_start - 1, index_end - index_start + 1),
+    };
+    Some(Match {
+        peptide: epitope.to_string(),
+        matched_sequence: DiscontinuousEpitope { residues: matched.clone() }.to_string(),
+        protein_number: protein.number,
+        protein_id: metadata.protein_id.clone(),
+        protein_name: metadata.protein_name.trim().to_string(),
+        species: metadata.species.trim().to_string(),
+        taxon_id: metadata.taxon_id.clone(),
+        gene: metadata.gene.clone(),
+        mismatches: mutated_positions.len(),
+        mutated_positions,
+        index_start,
+        index_end,
+        pe_level: metadata.pe_level,
+        gene_priority: metadata.gene_priority,
+        db: metadata.db.clone(),
+        isoform: metadata.isoform,
+        protein_length: metadata.length,
+        molecular_weight: metadata.molecular_weight,
+        discontinuous: true,
+        frame: metadata.frame,
+        genomic_start,
+        genomic_end,
+        left_context: String::new(),
+        right_context: String::new(),
+        score: 0,
+        n_terminus: String::new(),
+        mature_start: 0,
+        mature_end: 0,
+        query_start: 0,
+        query_end: 0,
+        e_value: 0.0,
+        ensembl: String::new(),
+        refseq: String::new(),
+        hgnc: String::new(),
+        kept: Vec::new(),
+    })
+}
+
+// the 1-based position of the residue closest to where it's expected, within tolerance
+fn find_residue(sequence: &[u8], residue: u8, position: usize, tolerance: usize) -> Option<usize> {
+    (0..=tolerance).find_map(|shift| {
+        [position.checked_sub(shift), Some(position + shift)]
+            .into_iter()
+            .flatten()
+            .find(|&candidate| candidate > 0 && sequence.get(candidate - 1) == Some(&residue))
+    })
+}
@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+// classes of residues that are the same residue when indexing and matching, like I and L,
+// written as ;-separated residues like ILJ;ND;QE, each residue stands for the first of its
+// class in the index and the query while hits keep the protein's own residues
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EquivalenceClasses {
+    classes: Vec<String>,
+}
+
+impl FromStr for EquivalenceClasses {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EquivalenceClasses, String> {
+        let mut classes: Vec<String> = Vec::new();
+        for class in s.split(';').map(str::trim).filter(|class| !class.is_empty()) {
+            let class = class.to_ascii_uppercase();
+            if !class.bytes().all(|residue| residue.is_ascii_uppercase()) {
+                return Err(format!("equivalence classes are residue letters: {}", class));
+            }
+            if class.len() < 2 {
+                return Err(format!("an equivalence class needs two residues or more: {}", class));
+            }
+            let repeated = |residue: &char| classes.iter().any(|other| other.contains(*residue)) || class.matches(*residue).count() > 1;
+            if let Some(residue) = class.chars().find(repeated) {
+                return Err(format!("{} is in more than one equivalence class", residue));
+            }
+            classes.push(class);
+        }
+        Ok(EquivalenceClasses { classes })
+    }
+}
+
+impl fmt::Display for EquivalenceClasses {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.classes.join(";"))
+    }
+}
+
+impl EquivalenceClasses {
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    // the residue a residue stands for, the first of its class
+    fn canonical(&self, residue: u8) -> u8 {
+        self.classes
+            .iter()
+            .find(|class| class.as_bytes().contains(&residue))
+            .map_or(residue, |class| class.as_bytes()[0])
+    }
+
+    // a sequence with every residue as the first of its class
+    pub fn canonicalize<'s>(&self, sequence: &'s str) -> Cow<'s, str> {
+        if self.is_empty() {
+            return Cow::Borrowed(sequence);
+        }
+        let canonical: Vec<u8> = sequence.bytes().map(|residue| self.canonical(residue)).collect();
+        Cow::Owned(String::from_utf8(canonical).unwrap_or_else(|_| sequence.to_string()))
+    }
+
+    // whether two residues are in the same class
+    pub fn equivalent(&self, a: u8, b: u8) -> bool {
+        self.canonical(a) == self.canonical(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn residues_stand_for_the_first_of_their_class() {
+        let classes: EquivalenceClasses = "ILJ; nd ;QE".parse().unwrap();
+        assert_eq!(classes.to_string(), "ILJ;ND;QE");
+        assert_eq!(classes.canonicalize("LIJDNEQK"), "IIINNQQK");
+        assert!(classes.equivalent(b'D', b'N'));
+        assert!(!classes.equivalent(b'D', b'E'));
+        assert_eq!(EquivalenceClasses::default().canonicalize("LIDN"), "LIDN");
+        assert!("".parse::<EquivalenceClasses>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn classes_have_to_be_disjoint_residues() {
+        assert!("IL;LV".parse::<EquivalenceClasses>().is_err());
+        assert!("IIL".parse::<EquivalenceClasses>().is_err());
+        assert!("I".parse::<EquivalenceClasses>().is_err());
+        assert!("I1".parse::<EquivalenceClasses>().is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use std::fmt::Display;
+
+// everything that can go wrong preprocessing and matching, with the file and
+// record the error came from so it can be reported without a backtrace
+#[derive(Debug, thiserror::Error)]
+pub enum PepMatchError {
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{path}: record {record}: {message}")]
+    Fasta { path: String, record: usize, message: String },
+
+    #[cfg(feature = "sqlite")]
+    #[error("{path}: {source}")]
+    Database {
+        path: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+
+    // a DuckDB index or results file that couldn't be read or written
+    #[cfg(feature = "duckdb")]
+    #[error("{path}: {source}")]
+    Duckdb {
+        path: String,
+        #[source]
+        source: duckdb::Error,
+    },
+
+    // a RocksDB or LMDB index that couldn't be read or written
+    #[error("{path}: {message}")]
+    Store { path: String, message: String },
+
+    #[error("{url}: {message}")]
+    Download { url: String, message: String },
+
+    // bad options or input that's readable but doesn't make sense
+    #[error("{0}")]
+    Invalid(String),
+}
+
+pub type Result<T> = std::result::Result<T, PepMatchError>;
+
+impl PepMatchError {
+    pub(crate) fn io(path: &str) -> impl FnOnce(std::io::Error) -> PepMatchError + '_ {
+        move |source| PepMatchError::Io { path: path.to_string(), source }
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn database(path: &str) -> impl FnOnce(rusqlite::Error) -> PepMatchError + '_ {
+        move |source| PepMatchError::Database { path: path.to_string(), source }
+    }
+
+    #[cfg(feature = "duckdb")]
+    pub(crate) fn duckdb(path: &str) -> impl FnOnce(duckdb::Error) -> PepMatchError + '_ {
+        move |source| PepMatchError::Duckdb { path: path.to_string(), source }
+    }
+
+    #[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+    pub(crate) fn store<E: Display>(path: &str) -> impl Fn(E) -> PepMatchError + '_ {
+        move |e| PepMatchError::Store { path: path.to_string(), message: e.to_string() }
+    }
+
+    pub(crate) fn invalid(message: impl Display) -> PepMatchError {
+        PepMatchError::Invalid(message.to_string())
+    }
+
+    // whether the error is a closed stdout, e.g. output piped into head
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, PepMatchError::Io { source, .. } if source.kind() == std::io::ErrorKind::BrokenPipe)
+    }
+}
@@ -0,0 +1,377 @@
+use crate::build_info::BuildInfo;
+use crate::error::{PepMatchError, Result};
+use crate::index::{self, decode_kmer_locations, kmers_table, open_read_only, shard_path, PythonTables};
+use crate::progress;
+use std::io::Write;
+
+// what of an index is exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportTable {
+    Metadata, // a row per protein with its metadata
+    Kmers,    // a row per location of every k-mer
+    Proteins, // a row per protein with its sequence
+}
+
+impl std::str::FromStr for ExportTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<ExportTable, String> {
+        match s.to_lowercase().as_str() {
+            "metadata" => Ok(ExportTable::Metadata),
+            "kmers" => Ok(ExportTable::Kmers),
+            "proteins" => Ok(ExportTable::Proteins),
+            _ => Err(format!("unknown table to export: {}, expected metadata, kmers or proteins", s)),
+        }
+    }
+}
+
+// file formats tables are exported in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Tsv,
+    Jsonl, // a JSON object per line
+    #[cfg(feature = "arrow")]
+    Parquet,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<ExportFormat, String> {
+        match s.to_lowercase().as_str() {
+            "tsv" => Ok(ExportFormat::Tsv),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            #[cfg(feature = "arrow")]
+            "parquet" => Ok(ExportFormat::Parquet),
+            #[cfg(not(feature = "arrow"))]
+            "parquet" => Err("parquet export needs pepmatch built with the arrow feature".to_string()),
+            _ => Err(format!("unknown export format: {}", s)),
+        }
+    }
+}
+
+// the type of a column, so each format can write its values as what they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Text,
+    Integer,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    Integer(i64),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Integer(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+const METADATA_COLUMNS: &[(&str, Kind)] = &[
+    ("protein_number", Kind::Integer),
+    ("protein_id", Kind::Text),
+    ("protein_name", Kind::Text),
+    ("species", Kind::Text),
+    ("taxon_id", Kind::Text),
+    ("gene", Kind::Text),
+    ("pe_level", Kind::Integer),
+    ("sequence_version", Kind::Integer),
+    ("proteome", Kind::Text),
+    ("gene_priority", Kind::Integer),
+    ("frame", Kind::Integer),
+    ("source_length", Kind::Integer),
+    ("db", Kind::Text),
+    ("isoform", Kind::Integer),
+    ("length", Kind::Integer),
+    ("molecular_weight", Kind::Integer),
+];
+const KMER_COLUMNS: &[(&str, Kind)] = &[("kmer", Kind::Text), ("protein_number", Kind::Integer), ("position", Kind::Integer)];
+const PROTEIN_COLUMNS: &[(&str, Kind)] = &[("protein_number", Kind::Integer), ("sequence", Kind::Text)];
+
+// write a table of an index to a file in a format, or to stdout without one, metadata and
+// proteins can be exported from any index and k-mers from native SQLite DBs, with their
+// positions counted from 0 as they're stored, returns the number of rows written
+pub fn export(
+    db_path: &str,
+    table: ExportTable,
+    format: ExportFormat,
+    output: Option<&str>,
+    show_progress: bool,
+) -> Result<usize> {
+    let path = output.unwrap_or("stdout");
+    let out: Box<dyn Write + Send> = match output {
+        Some(output) => Box::new(std::fs::File::create(output).map_err(PepMatchError::io(output))?),
+        None => Box::new(std::io::stdout()),
+    };
+    let columns = match table {
+        ExportTable::Metadata => METADATA_COLUMNS,
+        ExportTable::Kmers => KMER_COLUMNS,
+        ExportTable::Proteins => PROTEIN_COLUMNS,
+    };
+    let mut writer = row_writer(std::io::BufWriter::new(out), format, columns).map_err(PepMatchError::io(path))?;
+    let mut write = |row: Vec<Value>| writer.write_row(row).map_err(PepMatchError::io(path));
+    let rows = match table {
+        ExportTable::Kmers => export_kmers(db_path, &mut write)?,
+        _ => export_proteins(db_path, table, show_progress, &mut write)?,
+    };
+    writer.finish().map_err(PepMatchError::io(path))?;
+    Ok(rows)
+}
+
+// a row per protein of the index, its metadata or its sequence
+fn export_proteins(
+    db_path: &str,
+    table: ExportTable,
+    show_progress: bool,
+    write: &mut impl FnMut(Vec<Value>) -> Result<()>,
+) -> Result<usize> {
+    let index = index::open_db(db_path)?;
+    let proteomes = index.proteomes()?;
+    let bar = progress::proteins(show_progress, proteomes.iter().map(|(_, first, last)| last + 1 - first).sum());
+    let mut rows = 0;
+    for protein_number in proteomes.iter().flat_map(|(_, first, last)| *first..=*last) {
+        bar.inc(1);
+        let number = Value::Integer(protein_number as i64);
+        let row = match table {
+            ExportTable::Proteins => vec![number, Value::Text(index.protein_sequence(protein_number)?)],
+            _ => {
+                let metadata = index.protein_metadata(protein_number)?;
+                let integer = |n: usize| Value::Integer(n as i64);
+                vec![
+                    number,
+                    Value::Text(metadata.protein_id),
+                    Value::Text(metadata.protein_name),
+                    Value::Text(metadata.species),
+                    Value::Text(metadata.taxon_id),
+                    Value::Text(metadata.gene),
+                    integer(metadata.pe_level),
+                    integer(metadata.sequence_version),
+                    Value::Text(metadata.proteome),
+                    integer(metadata.gene_priority as usize),
+                    Value::Integer(metadata.frame as i64),
+                    integer(metadata.source_length),
+                    Value::Text(metadata.db),
+                    integer(metadata.isoform),
+                    integer(metadata.length),
+                    integer(metadata.molecular_weight),
+                ]
+            }
+        };
+        write(row)?;
+        rows += 1;
+    }
+    bar.finish_and_clear();
+    Ok(rows)
+}
+
+// a row per location of every k-mer of a native SQLite DB, of each of its k and shards
+fn export_kmers(db_path: &str, write: &mut impl FnMut(Vec<Value>) -> Result<()>) -> Result<usize> {
+    let conn = open_read_only(db_path)?;
+    if PythonTables::find(&conn).map_err(PepMatchError::database(db_path))?.is_some() || !index::is_sqlite_db(db_path) {
+        return Err(PepMatchError::invalid(format!("{}: only the k-mers of native SQLite DBs can be exported", db_path)));
+    }
+    let info = BuildInfo::from_connection(&conn).map_err(PepMatchError::database(db_path))?;
+    let tables: Vec<String> = std::iter::once("kmers".to_string()).chain(info.extra_k.iter().map(|k| kmers_table(*k))).collect();
+    let files: Vec<String> = match info.shards {
+        0 => vec![db_path.to_string()],
+        shards => (0..shards).map(|shard| shard_path(db_path, shard)).collect(),
+    };
+
+    let mut rows = 0;
+    for file in &files {
+        let conn = open_read_only(file)?;
+        for table in &tables {
+            let mut stmt =
+                conn.prepare(&format!("SELECT kmer, locations FROM {} ORDER BY kmer", table)).map_err(PepMatchError::database(file))?;
+            let mut kmers = stmt.query(rusqlite::params![]).map_err(PepMatchError::database(file))?;
+            while let Some(row) = kmers.next().map_err(PepMatchError::database(file))? {
+                let kmer: String = row.get(0).map_err(PepMatchError::database(file))?;
+                let blob: Vec<u8> = row.get(1).map_err(PepMatchError::database(file))?;
+                for (protein_number, position) in decode_kmer_locations(&blob, info.compression).map_err(PepMatchError::io(file))? {
+                    write(vec![Value::Text(kmer.clone()), Value::Integer(protein_number as i64), Value::Integer(position as i64)])?;
+                    rows += 1;
+                }
+            }
+        }
+    }
+    Ok(rows)
+}
+
+trait RowWriter {
+    fn write_row(&mut self, row: Vec<Value>) -> std::io::Result<()>;
+
+    fn finish(&mut self) -> std::io::Result<()>;
+}
+
+fn row_writer<'a>(
+    out: impl Write + Send + 'a,
+    format: ExportFormat,
+    columns: &'static [(&'static str, Kind)],
+) -> std::io::Result<Box<dyn RowWriter + 'a>> {
+    Ok(match format {
+        ExportFormat::Tsv => {
+            let mut writer = csv::WriterBuilder::new().delimiter(b'\t').from_writer(out);
+            writer.write_record(columns.iter().map(|(name, _)| name))?;
+            Box::new(TsvWriter(writer))
+        }
+        ExportFormat::Jsonl => Box::new(JsonlWriter { out, columns }),
+        #[cfg(feature = "arrow")]
+        ExportFormat::Parquet => Box::new(parquet::ParquetWriter::new(Box::new(out), columns)?),
+    })
+}
+
+struct TsvWriter<W: Write>(csv::Writer<W>);
+
+impl<W: Write> RowWriter for TsvWriter<W> {
+    fn write_row(&mut self, row: Vec<Value>) -> std::io::Result<()> {
+        self.0.write_record(row.iter().map(Value::to_string)).map_err(std::io::Error::other)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+struct JsonlWriter<W: Write> {
+    out: W,
+    columns: &'static [(&'static str, Kind)],
+}
+
+impl<W: Write> RowWriter for JsonlWriter<W> {
+    fn write_row(&mut self, row: Vec<Value>) -> std::io::Result<()> {
+        let object: serde_json::Map<String, serde_json::Value> = self
+            .columns
+            .iter()
+            .zip(row)
+            .map(|((name, _), value)| {
+                let value = match value {
+                    Value::Text(text) => serde_json::Value::from(text),
+                    Value::Integer(n) => serde_json::Value::from(n),
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        serde_json::to_writer(&mut self.out, &object)?;
+        writeln!(self.out)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(feature = "arrow")]
+mod parquet {
+    use super::{Kind, RowWriter, Value};
+    use arrow::array::{ArrayRef, Int64Array, RecordBatch, StringArray};
+    use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+    use parquet::arrow::ArrowWriter;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    // number of rows buffered into each record batch
+    const ROWS_PER_BATCH: usize = 65536;
+
+    // writes rows as record batches of a Parquet file, with text and integer columns
+    pub(super) struct ParquetWriter<'a> {
+        writer: ArrowWriter<Box<dyn Write + Send + 'a>>,
+        schema: SchemaRef,
+        columns: &'static [(&'static str, Kind)],
+        rows: Vec<Vec<Value>>,
+    }
+
+    impl<'a> ParquetWriter<'a> {
+        pub fn new(out: Box<dyn Write + Send + 'a>, columns: &'static [(&'static str, Kind)]) -> std::io::Result<ParquetWriter<'a>> {
+            let fields: Vec<Field> = columns
+                .iter()
+                .map(|(name, kind)| match kind {
+                    Kind::Text => Field::new(*name, DataType::Utf8, false),
+                    Kind::Integer => Field::new(*name, DataType::Int64, false),
+                })
+                .collect();
+            let schema = Arc::new(Schema::new(fields));
+            let writer = ArrowWriter::try_new(out, schema.clone(), None).map_err(std::io::Error::other)?;
+            Ok(ParquetWriter { writer, schema, columns, rows: Vec::new() })
+        }
+
+        fn write_batch(&mut self) -> std::io::Result<()> {
+            let arrays: Vec<ArrayRef> = self
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, (_, kind))| -> ArrayRef {
+                    match kind {
+                        Kind::Text => Arc::new(StringArray::from_iter_values(self.rows.iter().map(|row| match &row[i] {
+                            Value::Text(text) => text.clone(),
+                            value => value.to_string(),
+                        }))),
+                        Kind::Integer => Arc::new(Int64Array::from_iter_values(self.rows.iter().map(|row| match &row[i] {
+                            Value::Integer(n) => *n,
+                            Value::Text(_) => 0,
+                        }))),
+                    }
+                })
+                .collect();
+            self.rows.clear();
+            let batch = RecordBatch::try_new(self.schema.clone(), arrays).map_err(std::io::Error::other)?;
+            self.writer.write(&batch).map_err(std::io::Error::other)
+        }
+    }
+
+    impl RowWriter for ParquetWriter<'_> {
+        fn write_row(&mut self, row: Vec<Value>) -> std::io::Result<()> {
+            self.rows.push(row);
+            if self.rows.len() == ROWS_PER_BATCH {
+                self.write_batch()?;
+            }
+            Ok(())
+        }
+
+        fn finish(&mut self) -> std::io::Result<()> {
+            if !self.rows.is_empty() {
+                self.write_batch()?;
+            }
+            self.writer.finish().map(|_| ()).map_err(std::io::Error::other)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_written_in_each_format() {
+        let columns: &'static [(&'static str, Kind)] = KMER_COLUMNS;
+        let row = || vec![Value::Text("MKL".to_string()), Value::Integer(1), Value::Integer(0)];
+
+        let mut tsv = Vec::new();
+        let mut writer = row_writer(&mut tsv, ExportFormat::Tsv, columns).unwrap();
+        writer.write_row(row()).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(String::from_utf8(tsv).unwrap(), "kmer\tprotein_number\tposition\nMKL\t1\t0\n");
+
+        let mut jsonl = Vec::new();
+        let mut writer = row_writer(&mut jsonl, ExportFormat::Jsonl, columns).unwrap();
+        writer.write_row(row()).unwrap();
+        writer.write_row(row()).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        let line = r#"{"kmer":"MKL","protein_number":1,"position":0}"#;
+        assert_eq!(String::from_utf8(jsonl).unwrap(), format!("{}\n{}\n", line, line));
+    }
+
+    #[test]
+    fn tables_and_formats_parse() {
+        assert_eq!("KMERS".parse::<ExportTable>().unwrap(), ExportTable::Kmers);
+        assert!("peptides".parse::<ExportTable>().is_err());
+        assert_eq!("jsonl".parse::<ExportFormat>().unwrap(), ExportFormat::Jsonl);
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+}
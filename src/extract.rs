@@ -0,0 +1,157 @@
+use crate::error::{PepMatchError, Result};
+use crate::index;
+use crate::output::Coordinates;
+use crate::progress;
+use crate::proteome::ProteinMetadata;
+use bio::io::fasta;
+use std::collections::HashMap;
+
+// the proteins of a results file with hits and the 1-based (start, end) of each hit
+type HitRanges = HashMap<String, Vec<(usize, usize)>>;
+
+// write the proteins of a DB that have hits in a TSV or CSV results file to a FASTA file,
+// whole or as windows of the hits with `window` residues either side, overlapping windows
+// are merged, the results' positions are in the given coordinates, returns the number of
+// records written
+pub fn extract_proteins(
+    matches: &str,
+    db_path: &str,
+    output: &str,
+    window: Option<usize>,
+    coordinates: Coordinates,
+    show_progress: bool,
+) -> Result<usize> {
+    let hits = read_hit_ranges(matches, coordinates)?;
+    let index = index::open_db(db_path)?;
+    let proteomes = index.proteomes()?;
+    let total = proteomes.iter().map(|(_, first, last)| last + 1 - first).sum();
+
+    let file = std::fs::File::create(output).map_err(PepMatchError::io(output))?;
+    let mut writer = fasta::Writer::new(std::io::BufWriter::new(file));
+    let mut count = 0;
+    let mut found = 0;
+    let bar = progress::proteins(show_progress, total);
+    for protein_number in proteomes.iter().flat_map(|(_, first, last)| *first..=*last) {
+        bar.inc(1);
+        let metadata = index.protein_metadata(protein_number)?;
+        let Some(ranges) = hits.get(&metadata.protein_id) else {
+            continue;
+        };
+        found += 1;
+        let sequence = index.protein_sequence(protein_number)?;
+        if sequence.is_empty() {
+            return Err(PepMatchError::invalid(format!(
+                "{}: has no sequence for {}, preprocess the proteome again to extract it",
+                db_path, metadata.protein_id
+            )));
+        }
+        let records = match window {
+            None => vec![(metadata.protein_id.clone(), sequence.as_str())],
+            Some(window) => merge_windows(ranges, window, sequence.len())
+                .into_iter()
+                .map(|(start, end)| (format!("{}:{}-{}", metadata.protein_id, start, end), &sequence[start - 1..end]))
+                .collect(),
+        };
+        for (id, sequence) in records {
+            writer
+                .write(&id, description(&metadata).as_deref(), sequence.as_bytes())
+                .map_err(PepMatchError::io(output))?;
+            count += 1;
+        }
+    }
+    bar.finish_and_clear();
+    writer.flush().map_err(PepMatchError::io(output))?;
+    if found < hits.len() {
+        tracing::warn!("{} of the {} proteins with hits aren't in {}", hits.len() - found, hits.len(), db_path);
+    }
+    Ok(count)
+}
+
+// the hits of each protein from the Protein ID, Index start and Index end columns of a
+// results file written by match, comma-separated if it ends in .csv and tab-separated otherwise
+fn read_hit_ranges(path: &str, coordinates: Coordinates) -> Result<HitRanges> {
+    let delimiter = if path.to_lowercase().ends_with(".csv") { b',' } else { b'\t' };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| PepMatchError::invalid(format!("{}: {}", path, e)))?;
+    let headers = reader.headers().map_err(|e| PepMatchError::invalid(format!("{}: {}", path, e)))?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| PepMatchError::invalid(format!("{}: no {} column, is it the output of match?", path, name)))
+    };
+    let (protein_id, start, end) = (column("Protein ID")?, column("Index start")?, column("Index end")?);
+
+    let mut hits = HitRanges::new();
+    for (i, row) in reader.records().enumerate() {
+        let row = row.map_err(|e| PepMatchError::invalid(format!("{}: {}", path, e)))?;
+        let position = |column: usize| {
+            row.get(column).and_then(|value| value.parse::<usize>().ok()).ok_or_else(|| {
+                PepMatchError::invalid(format!("{}: line {}: expected a position in {}", path, i + 2, &headers[column]))
+            })
+        };
+        let range = coordinates.one_based(position(start)?, position(end)?).ok_or_else(|| {
+            PepMatchError::invalid(format!("{}: line {}: the hit's positions are out of range", path, i + 2))
+        })?;
+        hits.entry(row.get(protein_id).unwrap_or_default().to_string()).or_default().push(range);
+    }
+    Ok(hits)
+}
+
+// the 1-based (start, end) of the windows around hits, cut short at the ends of the
+// protein and merged where they overlap or touch
+fn merge_windows(ranges: &[(usize, usize)], window: usize, protein_length: usize) -> Vec<(usize, usize)> {
+    let mut windows: Vec<(usize, usize)> = ranges
+        .iter()
+        .map(|(start, end)| (start.saturating_sub(window).max(1), (end + window).min(protein_length)))
+        .filter(|(start, end)| start <= end)
+        .collect();
+    windows.sort();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in windows {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// a UniProt-like description of a protein, so the headers written still carry its metadata
+fn description(metadata: &ProteinMetadata) -> Option<String> {
+    let pe_level = if metadata.pe_level > 0 { metadata.pe_level.to_string() } else { String::new() };
+    let keys = [("OS", &metadata.species), ("OX", &metadata.taxon_id), ("GN", &metadata.gene), ("PE", &pe_level)];
+    let mut description = vec![metadata.protein_name.trim().to_string()];
+    description.extend(keys.iter().filter(|(_, value)| !value.is_empty()).map(|(key, value)| format!("{}={}", key, value)));
+    let description = description.join(" ").trim().to_string();
+    Some(description).filter(|description| !description.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_merge_and_stay_in_the_protein() {
+        assert_eq!(merge_windows(&[(5, 10)], 3, 100), vec![(2, 13)]);
+        assert_eq!(merge_windows(&[(2, 4)], 5, 8), vec![(1, 8)]);
+        assert_eq!(merge_windows(&[(30, 35), (5, 10), (12, 20)], 1, 100), vec![(4, 21), (29, 36)]);
+        assert_eq!(merge_windows(&[(5, 10), (20, 25)], 0, 100), vec![(5, 10), (20, 25)]);
+    }
+
+    #[test]
+    fn descriptions_leave_out_missing_keys() {
+        let metadata = ProteinMetadata {
+            protein_name: "Cellular tumor antigen p53".to_string(),
+            species: "Homo sapiens".to_string(),
+            gene: "TP53".to_string(),
+            pe_level: 1,
+            ..ProteinMetadata::default()
+        };
+        assert_eq!(description(&metadata).unwrap(), "Cellular tumor antigen p53 OS=Homo sapiens GN=TP53 PE=1");
+        assert_eq!(description(&ProteinMetadata::default()), None);
+    }
+}
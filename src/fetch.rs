@@ -0,0 +1,130 @@
+use crate::error::{PepMatchError, Result};
+use crate::progress;
+use regex::Regex;
+use std::io::{Read, Write};
+
+const UNIPROT_REST: &str = "https://rest.uniprot.org";
+const REFERENCE_PROTEOMES: &str =
+    "https://ftp.uniprot.org/pub/databases/uniprot/current_release/knowledgebase/reference_proteomes";
+
+// downloads UniProt proteomes by proteome ID, e.g. UP000005640 for human,
+// saving them gzipped as they're served
+pub struct Fetcher {
+    proteome_id: String,
+    dir: String,
+    isoforms: bool,
+    progress: bool,
+}
+
+impl Fetcher {
+    pub fn new(proteome_id: &str) -> Result<Fetcher> {
+        if !Regex::new(r"^UP\d{9}$").unwrap().is_match(proteome_id) {
+            return Err(PepMatchError::invalid(format!(
+                "{} isn't a UniProt proteome ID like UP000005640",
+                proteome_id
+            )));
+        }
+        Ok(Fetcher { proteome_id: proteome_id.to_string(), dir: ".".to_string(), isoforms: false, progress: false })
+    }
+
+    // directory the proteomes are saved to, the current one by default
+    pub fn dir(mut self, dir: &str) -> Fetcher {
+        self.dir = dir.to_string();
+        self
+    }
+
+    // also download the non-canonical isoforms of the proteins
+    pub fn isoforms(mut self, isoforms: bool) -> Fetcher {
+        self.isoforms = isoforms;
+        self
+    }
+
+    // show a progress bar on stderr while downloading
+    pub fn progress(mut self, progress: bool) -> Fetcher {
+        self.progress = progress;
+        self
+    }
+
+    // download every UniProtKB protein of the proteome to <dir>/<proteome ID>.fasta.gz
+    pub fn fetch_proteome(&self) -> Result<String> {
+        let url = format!(
+            "{}/uniprotkb/stream?format=fasta&compressed=true&includeIsoform={}&query=proteome:{}",
+            UNIPROT_REST, self.isoforms, self.proteome_id
+        );
+        let path = format!("{}/{}.fasta.gz", self.dir, self.proteome_id);
+        self.download(&url, &path)?;
+        Ok(path)
+    }
+
+    // download the proteome's gene priority set of one protein per gene to
+    // <dir>/<proteome ID>_gene_priority.fasta.gz, it's only on the FTP site
+    // under the proteome's superkingdom and taxon ID
+    pub fn fetch_gene_priority(&self) -> Result<String> {
+        let url = format!("{}/proteomes/{}?format=json", UNIPROT_REST, self.proteome_id);
+        let proteome: serde_json::Value = serde_json::from_reader(get(&url)?.into_reader())
+            .map_err(|e| PepMatchError::Download { url: url.clone(), message: e.to_string() })?;
+        let taxon_id = proteome["taxonomy"]["taxonId"].as_u64();
+        let superkingdom = proteome["superkingdom"].as_str();
+        let (Some(taxon_id), Some(superkingdom)) = (taxon_id, superkingdom) else {
+            return Err(PepMatchError::Download {
+                url,
+                message: "no taxon ID or superkingdom for the proteome".to_string(),
+            });
+        };
+
+        // the directories are capitalized, e.g. Eukaryota
+        let mut kingdom = superkingdom.to_lowercase();
+        kingdom[..1].make_ascii_uppercase();
+        let url = format!(
+            "{}/{}/{id}/{id}_{}.fasta.gz",
+            REFERENCE_PROTEOMES,
+            kingdom,
+            taxon_id,
+            id = self.proteome_id
+        );
+        let path = format!("{}/{}_gene_priority.fasta.gz", self.dir, self.proteome_id);
+        self.download(&url, &path)?;
+        Ok(path)
+    }
+
+    // stream a URL to a file, written under a temporary name first so an interrupted
+    // download doesn't leave something that looks complete
+    fn download(&self, url: &str, path: &str) -> Result<()> {
+        let response = get(url)?;
+        let length = response.header("Content-Length").and_then(|length| length.parse().ok());
+        let bar = progress::bytes(self.progress, length, path);
+
+        let partial = format!("{}.part", path);
+        let mut file = std::fs::File::create(&partial).map_err(PepMatchError::io(&partial))?;
+        let mut reader = response.into_reader();
+        let mut buffer = vec![0; 1 << 16];
+        loop {
+            let n = reader
+                .read(&mut buffer)
+                .map_err(|e| PepMatchError::Download { url: url.to_string(), message: e.to_string() })?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buffer[..n]).map_err(PepMatchError::io(&partial))?;
+            bar.inc(n as u64);
+        }
+        file.flush().map_err(PepMatchError::io(&partial))?;
+        std::fs::rename(&partial, path).map_err(PepMatchError::io(path))?;
+        bar.finish_and_clear();
+        Ok(())
+    }
+}
+
+fn get(url: &str) -> Result<ureq::Response> {
+    ureq::get(url).call().map_err(|e| {
+        // ureq's own messages repeat the URL
+        let message = match e {
+            ureq::Error::Status(status, response) => format!("HTTP {} {}", status, response.status_text()),
+            ureq::Error::Transport(transport) => match transport.message() {
+                Some(message) => format!("{}: {}", transport.kind(), message),
+                None => transport.kind().to_string(),
+            },
+        };
+        PepMatchError::Download { url: url.to_string(), message }
+    })
+}
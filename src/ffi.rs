@@ -0,0 +1,144 @@
+// C interface for linking the preprocessor and matcher into R, Julia and C++ programs,
+// include/pepmatch.h is generated from it with cbindgen --config cbindgen.toml,
+// doc comments here end up in the header
+use crate::error::{PepMatchError, Result};
+use crate::request::match_json;
+use crate::{Matcher, Preprocessor};
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+thread_local! {
+    // message of the last error on this thread, for pepmatch_last_error
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Preprocess a proteome FASTA file into a DB of its k-mers at db_path, or at
+/// <proteome>_k<k>.db in the current directory when db_path is NULL.
+///
+/// Returns 0 on success and -1 on failure, with the reason from pepmatch_last_error.
+///
+/// # Safety
+///
+/// proteome and db_path must be NULL or NUL-terminated UTF-8 strings.
+#[no_mangle]
+pub unsafe extern "C" fn pepmatch_preprocess(proteome: *const c_char, k: usize, db_path: *const c_char) -> i32 {
+    let preprocessed = guard(|| {
+        let mut preprocessor = Preprocessor::new(&string(proteome, "proteome")?, k);
+        if !db_path.is_null() {
+            preprocessor = preprocessor.db_path(&string(db_path, "db_path")?);
+        }
+        preprocessor.preprocess()
+    });
+    if preprocessed.is_some() { 0 } else { -1 }
+}
+
+/// Search peptides against a preprocessed DB or binary index. request is a JSON object
+/// of the "peptides" and optional search settings like "max_mismatches", the same as
+/// the body of a POST /match to pepmatch serve.
+///
+/// Returns the hits as a JSON array, to be freed with pepmatch_free_string, or NULL on
+/// failure with the reason from pepmatch_last_error.
+///
+/// # Safety
+///
+/// db_path and request must be NUL-terminated UTF-8 strings.
+#[no_mangle]
+pub unsafe extern "C" fn pepmatch_match_json(db_path: *const c_char, request: *const c_char) -> *mut c_char {
+    let hits = guard(|| {
+        let matcher = Matcher::new(&string(db_path, "db_path")?)?;
+        let request = serde_json::from_str(&string(request, "request")?)
+            .map_err(|e| PepMatchError::invalid(format!("the request isn't valid JSON: {}", e)))?;
+        let hits = match_json(&matcher, &request)?;
+        CString::new(hits).map_err(|_| PepMatchError::invalid("the hits contain a NUL byte"))
+    });
+    hits.map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// The message of the last error on this thread, or NULL if the last call succeeded.
+/// The string belongs to the library and is only valid until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn pepmatch_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(std::ptr::null(), |message| message.as_ptr()))
+}
+
+/// Free a string returned by the library, NULL is ignored.
+///
+/// # Safety
+///
+/// s must be NULL or a string returned by the library that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn pepmatch_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+// run a call, recording its error or panic for pepmatch_last_error as panics can't
+// unwind into the caller
+fn guard<T>(call: impl FnOnce() -> Result<T>) -> Option<T> {
+    let message = match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(value)) => {
+            LAST_ERROR.with(|error| error.replace(None));
+            return Some(value);
+        }
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => "pepmatch panicked".to_string(),
+    };
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| error.replace(Some(message)));
+    None
+}
+
+// a string argument, which has to be UTF-8
+unsafe fn string(s: *const c_char, name: &str) -> Result<String> {
+    if s.is_null() {
+        return Err(PepMatchError::invalid(format!("{} is NULL", name)));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map(str::to_string)
+        .map_err(|_| PepMatchError::invalid(format!("{} isn't UTF-8", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> Option<String> {
+        let error = pepmatch_last_error();
+        (!error.is_null()).then(|| unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn preprocess_and_match_through_the_c_interface() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-ffi-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        std::fs::write(&fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        let (fasta, db) = (CString::new(fasta.to_str().unwrap()).unwrap(), CString::new(db.to_str().unwrap()).unwrap());
+
+        let preprocessed = unsafe { pepmatch_preprocess(fasta.as_ptr(), 3, db.as_ptr()) };
+        let request = CString::new(r#"{"peptides": ["STAAQW"], "max_mismatches": 1}"#).unwrap();
+        let hits = unsafe { pepmatch_match_json(db.as_ptr(), request.as_ptr()) };
+        let succeeded = last_error();
+        let json = unsafe { CStr::from_ptr(hits) }.to_string_lossy().into_owned();
+        unsafe { pepmatch_free_string(hits) };
+        let malformed = CString::new(r#"{"peptides": "STAAQW"}"#).unwrap();
+        let failed = unsafe { pepmatch_match_json(db.as_ptr(), malformed.as_ptr()) };
+        let failure = last_error();
+        let null_proteome = unsafe { pepmatch_preprocess(std::ptr::null(), 3, db.as_ptr()) };
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(preprocessed, 0);
+        assert_eq!(succeeded, None);
+        let hits: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]["Protein ID"], "P00001");
+        assert!(failed.is_null());
+        let failure = failure.unwrap();
+        assert!(failure.contains("isn't a valid match request"), "{}", failure);
+        assert_eq!(null_proteome, -1);
+        assert_eq!(last_error().unwrap(), "proteome is NULL");
+    }
+}
@@ -0,0 +1,248 @@
+use crate::error::{PepMatchError, Result};
+use crate::{match_request, MatchHit, Matcher};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, Semaphore};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status, Streaming};
+
+// the messages and service of proto/pepmatch.proto
+pub mod proto {
+    tonic::include_proto!("pepmatch");
+}
+
+use proto::pep_match_server::{PepMatch, PepMatchServer};
+
+// replies a stream has searched before the client reads them
+const REPLIES_BUFFERED: usize = 16;
+
+// serve the streaming Match RPC of proto/pepmatch.proto until the process is stopped, up to
+// `threads` requests are searched at once across the streams, each with its own handle on
+// the index
+pub fn serve(matcher: &Matcher, address: &str, threads: usize) -> Result<()> {
+    let listen_error = |e: &dyn std::fmt::Display| PepMatchError::invalid(format!("can't listen on {}: {}", address, e));
+    let address = std::net::ToSocketAddrs::to_socket_addrs(address)
+        .map_err(|e| listen_error(&e))?
+        .next()
+        .ok_or_else(|| listen_error(&"the address has no IP"))?;
+    let threads = if threads == 0 { std::thread::available_parallelism().map_or(1, usize::from) } else { threads };
+    let service = Service::new(matcher, threads)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .max_blocking_threads(threads)
+        .build()
+        .map_err(PepMatchError::io("the async runtime"))?;
+    tracing::info!("Listening for gRPC on {}", address);
+    runtime
+        .block_on(tonic::transport::Server::builder().add_service(PepMatchServer::new(service)).serve(address))
+        .map_err(|e| PepMatchError::invalid(format!("can't serve gRPC on {}: {}", address, e)))
+}
+
+// the matchers requests are searched with, one taken for each request being searched
+#[derive(Clone)]
+struct Service {
+    matchers: Arc<Mutex<Vec<Matcher>>>,
+    searching: Arc<Semaphore>,
+}
+
+impl Service {
+    fn new(matcher: &Matcher, threads: usize) -> Result<Service> {
+        let matchers = (0..threads).map(|_| matcher.share()).collect::<Result<Vec<Matcher>>>()?;
+        Ok(Service { matchers: Arc::new(Mutex::new(matchers)), searching: Arc::new(Semaphore::new(threads)) })
+    }
+
+    // the reply to a request, with the error of one that isn't valid
+    async fn search(&self, request: proto::MatchRequest) -> std::result::Result<proto::MatchReply, Status> {
+        let permit = Arc::clone(&self.searching).acquire_owned().await.expect("the semaphore isn't closed");
+        let matchers = Arc::clone(&self.matchers);
+        let hits = tokio::task::spawn_blocking(move || {
+            let lent = Lent::take(matchers);
+            let hits = match_request(lent.matcher(), &request.into());
+            drop(lent);
+            drop(permit);
+            hits
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?;
+        match hits {
+            Ok(hits) => {
+                let hits = hits.into_iter().map(proto::Hit::from).collect();
+                Ok(proto::MatchReply { hits, error: String::new() })
+            }
+            Err(PepMatchError::Invalid(error)) => Ok(proto::MatchReply { hits: Vec::new(), error }),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
+
+// a matcher taken from the service's for a search, given back when the search is done or
+// panics so a panicking request doesn't leave the others a matcher short
+struct Lent {
+    matcher: Option<Matcher>,
+    matchers: Arc<Mutex<Vec<Matcher>>>,
+}
+
+impl Lent {
+    fn take(matchers: Arc<Mutex<Vec<Matcher>>>) -> Lent {
+        // a matcher is free for every permit
+        let matcher = matchers.lock().expect("no search panics holding the matchers").pop();
+        Lent { matcher: Some(matcher.expect("a matcher for each search running")), matchers }
+    }
+
+    fn matcher(&self) -> &Matcher {
+        self.matcher.as_ref().expect("the matcher is only given back on drop")
+    }
+}
+
+impl Drop for Lent {
+    fn drop(&mut self) {
+        // panicking again while unwinding would abort, the lock is never held across a search
+        if let (Some(matcher), Ok(mut matchers)) = (self.matcher.take(), self.matchers.lock()) {
+            matchers.push(matcher);
+        }
+    }
+}
+
+type Replies = Pin<Box<dyn Stream<Item = std::result::Result<proto::MatchReply, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl PepMatch for Service {
+    type MatchStream = Replies;
+
+    async fn r#match(
+        &self,
+        request: Request<Streaming<proto::MatchRequest>>,
+    ) -> std::result::Result<Response<Replies>, Status> {
+        let mut requests = request.into_inner();
+        let (replies, stream) = mpsc::channel(REPLIES_BUFFERED);
+        let service = self.clone();
+        tokio::spawn(async move {
+            // the stream ends when the client's does, or with an error searching the index
+            loop {
+                let reply = match requests.message().await {
+                    Ok(Some(request)) => service.search(request).await,
+                    Ok(None) => break,
+                    Err(status) => Err(status),
+                };
+                let failed = reply.is_err();
+                if replies.send(reply).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(stream))))
+    }
+}
+
+impl From<proto::MatchRequest> for crate::MatchRequest {
+    fn from(request: proto::MatchRequest) -> crate::MatchRequest {
+        crate::MatchRequest {
+            peptides: request.peptides,
+            max_mismatches: request.max_mismatches as usize,
+            best_match: request.best_match,
+            taxon_ids: request.taxon_ids,
+            species: request.species,
+            max_pe_level: request.max_pe_level.map(|level| level as usize),
+            swissprot_only: request.swissprot_only,
+            context: request.context as usize,
+            min_score: request.min_score,
+            limit: request.limit.map(|limit| limit as usize),
+            one_match_per_protein: request.one_match_per_protein,
+            proteomes: request.proteomes,
+        }
+    }
+}
+
+impl From<MatchHit> for proto::Hit {
+    fn from(hit: MatchHit) -> proto::Hit {
+        proto::Hit {
+            query_sequence: hit.query_sequence,
+            matched_sequence: hit.matched_sequence,
+            protein_id: hit.protein_id,
+            protein_name: hit.protein_name,
+            species: hit.species,
+            taxon_id: hit.taxon_id,
+            gene: hit.gene,
+            mismatches: hit.mismatches as u32,
+            mutated_positions: hit.mutated_positions,
+            index_start: hit.index_start as u64,
+            index_end: hit.index_end as u64,
+            protein_existence_level: hit.protein_existence_level as u32,
+            frame: hit.frame.map(i32::from),
+            genomic_start: hit.genomic_start.map(|start| start as u64),
+            genomic_end: hit.genomic_end.map(|end| end as u64),
+            left_context: hit.left_context,
+            right_context: hit.right_context,
+            score: hit.score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Preprocessor;
+    use proto::pep_match_client::PepMatchClient;
+
+    fn request(peptide: &str) -> proto::MatchRequest {
+        proto::MatchRequest { peptides: vec![peptide.to_string()], ..proto::MatchRequest::default() }
+    }
+
+    #[test]
+    fn streamed_requests_are_answered_in_order() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-grpc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        Preprocessor::new(fasta, 3).db_path(db).preprocess().unwrap();
+        let service = Service::new(&Matcher::new(db).unwrap(), 2).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let replies: Vec<proto::MatchReply> = runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let incoming = tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+            let server = tonic::transport::Server::builder().add_service(PepMatchServer::new(service));
+            tokio::spawn(server.serve_with_incoming(incoming));
+            let mut client = PepMatchClient::connect(format!("http://{}", address)).await.unwrap();
+            let requests = tokio_stream::iter(vec![request("MKLV"), request("MK1V"), request("QWDE")]);
+            let mut replies = client.r#match(requests).await.unwrap().into_inner();
+            let mut received = Vec::new();
+            while let Some(reply) = replies.message().await.unwrap() {
+                received.push(reply);
+            }
+            received
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0].hits[0].protein_id, "P00001");
+        assert_eq!(replies[0].hits[0].index_start, 1);
+        assert!(replies[1].hits.is_empty() && !replies[1].error.is_empty(), "an invalid peptide fails its own reply");
+        assert_eq!(replies[2].hits[0].matched_sequence, "QWDE");
+    }
+
+    #[test]
+    fn matchers_are_given_back_when_a_search_panics() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-grpc-lent-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        Preprocessor::new(fasta, 3).db_path(db).preprocess().unwrap();
+        let service = Service::new(&Matcher::new(db).unwrap(), 2).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let matchers = Arc::clone(&service.matchers);
+        let searched = std::thread::spawn(move || {
+            let lent = Lent::take(matchers);
+            assert_eq!(lent.matcher().k(), 3);
+            panic!("a search panicking");
+        })
+        .join();
+        assert!(searched.is_err());
+        assert_eq!(service.matchers.lock().unwrap().len(), 2);
+    }
+}
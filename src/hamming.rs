@@ -0,0 +1,85 @@
+// counting mismatches between a query peptide and a protein window of the same length
+// 8 residues at a time, each u64 word of one XORed with the other's leaves a nonzero byte
+// per mismatch, which is faster than a residue at a time for verifying many candidates
+
+const ONES: u64 = 0x0101_0101_0101_0101;
+const LOW_BITS: u64 = 0x7f7f_7f7f_7f7f_7f7f;
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+// the mismatches between a query without wildcards and a window of the protein, None
+// if the lengths differ or there are more than max
+pub(crate) fn mismatches_within(query: &[u8], window: &[u8], max: usize, i_l_equivalent: bool) -> Option<usize> {
+    if query.len() != window.len() {
+        return None;
+    }
+    let mut mismatches = 0;
+    for (a, b) in query.chunks_exact(8).zip(window.chunks_exact(8)) {
+        let (mut a, mut b) = (word(a), word(b));
+        if i_l_equivalent {
+            (a, b) = (l_to_i(a), l_to_i(b));
+        }
+        mismatches += nonzero_bytes(a ^ b).count_ones() as usize;
+        if mismatches > max {
+            return None;
+        }
+    }
+    let tail = query.len() - query.len() % 8;
+    for (a, b) in query[tail..].iter().zip(&window[tail..]) {
+        let same = a == b || (i_l_equivalent && matches!((a, b), (b'I', b'L') | (b'L', b'I')));
+        mismatches += usize::from(!same);
+    }
+    (mismatches <= max).then_some(mismatches)
+}
+
+fn word(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap_or_default())
+}
+
+// the high bit of every byte of x that isn't zero, adding 0x7f to the low bits of a byte
+// carries into its high bit unless they're all clear, and never into the next byte
+fn nonzero_bytes(x: u64) -> u64 {
+    (((x & LOW_BITS) + LOW_BITS) | x) & HIGH_BITS
+}
+
+// a word with each L turned into an I
+fn l_to_i(x: u64) -> u64 {
+    let is_l = !nonzero_bytes(x ^ (ONES * b'L' as u64)) & HIGH_BITS;
+    x ^ ((is_l >> 7) * (b'L' ^ b'I') as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a residue at a time, to check the word-wise counts against
+    fn naive(a: &[u8], b: &[u8], i_l_equivalent: bool) -> usize {
+        a.iter()
+            .zip(b)
+            .filter(|(a, b)| a != b && !(i_l_equivalent && matches!((a, b), (b'I', b'L') | (b'L', b'I'))))
+            .count()
+    }
+
+    #[test]
+    fn counts_match_a_residue_at_a_time() {
+        let pairs: [(&[u8], &[u8]); 5] = [
+            (b"MEEPQSDPSV", b"MEEPQSDPSV"),
+            (b"MEEPQSDPSVEPPLSQ", b"MKEPQSDPAVEPPLSW"),
+            (b"LLLLIIIILLKL", b"IIIILLLLLLKI"),
+            (b"ACD", b"ACE"),
+            (b"\x80\x7fAAAAAA", b"\x00\xffAAAAAA"),
+        ];
+        for (a, b) in pairs {
+            for i_l_equivalent in [false, true] {
+                assert_eq!(mismatches_within(a, b, a.len(), i_l_equivalent), Some(naive(a, b, i_l_equivalent)));
+            }
+        }
+    }
+
+    #[test]
+    fn stops_past_the_most_mismatches() {
+        assert_eq!(mismatches_within(b"MEEPQSDPSVEPPLSQ", b"MKEPQSDPAVEPPLSW", 2, false), None);
+        assert_eq!(mismatches_within(b"MEEPQSDPSVEPPLSQ", b"MKEPQSDPAVEPPLSW", 3, false), Some(3));
+        assert_eq!(mismatches_within(b"ILIL", b"LILI", 0, true), Some(0));
+        assert_eq!(mismatches_within(b"ACDE", b"ACD", 4, false), None);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::error::{PepMatchError, Result};
+use crate::proteome::ProteinMetadata;
+use regex::Regex;
+use std::str::FromStr;
+
+// metadata fields that can be parsed out of a header
+pub const FIELDS: [&str; 7] = [
+    "protein_id",
+    "protein_name",
+    "species",
+    "taxon_id",
+    "gene",
+    "pe_level",
+    "sequence_version",
+];
+
+// FASTA header conventions that can be parsed for protein metadata
+#[derive(Debug, Clone, Default)]
+pub enum HeaderFormat {
+    #[default]
+    Auto, // detect the format of each header
+    Uniprot,
+    Refseq,
+    Genbank,
+    Generic, // just the ID and the rest of the header as the protein name
+    Custom(CustomHeader),
+}
+
+impl FromStr for HeaderFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<HeaderFormat, String> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(HeaderFormat::Auto),
+            "uniprot" => Ok(HeaderFormat::Uniprot),
+            "refseq" => Ok(HeaderFormat::Refseq),
+            "genbank" => Ok(HeaderFormat::Genbank),
+            "generic" => Ok(HeaderFormat::Generic),
+            _ => Err(format!("unknown header format: {}", s)),
+        }
+    }
+}
+
+// parses protein metadata out of the ID and description of a FASTA header,
+// fields missing from the header are left empty or 0
+pub(crate) trait HeaderParser: Send {
+    fn parse(&self, id: &str, desc: &str) -> ProteinMetadata;
+}
+
+// user-defined header parsing for in-house proteomes, matched against the whole
+// header (without the >) with either one regex with a named group per field or
+// a regex per field with the value in its first group
+#[derive(Debug, Clone)]
+pub struct CustomHeader {
+    regex: Option<Regex>,
+    fields: Vec<(String, Regex)>,
+}
+
+impl CustomHeader {
+    // one regex with named groups, e.g. ^(?P<protein_id>\S+) .*gene=(?P<gene>\S+)
+    pub fn from_regex(pattern: &str) -> Result<CustomHeader> {
+        let regex = Regex::new(pattern).map_err(|e| PepMatchError::invalid(format!("invalid header regex: {}", e)))?;
+        if !regex.capture_names().flatten().any(|name| FIELDS.contains(&name)) {
+            return Err(PepMatchError::invalid(format!(
+                "header regex needs a named group for one of: {}",
+                FIELDS.join(", ")
+            )));
+        }
+        Ok(CustomHeader { regex: Some(regex), fields: Vec::new() })
+    }
+
+    // a TOML file mapping fields to regexes, e.g. gene = "gene=(\\S+)"
+    pub fn from_toml(path: &str) -> Result<CustomHeader> {
+        let contents = std::fs::read_to_string(path).map_err(PepMatchError::io(path))?;
+        let table: toml::Table = contents.parse().map_err(|e| PepMatchError::invalid(format!("{}: {}", path, e)))?;
+
+        let mut fields = Vec::new();
+        for (field, pattern) in table {
+            if !FIELDS.contains(&field.as_str()) {
+                return Err(PepMatchError::invalid(format!(
+                    "{}: unknown header field {}, expected one of: {}",
+                    path,
+                    field,
+                    FIELDS.join(", ")
+                )));
+            }
+            let pattern = pattern
+                .as_str()
+                .ok_or_else(|| PepMatchError::invalid(format!("{}: {} must be a regex string", path, field)))?;
+            let regex = Regex::new(pattern)
+                .map_err(|e| PepMatchError::invalid(format!("{}: invalid regex for {}: {}", path, field, e)))?;
+            fields.push((field, regex));
+        }
+        Ok(CustomHeader { regex: None, fields })
+    }
+
+    // the value of a field in a header, if it's captured
+    fn capture(&self, field: &str, header: &str) -> Option<String> {
+        let value = match &self.regex {
+            Some(regex) => regex.captures(header)?.name(field)?.as_str(),
+            None => {
+                let (_, regex) = self.fields.iter().find(|(name, _)| name == field)?;
+                regex.captures(header)?.get(1)?.as_str()
+            }
+        };
+        Some(value.trim().to_string())
+    }
+}
+
+impl HeaderParser for CustomHeader {
+    fn parse(&self, id: &str, desc: &str) -> ProteinMetadata {
+        let header = format!("{} {}", id, desc);
+        let capture = |field: &str| self.capture(field, &header).unwrap_or_default();
+
+        let mut protein_id = capture("protein_id");
+        if protein_id.is_empty() {
+            protein_id = id.to_string();
+        }
+
+        ProteinMetadata {
+            protein_id,
+            protein_name: capture("protein_name"),
+            species: capture("species"),
+            taxon_id: capture("taxon_id"),
+            gene: capture("gene"),
+            pe_level: capture("pe_level").parse().unwrap_or(0),
+            sequence_version: capture("sequence_version").parse().unwrap_or(0),
+            ..ProteinMetadata::default()
+        }
+    }
+}
+
+// get the parser for a header format
+pub(crate) fn parser(format: &HeaderFormat) -> Box<dyn HeaderParser> {
+    match format {
+        HeaderFormat::Auto => Box::new(AutoParser::new()),
+        HeaderFormat::Uniprot => Box::new(UniprotParser::new()),
+        HeaderFormat::Refseq => Box::new(NcbiParser::refseq()),
+        HeaderFormat::Genbank => Box::new(NcbiParser::genbank()),
+        HeaderFormat::Generic => Box::new(GenericParser),
+        HeaderFormat::Custom(custom) => Box::new(custom.clone()),
+    }
+}
+
+// UniProt headers, e.g.
+// >sp|P04637|P53_HUMAN Cellular tumor antigen p53 OS=Homo sapiens OX=9606 GN=TP53 PE=1 SV=4
+struct UniprotParser {
+    protein_id: Regex,
+    protein_name: Regex,
+    species: Regex,
+    taxon_id: Regex,
+    gene: Regex,
+    pe_level: Regex,
+    sequence_version: Regex,
+}
+
+impl UniprotParser {
+    fn new() -> UniprotParser {
+        UniprotParser {
+            protein_id: Regex::new(r"\|([^|]*)\|").unwrap(),                 // between | and |
+            protein_name: Regex::new(r"^(.*?)(?:\s+[A-Z]{2}=|$)").unwrap(),  // up to the first key like OS=
+            species: Regex::new(r"OS=(.+?)(?:\s+[A-Z]{2}=|$)").unwrap(),     // between OS= and the next key (species can have spaces)
+            taxon_id: Regex::new(r"OX=(\d+)").unwrap(),
+            gene: Regex::new(r"GN=(\S+)").unwrap(),
+            pe_level: Regex::new(r"PE=(\d+)").unwrap(),
+            sequence_version: Regex::new(r"SV=(\d+)").unwrap(),
+        }
+    }
+}
+
+impl HeaderParser for UniprotParser {
+    fn parse(&self, id: &str, desc: &str) -> ProteinMetadata {
+        let capture = |regex: &Regex, text: &str| {
+            regex.captures(text).map(|capture| capture[1].trim().to_string()).unwrap_or_default()
+        };
+
+        let mut protein_id = capture(&self.protein_id, id);
+        if protein_id.is_empty() {
+            protein_id = id.to_string();
+        }
+        let isoform = isoform(&protein_id);
+
+        ProteinMetadata {
+            protein_id,
+            protein_name: capture(&self.protein_name, desc),
+            species: capture(&self.species, desc),
+            taxon_id: capture(&self.taxon_id, desc),
+            gene: capture(&self.gene, desc),
+            pe_level: capture(&self.pe_level, desc).parse().unwrap_or(0),
+            sequence_version: capture(&self.sequence_version, desc).parse().unwrap_or(0),
+            db: match id.split_once('|') {
+                Some((db @ ("sp" | "tr"), _)) => db.to_string(),
+                _ => String::new(),
+            },
+            isoform,
+            ..ProteinMetadata::default()
+        }
+    }
+}
+
+// number of a non-canonical UniProt isoform from its accession, e.g. P04637-2 --> 2,
+// 0 for the canonical sequence
+fn isoform(accession: &str) -> usize {
+    accession.rsplit_once('-').and_then(|(_, isoform)| isoform.parse().ok()).unwrap_or(0)
+}
+
+// NCBI RefSeq and GenBank headers, e.g.
+// >NP_000546.2 cellular tumor antigen p53 [Homo sapiens]
+// >gi|120407068|gb|AAI30208.1| TP53 protein [Homo sapiens]
+struct NcbiParser {
+    databases: &'static [&'static str], // database tags in old style piped IDs
+    species: Regex,
+}
+
+impl NcbiParser {
+    fn refseq() -> NcbiParser {
+        NcbiParser::new(&["ref"])
+    }
+
+    fn genbank() -> NcbiParser {
+        NcbiParser::new(&["gb", "emb", "dbj"])
+    }
+
+    fn new(databases: &'static [&'static str]) -> NcbiParser {
+        NcbiParser {
+            databases,
+            species: Regex::new(r"\[([^\[\]]+)\]\s*$").unwrap(), // in brackets at the end
+        }
+    }
+}
+
+impl HeaderParser for NcbiParser {
+    fn parse(&self, id: &str, desc: &str) -> ProteinMetadata {
+        // take the accession after the database tag of a piped ID, otherwise the ID itself
+        let fields: Vec<&str> = id.split('|').collect();
+        let protein_id = fields
+            .iter()
+            .position(|field| self.databases.contains(field))
+            .and_then(|i| fields.get(i + 1))
+            .filter(|accession| !accession.is_empty())
+            .unwrap_or(&id);
+
+        let (protein_name, species) = match self.species.captures(desc) {
+            Some(capture) => (
+                desc[..capture.get(0).unwrap().start()].trim().to_string(),
+                capture[1].trim().to_string(),
+            ),
+            None => (desc.trim().to_string(), String::new()),
+        };
+
+        ProteinMetadata {
+            protein_id: protein_id.to_string(),
+            protein_name,
+            species,
+            ..ProteinMetadata::default()
+        }
+    }
+}
+
+// anything else, the ID and the description as the protein name
+struct GenericParser;
+
+impl HeaderParser for GenericParser {
+    fn parse(&self, id: &str, desc: &str) -> ProteinMetadata {
+        ProteinMetadata {
+            protein_id: id.to_string(),
+            protein_name: desc.trim().to_string(),
+            ..ProteinMetadata::default()
+        }
+    }
+}
+
+// picks the parser for each header by what it looks like
+struct AutoParser {
+    uniprot: UniprotParser,
+    refseq: NcbiParser,
+    genbank: NcbiParser,
+    refseq_id: Regex,
+    genbank_id: Regex,
+}
+
+impl AutoParser {
+    fn new() -> AutoParser {
+        AutoParser {
+            uniprot: UniprotParser::new(),
+            refseq: NcbiParser::refseq(),
+            genbank: NcbiParser::genbank(),
+            refseq_id: Regex::new(r"^(?:ref\|)?[ANXYWZ]P_\d+").unwrap(),
+            genbank_id: Regex::new(r"^(?:gi\|\d+\|)?(?:gb|emb|dbj)\||^[A-Z]{3}\d{5}(?:\.\d+)?$").unwrap(),
+        }
+    }
+}
+
+impl HeaderParser for AutoParser {
+    fn parse(&self, id: &str, desc: &str) -> ProteinMetadata {
+        if id.starts_with("sp|") || id.starts_with("tr|") || desc.contains("OS=") {
+            self.uniprot.parse(id, desc)
+        } else if self.refseq_id.is_match(id) {
+            self.refseq.parse(id, desc)
+        } else if self.genbank_id.is_match(id) {
+            self.genbank.parse(id, desc)
+        } else {
+            GenericParser.parse(id, desc)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniprot() {
+        let metadata = UniprotParser::new().parse(
+            "sp|P04637-2|P53_HUMAN",
+            "Isoform 2 of Cellular tumor antigen p53 OS=Homo sapiens OX=9606 GN=TP53 PE=1 SV=4",
+        );
+        assert_eq!(metadata.protein_id, "P04637-2");
+        assert_eq!(metadata.protein_name, "Isoform 2 of Cellular tumor antigen p53");
+        assert_eq!(metadata.species, "Homo sapiens");
+        assert_eq!(metadata.taxon_id, "9606");
+        assert_eq!(metadata.gene, "TP53");
+        assert_eq!(metadata.pe_level, 1);
+        assert_eq!(metadata.sequence_version, 4);
+        assert_eq!(metadata.db, "sp");
+        assert_eq!(metadata.isoform, 2);
+    }
+
+    #[test]
+    fn uniprot_without_keys() {
+        let metadata = UniprotParser::new().parse("tr|A0A000|A0A000_9VIRU", "Uncharacterized protein");
+        assert_eq!(metadata.protein_id, "A0A000");
+        assert_eq!(metadata.protein_name, "Uncharacterized protein");
+        assert_eq!(metadata.gene, "");
+        assert_eq!(metadata.pe_level, 0);
+        assert_eq!(metadata.db, "tr");
+        assert_eq!(metadata.isoform, 0);
+    }
+
+    #[test]
+    fn refseq() {
+        let metadata = NcbiParser::refseq().parse("NP_000546.2", "cellular tumor antigen p53 [Homo sapiens]");
+        assert_eq!(metadata.protein_id, "NP_000546.2");
+        assert_eq!(metadata.protein_name, "cellular tumor antigen p53");
+        assert_eq!(metadata.species, "Homo sapiens");
+    }
+
+    #[test]
+    fn genbank_piped_id() {
+        let metadata = NcbiParser::genbank().parse("gi|120407068|gb|AAI30208.1|", "TP53 protein [Homo sapiens]");
+        assert_eq!(metadata.protein_id, "AAI30208.1");
+        assert_eq!(metadata.protein_name, "TP53 protein");
+        assert_eq!(metadata.species, "Homo sapiens");
+    }
+
+    #[test]
+    fn generic_keeps_numeric_ids() {
+        let metadata = GenericParser.parse("0001", " some protein ");
+        assert_eq!(metadata.protein_id, "0001");
+        assert_eq!(metadata.protein_name, "some protein");
+    }
+
+    #[test]
+    fn auto_picks_by_header() {
+        let parser = parser(&HeaderFormat::Auto);
+        assert_eq!(parser.parse("sp|P12345|X_HUMAN", "X OS=Homo sapiens GN=X").gene, "X");
+        assert_eq!(parser.parse("NP_000546.2", "p53 [Homo sapiens]").species, "Homo sapiens");
+    }
+}
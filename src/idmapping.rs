@@ -0,0 +1,103 @@
+use crate::error::{PepMatchError, Result};
+use crate::proteome::open_proteome;
+use crate::Match;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+
+// the ID types of a UniProt idmapping file that are added to hits, in the order of their columns
+const ID_TYPES: [&str; 3] = ["Ensembl", "RefSeq", "HGNC"];
+
+// IDs of UniProt proteins in Ensembl, RefSeq and HGNC from a UniProt idmapping file, e.g.
+// HUMAN_9606_idmapping.dat.gz, whose rows are an accession, an ID type and an ID
+#[derive(Debug, Clone, Default)]
+pub struct IdMapping {
+    ids: HashMap<String, [Vec<String>; 3]>, // accession --> IDs of each of ID_TYPES
+}
+
+impl IdMapping {
+    // read an idmapping file, gzipped or not, keeping only the ID types hits get
+    pub fn load(path: &str) -> Result<IdMapping> {
+        let mut mapping = IdMapping::default();
+        for (i, line) in BufReader::new(open_proteome(path)?).lines().enumerate() {
+            let line = line.map_err(PepMatchError::io(path))?;
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let (Some(accession), Some(id_type), Some(id)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(PepMatchError::invalid(format!("{}: line {}: expected accession, ID type and ID", path, i + 1)));
+            };
+            if let Some(column) = ID_TYPES.iter().position(|name| *name == id_type) {
+                let ids = &mut mapping.ids.entry(accession.to_string()).or_default()[column];
+                if !ids.iter().any(|known| known == id) {
+                    ids.push(id.to_string());
+                }
+            }
+        }
+        Ok(mapping)
+    }
+
+    // the IDs of each ID type of a protein, ;-separated, an isoform without IDs of its own
+    // gets its canonical protein's
+    fn ids(&self, accession: &str) -> [String; 3] {
+        let ids = self.ids.get(accession).or_else(|| {
+            let (canonical, _) = accession.rsplit_once('-')?;
+            self.ids.get(canonical)
+        });
+        match ids {
+            Some(ids) => ids.clone().map(|ids| ids.join(";")),
+            None => Default::default(),
+        }
+    }
+
+    pub fn annotate(&self, hits: &mut [Match]) {
+        for hit in hits {
+            [hit.ensembl, hit.refseq, hit.hgnc] = self.ids(&hit.protein_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(rows: &str) -> IdMapping {
+        let path = std::env::temp_dir().join(format!("pepmatch-idmapping-{}.dat", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, rows).unwrap();
+        let mapping = IdMapping::load(path);
+        std::fs::remove_file(path).unwrap();
+        mapping.unwrap()
+    }
+
+    #[test]
+    fn ids_of_each_type() {
+        let mapping = mapping(
+            "P04637\tUniProtKB-ID\tP53_HUMAN\n\
+             P04637\tEnsembl\tENSG00000141510\n\
+             P04637\tRefSeq\tNP_000537.3\n\
+             P04637\tRefSeq\tNP_001119584.1\n\
+             P04637\tHGNC\tHGNC:11998\n\
+             P04637\tEnsembl\tENSG00000141510\n",
+        );
+        assert_eq!(mapping.ids("P04637"), ["ENSG00000141510", "NP_000537.3;NP_001119584.1", "HGNC:11998"]);
+        assert_eq!(mapping.ids("P04637-2"), mapping.ids("P04637"));
+        assert_eq!(mapping.ids("Q00000"), [String::new(), String::new(), String::new()]);
+    }
+
+    #[test]
+    fn isoforms_keep_their_own_ids() {
+        let mapping = mapping("P04637\tRefSeq\tNP_000537.3\nP04637-2\tRefSeq\tNP_001119586.1\n");
+        assert_eq!(mapping.ids("P04637-2")[1], "NP_001119586.1");
+    }
+
+    #[test]
+    fn short_rows_are_rejected() {
+        let path = std::env::temp_dir().join(format!("pepmatch-idmapping-bad-{}.dat", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "P04637\tEnsembl\n").unwrap();
+        let error = IdMapping::load(path).unwrap_err().to_string();
+        std::fs::remove_file(path).unwrap();
+        assert!(error.contains("line 1"), "{}", error);
+    }
+}
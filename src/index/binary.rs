@@ -0,0 +1,302 @@
+use super::{region, Location, MemoryIndex, PepMatchError, ProteinMetadata, ProteomeIndex, ProteomeRange, Result};
+use fxhash::FxHashMap;
+use memmap2::Mmap;
+use std::io::Write;
+
+// first bytes of a binary index file
+pub(crate) const MAGIC: &[u8; 8] = b"PEPMBIN1";
+
+// magic then the k, I/L flag and counts below as little-endian u64s
+const HEADER_LEN: usize = 8 + 7 * 8;
+
+// a compact index read straight out of a memory-mapped file, laid out as the header
+// and then sections padded to 8 bytes:
+//   k-mers        sorted fixed-width k-mers, binary searched in place
+//   offsets       u64 start of each k-mer's locations plus the end of the last
+//   locations     u32 (protein number, position) pairs grouped by k-mer
+//   sequences     u64 start of each protein's residues plus the end, then the residues
+//   metadata      JSON of the proteomes and the proteins' metadata, parsed on open
+pub(crate) struct BinaryIndex {
+    mmap: Mmap,
+    k: usize,
+    i_l_equivalent: bool,
+    kmer_count: usize,
+    kmers: usize, // byte offsets of the sections
+    offsets: usize,
+    locations: usize,
+    sequence_offsets: usize,
+    residues: usize,
+    protein_numbers: FxHashMap<usize, usize>, // protein number --> its position in the file
+    metadata: Vec<ProteinMetadata>,
+    proteomes: Vec<ProteomeRange>,
+}
+
+impl BinaryIndex {
+    pub fn open(path: &str) -> Result<BinaryIndex> {
+        let invalid = |reason: &str| PepMatchError::invalid(format!("{}: {}", path, reason));
+        let file = std::fs::File::open(path).map_err(PepMatchError::io(path))?;
+        // the file isn't modified while it's mapped, preprocessing writes a new one
+        let mmap = unsafe { Mmap::map(&file) }.map_err(PepMatchError::io(path))?;
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Err(invalid("not a binary index"));
+        }
+
+        let header = |i: usize| read_u64(&mmap, 8 + i * 8);
+        let (k, i_l_equivalent, kmer_count, location_count) = (header(0), header(1) != 0, header(2), header(3));
+        let (protein_count, residue_count, metadata_len) = (header(4), header(5), header(6));
+        let kmers = HEADER_LEN;
+        let offsets = kmers + padded(kmer_count * k);
+        let locations = offsets + (kmer_count + 1) * 8;
+        let sequence_offsets = locations + location_count * 8;
+        let residues = sequence_offsets + (protein_count + 1) * 8;
+        let metadata_start = residues + padded(residue_count);
+        let metadata = mmap
+            .get(metadata_start..metadata_start + metadata_len)
+            .ok_or_else(|| invalid("truncated binary index"))?;
+        let metadata: serde_json::Value =
+            serde_json::from_slice(metadata).map_err(|e| invalid(&format!("invalid metadata: {}", e)))?;
+
+        let proteomes = metadata["proteomes"]
+            .as_array()
+            .map(|proteomes| {
+                proteomes
+                    .iter()
+                    .map(|proteome| (text(&proteome[0]), number(&proteome[1]), number(&proteome[2])))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let proteins = metadata["proteins"].as_array().cloned().unwrap_or_default();
+        if proteins.len() != protein_count {
+            return Err(invalid("metadata doesn't match the proteins"));
+        }
+        let protein_numbers = proteins.iter().enumerate().map(|(i, protein)| (number(&protein[0]), i)).collect();
+        let metadata = proteins.iter().map(|protein| parse_metadata(protein).1).collect();
+
+        Ok(BinaryIndex {
+            mmap,
+            k,
+            i_l_equivalent,
+            kmer_count,
+            kmers,
+            offsets,
+            locations,
+            sequence_offsets,
+            residues,
+            protein_numbers,
+            metadata,
+            proteomes,
+        })
+    }
+
+    fn kmer(&self, i: usize) -> &[u8] {
+        &self.mmap[self.kmers + i * self.k..self.kmers + (i + 1) * self.k]
+    }
+
+    fn sequence(&self, protein_number: usize) -> &str {
+        let Some(&i) = self.protein_numbers.get(&protein_number) else {
+            return "";
+        };
+        let start = read_u64(&self.mmap, self.sequence_offsets + i * 8);
+        let end = read_u64(&self.mmap, self.sequence_offsets + (i + 1) * 8);
+        std::str::from_utf8(&self.mmap[self.residues + start..self.residues + end]).unwrap_or_default()
+    }
+}
+
+impl ProteomeIndex for BinaryIndex {
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn i_l_equivalent(&self) -> Option<bool> {
+        Some(self.i_l_equivalent)
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        let (mut low, mut high) = (0, self.kmer_count);
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.kmer(middle).cmp(kmer.as_bytes()) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => {
+                    let start = read_u64(&self.mmap, self.offsets + middle * 8);
+                    let end = read_u64(&self.mmap, self.offsets + (middle + 1) * 8);
+                    let locations = &self.mmap[self.locations + start * 8..self.locations + end * 8];
+                    return Ok(locations
+                        .chunks_exact(8)
+                        .map(|pair| (read_u32(&pair[..4]), read_u32(&pair[4..])))
+                        .collect());
+                }
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        Ok(region(self.sequence(start.0), start.1, len))
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        Ok(self.protein_numbers
+            .get(&protein_number)
+            .map(|&i| self.metadata[i].clone())
+            .unwrap_or_default())
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        Ok(self.proteomes.clone())
+    }
+}
+
+// write an index built in memory to a binary index file
+pub(crate) fn write(index: &MemoryIndex, path: &str, i_l_equivalent: bool) -> Result<()> {
+    let mut kmers: Vec<(&String, &Vec<Location>)> = index.kmers.iter().collect();
+    kmers.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    let mut protein_numbers: Vec<usize> = index.sequences.keys().copied().collect();
+    protein_numbers.sort_unstable();
+
+    let location_count: usize = kmers.iter().map(|(_, locations)| locations.len()).sum();
+    let residue_count: usize = index.sequences.values().map(String::len).sum();
+    let proteins: Vec<serde_json::Value> =
+        protein_numbers.iter().map(|number| metadata_json(*number, &index.metadata[number])).collect();
+    let metadata = serde_json::json!({ "proteomes": index.proteomes, "proteins": proteins }).to_string();
+
+    let file = std::fs::File::create(path).map_err(PepMatchError::io(path))?;
+    let mut out = std::io::BufWriter::new(file);
+    let mut write = |bytes: &[u8]| out.write_all(bytes).map_err(PepMatchError::io(path));
+    write(MAGIC)?;
+    for value in [index.k, i_l_equivalent as usize, kmers.len(), location_count, protein_numbers.len(), residue_count, metadata.len()] {
+        write(&(value as u64).to_le_bytes())?;
+    }
+
+    for (kmer, _) in &kmers {
+        write(kmer.as_bytes())?;
+    }
+    write(&padding(kmers.len() * index.k))?;
+    let mut offset = 0u64;
+    write(&offset.to_le_bytes())?;
+    for (_, locations) in &kmers {
+        offset += locations.len() as u64;
+        write(&offset.to_le_bytes())?;
+    }
+    for (_, locations) in &kmers {
+        for (protein_number, position) in locations.iter() {
+            write(&(*protein_number as u32).to_le_bytes())?;
+            write(&(*position as u32).to_le_bytes())?;
+        }
+    }
+
+    let mut offset = 0u64;
+    write(&offset.to_le_bytes())?;
+    for number in &protein_numbers {
+        offset += index.sequences[number].len() as u64;
+        write(&offset.to_le_bytes())?;
+    }
+    for number in &protein_numbers {
+        write(index.sequences[number].as_bytes())?;
+    }
+    write(&padding(residue_count))?;
+    write(metadata.as_bytes())?;
+    out.flush().map_err(PepMatchError::io(path))
+}
+
+// a protein's number and metadata as a JSON array, the way indices outside SQLite store them
+pub(crate) fn metadata_json(number: usize, metadata: &ProteinMetadata) -> serde_json::Value {
+    serde_json::json!([
+        number, metadata.protein_id, metadata.protein_name, metadata.species, metadata.taxon_id,
+        metadata.gene, metadata.pe_level, metadata.sequence_version, metadata.proteome,
+        metadata.gene_priority, metadata.frame, metadata.source_length, metadata.db,
+        metadata.isoform, metadata.length, metadata.molecular_weight
+    ])
+}
+
+pub(super) fn parse_metadata(protein: &serde_json::Value) -> (usize, ProteinMetadata) {
+    let metadata = ProteinMetadata {
+        protein_id: text(&protein[1]),
+        protein_name: text(&protein[2]),
+        species: text(&protein[3]),
+        taxon_id: text(&protein[4]),
+        gene: text(&protein[5]),
+        pe_level: number(&protein[6]),
+        sequence_version: number(&protein[7]),
+        proteome: text(&protein[8]),
+        gene_priority: protein[9].as_bool().unwrap_or_default(),
+        frame: protein[10].as_i64().unwrap_or_default() as i8,
+        source_length: number(&protein[11]),
+        db: text(&protein[12]),
+        isoform: number(&protein[13]),
+        length: number(&protein[14]),
+        molecular_weight: number(&protein[15]),
+    };
+    (number(&protein[0]), metadata)
+}
+
+// whether a file is a binary index going by its first bytes
+pub(crate) fn is_binary_index(path: &str) -> bool {
+    let mut magic = [0; 8];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut magic))
+        .is_ok_and(|_| &magic == MAGIC)
+}
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(8) * 8
+}
+
+fn padding(len: usize) -> Vec<u8> {
+    vec![0; padded(len) - len]
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> usize {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+}
+
+fn read_u32(bytes: &[u8]) -> usize {
+    u32::from_le_bytes(bytes.try_into().unwrap()) as usize
+}
+
+pub(super) fn text(value: &serde_json::Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+pub(super) fn number(value: &serde_json::Value) -> usize {
+    value.as_u64().unwrap_or_default() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Preprocessor;
+
+    const FASTA: &str = ">sp|P00001|ONE_HUMAN First protein OS=Homo sapiens OX=9606 GN=ONE PE=1 SV=2
+MKLVSTAAQW
+>sp|P00002|TWO_HUMAN Second protein OS=Homo sapiens OX=9606 GN=TWO PE=3 SV=1
+STAAQWLLKPE
+";
+
+    #[test]
+    fn write_open_round_trip() {
+        let index = MemoryIndex::build(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap();
+        let path = std::env::temp_dir().join(format!("pepmatch-binary-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        write(&index, path, true).unwrap();
+        assert!(is_binary_index(path));
+
+        let opened = BinaryIndex::open(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(opened.k(), 3);
+        assert_eq!(opened.i_l_equivalent(), Some(true));
+        for kmer in ["STA", "AQW", "MKL", "LKP", "WWW"] {
+            let mut expected = index.lookup(kmer).unwrap();
+            expected.sort();
+            assert_eq!(opened.lookup(kmer).unwrap(), expected, "{}", kmer);
+        }
+        let (protein, _) = index.lookup("MKL").unwrap()[0];
+        assert_eq!(opened.protein_region((protein, 3), 4).unwrap(), "VSTA");
+        let metadata = opened.protein_metadata(protein).unwrap();
+        assert_eq!(metadata.protein_id, "P00001");
+        assert_eq!(metadata.gene, "ONE");
+        assert_eq!(metadata.pe_level, 1);
+        assert_eq!(opened.proteomes().unwrap(), index.proteomes().unwrap());
+    }
+}
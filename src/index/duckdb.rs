@@ -0,0 +1,145 @@
+use super::{Location, Lookups, ProteinMetadata, ProteomeIndex, ProteomeRange, Result, LOOKUP_CHUNK};
+use crate::error::PepMatchError;
+use crate::schema;
+use duckdb::OptionalExt;
+
+// index stored in a DuckDB file written by the Preprocessor, with a row per k-mer location
+pub(crate) struct DuckdbIndex {
+    conn: duckdb::Connection,
+    path: String,
+    k: usize,
+    i_l_equivalent: Option<bool>,
+}
+
+impl DuckdbIndex {
+    pub fn open(db_path: &str) -> Result<DuckdbIndex> {
+        let config = duckdb::Config::default()
+            .access_mode(duckdb::AccessMode::ReadOnly)
+            .map_err(PepMatchError::duckdb(db_path))?;
+        let conn = duckdb::Connection::open_with_flags(db_path, config).map_err(PepMatchError::duckdb(db_path))?;
+        let k = conn
+            .query_row("SELECT CAST(value AS UINTEGER) FROM meta WHERE key = 'k'", [], |row| row.get::<_, u32>(0))
+            .optional()
+            .map_err(PepMatchError::duckdb(db_path))?
+            .ok_or_else(|| {
+                PepMatchError::invalid(format!("{}: preprocessing it didn't finish, rebuild it with --overwrite", db_path))
+            })?;
+        let i_l_equivalent = conn
+            .query_row("SELECT value FROM meta WHERE key = 'i_l_equivalent'", [], |row| row.get::<_, String>(0))
+            .optional()
+            .map_err(PepMatchError::duckdb(db_path))?
+            .and_then(|i_l| i_l.parse().ok());
+        let version = conn
+            .query_row("SELECT value FROM meta WHERE key = 'schema_version'", [], |row| row.get::<_, String>(0))
+            .optional()
+            .map_err(PepMatchError::duckdb(db_path))?
+            .and_then(|version| version.parse().ok());
+        schema::check_duckdb(version, db_path)?;
+        Ok(DuckdbIndex { conn, path: db_path.to_string(), k: k as usize, i_l_equivalent })
+    }
+
+    fn error(&self) -> impl FnOnce(duckdb::Error) -> PepMatchError + '_ {
+        PepMatchError::duckdb(&self.path)
+    }
+}
+
+impl ProteomeIndex for DuckdbIndex {
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn i_l_equivalent(&self) -> Option<bool> {
+        self.i_l_equivalent
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        self.conn
+            .prepare_cached("SELECT protein_number, position FROM kmers WHERE kmer = ? ORDER BY protein_number, position")
+            .and_then(|mut stmt| {
+                stmt.query_map([kmer], |row| Ok((row.get::<_, u32>(0)? as usize, row.get::<_, u32>(1)? as usize)))?
+                    .collect()
+            })
+            .map_err(self.error())
+    }
+
+    fn lookup_many(&self, kmers: &[String]) -> Result<Lookups> {
+        let mut lookups = Lookups::default();
+        for chunk in kmers.chunks(LOOKUP_CHUNK) {
+            let params: Vec<&str> = chunk.iter().map(|_| "?").collect();
+            let sql = format!(
+                "SELECT kmer, protein_number, position FROM kmers WHERE kmer IN ({}) ORDER BY kmer, protein_number, position",
+                params.join(", ")
+            );
+            self.conn
+                .prepare_cached(&sql)
+                .and_then(|mut stmt| {
+                    let mut rows = stmt.query(duckdb::params_from_iter(chunk))?;
+                    while let Some(row) = rows.next()? {
+                        let location = (row.get::<_, u32>(1)? as usize, row.get::<_, u32>(2)? as usize);
+                        lookups.entry(row.get(0)?).or_default().push(location);
+                    }
+                    Ok(())
+                })
+                .map_err(self.error())?;
+        }
+        Ok(lookups)
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        let region: Option<String> = self.conn
+            .prepare_cached("SELECT SUBSTR(sequence, ?, ?) FROM proteins WHERE protein_number = ?")
+            .and_then(|mut stmt| {
+                stmt.query_row(duckdb::params![(start.1 + 1) as u32, len as u32, start.0 as u32], |row| row.get(0))
+                    .optional()
+            })
+            .map_err(self.error())?;
+        Ok(region.unwrap_or_default())
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        self.conn
+            .prepare_cached(
+                "SELECT protein_id, protein_name, species, taxon_id, gene, pe_level, sequence_version, proteome,
+                    gene_priority, frame, source_length, db, isoform, length, molecular_weight
+                 FROM metadata WHERE protein_number = ?",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_row([protein_number as u32], |row| {
+                    Ok(ProteinMetadata {
+                        protein_id: row.get(0)?,
+                        protein_name: row.get(1)?,
+                        species: row.get(2)?,
+                        taxon_id: row.get(3)?,
+                        gene: row.get(4)?,
+                        pe_level: row.get::<_, u8>(5)? as usize,
+                        sequence_version: row.get::<_, u32>(6)? as usize,
+                        proteome: row.get(7)?,
+                        gene_priority: row.get(8)?,
+                        frame: row.get(9)?,
+                        source_length: row.get::<_, u32>(10)? as usize,
+                        db: row.get(11)?,
+                        isoform: row.get::<_, u32>(12)? as usize,
+                        length: row.get::<_, u32>(13)? as usize,
+                        molecular_weight: row.get::<_, u32>(14)? as usize,
+                    })
+                })
+            })
+            .map_err(self.error())
+    }
+
+    // the connections of a database opened once share its cache
+    fn worker(&self) -> Result<Box<dyn ProteomeIndex + Send>> {
+        let conn = self.conn.try_clone().map_err(self.error())?;
+        Ok(Box::new(DuckdbIndex { conn, path: self.path.clone(), k: self.k, i_l_equivalent: self.i_l_equivalent }))
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        self.conn
+            .prepare("SELECT proteome, first_protein, last_protein FROM proteomes ORDER BY first_protein")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| Ok((row.get(0)?, row.get::<_, u32>(1)? as usize, row.get::<_, u32>(2)? as usize)))?
+                    .collect()
+            })
+            .map_err(self.error())
+    }
+}
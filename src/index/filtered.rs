@@ -0,0 +1,70 @@
+use super::{Location, Lookups, ProteinMetadata, ProteomeIndex, ProteomeRange, Result};
+
+// restricts an index's k-mer lookups to the proteins of some of its proteomes
+pub(crate) struct FilteredIndex {
+    inner: Box<dyn ProteomeIndex + Send>,
+    proteomes: Vec<ProteomeRange>,
+}
+
+impl FilteredIndex {
+    pub fn new(inner: Box<dyn ProteomeIndex + Send>, proteomes: Vec<ProteomeRange>) -> FilteredIndex {
+        FilteredIndex { inner, proteomes }
+    }
+
+    fn contains(&self, protein_number: usize) -> bool {
+        self.proteomes.iter().any(|(_, first, last)| (*first..=*last).contains(&protein_number))
+    }
+}
+
+impl ProteomeIndex for FilteredIndex {
+    fn k(&self) -> usize {
+        self.inner.k()
+    }
+
+    fn i_l_equivalent(&self) -> Option<bool> {
+        self.inner.i_l_equivalent()
+    }
+
+    fn k_values(&self) -> Vec<usize> {
+        self.inner.k_values()
+    }
+
+    fn any_k(&self) -> bool {
+        self.inner.any_k()
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        let mut locations = self.inner.lookup(kmer)?;
+        locations.retain(|(protein_number, _)| self.contains(*protein_number));
+        Ok(locations)
+    }
+
+    fn lookup_many(&self, kmers: &[String]) -> Result<Lookups> {
+        let mut lookups = self.inner.lookup_many(kmers)?;
+        for locations in lookups.values_mut() {
+            locations.retain(|(protein_number, _)| self.contains(*protein_number));
+        }
+        lookups.retain(|_, locations| !locations.is_empty());
+        Ok(lookups)
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        self.inner.protein_region(start, len)
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        self.inner.protein_metadata(protein_number)
+    }
+
+    fn synonyms(&self, protein_number: usize) -> Result<Vec<(usize, ProteinMetadata)>> {
+        self.inner.synonyms(protein_number)
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        Ok(self.proteomes.clone())
+    }
+
+    fn worker(&self) -> Result<Box<dyn ProteomeIndex + Send>> {
+        Ok(Box::new(FilteredIndex::new(self.inner.worker()?, self.proteomes.clone())))
+    }
+}
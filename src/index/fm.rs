@@ -0,0 +1,151 @@
+use super::{region, Location, ProteinMetadata, ProteomeIndex, ProteomeRange, Result};
+use crate::progress;
+use crate::Preprocessor;
+use bio::alphabets::Alphabet;
+use bio::data_structures::bwt::{bwt, less, Occ, BWT, Less};
+use bio::data_structures::fmindex::{BackwardSearchResult, FMIndex, FMIndexable};
+use bio::data_structures::suffix_array::{suffix_array, RawSuffixArray};
+use fxhash::FxHashMap;
+
+// between proteins in the text, so no hit spans two of them
+const SEPARATOR: u8 = b'|';
+// ends the text, sorting before everything else in it
+const SENTINEL: u8 = b'$';
+
+// every BWT position whose occurrences are counted, the rest are counted from the last one
+const OCC_SAMPLING: u32 = 32;
+
+// an FM-index of the proteins concatenated and built in RAM straight from a proteome FASTA
+// file, it finds a k-mer of any length so peptides are looked up whole in exact searches
+// and by the largest k-mers that stay intact in mismatched ones, k only seeds the searches
+// that need a fixed k, the whole suffix array is kept rather than a sample of it
+pub(crate) struct FmIndex {
+    k: usize,
+    fm: FMIndex<BWT, Less, Occ>,
+    suffix_array: RawSuffixArray,
+    residues: [bool; 256], // the bytes in the text, others can't be searched for
+    starts: Vec<usize>,    // where each protein starts in the text
+    numbers: Vec<usize>,   // the protein number of each of starts
+    sequences: FxHashMap<usize, String>,
+    metadata: FxHashMap<usize, ProteinMetadata>,
+    proteomes: Vec<ProteomeRange>,
+}
+
+impl FmIndex {
+    // read the proteome the way the preprocessor would for a DB and index its text
+    pub fn build(preprocessor: &Preprocessor) -> Result<FmIndex> {
+        let _span = tracing::info_span!("build_fm_index").entered();
+        let (mut text, mut starts, mut numbers) = (Vec::new(), Vec::new(), Vec::new());
+        let (mut sequences, mut metadata, mut proteomes) = (FxHashMap::default(), FxHashMap::default(), Vec::new());
+
+        let bar = progress::proteins(preprocessor.progress, preprocessor.count_proteins()?);
+        for protein in preprocessor.read_proteome()? {
+            let protein = protein?;
+            bar.inc(1);
+            starts.push(text.len());
+            numbers.push(protein.number);
+            text.extend_from_slice(preprocessor.canonical(&protein.sequence).as_bytes());
+            text.push(SEPARATOR);
+            // proteins are numbered contiguously one proteome after the other
+            match proteomes.last_mut() {
+                Some((name, _, last)) if *name == protein.metadata.proteome => *last = protein.number,
+                _ => proteomes.push((protein.metadata.proteome.clone(), protein.number, protein.number)),
+            }
+            metadata.insert(protein.number, protein.metadata);
+            sequences.insert(protein.number, protein.sequence);
+        }
+        bar.finish_and_clear();
+        text.push(SENTINEL);
+
+        let _span = tracing::debug_span!("suffix_array", residues = text.len()).entered();
+        let mut residues = [false; 256];
+        for residue in &text {
+            residues[*residue as usize] = true;
+        }
+        let alphabet = Alphabet::new((0..=255u8).filter(|residue| residues[*residue as usize]));
+        let suffix_array = suffix_array(&text);
+        let bwt = bwt(&text, &suffix_array);
+        let less = less(&bwt, &alphabet);
+        let occ = Occ::new(&bwt, OCC_SAMPLING, &alphabet);
+        residues[SEPARATOR as usize] = false;
+        residues[SENTINEL as usize] = false;
+        Ok(FmIndex {
+            k: preprocessor.k,
+            fm: FMIndex::new(bwt, less, occ),
+            suffix_array,
+            residues,
+            starts,
+            numbers,
+            sequences,
+            metadata,
+            proteomes,
+        })
+    }
+
+    // the protein and position in it of a position in the text
+    fn location(&self, position: usize) -> Location {
+        let i = self.starts.partition_point(|start| *start <= position) - 1;
+        (self.numbers[i], position - self.starts[i])
+    }
+}
+
+impl ProteomeIndex for FmIndex {
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn any_k(&self) -> bool {
+        true
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        if kmer.is_empty() || !kmer.bytes().all(|residue| self.residues[residue as usize]) {
+            return Ok(Vec::new());
+        }
+        let mut locations: Vec<Location> = match self.fm.backward_search(kmer.as_bytes().iter()) {
+            BackwardSearchResult::Complete(interval) => {
+                interval.occ(&self.suffix_array).into_iter().map(|position| self.location(position)).collect()
+            }
+            _ => Vec::new(),
+        };
+        locations.sort();
+        Ok(locations)
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        Ok(self.sequences
+            .get(&start.0)
+            .map(|seq| region(seq, start.1, len))
+            .unwrap_or_default())
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        Ok(self.metadata[&protein_number].clone())
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        Ok(self.proteomes.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FASTA: &str = ">sp|P00001|ONE_HUMAN First protein OS=Homo sapiens OX=9606 GN=ONE PE=1 SV=2
+MKLVSTAAQWDEQ
+>sp|P00002|TWO_HUMAN Second protein OS=Homo sapiens OX=9606 GN=TWO PE=3 SV=1
+PESTAVQWLLK
+";
+
+    #[test]
+    fn kmers_of_any_length_are_found() {
+        let index = FmIndex::build(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap();
+        assert_eq!(index.lookup("STA").unwrap(), vec![(1, 4), (2, 2)]);
+        assert_eq!(index.lookup("MKLVSTAAQWDEQ").unwrap(), vec![(1, 0)]);
+        assert_eq!(index.lookup("K").unwrap(), vec![(1, 1), (2, 10)]);
+        assert!(index.lookup("QP").unwrap().is_empty(), "hits don't span proteins");
+        assert!(index.lookup("QWLLKZ").unwrap().is_empty());
+        assert!(index.lookup("").unwrap().is_empty());
+    }
+}
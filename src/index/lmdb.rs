@@ -0,0 +1,67 @@
+use super::{KmerStore, PepMatchError, Result};
+use heed::types::Bytes;
+use heed::{Database, Env, EnvOpenOptions};
+
+// how big the memory map of an environment can grow, LMDB only takes up the disk and
+// memory the data needs so it's set far beyond any proteome
+const MAP_SIZE: usize = 1 << 40;
+
+// an LMDB environment of a directory with a single unnamed database
+pub(crate) struct LmdbStore {
+    env: Env,
+    db: Database<Bytes, Bytes>,
+    path: String,
+}
+
+impl LmdbStore {
+    // create the environment's directory and database, which mustn't exist yet
+    pub fn create(path: &str) -> Result<LmdbStore> {
+        std::fs::create_dir_all(path).map_err(PepMatchError::io(path))?;
+        let env = open_env(path)?;
+        let mut txn = env.write_txn().map_err(PepMatchError::store(path))?;
+        let db = env.create_database(&mut txn, None).map_err(PepMatchError::store(path))?;
+        txn.commit().map_err(PepMatchError::store(path))?;
+        Ok(LmdbStore { env, db, path: path.to_string() })
+    }
+
+    pub fn open(path: &str) -> Result<LmdbStore> {
+        let env = open_env(path)?;
+        let txn = env.read_txn().map_err(PepMatchError::store(path))?;
+        let db = env
+            .open_database(&txn, None)
+            .map_err(PepMatchError::store(path))?
+            .ok_or_else(|| PepMatchError::invalid(format!("{}: isn't an index", path)))?;
+        txn.commit().map_err(PepMatchError::store(path))?;
+        Ok(LmdbStore { env, db, path: path.to_string() })
+    }
+}
+
+impl KmerStore for LmdbStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let txn = self.env.read_txn().map_err(PepMatchError::store(&self.path))?;
+        let value = self.db.get(&txn, key).map_err(PepMatchError::store(&self.path))?;
+        Ok(value.map(<[u8]>::to_vec))
+    }
+
+    fn put(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        let mut txn = self.env.write_txn().map_err(PepMatchError::store(&self.path))?;
+        for (key, value) in entries {
+            self.db.put(&mut txn, key, value).map_err(PepMatchError::store(&self.path))?;
+        }
+        txn.commit().map_err(PepMatchError::store(&self.path))
+    }
+
+    fn iterate(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let txn = self.env.read_txn().map_err(PepMatchError::store(&self.path))?;
+        let entries = self.db.prefix_iter(&txn, prefix).map_err(PepMatchError::store(&self.path))?;
+        entries
+            .map(|entry| entry.map(|(key, value)| (key.to_vec(), value.to_vec())).map_err(PepMatchError::store(&self.path)))
+            .collect()
+    }
+}
+
+fn open_env(path: &str) -> Result<Env> {
+    // the environment's files are only opened by one process at a time writing it, and
+    // aren't changed while they're searched
+    unsafe { EnvOpenOptions::new().map_size(MAP_SIZE).open(path) }.map_err(PepMatchError::store(path))
+}
@@ -0,0 +1,81 @@
+use super::{region, Location, ProteinMetadata, ProteomeIndex, ProteomeRange, Result};
+use crate::progress;
+use crate::Preprocessor;
+use fxhash::FxHashMap;
+
+// index built in RAM straight from a proteome FASTA file, for one-off
+// searches that don't need a preprocessed DB
+pub(crate) struct MemoryIndex {
+    pub(super) k: usize,
+    pub(super) extra_k: Vec<usize>,
+    pub(super) kmers: FxHashMap<String, Vec<Location>>,
+    pub(super) sequences: FxHashMap<usize, String>,
+    pub(super) metadata: FxHashMap<usize, ProteinMetadata>,
+    pub(super) proteomes: Vec<ProteomeRange>,
+}
+
+impl MemoryIndex {
+    // parse and k-merize the proteome the same way the preprocessor would for a DB
+    pub fn build(preprocessor: &Preprocessor) -> Result<MemoryIndex> {
+        let _span = tracing::info_span!("build_index", k = preprocessor.k).entered();
+        let mut index = MemoryIndex {
+            k: preprocessor.k,
+            extra_k: preprocessor.extra_k.clone(),
+            kmers: FxHashMap::default(),
+            sequences: FxHashMap::default(),
+            metadata: FxHashMap::default(),
+            proteomes: Vec::new(),
+        };
+
+        let bar = progress::proteins(preprocessor.progress, preprocessor.count_proteins()?);
+        for protein in preprocessor.read_proteome()? {
+            let protein = protein?;
+            let _span = tracing::trace_span!("protein", number = protein.number, id = %protein.metadata.protein_id).entered();
+            bar.inc(1);
+            // k-mers of every k share the map, their lengths tell them apart
+            for k in std::iter::once(preprocessor.k).chain(preprocessor.extra_k.iter().copied()) {
+                for (kmer, position) in preprocessor.split_protein(&protein.sequence, k) {
+                    index.kmers.entry(kmer).or_default().push((protein.number, position));
+                }
+            }
+            // proteins are numbered contiguously one proteome after the other
+            match index.proteomes.last_mut() {
+                Some((name, _, last)) if *name == protein.metadata.proteome => *last = protein.number,
+                _ => index.proteomes.push((protein.metadata.proteome.clone(), protein.number, protein.number)),
+            }
+            index.metadata.insert(protein.number, protein.metadata);
+            index.sequences.insert(protein.number, protein.sequence);
+        }
+        bar.finish_and_clear();
+        Ok(index)
+    }
+}
+
+impl ProteomeIndex for MemoryIndex {
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn k_values(&self) -> Vec<usize> {
+        std::iter::once(self.k).chain(self.extra_k.iter().copied()).collect()
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        Ok(self.kmers.get(kmer).cloned().unwrap_or_default())
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        Ok(self.sequences
+            .get(&start.0)
+            .map(|seq| region(seq, start.1, len))
+            .unwrap_or_default())
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        Ok(self.metadata[&protein_number].clone())
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        Ok(self.proteomes.clone())
+    }
+}
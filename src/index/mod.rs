@@ -0,0 +1,304 @@
+#[cfg(feature = "sqlite")]
+use crate::build_info::has_table;
+#[cfg(feature = "sqlite")]
+use crate::preprocessor::PROGRESS_TABLE;
+#[cfg(feature = "sqlite")]
+use crate::schema::{self, SCHEMA_VERSION};
+use fxhash::FxHashMap;
+#[cfg(feature = "sqlite")]
+use rusqlite::OpenFlags;
+
+pub(crate) mod binary;
+#[cfg(feature = "duckdb")]
+mod duckdb;
+mod filtered;
+mod fm;
+#[cfg(feature = "lmdb")]
+mod lmdb;
+mod memory;
+mod prefetched;
+#[cfg(feature = "sqlite")]
+mod python;
+#[cfg(feature = "rocksdb")]
+mod rocksdb;
+mod shared;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+pub(crate) mod store;
+
+pub(crate) use binary::write as write_binary_index;
+pub(crate) use filtered::FilteredIndex;
+pub(crate) use fm::FmIndex;
+#[cfg(feature = "lmdb")]
+pub(crate) use lmdb::LmdbStore;
+pub(crate) use memory::MemoryIndex;
+pub(crate) use prefetched::PrefetchedIndex;
+#[cfg(feature = "sqlite")]
+pub(crate) use python::{pack_idx, PythonTables, IDX_BASE};
+#[cfg(feature = "rocksdb")]
+pub(crate) use rocksdb::RocksStore;
+pub(crate) use shared::SharedIndex;
+#[cfg(feature = "sqlite")]
+pub(crate) use sqlite::{decode_kmer_locations, encode_kmer_locations, kmers_table, shard_of, shard_path, SqliteIndex};
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+pub(crate) use store::KmerStore;
+pub(crate) use store::{StoreIndex, StoreKind};
+
+pub(crate) use crate::error::{PepMatchError, Result};
+pub(crate) use crate::proteome::ProteinMetadata;
+
+// protein number and 0-based position of a residue in that protein
+pub(crate) type Location = (usize, usize);
+
+// locations of each of a batch of k-mers, k-mers that aren't in the index are left out
+pub(crate) type Lookups = FxHashMap<String, Vec<Location>>;
+
+// a proteome's name and the first and last numbers of its proteins
+pub(crate) type ProteomeRange = (String, usize, usize);
+
+// storage backend a Matcher searches, a k-mer --> locations index plus the
+// protein sequences and metadata needed to verify and report hits
+pub(crate) trait ProteomeIndex {
+    // the k the index was built with
+    fn k(&self) -> usize;
+
+    // whether the index was built with I and L treated as the same residue, None for
+    // indices that don't record it, whose builds are checked some other way
+    fn i_l_equivalent(&self) -> Option<bool> {
+        None
+    }
+
+    // every k the index has k-mers of, its own k first, indices preprocessed with extra k
+    // values look k-mers of each of them up in their own tables
+    fn k_values(&self) -> Vec<usize> {
+        vec![self.k()]
+    }
+
+    // whether k-mers of any length can be looked up rather than only of its k values,
+    // as in an FM-index
+    fn any_k(&self) -> bool {
+        false
+    }
+
+    // every location of a k-mer in the proteome
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>>;
+
+    // every location of each of a batch of k-mers, backends with a round-trip per
+    // lookup should fetch them all at once
+    fn lookup_many(&self, kmers: &[String]) -> Result<Lookups> {
+        let mut lookups = Lookups::default();
+        for kmer in kmers {
+            let locations = self.lookup(kmer)?;
+            if !locations.is_empty() {
+                lookups.insert(kmer.clone(), locations);
+            }
+        }
+        Ok(lookups)
+    }
+
+    // the protein sequence of a given length starting at a location,
+    // None if the window runs off the end of the protein
+    fn protein_window(&self, start: Location, len: usize) -> Result<Option<String>> {
+        let window = self.protein_region(start, len)?;
+        Ok(Some(window).filter(|window| window.len() == len))
+    }
+
+    // the protein sequence of up to a given length starting at a location,
+    // cut short at the end of the protein
+    fn protein_region(&self, start: Location, len: usize) -> Result<String>;
+
+    // the whole sequence of a protein
+    fn protein_sequence(&self, protein_number: usize) -> Result<String> {
+        self.protein_region((protein_number, 0), i32::MAX as usize)
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata>;
+
+    // the proteins with the same sequence as an indexed one that were left out of the index
+    // in its favour, each with its own protein number, indices preprocessed without
+    // deduplicating sequences have none
+    fn synonyms(&self, _protein_number: usize) -> Result<Vec<(usize, ProteinMetadata)>> {
+        Ok(Vec::new())
+    }
+
+    // the proteomes in the index, in the order they were read
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>>;
+
+    // a handle on the index for another thread to search, DB backed indices open
+    // another connection and indices in memory are shared
+    fn worker(&self) -> Result<Box<dyn ProteomeIndex + Send>> {
+        Err(PepMatchError::invalid("this index can't be searched from several threads"))
+    }
+}
+
+// open a preprocessed DB read-only, whether it was written by this tool or the Python PEPMatch,
+// or a binary index, a DuckDB file or a RocksDB or LMDB index, but for a SQLite DB of an older
+// schema version, which is first migrated in place (written to) if it can be
+pub(crate) fn open_db(db_path: &str) -> Result<Box<dyn ProteomeIndex + Send>> {
+    if binary::is_binary_index(db_path) {
+        return Ok(Box::new(SharedIndex::new(binary::BinaryIndex::open(db_path)?)));
+    }
+    if let Some(kind) = StoreKind::detect(db_path) {
+        return Ok(Box::new(SharedIndex::new(StoreIndex::open(db_path, kind)?)));
+    }
+    if is_duckdb(db_path) {
+        #[cfg(feature = "duckdb")]
+        return Ok(Box::new(self::duckdb::DuckdbIndex::open(db_path)?));
+        #[cfg(not(feature = "duckdb"))]
+        return Err(PepMatchError::invalid(format!(
+            "{}: is a DuckDB index, and pepmatch was built without the duckdb feature",
+            db_path
+        )));
+    }
+    open_sqlite(db_path)
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(db_path: &str) -> Result<Box<dyn ProteomeIndex + Send>> {
+    let conn = open_read_only(db_path)?;
+    if has_table(&conn, PROGRESS_TABLE).map_err(PepMatchError::database(db_path))? {
+        return Err(PepMatchError::invalid(format!(
+            "{}: preprocessing it didn't finish, continue it with preprocess --resume",
+            db_path
+        )));
+    }
+    match PythonTables::find(&conn).map_err(PepMatchError::database(db_path))? {
+        Some(tables) => Ok(Box::new(python::PythonIndex::open(conn, db_path, tables))),
+        None => {
+            if let Some(version) = schema::outdated(&conn, db_path)? {
+                // a DB that can't be written to is searched as it is, with what it lacks left empty
+                match schema::upgrade(db_path) {
+                    Ok(()) => tracing::info!("Upgraded {} from schema version {} to {}", db_path, version, SCHEMA_VERSION),
+                    Err(e) => tracing::warn!("Searching {} as it is, it couldn't be upgraded: {}", db_path, e),
+                }
+            }
+            Ok(Box::new(SqliteIndex::open(conn, db_path)?))
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(db_path: &str) -> Result<Box<dyn ProteomeIndex + Send>> {
+    Err(PepMatchError::invalid(format!(
+        "{}: isn't a binary index, and pepmatch was built without SQLite to search DBs",
+        db_path
+    )))
+}
+
+// number of k-mers looked up per IN (...) query, well under SQLite's limit on bound parameters
+#[cfg(any(feature = "sqlite", feature = "duckdb"))]
+pub(crate) const LOOKUP_CHUNK: usize = 500;
+
+// SELECT <columns> FROM <table> WHERE kmer IN (?1, ..., ?n)
+#[cfg(feature = "sqlite")]
+pub(crate) fn lookup_sql(columns: &str, table: &str, n: usize) -> String {
+    let params: Vec<String> = (1..=n).map(|i| format!("?{}", i)).collect();
+    format!("SELECT {} FROM {} WHERE kmer IN ({})", columns, table, params.join(", "))
+}
+
+// (protein number, position) pairs packed as little-endian u32s, the way the DB and key-value
+// stores keep a k-mer's locations
+#[cfg(any(feature = "sqlite", feature = "rocksdb", feature = "lmdb"))]
+pub(crate) fn encode_locations(locations: &[Location]) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(locations.len() * 8);
+    for (protein_number, position) in locations {
+        blob.extend_from_slice(&(*protein_number as u32).to_le_bytes());
+        blob.extend_from_slice(&(*position as u32).to_le_bytes());
+    }
+    blob
+}
+
+pub(crate) fn decode_locations(blob: &[u8]) -> Vec<Location> {
+    let read = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap()) as usize;
+    blob.chunks_exact(8)
+        .map(|pair| (read(&pair[..4]), read(&pair[4..])))
+        .collect()
+}
+
+// DuckDB files have their magic number after an 8 byte checksum
+fn is_duckdb(path: &str) -> bool {
+    let mut header = [0; 12];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+        .is_ok_and(|_| &header[8..] == b"DUCK")
+}
+
+// SQLite files start with their format's name
+#[cfg(feature = "sqlite")]
+pub(crate) fn is_sqlite_db(path: &str) -> bool {
+    let mut header = [0; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+        .is_ok_and(|_| &header == b"SQLite format 3\0")
+}
+
+#[cfg(feature = "sqlite")]
+pub(crate) fn open_read_only(db_path: &str) -> Result<rusqlite::Connection> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let conn = rusqlite::Connection::open_with_flags(db_path, flags).map_err(PepMatchError::database(db_path))?;
+    // room for the single lookups plus an IN (...) query per chunk size
+    conn.set_prepared_statement_cache_capacity(32);
+    Ok(conn)
+}
+
+// up to len residues of a sequence from a 0-based position, cut short at its end
+pub(crate) fn region(sequence: &str, position: usize, len: usize) -> String {
+    let start = position.min(sequence.len());
+    sequence[start..(start + len).min(sequence.len())].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "sqlite", feature = "rocksdb", feature = "lmdb"))]
+    #[test]
+    fn locations_round_trip() {
+        let locations = vec![(0, 0), (1, 41), (70_000, 4_000_000)];
+        let blob = encode_locations(&locations);
+        assert_eq!(blob.len(), 8 * locations.len());
+        assert_eq!(decode_locations(&blob), locations);
+    }
+
+    #[test]
+    fn trailing_bytes_are_ignored() {
+        let mut blob = 7u32.to_le_bytes().to_vec();
+        blob.extend_from_slice(&3u32.to_le_bytes());
+        blob.push(1);
+        assert_eq!(decode_locations(&blob), vec![(7, 3)]);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn kmers_sharing_a_prefix_share_a_shard() {
+        assert_eq!(shard_of("MKLVST", 16), shard_of("MKLAAA", 16));
+        assert_eq!(shard_of("MK", 16), shard_of("MK", 16));
+        let shards: std::collections::HashSet<usize> =
+            ["AAA", "MKL", "PES", "WWW", "QRS", "DEQ", "LLK"].iter().map(|kmer| shard_of(kmer, 4)).collect();
+        assert!(shards.iter().all(|shard| *shard < 4));
+        assert!(shards.len() > 1, "k-mers are spread across the shards");
+        assert_eq!(shard_path("human_k5.db", 3), "human_k5.db.shard-3");
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn compressed_locations_round_trip() {
+        use crate::preprocessor::Compression;
+        let few = vec![(3, 17), (3, 200), (70_000, 4_000_000)];
+        let many: Vec<Location> = (1..2000).map(|protein| (protein, protein % 7)).collect();
+        for locations in [few.clone(), many.clone(), Vec::new()] {
+            let blob = encode_kmer_locations(&locations, Compression::Zstd).unwrap();
+            assert_eq!(decode_kmer_locations(&blob, Compression::Zstd).unwrap(), locations);
+        }
+        let blob = encode_kmer_locations(&few, Compression::Zstd).unwrap();
+        assert_eq!(blob[0], 0, "few locations aren't worth compressing");
+        let blob = encode_kmer_locations(&many, Compression::Zstd).unwrap();
+        assert!(blob[0] == 1 && blob.len() * 4 < encode_locations(&many).len());
+        assert_eq!(encode_kmer_locations(&few, Compression::None).unwrap(), encode_locations(&few));
+        assert!(decode_kmer_locations(&[0, 5], Compression::Zstd).is_err(), "a position is missing");
+        assert!(decode_kmer_locations(&[0, 5, 0x80], Compression::Zstd).is_err(), "a varint is cut short");
+        let endless = [[0].as_slice(), &[0xff; 11], &[1, 1]].concat();
+        let error = decode_kmer_locations(&endless, Compression::Zstd).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,61 @@
+use super::{Location, Lookups, ProteinMetadata, ProteomeIndex, ProteomeRange, Result};
+
+// an index with the locations of a batch of k-mers already fetched in one go,
+// lookups of k-mers outside the batch fall through to the index itself
+pub(crate) struct PrefetchedIndex<'a> {
+    inner: &'a dyn ProteomeIndex,
+    kmers: Vec<String>,
+    lookups: Lookups,
+}
+
+impl<'a> PrefetchedIndex<'a> {
+    pub fn fetch(inner: &'a dyn ProteomeIndex, mut kmers: Vec<String>) -> Result<PrefetchedIndex<'a>> {
+        kmers.sort();
+        kmers.dedup();
+        let lookups = inner.lookup_many(&kmers)?;
+        Ok(PrefetchedIndex { inner, kmers, lookups })
+    }
+}
+
+impl ProteomeIndex for PrefetchedIndex<'_> {
+    fn k(&self) -> usize {
+        self.inner.k()
+    }
+
+    fn i_l_equivalent(&self) -> Option<bool> {
+        self.inner.i_l_equivalent()
+    }
+
+    fn k_values(&self) -> Vec<usize> {
+        self.inner.k_values()
+    }
+
+    fn any_k(&self) -> bool {
+        self.inner.any_k()
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        match self.lookups.get(kmer) {
+            Some(locations) => Ok(locations.clone()),
+            // fetched k-mers missing from the lookups aren't in the index
+            None if self.kmers.binary_search_by(|fetched| fetched.as_str().cmp(kmer)).is_ok() => Ok(Vec::new()),
+            None => self.inner.lookup(kmer),
+        }
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        self.inner.protein_region(start, len)
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        self.inner.protein_metadata(protein_number)
+    }
+
+    fn synonyms(&self, protein_number: usize) -> Result<Vec<(usize, ProteinMetadata)>> {
+        self.inner.synonyms(protein_number)
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        self.inner.proteomes()
+    }
+}
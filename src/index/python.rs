@@ -0,0 +1,217 @@
+use super::{lookup_sql, open_read_only, region, Location, Lookups, ProteinMetadata, ProteomeIndex, ProteomeRange, Result, LOOKUP_CHUNK};
+use crate::error::PepMatchError;
+use fxhash::FxHashMap;
+use rusqlite::types::Value;
+use rusqlite::OptionalExtension;
+use std::sync::{Arc, OnceLock};
+
+// k-mer locations are packed into one idx column as protein number * IDX_BASE + position
+pub(crate) const IDX_BASE: usize = 1_000_000;
+
+// tables of a DB in the layout the Python PEPMatch preprocesses proteomes into:
+// "<proteome>_<k>mers" (kmer TEXT, idx INTEGER) and "<proteome>_metadata" (protein_number,
+// protein_id, protein_name, species, taxon_id, gene, pe_level, sequence_version, gene_priority)
+#[derive(Debug, Clone)]
+pub(crate) struct PythonTables {
+    pub proteome: String,
+    pub k: usize,
+    pub kmers: String,
+    pub metadata: String,
+}
+
+impl PythonTables {
+    pub fn new(proteome: &str, k: usize) -> PythonTables {
+        PythonTables {
+            proteome: proteome.to_string(),
+            k,
+            kmers: format!("{}_{}mers", proteome, k),
+            metadata: format!("{}_metadata", proteome),
+        }
+    }
+
+    // find the Python layout's tables in a DB, None if it isn't in that layout
+    pub fn find(conn: &rusqlite::Connection) -> rusqlite::Result<Option<PythonTables>> {
+        let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE '%mers'")?;
+        let names: Vec<String> = stmt.query_map(rusqlite::params![], |row| row.get(0))?.collect::<rusqlite::Result<_>>()?;
+
+        for name in names {
+            let Some((proteome, k)) = name.strip_suffix("mers").and_then(|name| name.rsplit_once('_')) else {
+                continue;
+            };
+            let Ok(k) = k.parse() else {
+                continue;
+            };
+            let tables = PythonTables::new(proteome, k);
+            let has_metadata: Option<String> = conn
+                .query_row(
+                    "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                    rusqlite::params![tables.metadata],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if has_metadata.is_some() {
+                return Ok(Some(tables));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// index stored in a DB preprocessed by the Python PEPMatch, which has no protein
+// sequences table so sequences are pieced back together from the k-mers when needed
+pub(crate) struct PythonIndex {
+    conn: rusqlite::Connection,
+    path: String,
+    tables: PythonTables,
+    sequences: Arc<OnceLock<FxHashMap<usize, String>>>, // read once, by whichever worker needs them first
+}
+
+impl PythonIndex {
+    pub fn open(conn: rusqlite::Connection, path: &str, tables: PythonTables) -> PythonIndex {
+        PythonIndex { conn, path: path.to_string(), tables, sequences: Arc::new(OnceLock::new()) }
+    }
+
+    fn error(&self) -> impl FnOnce(rusqlite::Error) -> PepMatchError + '_ {
+        PepMatchError::database(&self.path)
+    }
+
+    // rebuild every protein sequence from its overlapping k-mers in one pass over the table
+    fn read_sequences(&self) -> rusqlite::Result<FxHashMap<usize, String>> {
+        let k = self.tables.k;
+        let mut residues: FxHashMap<usize, Vec<u8>> = FxHashMap::default();
+
+        let mut stmt = self.conn.prepare(&format!("SELECT kmer, idx FROM \"{}\"", self.tables.kmers))?;
+        let mut rows = stmt.query(rusqlite::params![])?;
+        while let Some(row) = rows.next()? {
+            let kmer: String = row.get(0)?;
+            let (protein_number, position) = unpack_idx(row.get::<_, i64>(1)? as usize);
+
+            let sequence = residues.entry(protein_number).or_default();
+            if sequence.len() < position + k {
+                sequence.resize(position + k, b'X');
+            }
+            sequence[position..position + k].copy_from_slice(&kmer.as_bytes()[..k]);
+        }
+
+        Ok(residues
+            .into_iter()
+            .map(|(protein_number, sequence)| (protein_number, String::from_utf8_lossy(&sequence).to_string()))
+            .collect())
+    }
+}
+
+impl ProteomeIndex for PythonIndex {
+    fn k(&self) -> usize {
+        self.tables.k
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        self.conn
+            .prepare_cached(&format!("SELECT idx FROM \"{}\" WHERE kmer = ?1", self.tables.kmers))
+            .and_then(|mut stmt| {
+                stmt.query_map(rusqlite::params![kmer], |row| Ok(unpack_idx(row.get::<_, i64>(0)? as usize)))?
+                    .collect()
+            })
+            .map_err(self.error())
+    }
+
+    fn lookup_many(&self, kmers: &[String]) -> Result<Lookups> {
+        let table = format!("\"{}\"", self.tables.kmers);
+        let mut lookups = Lookups::default();
+        for chunk in kmers.chunks(LOOKUP_CHUNK) {
+            self.conn
+                .prepare_cached(&lookup_sql("kmer, idx", &table, chunk.len()))
+                .and_then(|mut stmt| {
+                    let mut rows = stmt.query(rusqlite::params_from_iter(chunk))?;
+                    while let Some(row) = rows.next()? {
+                        let location = unpack_idx(row.get::<_, i64>(1)? as usize);
+                        lookups.entry(row.get(0)?).or_default().push(location);
+                    }
+                    Ok(())
+                })
+                .map_err(self.error())?;
+        }
+        Ok(lookups)
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        if self.sequences.get().is_none() {
+            let sequences = self.read_sequences().map_err(self.error())?;
+            // another worker may have got there first, they read the same sequences
+            let _ = self.sequences.set(sequences);
+        }
+        Ok(self.sequences
+            .get()
+            .and_then(|sequences| sequences.get(&start.0))
+            .map(|sequence| region(sequence, start.1, len))
+            .unwrap_or_default())
+    }
+
+    fn worker(&self) -> Result<Box<dyn ProteomeIndex + Send>> {
+        Ok(Box::new(PythonIndex {
+            conn: open_read_only(&self.path)?,
+            path: self.path.clone(),
+            tables: self.tables.clone(),
+            sequences: self.sequences.clone(),
+        }))
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        self.conn
+            .prepare_cached(&format!(
+                "SELECT protein_id, protein_name, species, taxon_id, gene, pe_level, sequence_version, gene_priority
+                 FROM \"{}\" WHERE protein_number = ?1",
+                self.tables.metadata
+            ))
+            .and_then(|mut stmt| {
+                stmt.query_row(rusqlite::params![protein_number as i64], |row| {
+                    Ok(ProteinMetadata {
+                        protein_id: text(row.get(0)?),
+                        protein_name: text(row.get(1)?),
+                        species: text(row.get(2)?),
+                        taxon_id: text(row.get(3)?),
+                        gene: text(row.get(4)?),
+                        pe_level: text(row.get(5)?).parse().unwrap_or(0),
+                        sequence_version: text(row.get(6)?).parse().unwrap_or(0),
+                        proteome: self.tables.proteome.clone(),
+                        gene_priority: text(row.get(7)?) == "1",
+                        ..ProteinMetadata::default()
+                    })
+                })
+            })
+            .map_err(self.error())
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        let (first, last) = self.conn
+            .query_row(
+                &format!("SELECT MIN(protein_number), MAX(protein_number) FROM \"{}\"", self.tables.metadata),
+                rusqlite::params![],
+                |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?)),
+            )
+            .map_err(self.error())?;
+        Ok(match (first, last) {
+            (Some(first), Some(last)) => vec![(self.tables.proteome.clone(), first as usize, last as usize)],
+            _ => Vec::new(),
+        })
+    }
+}
+
+pub(crate) fn pack_idx((protein_number, position): Location) -> usize {
+    protein_number * IDX_BASE + position
+}
+
+fn unpack_idx(idx: usize) -> Location {
+    (idx / IDX_BASE, idx % IDX_BASE)
+}
+
+// the Python tool's columns aren't always typed the same, e.g. taxon IDs as numbers
+fn text(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(n) => n.to_string(),
+        Value::Real(n) => n.to_string(),
+        Value::Text(s) => s,
+        Value::Blob(b) => String::from_utf8_lossy(&b).to_string(),
+    }
+}
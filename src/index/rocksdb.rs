@@ -0,0 +1,53 @@
+use super::{KmerStore, PepMatchError, Result};
+use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
+
+// a RocksDB database of a directory
+pub(crate) struct RocksStore {
+    db: DB,
+    path: String,
+}
+
+impl RocksStore {
+    // create the database's directory, which mustn't exist yet
+    pub fn create(path: &str) -> Result<RocksStore> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.set_error_if_exists(true);
+        // preprocessing is one big bulk load
+        options.prepare_for_bulk_load();
+        options.increase_parallelism(std::thread::available_parallelism().map_or(1, usize::from) as i32);
+        let db = DB::open(&options, path).map_err(PepMatchError::store(path))?;
+        Ok(RocksStore { db, path: path.to_string() })
+    }
+
+    pub fn open(path: &str) -> Result<RocksStore> {
+        let db = DB::open_for_read_only(&Options::default(), path, false).map_err(PepMatchError::store(path))?;
+        Ok(RocksStore { db, path: path.to_string() })
+    }
+}
+
+impl KmerStore for RocksStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.db.get(key).map_err(PepMatchError::store(&self.path))
+    }
+
+    fn put(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for (key, value) in entries {
+            batch.put(key, value);
+        }
+        self.db.write(batch).map_err(PepMatchError::store(&self.path))
+    }
+
+    fn iterate(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut entries = Vec::new();
+        for entry in self.db.iterator(IteratorMode::From(prefix, Direction::Forward)) {
+            let (key, value) = entry.map_err(PepMatchError::store(&self.path))?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,58 @@
+use super::{Location, Lookups, ProteinMetadata, ProteomeIndex, ProteomeRange, Result};
+use std::sync::Arc;
+
+// an index that's read-only once built, so the threads matching peptides share it
+// instead of each getting their own
+pub(crate) struct SharedIndex<T>(Arc<T>);
+
+impl<T> SharedIndex<T> {
+    pub fn new(index: T) -> SharedIndex<T> {
+        SharedIndex(Arc::new(index))
+    }
+}
+
+impl<T: ProteomeIndex + Send + Sync + 'static> ProteomeIndex for SharedIndex<T> {
+    fn k(&self) -> usize {
+        self.0.k()
+    }
+
+    fn i_l_equivalent(&self) -> Option<bool> {
+        self.0.i_l_equivalent()
+    }
+
+    fn k_values(&self) -> Vec<usize> {
+        self.0.k_values()
+    }
+
+    fn any_k(&self) -> bool {
+        self.0.any_k()
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        self.0.lookup(kmer)
+    }
+
+    fn lookup_many(&self, kmers: &[String]) -> Result<Lookups> {
+        self.0.lookup_many(kmers)
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        self.0.protein_region(start, len)
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        self.0.protein_metadata(protein_number)
+    }
+
+    fn synonyms(&self, protein_number: usize) -> Result<Vec<(usize, ProteinMetadata)>> {
+        self.0.synonyms(protein_number)
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        self.0.proteomes()
+    }
+
+    fn worker(&self) -> Result<Box<dyn ProteomeIndex + Send>> {
+        Ok(Box::new(SharedIndex(self.0.clone())))
+    }
+}
@@ -0,0 +1,346 @@
+use super::{decode_locations, encode_locations, lookup_sql, open_read_only, Location, Lookups, ProteinMetadata, ProteomeIndex, ProteomeRange, Result, LOOKUP_CHUNK};
+use crate::build_info::{has_table, BuildInfo};
+use crate::error::PepMatchError;
+use crate::preprocessor::Compression;
+use rusqlite::OptionalExtension;
+
+// index stored in a SQLite DB written by the Preprocessor
+pub(crate) struct SqliteIndex {
+    conn: rusqlite::Connection,
+    path: String,
+    k: usize,
+    extra_tables: Vec<(usize, String)>, // (k, table) of the k-mers of each extra k the DB was preprocessed with
+    metadata_sql: String,
+    has_synonyms: bool, // whether identical sequences were deduplicated into a synonyms table
+    shards: Vec<rusqlite::Connection>, // the shard files of a DB preprocessed in shards, their k-mers aren't in the DB
+    compression: Compression,
+}
+
+// residues at the start of a k-mer that pick its shard
+const SHARD_PREFIX: usize = 3;
+
+// the first byte of a compressed DB's location blob, whether the varints after it are compressed
+const VARINTS: u8 = 0;
+const ZSTD_VARINTS: u8 = 1;
+
+// the most bytes of a varint, enough for a u64
+const MAX_VARINT_BYTES: usize = 10;
+
+// the synonyms of an indexed protein, with metadata columns in the order of the metadata table's
+const SYNONYMS_SQL: &str = "SELECT protein_number, protein_id, protein_name, species, taxon_id, gene, pe_level,
+     sequence_version, proteome, gene_priority, 0, 0, db, isoform, 0, 0
+     FROM synonyms WHERE representative = ?1 ORDER BY protein_number";
+
+// the table of the k-mers of an extra k a DB was preprocessed with, next to the kmers
+// table of its own k
+pub(crate) fn kmers_table(k: usize) -> String {
+    format!("kmers_{}", k)
+}
+
+// the file with the k-mers of one of the shards of a DB preprocessed in shards, next to the DB
+pub(crate) fn shard_path(db_path: &str, shard: usize) -> String {
+    format!("{}.shard-{}", db_path, shard)
+}
+
+// the shard a k-mer is kept in of a DB preprocessed in n, k-mers starting with the same
+// residues are kept together
+pub(crate) fn shard_of(kmer: &str, shards: usize) -> usize {
+    let prefix = &kmer.as_bytes()[..kmer.len().min(SHARD_PREFIX)];
+    let hash = prefix.iter().fold(0usize, |hash, residue| hash.wrapping_mul(31).wrapping_add(*residue as usize));
+    hash % shards
+}
+
+// pack a k-mer's sorted locations the way a DB with the compression keeps them, compressed
+// blobs are varints of how far each location is from the one before, zstd-compressed unless
+// that's no smaller, as it isn't for k-mers with few locations
+pub(crate) fn encode_kmer_locations(locations: &[Location], compression: Compression) -> std::io::Result<Vec<u8>> {
+    if compression == Compression::None {
+        return Ok(encode_locations(locations));
+    }
+    let mut varints = Vec::with_capacity(locations.len() * 3);
+    let mut previous = (0, 0);
+    for &(protein_number, position) in locations {
+        // a position counts from the one before in the same protein and from 0 in the next
+        let from = if protein_number == previous.0 { previous.1 } else { 0 };
+        write_varint(&mut varints, protein_number - previous.0);
+        write_varint(&mut varints, position - from);
+        previous = (protein_number, position);
+    }
+    let compressed = zstd::bulk::compress(&varints, 0)?;
+    let (kind, packed) = if compressed.len() < varints.len() { (ZSTD_VARINTS, compressed) } else { (VARINTS, varints) };
+    let mut blob = Vec::with_capacity(packed.len() + 1);
+    blob.push(kind);
+    blob.extend(packed);
+    Ok(blob)
+}
+
+// unpack a k-mer's locations from a blob of a DB with the compression
+pub(crate) fn decode_kmer_locations(blob: &[u8], compression: Compression) -> std::io::Result<Vec<Location>> {
+    if compression == Compression::None {
+        return Ok(decode_locations(blob));
+    }
+    let varints: std::borrow::Cow<[u8]> = match blob.split_first() {
+        Some((&ZSTD_VARINTS, frame)) => zstd::stream::decode_all(frame)?.into(),
+        Some((_, varints)) => varints.into(),
+        None => return Ok(Vec::new()),
+    };
+    let mut bytes = varints.iter();
+    let mut locations = Vec::new();
+    let mut previous = (0, 0);
+    while let Some(protein_step) = read_varint(&mut bytes)? {
+        let position_step = read_varint(&mut bytes)?.ok_or_else(|| invalid_locations("a k-mer's locations are cut short"))?;
+        let from = if protein_step == 0 { previous.1 } else { 0 };
+        previous = (previous.0 + protein_step, from + position_step);
+        locations.push(previous);
+    }
+    Ok(locations)
+}
+
+// LEB128, 7 bits to a byte with the high bit set on all but the last
+fn write_varint(bytes: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        bytes.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+// None at the end of the bytes, longer varints than a u64 takes aren't ones pepmatch wrote
+fn read_varint<'a>(bytes: &mut impl Iterator<Item = &'a u8>) -> std::io::Result<Option<usize>> {
+    let mut n = 0u64;
+    for i in 0..MAX_VARINT_BYTES {
+        let Some(byte) = bytes.next() else {
+            return if i == 0 { Ok(None) } else { Err(invalid_locations("a k-mer's locations are cut short")) };
+        };
+        n |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some(n as usize));
+        }
+    }
+    Err(invalid_locations("a k-mer's locations have a varint of more than 10 bytes"))
+}
+
+fn invalid_locations(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+impl SqliteIndex {
+    // get k from the stored k-mers of a preprocessed DB opened read-only
+    pub fn open(conn: rusqlite::Connection, db_path: &str) -> Result<SqliteIndex> {
+        // the k-mers of a DB preprocessed in shards are in the shard files, its meta table has its k
+        let info = BuildInfo::from_connection(&conn).map_err(PepMatchError::database(db_path))?;
+        let shards = (0..info.shards).map(|shard| open_read_only(&shard_path(db_path, shard))).collect::<Result<Vec<_>>>()?;
+        let k = match info.k {
+            Some(k) if !shards.is_empty() => Some(k as i64),
+            _ => conn
+                .query_row("SELECT LENGTH(kmer) FROM kmers LIMIT 1", rusqlite::params![], |row| row.get::<_, i64>(0))
+                .optional()
+                .map_err(PepMatchError::database(db_path))?,
+        }
+        .ok_or_else(|| PepMatchError::invalid(format!("{}: no k-mers, is it a preprocessed DB?", db_path)))?;
+
+        let extra_tables: Vec<String> = shards
+            .first()
+            .unwrap_or(&conn)
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name GLOB 'kmers_[0-9]*' ORDER BY name")
+            .and_then(|mut stmt| stmt.query_map(rusqlite::params![], |row| row.get::<_, String>(0))?.collect())
+            .map_err(PepMatchError::database(db_path))?;
+        let mut extra_tables: Vec<(usize, String)> = extra_tables
+            .into_iter()
+            .filter_map(|table| Some((table.strip_prefix("kmers_")?.parse().ok()?, table)))
+            .collect();
+        extra_tables.sort();
+
+        // DBs preprocessed before these columns were added don't have them, they're all 0 or empty for those
+        let mut optional_columns = Vec::new();
+        for (column, default) in
+            [
+                ("gene_priority", "0"),
+                ("frame", "0"),
+                ("source_length", "0"),
+                ("db", "''"),
+                ("isoform", "0"),
+                ("length", "0"),
+                ("molecular_weight", "0"),
+            ]
+        {
+            let exists = conn
+                .prepare("SELECT 1 FROM pragma_table_info('metadata') WHERE name = ?1")
+                .and_then(|mut stmt| stmt.exists(rusqlite::params![column]))
+                .map_err(PepMatchError::database(db_path))?;
+            optional_columns.push(if exists { column } else { default });
+        }
+        // DBs preprocessed before protein_id was a TEXT column have numeric IDs stored as integers
+        let metadata_sql = format!(
+            "SELECT CAST(protein_id AS TEXT), protein_name, species, taxon_id, gene, pe_level, sequence_version, proteome, {}
+             FROM metadata WHERE protein_number = ?1",
+            optional_columns.join(", ")
+        );
+        let has_synonyms = has_table(&conn, "synonyms").map_err(PepMatchError::database(db_path))?;
+        Ok(SqliteIndex { conn, path: db_path.to_string(), k: k as usize, extra_tables, metadata_sql, has_synonyms, shards, compression: info.compression })
+    }
+
+    // the table a k-mer's locations are in going by its length, None for a k the DB has no k-mers of
+    fn table(&self, k: usize) -> Option<&str> {
+        if k == self.k {
+            return Some("kmers");
+        }
+        self.extra_tables.iter().find(|(extra_k, _)| *extra_k == k).map(|(_, table)| table.as_str())
+    }
+
+    // the shard of the DB a k-mer's locations are in, 0 for a DB that isn't sharded
+    fn shard(&self, kmer: &str) -> usize {
+        if self.shards.is_empty() { 0 } else { shard_of(kmer, self.shards.len()) }
+    }
+
+    // the connection to the shard file of a shard, or to the DB itself if it isn't sharded
+    fn kmer_conn(&self, shard: usize) -> &rusqlite::Connection {
+        self.shards.get(shard).unwrap_or(&self.conn)
+    }
+
+    fn decode(&self, blob: &[u8]) -> Result<Vec<Location>> {
+        decode_kmer_locations(blob, self.compression).map_err(PepMatchError::io(&self.path))
+    }
+
+    fn error(&self) -> impl FnOnce(rusqlite::Error) -> PepMatchError + '_ {
+        PepMatchError::database(&self.path)
+    }
+}
+
+impl ProteomeIndex for SqliteIndex {
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn k_values(&self) -> Vec<usize> {
+        std::iter::once(self.k).chain(self.extra_tables.iter().map(|(k, _)| *k)).collect()
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        let Some(table) = self.table(kmer.len()) else {
+            return Ok(Vec::new());
+        };
+        let locations: Option<Vec<u8>> = self
+            .kmer_conn(self.shard(kmer))
+            .prepare_cached(&format!("SELECT locations FROM {} WHERE kmer = ?1", table))
+            .and_then(|mut stmt| stmt.query_row(rusqlite::params![kmer], |row| row.get(0)).optional())
+            .map_err(self.error())?;
+        locations.map(|blob| self.decode(&blob)).transpose().map(Option::unwrap_or_default)
+    }
+
+    fn lookup_many(&self, kmers: &[String]) -> Result<Lookups> {
+        // k-mers of each k are looked up in their own table, in their own shard of a sharded DB
+        let mut by_table: Vec<(&str, usize, Vec<&String>)> = Vec::new();
+        for kmer in kmers {
+            let Some(table) = self.table(kmer.len()) else {
+                continue;
+            };
+            let shard = self.shard(kmer);
+            match by_table.iter_mut().find(|(known, known_shard, _)| *known == table && *known_shard == shard) {
+                Some((_, _, kmers)) => kmers.push(kmer),
+                None => by_table.push((table, shard, vec![kmer])),
+            }
+        }
+
+        let mut blobs: Vec<(String, Vec<u8>)> = Vec::new();
+        for (table, shard, kmers) in by_table {
+            for chunk in kmers.chunks(LOOKUP_CHUNK) {
+                self.kmer_conn(shard)
+                    .prepare_cached(&lookup_sql("kmer, locations", table, chunk.len()))
+                    .and_then(|mut stmt| {
+                        let mut rows = stmt.query(rusqlite::params_from_iter(chunk))?;
+                        while let Some(row) = rows.next()? {
+                            blobs.push((row.get(0)?, row.get(1)?));
+                        }
+                        Ok(())
+                    })
+                    .map_err(self.error())?;
+            }
+        }
+        blobs.into_iter().map(|(kmer, blob)| Ok((kmer, self.decode(&blob)?))).collect()
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        let region: Option<String> = self.conn
+            .prepare_cached("SELECT SUBSTR(sequence, ?2, ?3) FROM proteins WHERE protein_number = ?1")
+            .and_then(|mut stmt| {
+                stmt.query_row(
+                    rusqlite::params![start.0 as i64, (start.1 + 1) as i64, len as i64],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .map_err(self.error())?;
+        Ok(region.unwrap_or_default())
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        self.conn
+            .prepare_cached(&self.metadata_sql)
+            .and_then(|mut stmt| stmt.query_row(rusqlite::params![protein_number as i64], |row| metadata(row, 0)))
+            .map_err(self.error())
+    }
+
+    fn synonyms(&self, protein_number: usize) -> Result<Vec<(usize, ProteinMetadata)>> {
+        if !self.has_synonyms {
+            return Ok(Vec::new());
+        }
+        self.conn
+            .prepare_cached(SYNONYMS_SQL)
+            .and_then(|mut stmt| {
+                stmt.query_map(rusqlite::params![protein_number as i64], |row| {
+                    Ok((row.get::<_, i64>(0)? as usize, metadata(row, 1)?))
+                })?
+                .collect()
+            })
+            .map_err(self.error())
+    }
+
+    fn worker(&self) -> Result<Box<dyn ProteomeIndex + Send>> {
+        let conn = open_read_only(&self.path)?;
+        let shards = (0..self.shards.len()).map(|shard| open_read_only(&shard_path(&self.path, shard))).collect::<Result<_>>()?;
+        Ok(Box::new(SqliteIndex {
+            conn,
+            path: self.path.clone(),
+            k: self.k,
+            extra_tables: self.extra_tables.clone(),
+            metadata_sql: self.metadata_sql.clone(),
+            has_synonyms: self.has_synonyms,
+            shards,
+            compression: self.compression,
+        }))
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        self.conn
+            .prepare("SELECT proteome, first_protein, last_protein FROM proteomes ORDER BY first_protein")
+            .and_then(|mut stmt| {
+                stmt.query_map(rusqlite::params![], |row| {
+                        Ok((row.get(0)?, row.get::<_, i64>(1)? as usize, row.get::<_, i64>(2)? as usize))
+                    })?
+                    .collect()
+            })
+            .map_err(self.error())
+    }
+}
+
+// a protein's metadata from the columns of a row starting at first, in the order of the metadata table's
+fn metadata(row: &rusqlite::Row, first: usize) -> rusqlite::Result<ProteinMetadata> {
+    Ok(ProteinMetadata {
+        protein_id: row.get(first)?,
+        protein_name: row.get(first + 1)?,
+        species: row.get(first + 2)?,
+        taxon_id: row.get(first + 3)?,
+        gene: row.get(first + 4)?,
+        pe_level: row.get::<_, i64>(first + 5)? as usize,
+        sequence_version: row.get::<_, i64>(first + 6)? as usize,
+        proteome: row.get(first + 7)?,
+        gene_priority: row.get(first + 8)?,
+        frame: row.get(first + 9)?,
+        source_length: row.get::<_, i64>(first + 10)? as usize,
+        db: row.get(first + 11)?,
+        isoform: row.get::<_, i64>(first + 12)? as usize,
+        length: row.get::<_, i64>(first + 13)? as usize,
+        molecular_weight: row.get::<_, i64>(first + 14)? as usize,
+    })
+}
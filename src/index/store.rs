@@ -0,0 +1,150 @@
+use super::binary::{number, parse_metadata, text};
+use super::{decode_locations, region, Location, PepMatchError, ProteinMetadata, ProteomeIndex, ProteomeRange, Result};
+use fxhash::FxHashMap;
+
+// a sorted key-value store an index is kept in, for backends like RocksDB and LMDB that
+// take heavy writes better than SQLite
+pub(crate) trait KmerStore: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    // write a batch of entries at once
+    #[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+    fn put(&self, entries: &[(Vec<u8>, Vec<u8>)]) -> Result<()>;
+
+    // the entries with keys starting with a prefix, in key order
+    fn iterate(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+}
+
+// keys of the store, every k-mer has an entry per batch of proteins it's in so batches
+// are written without reading back what's already there:
+//   meta                      JSON of k, the I/L flag and the proteomes, written last
+//   k<kmer><batch u32>        u32 (protein number, position) pairs
+//   p<protein number u64>     JSON of the protein's metadata
+//   s<protein number u64>     the protein's residues
+pub(crate) const META_KEY: &[u8] = b"meta";
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+pub(crate) fn kmer_key(kmer: &str, batch: u32) -> Vec<u8> {
+    [b"k".as_slice(), kmer.as_bytes(), &batch.to_be_bytes()].concat()
+}
+
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+pub(crate) fn metadata_key(protein_number: usize) -> Vec<u8> {
+    [b"p".as_slice(), &(protein_number as u64).to_be_bytes()].concat()
+}
+
+pub(crate) fn sequence_key(protein_number: usize) -> Vec<u8> {
+    [b"s".as_slice(), &(protein_number as u64).to_be_bytes()].concat()
+}
+
+// which kind of store a path is, going by the files the stores keep in their directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StoreKind {
+    Rocksdb,
+    Lmdb,
+}
+
+impl StoreKind {
+    pub fn detect(path: &str) -> Option<StoreKind> {
+        let path = std::path::Path::new(path);
+        if !path.is_dir() {
+            None
+        } else if path.join("data.mdb").exists() {
+            Some(StoreKind::Lmdb)
+        } else if path.join("CURRENT").exists() {
+            Some(StoreKind::Rocksdb)
+        } else {
+            None
+        }
+    }
+}
+
+// open the store at a path, pepmatch has to be built with the store's feature
+fn open_store(path: &str, kind: StoreKind) -> Result<Box<dyn KmerStore>> {
+    match kind {
+        #[cfg(feature = "rocksdb")]
+        StoreKind::Rocksdb => Ok(Box::new(super::rocksdb::RocksStore::open(path)?)),
+        #[cfg(not(feature = "rocksdb"))]
+        StoreKind::Rocksdb => Err(without_feature(path, "RocksDB", "rocksdb")),
+        #[cfg(feature = "lmdb")]
+        StoreKind::Lmdb => Ok(Box::new(super::lmdb::LmdbStore::open(path)?)),
+        #[cfg(not(feature = "lmdb"))]
+        StoreKind::Lmdb => Err(without_feature(path, "LMDB", "lmdb")),
+    }
+}
+
+#[cfg(not(all(feature = "rocksdb", feature = "lmdb")))]
+fn without_feature(path: &str, store: &str, feature: &str) -> PepMatchError {
+    PepMatchError::invalid(format!("{}: is an index kept in {}, and pepmatch was built without the {} feature", path, store, feature))
+}
+
+// an index searched in a key-value store, the metadata is read in on open and the
+// k-mers and sequences are looked up as they're needed
+pub(crate) struct StoreIndex {
+    store: Box<dyn KmerStore>,
+    k: usize,
+    i_l_equivalent: Option<bool>,
+    metadata: FxHashMap<usize, ProteinMetadata>,
+    proteomes: Vec<ProteomeRange>,
+}
+
+impl StoreIndex {
+    pub fn open(path: &str, kind: StoreKind) -> Result<StoreIndex> {
+        let invalid = |reason: &str| PepMatchError::invalid(format!("{}: {}", path, reason));
+        let store = open_store(path, kind)?;
+        let meta = store.get(META_KEY)?.ok_or_else(|| invalid("preprocessing it didn't finish, rebuild it with --overwrite"))?;
+        let meta: serde_json::Value =
+            serde_json::from_slice(&meta).map_err(|e| invalid(&format!("invalid metadata: {}", e)))?;
+
+        let proteomes = meta["proteomes"]
+            .as_array()
+            .map(|proteomes| {
+                proteomes
+                    .iter()
+                    .map(|proteome| (text(&proteome[0]), number(&proteome[1]), number(&proteome[2])))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut metadata = FxHashMap::default();
+        for (_, protein) in store.iterate(b"p")? {
+            let protein: serde_json::Value =
+                serde_json::from_slice(&protein).map_err(|e| invalid(&format!("invalid metadata: {}", e)))?;
+            let (protein_number, protein) = parse_metadata(&protein);
+            metadata.insert(protein_number, protein);
+        }
+        let i_l_equivalent = meta["i_l_equivalent"].as_bool();
+        Ok(StoreIndex { store, k: number(&meta["k"]), i_l_equivalent, metadata, proteomes })
+    }
+}
+
+impl ProteomeIndex for StoreIndex {
+    fn k(&self) -> usize {
+        self.k
+    }
+
+    fn i_l_equivalent(&self) -> Option<bool> {
+        self.i_l_equivalent
+    }
+
+    fn lookup(&self, kmer: &str) -> Result<Vec<Location>> {
+        let prefix = [b"k".as_slice(), kmer.as_bytes()].concat();
+        let mut locations = Vec::new();
+        for (_, value) in self.store.iterate(&prefix)? {
+            locations.extend(decode_locations(&value));
+        }
+        Ok(locations)
+    }
+
+    fn protein_region(&self, start: Location, len: usize) -> Result<String> {
+        let sequence = self.store.get(&sequence_key(start.0))?.unwrap_or_default();
+        Ok(region(&String::from_utf8_lossy(&sequence), start.1, len))
+    }
+
+    fn protein_metadata(&self, protein_number: usize) -> Result<ProteinMetadata> {
+        Ok(self.metadata.get(&protein_number).cloned().unwrap_or_default())
+    }
+
+    fn proteomes(&self) -> Result<Vec<ProteomeRange>> {
+        Ok(self.proteomes.clone())
+    }
+}
@@ -0,0 +1,150 @@
+use crate::build_info::BuildInfo;
+use crate::error::{PepMatchError, Result};
+use crate::index::{decode_kmer_locations, open_read_only, shard_path, PythonTables, IDX_BASE};
+use crate::preprocessor::Compression;
+
+// summary of a preprocessed proteome DB
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub k: usize,
+    pub proteins: usize,
+    pub kmers: usize,     // distinct k-mers
+    pub locations: usize, // k-mer occurrences across the proteome
+    pub residues: usize,  // total length of the proteins
+    pub proteomes: Vec<(String, usize)>, // name and protein count of each proteome
+    pub species: Vec<(String, usize)>,   // protein count of each species, most proteins first
+    pub db_size: u64, // bytes, with its shard files
+    pub built_at: Option<u64>, // seconds since the Unix epoch, None for DBs without a meta table
+}
+
+// count what's stored in a preprocessed DB, in either layout
+pub fn summarize(db_path: &str) -> Result<Summary> {
+    let conn = open_read_only(db_path)?;
+    let db_size = std::fs::metadata(db_path).map_err(PepMatchError::io(db_path))?.len();
+    let info = BuildInfo::from_connection(&conn).map_err(PepMatchError::database(db_path))?;
+    let built_at = info.built_at;
+
+    if let Some(tables) = PythonTables::find(&conn).map_err(PepMatchError::database(db_path))? {
+        let summary = summarize_python(&conn, &tables).map_err(PepMatchError::database(db_path))?;
+        return Ok(Summary { db_size, built_at, ..summary });
+    }
+
+    let count = |sql: &str| {
+        conn.query_row(sql, rusqlite::params![], |row| row.get::<_, i64>(0))
+            .map(|count| count as usize)
+            .map_err(PepMatchError::database(db_path))
+    };
+
+    // the k-mers of a DB preprocessed in shards are in the shard files next to it
+    let mut k = count("SELECT COALESCE((SELECT LENGTH(kmer) FROM kmers LIMIT 1), 0)")?;
+    let (mut kmers, mut locations) = count_kmers(&conn, db_path, info.compression)?;
+    let mut db_size = db_size;
+    for shard in 0..info.shards {
+        let path = shard_path(db_path, shard);
+        let (shard_kmers, shard_locations) = count_kmers(&open_read_only(&path)?, &path, info.compression)?;
+        k = info.k.unwrap_or(k);
+        kmers += shard_kmers;
+        locations += shard_locations;
+        db_size += std::fs::metadata(&path).map_err(PepMatchError::io(&path))?.len();
+    }
+
+    Ok(Summary {
+        k,
+        proteins: count("SELECT COUNT(*) FROM metadata")?,
+        kmers,
+        locations,
+        residues: count("SELECT COALESCE(SUM(LENGTH(sequence)), 0) FROM proteins")?,
+        proteomes: proteomes(&conn).map_err(PepMatchError::database(db_path))?,
+        species: species(&conn, "metadata").map_err(PepMatchError::database(db_path))?,
+        db_size,
+        built_at,
+    })
+}
+
+// the k-mers in a DB's or shard's kmers table and their locations, a compressed DB's
+// locations are unpacked to be counted
+fn count_kmers(conn: &rusqlite::Connection, path: &str, compression: Compression) -> Result<(usize, usize)> {
+    let count = |sql: &str| {
+        conn.query_row(sql, rusqlite::params![], |row| row.get::<_, i64>(0))
+            .map(|count| count as usize)
+            .map_err(PepMatchError::database(path))
+    };
+    let kmers = count("SELECT COUNT(*) FROM kmers")?;
+    if compression == Compression::None {
+        return Ok((kmers, count("SELECT COALESCE(SUM(LENGTH(locations)), 0) / 8 FROM kmers")?));
+    }
+    let mut stmt = conn.prepare("SELECT locations FROM kmers").map_err(PepMatchError::database(path))?;
+    let mut rows = stmt.query(rusqlite::params![]).map_err(PepMatchError::database(path))?;
+    let mut locations = 0;
+    while let Some(row) = rows.next().map_err(PepMatchError::database(path))? {
+        let blob: Vec<u8> = row.get(0).map_err(PepMatchError::database(path))?;
+        locations += decode_kmer_locations(&blob, compression).map_err(PepMatchError::io(path))?.len();
+    }
+    Ok((kmers, locations))
+}
+
+// the Python PEPMatch's layout has a row per k-mer location and a single proteome,
+// each protein's length is where its last k-mer ends
+fn summarize_python(conn: &rusqlite::Connection, tables: &PythonTables) -> rusqlite::Result<Summary> {
+    let count = |sql: String| conn.query_row(&sql, rusqlite::params![], |row| row.get::<_, i64>(0)).map(|count| count as usize);
+
+    let proteins = count(format!("SELECT COUNT(*) FROM \"{}\"", tables.metadata))?;
+    Ok(Summary {
+        k: tables.k,
+        proteins,
+        kmers: count(format!("SELECT COUNT(DISTINCT kmer) FROM \"{}\"", tables.kmers))?,
+        locations: count(format!("SELECT COUNT(*) FROM \"{}\"", tables.kmers))?,
+        residues: count(format!(
+            "SELECT COALESCE(SUM(last + {k}), 0) FROM (SELECT MAX(idx % {base}) AS last FROM \"{table}\" GROUP BY idx / {base})",
+            k = tables.k,
+            base = IDX_BASE,
+            table = tables.kmers
+        ))?,
+        proteomes: vec![(tables.proteome.clone(), proteins)],
+        species: species(conn, &format!("\"{}\"", tables.metadata))?,
+        db_size: 0,
+        built_at: None,
+    })
+}
+
+fn proteomes(conn: &rusqlite::Connection) -> rusqlite::Result<Vec<(String, usize)>> {
+    let mut stmt = conn
+        .prepare("SELECT proteome, last_protein - first_protein + 1 FROM proteomes ORDER BY first_protein")?;
+    let proteomes = stmt.query_map(rusqlite::params![], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?;
+    proteomes.collect()
+}
+
+fn species(conn: &rusqlite::Connection, metadata: &str) -> rusqlite::Result<Vec<(String, usize)>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT species, COUNT(*) FROM {} GROUP BY species ORDER BY COUNT(*) DESC, species",
+        metadata
+    ))?;
+    let species = stmt.query_map(rusqlite::params![], |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)))?;
+    species.collect()
+}
+
+// a Unix timestamp as a UTC date and time, e.g. 2024-03-01 12:30:00 UTC
+pub fn format_timestamp(seconds: u64) -> String {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // days since the epoch to a civil date, from Howard Hinnant's date algorithms
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
@@ -0,0 +1,60 @@
+pub mod accessions;
+pub mod bench;
+#[cfg(feature = "sqlite")]
+pub mod build_info;
+#[cfg(feature = "sqlite")]
+pub mod cache;
+pub mod config;
+pub mod coverage;
+pub mod decoy;
+pub mod digest;
+pub mod discontinuous;
+pub mod equivalence;
+mod error;
+#[cfg(feature = "sqlite")]
+pub mod export;
+pub mod extract;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod fetch;
+pub mod ffi;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod hamming;
+pub mod header;
+pub mod idmapping;
+mod index;
+#[cfg(feature = "sqlite")]
+pub mod inspect;
+mod matcher;
+pub mod mature;
+pub mod motif;
+pub mod output;
+#[cfg(feature = "async-io")]
+pub mod pipeline;
+mod preprocessor;
+mod progress;
+mod proteome;
+mod query;
+mod request;
+pub mod ranking;
+mod scan;
+#[cfg(any(feature = "sqlite", feature = "duckdb"))]
+mod schema;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod similarity;
+pub mod statistics;
+pub mod summary;
+mod translate;
+pub mod taxonomy;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use error::{PepMatchError, Result};
+pub use matcher::{auto_k, Match, Matcher};
+pub use preprocessor::{default_db_path, proteome_name, Compression, DbLayout, DryRun, IndexBackend, Preprocessor};
+pub use proteome::ProteinFilter;
+pub use query::{read_peptides, read_query, InputFormat, Query, QueryReader};
+pub use request::{match_request, MatchHit, MatchRequest, MatchResponse};
+pub use scan::scan_proteome;
@@ -1,201 +1,1874 @@
-use bio::io::fasta;
-use clap::{App, Arg};
-use regex::Regex;
-use rusqlite;
-
-
-// read in proteome FASTA file and return a vector of sequences and metadata from header
-fn get_data_from_proteome(filename: &str) -> (Vec<(String, usize)>, Vec<(String, String, String, String, String, String, usize, usize)>) {
-    let mut i: usize = 1; // protein number
-
-    let mut seqs = Vec::new();
-    let mut metadata = Vec::new();
-    let reader = fasta::Reader::from_file(filename).unwrap();
-
-    // regexes to parse the header
-    let regexes = [
-        ("protein_id", Regex::new(r"\|([^|]*)\|").unwrap()),           // between | and |
-        ("protein_name", Regex::new(r"\s(.+?)OS").unwrap()),           // between first space and OS=
-        ("species", Regex::new(r"OS=(.+?)OX").unwrap()),               // between OS= and OX (species can have spaces)
-        ("taxon_id", Regex::new(r"OX=(\d+?)\s").unwrap()),             // between OX= and space
-        ("gene", Regex::new(r"GN=(.+?)\s").unwrap()),                  // between GN= and space
-        ("pe_level", Regex::new(r"PE=(\d+?)\s").unwrap()),             // between PE= and space
-        ("sequence_version", Regex::new(r"SV=(\d+?)(\s|$)").unwrap()), // between SV= and space or end of line
-    ];
-
-    for result in reader.records() {
-        let record = result.unwrap();
-        let seq_str = std::str::from_utf8(record.seq()).unwrap();
-        seqs.push((seq_str.to_string(), i)); // store the sequence
-        
-        // concatenate the id and description to get the full header
-        let header = format!("{} {}", record.id(), record.desc().unwrap_or(""));
-
-        // loop through the regexes and parse the header
-        let mut metadata_entry: Vec<String> = vec![i.to_string()];
-        for (key, regex) in &regexes {
-            let match_option = regex.captures(&header);
-            
-            if let Some(capture) = match_option {
-                metadata_entry.push(capture.get(1).unwrap().as_str().to_string());
-            } else {
-                if key == &"protein_id" {
-                    metadata_entry.push(record.id().to_string());
-                } else if ["pe_level", "sequence_version"].contains(key) {
-                    metadata_entry.push("0".to_string());
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use pepmatch::accessions::{read_accessions, source_filters, AccessionFilter};
+use pepmatch::bench::{Benchmark, Dataset};
+use pepmatch::build_info::BuildInfo;
+use pepmatch::cache::ResultCache;
+use pepmatch::config::{Config, Setting, CONFIG_FILE};
+use pepmatch::decoy::{self, DecoyMode};
+use pepmatch::digest::{digest_proteome, Digestion, Protease};
+use pepmatch::discontinuous::{match_discontinuous, DiscontinuousEpitope};
+use pepmatch::equivalence::EquivalenceClasses;
+use pepmatch::export::{export, ExportFormat, ExportTable};
+use pepmatch::extract::extract_proteins;
+use pepmatch::fetch::Fetcher;
+use pepmatch::header::{CustomHeader, HeaderFormat};
+use pepmatch::idmapping::IdMapping;
+use pepmatch::mature::MatureProteins;
+use pepmatch::motif::{scan_motifs, Motif};
+use pepmatch::output::{self, Columns, Coordinates, Format, MatchWriter};
+use pepmatch::ranking::{sort_by_keys, SortKey};
+#[cfg(feature = "server")]
+use pepmatch::server::{read_api_keys, ServeOptions};
+use pepmatch::similarity::ScoringMatrix;
+use pepmatch::statistics;
+use pepmatch::summary::Summary;
+use pepmatch::taxonomy::TaxonFilter;
+use pepmatch::validate::{check_carbamidomethylated_peptides, check_peptides, PeptideCheck, PeptideStatus};
+use pepmatch::{
+    auto_k, inspect, proteome_name, read_peptides, scan_proteome, Compression, DbLayout, IndexBackend, InputFormat, Match, Matcher, PepMatchError, Preprocessor,
+    ProteinFilter, Query, QueryReader, Result,
+};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::str::FromStr;
+use tracing::{error, info, warn, Level};
+use tracing_subscriber::fmt::format::FmtSpan;
+
+// chunks of the query searched at once with --async-io, each on every thread
+#[cfg(feature = "async-io")]
+const CHUNKS_SEARCHED_AT_ONCE: usize = 4;
+
+#[derive(Parser)]
+#[clap(name = "pepmatch", about = "Search peptides against a proteome.")]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+
+    /// Don't show progress bars
+    #[clap(long, global = true)]
+    quiet: bool,
+
+    /// Log how long each step and batch takes, repeat to log every protein too
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Log as text or as JSON lines
+    #[clap(long, value_enum, global = true, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// TOML file of defaults for the args not given, pepmatch.toml in the working directory is read if there is one
+    #[clap(long, global = true, value_name = "FILE")]
+    config: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Preprocess proteome.
+    Preprocess(Box<PreprocessArgs>),
+    /// Search peptides against the preprocessed proteome.
+    Match(Box<MatchArgs>),
+    /// Summarize a preprocessed proteome DB.
+    Inspect(InspectArgs),
+    /// Report the residue frequencies, protein lengths and k-mers of a preprocessed proteome.
+    Stats(StatsArgs),
+    /// Download a UniProt proteome.
+    Fetch(FetchArgs),
+    /// Find PROSITE motifs in the preprocessed proteome.
+    Motif(MotifArgs),
+    /// Generate a decoy proteome of reversed or shuffled sequences.
+    Decoy(DecoyArgs),
+    /// Cut the proteins of a proteome into overlapping peptides, e.g. for peptide arrays, or digest them with a protease.
+    Digest(DigestArgs),
+    /// Write the proteins with hits in a results file as FASTA.
+    Extract(ExtractArgs),
+    /// Dump the metadata, k-mers or proteins of a preprocessed proteome.
+    Export(ExportArgs),
+    /// Serve searches of a preprocessed proteome over an HTTP JSON API.
+    ///
+    /// Requests are searched on --threads threads that each take one at a time, not on the tokio runtime of
+    /// match --async-io, so clients beyond that many wait for a thread.
+    #[cfg(feature = "server")]
+    Serve(ServeArgs),
+    /// Serve streaming searches of a preprocessed proteome over gRPC.
+    #[cfg(feature = "grpc")]
+    Grpc(GrpcArgs),
+    /// Time a search of the Python PEPMatch's benchmarks and check its recall.
+    Bench(BenchArgs),
+}
+
+#[derive(Args)]
+struct PreprocessArgs {
+    /// Input FASTA file, repeat to put several proteomes in one DB, named after
+    /// their files unless given as NAME=FILE
+    #[clap(short, long, value_name = "[NAME=]FILE", required = true, multiple_occurrences = true)]
+    proteome: Vec<String>,
+
+    /// Value of k for k-mers, or auto to pick it from the peptide lengths
+    #[clap(short, long = "k_value", value_name = "K")]
+    k: KValue,
+
+    /// Expected query peptide lengths for -k auto, e.g. 9-15
+    #[clap(long, value_name = "MIN[-MAX]", required_if_eq("k", "auto"))]
+    peptide_lengths: Option<String>,
+
+    /// Most mismatches that will be searched for with -k auto
+    #[clap(short, long, value_name = "N", default_value_t = 0)]
+    max_mismatches: usize,
+
+    /// Also index k-mers of these k in the same SQLite DB, e.g. 5,12, each peptide is then
+    /// looked up with the largest k that still finds all its hits
+    #[clap(long, value_name = "K", use_value_delimiter = true)]
+    extra_k: Vec<usize>,
+
+    /// Output DB file, defaults to <proteome>_k<k>.db
+    #[clap(short, long, value_name = "FILE")]
+    db: Option<String>,
+
+    /// Number of threads to k-merize with, 0 uses all cores
+    #[clap(short, long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+
+    /// Treat I and L as the same residue when indexing
+    #[clap(long)]
+    treat_i_l_equivalent: bool,
+
+    /// Classes of residues to treat as the same residue when indexing, like ILJ;ND;QE, which only native SQLite DBs record
+    #[clap(long, value_name = "CLASSES", default_value = "")]
+    equivalence_classes: EquivalenceClasses,
+
+    /// UniProt gene priority proteome FASTA, its proteins are preferred in best match mode
+    #[clap(long, value_name = "FILE")]
+    gene_priority: Option<String>,
+
+    /// The proteome files are nucleotide FASTA to translate in all six reading frames
+    #[clap(long)]
+    translate: bool,
+
+    /// Index each sequence found more than once in a proteome under its best evidenced protein,
+    /// keeping the others as synonyms that match --expand-synonyms reports too (native SQLite only)
+    #[clap(long, conflicts_with = "translate")]
+    dedupe_sequences: bool,
+
+    /// Split the k-mers across N SQLite files next to the DB, <db>.shard-0 and on, by their
+    /// first residues, for proteomes too large for one file
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    shards: usize,
+
+    /// Size of SQLite's page cache while writing the DB
+    #[clap(long, value_name = "MB", default_value_t = 256)]
+    sqlite_cache_mb: usize,
+
+    /// SQLite page size of the new DB in bytes
+    #[clap(long, value_name = "BYTES", default_value_t = 16384)]
+    sqlite_page_size: usize,
+
+    /// Write the DB in WAL journal mode, switched back once preprocessing is done
+    #[clap(long)]
+    wal: bool,
+
+    /// DB table layout, python writes the Python PEPMatch's tables (one proteome only)
+    #[clap(long, value_name = "native|python", default_value = "native")]
+    layout: DbLayout,
+
+    /// How each k-mer's locations are stored, zstd takes half the space or less (native SQLite only)
+    #[clap(long, value_name = "none|zstd", default_value = "none")]
+    compression: Compression,
+
+    /// Write a SQLite DB, a memory-mapped binary index of sorted k-mers for faster searches, or a
+    /// DuckDB file or RocksDB or LMDB directory when built with the duckdb, rocksdb or lmdb feature
+    #[clap(long, value_name = "sqlite|bin|duckdb|rocksdb|lmdb", default_value = "sqlite")]
+    backend: IndexBackend,
+
+    /// Continue preprocessing into a DB an interrupted run left partly written
+    #[clap(long)]
+    resume: bool,
+
+    /// Replace the DB if it already exists
+    #[clap(long, conflicts_with = "resume")]
+    overwrite: bool,
+
+    /// Rebuild the DB if it already exists, unless it was built the same way from the
+    /// proteome files as they are now
+    #[clap(long, conflicts_with_all = &["resume", "overwrite"])]
+    auto_rebuild: bool,
+
+    /// Read and check the proteome files and report the proteins, k-mers and estimated DB size
+    /// preprocessing them would write, without writing anything
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Leave out proteins that don't pass these filters
+    #[clap(flatten)]
+    protein_filter: ProteinFilterArgs,
+
+    #[clap(flatten)]
+    header: HeaderArgs,
+}
+
+// a fixed k or one picked from the query peptides
+#[derive(Clone, Copy)]
+enum KValue {
+    Auto,
+    Fixed(usize),
+}
+
+impl FromStr for KValue {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<KValue, String> {
+        match s {
+            "auto" => Ok(KValue::Auto),
+            _ => match s.parse() {
+                Ok(k) if k > 0 => Ok(KValue::Fixed(k)),
+                _ => Err(format!("k must be a positive number or auto: {}", s)),
+            },
+        }
+    }
+}
+
+impl KValue {
+    // the k to use for peptides as short as min_len, auto picks the largest that
+    // still finds every hit with up to max_mismatches mismatches
+    fn resolve(self, min_len: usize, max_mismatches: usize) -> usize {
+        match self {
+            KValue::Fixed(k) => k,
+            KValue::Auto => {
+                let k = auto_k(min_len, max_mismatches);
+                info!("Using k = {} for peptides of length {} and up with {} mismatches", k, min_len, max_mismatches);
+                k
+            }
+        }
+    }
+}
+
+// (name, path) of each proteome given as FILE or NAME=FILE
+fn named_proteomes(proteomes: &[String]) -> Result<Vec<(String, String)>> {
+    let mut named: Vec<(String, String)> = Vec::new();
+    for proteome in proteomes {
+        let (name, path) = match proteome.split_once('=') {
+            Some((name, path)) => (name.to_string(), path.to_string()),
+            None => (proteome_name(proteome), proteome.clone()),
+        };
+        if named.iter().any(|(other, _)| *other == name) {
+            return Err(PepMatchError::Invalid(format!(
+                "more than one proteome named {}, name them with NAME=FILE",
+                name
+            )));
+        }
+        named.push((name, path));
+    }
+    Ok(named)
+}
+
+// the shortest length in a range like 9-15 or a single length
+fn min_peptide_length(lengths: &str) -> Result<usize> {
+    let min = lengths.split('-').next().unwrap_or_default().trim();
+    min.parse()
+        .map_err(|_| PepMatchError::Invalid(format!("invalid peptide lengths: {}", lengths)))
+}
+
+// the query peptides as they're searched, uppercased and without gaps, skipping the invalid
+// ones or failing on them, a report of every peptide is written out if asked for
+fn validate_peptides(args: &MatchArgs, query: Query, report: Option<&mut ValidationReport>) -> Result<Query> {
+    let checks = if args.chemical_equivalence.contains(&ChemicalEquivalence::Carbamidomethyl) {
+        check_carbamidomethylated_peptides(&query.peptides)
+    } else {
+        check_peptides(&query.peptides)
+    };
+    if let (Some(report), Some(path)) = (report, &args.validation_report) {
+        write_validation_report(report, &checks).map_err(|source| PepMatchError::Io { path: path.clone(), source })?;
+    }
+    let normalized = checks.iter().filter(|check| check.status == PeptideStatus::Normalized).count();
+    if normalized > 0 {
+        info!("Normalized {} peptides to uppercase residues without gaps or stops", normalized);
+    }
+    let invalid: Vec<(&str, &str)> = checks
+        .iter()
+        .filter_map(|check| match &check.status {
+            PeptideStatus::Invalid(problem) => Some((check.input.as_str(), problem.as_str())),
+            _ => None,
+        })
+        .collect();
+    if let Some((peptide, problem)) = invalid.first() {
+        let summary = format!("{} peptides are invalid, the first is {} with {}", invalid.len(), peptide, problem);
+        if args.invalid_peptides == InvalidPeptides::Error {
+            return Err(PepMatchError::Invalid(summary));
+        }
+        warn!("{}, they're skipped", summary);
+    }
+    // the kept columns and sources of the skipped peptides' rows go with them
+    let kept = query.kept.into_iter().zip(&checks).filter(|(_, check)| !check.status.is_invalid()).map(|(row, _)| row);
+    let kept = kept.collect();
+    let sources = query.sources.into_iter().zip(&checks).filter(|(_, check)| !check.status.is_invalid());
+    let sources = sources.map(|(source, _)| source).collect();
+    let peptides = checks.into_iter().filter(|check| !check.status.is_invalid()).map(|check| check.peptide).collect();
+    Ok(Query { peptides, kept, sources })
+}
+
+type ValidationReport = csv::Writer<std::fs::File>;
+
+// start the report of how each peptide was validated if asked for
+fn validation_report(args: &MatchArgs) -> Result<Option<ValidationReport>> {
+    let Some(path) = &args.validation_report else {
+        return Ok(None);
+    };
+    let create = || -> std::io::Result<ValidationReport> {
+        let mut writer = csv::WriterBuilder::new().delimiter(b'\t').from_path(path)?;
+        writer.write_record(["Peptide", "Searched As", "Status", "Problem"])?;
+        Ok(writer)
+    };
+    create().map(Some).map_err(|source| PepMatchError::Io { path: path.clone(), source })
+}
+
+fn write_validation_report(writer: &mut ValidationReport, checks: &[PeptideCheck]) -> std::io::Result<()> {
+    for check in checks {
+        let (status, problem) = match &check.status {
+            PeptideStatus::Valid => ("valid", ""),
+            PeptideStatus::Normalized => ("normalized", ""),
+            PeptideStatus::Invalid(problem) => ("invalid", problem.as_str()),
+        };
+        let searched_as = if check.status.is_invalid() { "" } else { check.peptide.as_str() };
+        writer.write_record([check.input.as_str(), searched_as, status, problem])?;
+    }
+    writer.flush()
+}
+
+// warn about peptides too short for the index's k to find every hit of, with n disjoint
+// k-mers a hit with up to n - 1 mismatches is guaranteed to still be seeded, peptides
+// shorter than k have no k-mers and are scanned for instead
+fn warn_short_peptides(peptides: &[String], k: usize, max_mismatches: usize) {
+    let scanned = peptides.iter().filter(|peptide| peptide.len() < k).count();
+    if scanned > 0 {
+        info!("{} peptides are shorter than k = {}, the protein sequences are scanned for them", scanned, k);
+    }
+    let short: Vec<&String> = peptides
+        .iter()
+        .filter(|peptide| peptide.len() >= k && auto_k(peptide.len(), max_mismatches) < k)
+        .collect();
+    if let Some(shortest) = short.iter().map(|peptide| peptide.len()).min() {
+        warn!(
+            "{} peptides are shorter than k * (max mismatches + 1) = {} and may miss hits, use k <= {} for them",
+            short.len(),
+            k * (max_mismatches + 1),
+            auto_k(shortest, max_mismatches)
+        );
+    }
+}
+
+#[derive(Args)]
+struct HeaderArgs {
+    /// FASTA header format: auto, uniprot, refseq, genbank or generic
+    #[clap(long, value_name = "FORMAT", default_value = "auto")]
+    header_format: HeaderFormat,
+
+    /// Parse headers with a regex with named groups like protein_id, gene and species
+    #[clap(long, value_name = "REGEX", conflicts_with = "header-map")]
+    header_regex: Option<String>,
+
+    /// Parse headers with a TOML file mapping fields to regexes
+    #[clap(long, value_name = "FILE")]
+    header_map: Option<String>,
+}
+
+impl HeaderArgs {
+    // a custom regex or mapping takes precedence over the named formats
+    fn format(&self) -> Result<HeaderFormat> {
+        if let Some(pattern) = &self.header_regex {
+            Ok(HeaderFormat::Custom(CustomHeader::from_regex(pattern)?))
+        } else if let Some(path) = &self.header_map {
+            Ok(HeaderFormat::Custom(CustomHeader::from_toml(path)?))
+        } else {
+            Ok(self.header_format.clone())
+        }
+    }
+}
+
+#[derive(Args)]
+struct CoordinateArgs {
+    /// Position of a protein's first residue in the Index and Genomic columns, 0 or 1
+    #[clap(long, value_name = "0|1", default_value_t = 1, possible_values = &["0", "1"])]
+    offset_base: usize,
+
+    /// Give the ends of hits as one past their last residue, the default ends are inclusive
+    #[clap(long)]
+    end_exclusive: bool,
+}
+
+impl CoordinateArgs {
+    fn coordinates(&self) -> Coordinates {
+        Coordinates { base: self.offset_base, end_exclusive: self.end_exclusive }
+    }
+}
+
+#[derive(Args)]
+struct ProteinFilterArgs {
+    /// Only keep proteins with a protein existence level of at most this, 1 to 5
+    #[clap(long, value_name = "N")]
+    max_pe_level: Option<usize>,
+
+    /// Only keep reviewed Swiss-Prot (sp|) proteins
+    #[clap(long)]
+    swissprot_only: bool,
+
+    /// Only keep proteins of at least this many residues
+    #[clap(long, value_name = "N")]
+    min_protein_length: Option<usize>,
+}
+
+impl ProteinFilterArgs {
+    fn filter(&self) -> ProteinFilter {
+        let mut filter = ProteinFilter::new().swissprot_only(self.swissprot_only);
+        if let Some(max_pe_level) = self.max_pe_level {
+            filter = filter.max_pe_level(max_pe_level);
+        }
+        if let Some(min_length) = self.min_protein_length {
+            filter = filter.min_length(min_length);
+        }
+        filter
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    Sqlite,
+    Memory,
+    Fm,
+    Bin,
+    Duckdb,
+    Rocksdb,
+    Lmdb,
+}
+
+#[derive(Args)]
+struct MatchArgs {
+    /// FASTA, CSV/TSV, spreadsheet or text file of peptides, one per line, or - for stdin
+    #[clap(short, long, value_name = "FILE", default_value = "-")]
+    query: String,
+
+    /// Format of the query file: auto, fasta, text, csv or tsv, or xlsx when built with the xlsx feature
+    #[clap(long, value_name = "FORMAT", default_value = "auto")]
+    input_format: InputFormat,
+
+    /// Column of a CSV/TSV query file with the peptides, detected by name by default
+    #[clap(long, value_name = "NAME")]
+    peptide_column: Option<String>,
+
+    /// Sheet of a spreadsheet query file with the peptides, by name or number, the first by default
+    #[clap(long, value_name = "SHEET")]
+    sheet: Option<String>,
+
+    /// Columns of a CSV/TSV query file to report with each of its peptide's hits, like id,assay
+    #[clap(long, value_name = "NAMES", use_value_delimiter = true, conflicts_with_all = &["count-only", "coverage"])]
+    keep_columns: Vec<String>,
+
+    /// Report every column of a CSV/TSV query file with each of its peptide's hits, this ID column first
+    #[clap(long, value_name = "NAME", conflicts_with_all = &["keep-columns", "count-only", "coverage"])]
+    join_on: Option<String>,
+
+    /// UniProt idmapping file, gzipped or not, to report the Ensembl, RefSeq and HGNC IDs of hit proteins
+    #[clap(long, value_name = "FILE", conflicts_with_all = &["count-only", "coverage"])]
+    id_mapping: Option<String>,
+
+    /// Report the length and molecular weight of the protein of each hit
+    #[clap(long, conflicts_with_all = &["count-only", "coverage"])]
+    protein_properties: bool,
+
+    /// UniProt TSV of signal peptides, propeptides and transit peptides, gzipped or not, as downloaded with the
+    /// ft_signal, ft_propep and ft_transit fields, to report the positions of hits on the mature proteins too
+    #[clap(long, value_name = "FILE", conflicts_with_all = &["count-only", "coverage"])]
+    mature_features: Option<String>,
+
+    /// Preprocessed proteome DB, binary index or store directory, required with all but the memory and fm backends,
+    /// without one the proteome is indexed in memory for the search, a SQLite DB preprocessed by an older pepmatch
+    /// is upgraded in place, written to, the first time it's searched
+    #[clap(short, long, value_name = "FILE")]
+    db: Option<String>,
+
+    /// Search a preprocessed DB, build the index in memory from the proteome or an FM-index of it
+    /// that needs no k, or search a binary index, DuckDB file, RocksDB or LMDB directory
+    #[clap(long, value_enum, default_value_t = Backend::Sqlite)]
+    backend: Backend,
+
+    /// Proteome FASTA file, required with the memory and fm backends, repeat for several as NAME=FILE
+    #[clap(
+        short,
+        long,
+        value_name = "[NAME=]FILE",
+        required_if_eq_any(&[("backend", "memory"), ("backend", "fm")]),
+        multiple_occurrences = true
+    )]
+    proteome: Vec<String>,
+
+    /// UniProt gene priority proteome FASTA for the memory backend and --no-index
+    #[clap(long, value_name = "FILE")]
+    gene_priority: Option<String>,
+
+    /// Translate the nucleotide --proteome files in six frames for the memory backend and --no-index
+    #[clap(long)]
+    translate: bool,
+
+    /// Only search the named proteome of a multi-proteome DB, repeat for several
+    #[clap(long, value_name = "NAME", multiple_occurrences = true)]
+    only_proteome: Vec<String>,
+
+    /// Only report hits in proteins of this taxon ID, repeat for several
+    #[clap(long, value_name = "ID", multiple_occurrences = true)]
+    taxon_id: Vec<String>,
+
+    /// Only report hits in proteins whose species contains this, repeat for several
+    #[clap(long, value_name = "NAME", multiple_occurrences = true)]
+    species: Vec<String>,
+
+    /// NCBI taxonomy nodes.dmp (or its directory) to also match descendants of --taxon-id
+    #[clap(long, value_name = "FILE", requires = "taxon-id")]
+    taxonomy: Option<String>,
+
+    /// Only report hits in proteins with an accession in this file, one per line
+    #[clap(long, value_name = "FILE")]
+    include_proteins: Option<String>,
+
+    /// Leave out hits in proteins with an accession in this file, one per line, like the peptides' own source proteins
+    #[clap(long, value_name = "FILE")]
+    exclude_proteins: Option<String>,
+
+    /// Leave out the hits of each peptide in its own source protein, by the accessions in this column of a CSV/TSV
+    /// query, ;-separated or as UniProt IRIs, for screening epitopes for cross-reactivity
+    #[clap(long, value_name = "NAME", conflicts_with_all = &["discontinuous", "count-only"])]
+    exclude_source: Option<String>,
+
+    /// Find exact hits by streaming the proteome once without indexing it, for a few peptides
+    #[clap(long, conflicts_with_all = &["db", "backend", "k", "only-proteome", "best-match"])]
+    no_index: bool,
+
+    /// Search discontinuous epitopes like "A12, K15, R18" by residue position in the --proteome files
+    #[clap(long, conflicts_with_all = &["db", "backend", "k", "only-proteome", "best-match", "no-index"])]
+    discontinuous: bool,
+
+    /// How many positions away from its given one a discontinuous epitope residue can be found
+    #[clap(long, value_name = "N", default_value_t = 0, requires = "discontinuous")]
+    tolerance: usize,
+
+    /// Value of k for k-mers, or auto to pick it from the query, the default for a proteome indexed in memory
+    #[clap(short, long = "k_value", value_name = "K")]
+    k: Option<KValue>,
+
+    /// Maximum number of residue mismatches per hit
+    #[clap(short, long, value_name = "N", default_value_t = 0)]
+    max_mismatches: usize,
+
+    /// Score hits with a substitution matrix and report those scoring at least this instead of counting mismatches
+    #[clap(long, value_name = "SCORE", allow_hyphen_values = true, conflicts_with_all = &["max-mismatches", "no-index", "discontinuous"])]
+    min_score: Option<i32>,
+
+    /// Substitution matrix for --min-score in the NCBI format, BLOSUM62 by default
+    #[clap(long, value_name = "FILE", default_value = "blosum62", requires = "min-score")]
+    matrix: String,
+
+    /// Number of threads to match peptides with, 0 uses all cores
+    #[clap(short = 't', long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+
+    /// Only report the best hit per peptide, allowing more mismatches until one is found
+    #[clap(short, long)]
+    best_match: bool,
+
+    /// For peptides without any hit in best match mode, report the longest stretch of the
+    /// peptide found exactly in a protein instead, with its positions in Query Start and Query End
+    #[clap(long, requires = "best-match")]
+    best_region: bool,
+
+    /// Treat I and L as the same residue, use the same setting as when preprocessing
+    #[clap(long)]
+    treat_i_l_equivalent: bool,
+
+    /// Classes of residues to treat as the same residue, like ILJ;ND;QE, the same ones as when preprocessing
+    #[clap(long, value_name = "CLASSES", default_value = "")]
+    equivalence_classes: EquivalenceClasses,
+
+    /// Only report hits in proteins that pass these filters
+    #[clap(flatten)]
+    protein_filter: ProteinFilterArgs,
+
+    /// How the proteome's headers are parsed with the memory backend or --auto-rebuild
+    #[clap(flatten)]
+    header: HeaderArgs,
+
+    /// How the positions of hits are written, except in GFF3 and BED
+    #[clap(flatten)]
+    coordinates: CoordinateArgs,
+
+    /// Tolerate modifications of peptides from mass spectrometry: deamidation, matching N with D and Q with E, and
+    /// carbamidomethyl, reading cysteines written like C[+57.021] as C, deamidation alone without a value
+    #[clap(
+        long,
+        value_name = "MODS",
+        value_enum,
+        use_value_delimiter = true,
+        min_values = 0,
+        default_missing_value = "deamidation",
+        conflicts_with = "discontinuous"
+    )]
+    chemical_equivalence: Vec<ChemicalEquivalence>,
+
+    /// Report up to N residues of the protein on either side of each hit
+    #[clap(long, value_name = "N", default_value_t = 0, conflicts_with = "discontinuous")]
+    context: usize,
+
+    /// Flag hits at the N-terminus of their protein as "protein", and hits right after an initiator Met,
+    /// at the N-terminus of the mature protein once it's cleaved, as "mature", in an N-terminus column
+    #[clap(long, conflicts_with = "discontinuous")]
+    met_cleavage: bool,
+
+    /// Report the E-value of each hit, how many hits its peptide with up to as many mismatches is expected
+    /// to have in a random proteome of the same size and residue composition, and the P-value of any
+    #[clap(long, conflicts_with_all = &["discontinuous", "no-index", "count-only", "coverage"])]
+    statistics: bool,
+
+    /// Search the DB even if -k, --treat-i-l-equivalent or the --proteome files don't match how it was built
+    #[clap(long)]
+    force: bool,
+
+    /// Preprocess the DB again first if the proteome files it was built from changed since,
+    /// rather than warning its hits may be out of date
+    #[clap(long)]
+    auto_rebuild: bool,
+
+    /// Search every peptide rather than looking up hits cached by earlier searches of the DB
+    #[clap(long)]
+    no_cache: bool,
+
+    /// Delete the cache of earlier searches before searching
+    #[clap(long)]
+    clear_cache: bool,
+
+    /// Output format: tsv, csv, json, gff3 or bed, or arrow or parquet when built with the arrow feature
+    #[clap(short, long, value_name = "FORMAT", default_value = "tsv")]
+    format: Format,
+
+    /// Write the hits into a matches table of a SQLite DB, or a DuckDB file ending in .duckdb, instead of to stdout
+    #[clap(long, value_name = "FILE", conflicts_with = "format")]
+    output_db: Option<String>,
+
+    /// Report the peptides and residues covered in each protein with hits instead of every hit
+    #[clap(long, conflicts_with_all = &["no-index", "discontinuous", "output-db"])]
+    coverage: bool,
+
+    /// Report at most N hits per peptide, the best ones
+    #[clap(long, value_name = "N", conflicts_with_all = &["no-index", "discontinuous", "best-match"])]
+    limit: Option<usize>,
+
+    /// Only report the best hit of a peptide in each protein
+    #[clap(long, conflicts_with_all = &["no-index", "discontinuous", "best-match"])]
+    one_match_per_protein: bool,
+
+    /// Order each peptide's hits by these keys in turn, like mismatches,pe_level: mismatches,
+    /// position, protein_id, pe_level or score (highest first), peptides stay in the query's order
+    #[clap(long, value_name = "KEYS", use_value_delimiter = true, default_value = "protein_id,position")]
+    sort: Vec<SortKey>,
+
+    /// Report hits in proteins of a DB preprocessed with --dedupe-sequences in every protein
+    /// with the same sequence too
+    #[clap(long, conflicts_with_all = &["no-index", "discontinuous", "coverage"])]
+    expand_synonyms: bool,
+
+    /// Only report the number of hits of each peptide
+    #[clap(long, conflicts_with_all = &["coverage", "output-db"])]
+    count_only: bool,
+
+    /// Skip peptides with characters that aren't amino acids with a warning, or stop with an error
+    #[clap(long, value_enum, default_value_t = InvalidPeptides::Skip)]
+    invalid_peptides: InvalidPeptides,
+
+    /// Write how each peptide was normalized or why it's invalid to a TSV file
+    #[clap(long, value_name = "FILE")]
+    validation_report: Option<String>,
+
+    /// Write a row with empty hit fields for every query row without hits, like the Python PEPMatch, as TSV, CSV or JSON
+    #[clap(long, conflicts_with_all = &["coverage", "count-only", "output-db"])]
+    unmatched_rows: bool,
+
+    /// Write the peptides without hits to a file, a line each, to search them again with other settings
+    #[clap(long, value_name = "FILE", conflicts_with_all = &["coverage", "count-only"])]
+    unmatched_out: Option<String>,
+
+    /// Read and search the query N peptides at a time, writing out each chunk's hits before the next
+    #[clap(long, value_name = "N", conflicts_with_all = &["no-index", "discontinuous", "coverage", "count-only"])]
+    chunk_size: Option<usize>,
+
+    /// Search several chunks at once on a tokio runtime while the next are read and the hits of earlier ones written
+    #[cfg(feature = "async-io")]
+    #[clap(long, requires = "chunk-size", conflicts_with = "exclude-source")]
+    async_io: bool,
+}
+
+// modifications of peptides from mass spectrometry that are searched as the residues they're of
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ChemicalEquivalence {
+    Deamidation,     // N and D, and Q and E, match each other
+    Carbamidomethyl, // cysteines written like C[+57.021] or C(UniMod:4) are read as C
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InvalidPeptides {
+    Skip,
+    Error,
+}
+
+#[derive(Args)]
+struct BenchArgs {
+    /// Benchmark to run: mhc-ligands (exact), neoepitopes (3 mismatches) or coronavirus (best match)
+    #[clap(value_name = "DATASET")]
+    dataset: Dataset,
+
+    /// The benchmark's proteome FASTA file
+    #[clap(short, long, value_name = "FILE", required_unless_present = "db")]
+    proteome: Option<String>,
+
+    /// The benchmark's query peptides
+    #[clap(short, long, value_name = "FILE")]
+    query: String,
+
+    /// Results of the Python PEPMatch for the benchmark to measure recall against
+    #[clap(short, long, value_name = "FILE")]
+    expected: Option<String>,
+
+    /// Value of k to index the proteome with, the benchmark's own by default, a --db has to have been built with it
+    #[clap(short, long = "k_value", value_name = "K")]
+    k: Option<usize>,
+
+    /// Search a preprocessed DB or binary index instead of indexing the proteome in memory
+    #[clap(short, long, value_name = "FILE", conflicts_with = "proteome")]
+    db: Option<String>,
+
+    /// Number of threads to search with, 0 uses all cores
+    #[clap(short, long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+}
+
+#[derive(Args)]
+struct InspectArgs {
+    /// Preprocessed proteome DB
+    db: String,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// Preprocessed proteome DB or binary index
+    #[clap(short, long, value_name = "FILE")]
+    db: String,
+
+    /// Length of the k-mers counted, defaults to the k the DB was built with
+    #[clap(short, long)]
+    k: Option<usize>,
+
+    /// Number of the most frequent k-mers to report
+    #[clap(long, value_name = "N", default_value_t = 10)]
+    top: usize,
+}
+
+#[derive(Args)]
+struct MotifArgs {
+    /// PROSITE patterns like N-{P}-[ST]-{P}
+    #[clap(value_name = "PATTERN", required = true)]
+    patterns: Vec<Motif>,
+
+    /// Preprocessed proteome DB
+    #[clap(short, long, value_name = "FILE")]
+    db: String,
+
+    /// Output format: tsv, csv, json, gff3 or bed, or arrow or parquet when built with the arrow feature
+    #[clap(short, long, value_name = "FORMAT", default_value = "tsv")]
+    format: Format,
+
+    /// Write the hits into a matches table of a SQLite DB, or a DuckDB file ending in .duckdb, instead of to stdout
+    #[clap(long, value_name = "FILE", conflicts_with = "format")]
+    output_db: Option<String>,
+}
+
+#[derive(Args)]
+struct DecoyArgs {
+    /// Input proteome FASTA file
+    #[clap(short, long, value_name = "FILE")]
+    proteome: String,
+
+    /// How decoy sequences are made: reverse or shuffle
+    #[clap(long, value_name = "MODE", default_value = "reverse")]
+    mode: DecoyMode,
+
+    /// Seed for shuffled sequences
+    #[clap(long, value_name = "N", default_value_t = 0)]
+    seed: u64,
+
+    /// Output decoy FASTA file, by default named after the proteome
+    #[clap(short, long, value_name = "FILE")]
+    output: Option<String>,
+
+    /// Also preprocess the proteome and its decoys into one DB with this k
+    #[clap(short, long = "k_value", value_name = "K")]
+    k: Option<usize>,
+
+    /// Path of the DB with -k, named after the proteome and k by default
+    #[clap(short, long, value_name = "FILE", requires = "k")]
+    db: Option<String>,
+}
+
+#[derive(Args)]
+struct DigestArgs {
+    /// Input proteome FASTA file
+    #[clap(short, long, value_name = "FILE")]
+    proteome: String,
+
+    /// Residues in each peptide, proteins shorter than this are written whole
+    #[clap(short, long, value_name = "N", default_value_t = 15, conflicts_with = "enzyme")]
+    length: usize,
+
+    /// Residues each peptide shares with the one before it, less than --length
+    #[clap(long, value_name = "N", default_value_t = 11, conflicts_with = "enzyme")]
+    overlap: usize,
+
+    /// Digest with a protease instead: trypsin, trypsin/p, chymotrypsin, lys-c, arg-c, glu-c or asp-n
+    #[clap(short, long, value_name = "PROTEASE")]
+    enzyme: Option<Protease>,
+
+    /// Protease sites a peptide can span uncut
+    #[clap(long, value_name = "N", default_value_t = 0, requires = "enzyme")]
+    missed_cleavages: usize,
+
+    /// Shortest peptide of a protease to write
+    #[clap(long, value_name = "N", default_value_t = 7, requires = "enzyme")]
+    min_length: usize,
+
+    /// Longest peptide of a protease to write
+    #[clap(long, value_name = "N", default_value_t = 30, requires = "enzyme")]
+    max_length: usize,
+
+    /// Output TSV file of the peptides with the protein and positions each is from, stdout by default
+    #[clap(short, long, value_name = "FILE")]
+    out: Option<String>,
+
+    /// How the positions of the peptides are written
+    #[clap(flatten)]
+    coordinates: CoordinateArgs,
+
+    #[clap(flatten)]
+    header: HeaderArgs,
+}
+
+#[derive(Args)]
+struct ExtractArgs {
+    /// TSV or CSV results of match, with its Protein ID, Index start and Index end columns
+    #[clap(short, long, value_name = "FILE")]
+    matches: String,
+
+    /// Preprocessed proteome DB, binary index or store directory the results are from
+    #[clap(short, long, value_name = "FILE")]
+    db: String,
+
+    /// Output FASTA file
+    #[clap(short, long, value_name = "FILE")]
+    out: String,
+
+    /// Only write the hits with this many residues either side, merging windows that overlap
+    #[clap(short, long, value_name = "N")]
+    window: Option<usize>,
+
+    /// How the positions in the results were written
+    #[clap(flatten)]
+    coordinates: CoordinateArgs,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Preprocessed proteome DB, binary index or store directory, k-mers are only exported from SQLite DBs
+    #[clap(short, long, value_name = "FILE")]
+    db: String,
+
+    /// What to export: metadata, kmers, with a row per location and 0-based positions, or proteins
+    #[clap(long, value_name = "TABLE")]
+    what: ExportTable,
+
+    /// Output format: tsv, jsonl, or parquet when built with the arrow feature
+    #[clap(short, long, value_name = "FORMAT", default_value = "tsv")]
+    format: ExportFormat,
+
+    /// Output file, stdout by default
+    #[clap(short, long, value_name = "FILE")]
+    out: Option<String>,
+}
+
+#[cfg(feature = "server")]
+#[derive(Args)]
+struct ServeArgs {
+    /// Preprocessed proteome DB or binary index to search
+    #[clap(short, long, value_name = "FILE")]
+    db: String,
+
+    /// Address to listen on
+    #[clap(long, value_name = "HOST", default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on
+    #[clap(long, value_name = "PORT", default_value_t = 8080)]
+    port: u16,
+
+    /// Number of requests searched at once, 0 for one per core
+    #[clap(short, long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+
+    /// Treat I and L as the same residue, the DB has to have been preprocessed with it
+    #[clap(long)]
+    treat_i_l_equivalent: bool,
+
+    /// Classes of residues to treat as the same residue, the ones the DB was preprocessed with
+    #[clap(long, value_name = "CLASSES", default_value = "")]
+    equivalence_classes: EquivalenceClasses,
+
+    /// File of API keys, one per line, requests but for /health need one as a Bearer token or X-API-Key header
+    #[clap(long, value_name = "FILE")]
+    api_keys: Option<String>,
+
+    /// Most requests a minute for each API key, or each client address without keys
+    #[clap(long, value_name = "N")]
+    rate_limit: Option<u32>,
+
+    /// Most peptides a match request can have
+    #[clap(long, value_name = "N")]
+    max_peptides: Option<usize>,
+}
+
+#[cfg(feature = "grpc")]
+#[derive(Args)]
+struct GrpcArgs {
+    /// Preprocessed proteome DB or binary index to search
+    #[clap(short, long, value_name = "FILE")]
+    db: String,
+
+    /// Address to listen on
+    #[clap(long, value_name = "HOST", default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on
+    #[clap(long, value_name = "PORT", default_value_t = 50051)]
+    port: u16,
+
+    /// Number of requests searched at once across the streams, 0 for one per core
+    #[clap(short, long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+
+    /// Treat I and L as the same residue, the DB has to have been preprocessed with it
+    #[clap(long)]
+    treat_i_l_equivalent: bool,
+
+    /// Classes of residues to treat as the same residue, the ones the DB was preprocessed with
+    #[clap(long, value_name = "CLASSES", default_value = "")]
+    equivalence_classes: EquivalenceClasses,
+}
+
+#[derive(Args)]
+struct FetchArgs {
+    /// UniProt proteome ID, e.g. UP000005640
+    proteome_id: String,
+
+    /// Directory to save the proteome to
+    #[clap(short, long, value_name = "DIR", default_value = ".")]
+    output_dir: String,
+
+    /// Include the non-canonical isoforms
+    #[clap(long)]
+    isoforms: bool,
+
+    /// Also download the gene priority proteome of one protein per gene
+    #[clap(long)]
+    gene_priority: bool,
+
+    /// Preprocess the proteome with this k once it's downloaded
+    #[clap(short, long = "k_value", value_name = "K")]
+    k: Option<usize>,
+
+    /// Output DB file when preprocessing, defaults to <proteome ID>_k<k>.db
+    #[clap(short, long, value_name = "FILE", requires = "k")]
+    db: Option<String>,
+}
+
+fn main() {
+    let args = with_config(std::env::args_os().collect())
+        .unwrap_or_else(|e| Cli::command().error(clap::ErrorKind::InvalidValue, e).exit());
+    let cli = Cli::parse_from(args);
+    init_logging(cli.verbose, cli.log_format);
+    if let Err(e) = run(cli) {
+        // stop quietly when the output is piped into something like head that exits early
+        if e.is_broken_pipe() {
+            return;
+        }
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+// the command line with the config file's settings added for the args it doesn't give, the
+// file given with --config or pepmatch.toml in the working directory, settings for args the
+// command line gives a conflicting arg of are left out
+fn with_config(mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let given: Vec<&str> = args.iter().map(|arg| arg.to_str().unwrap_or_default()).collect();
+    let path = match given.iter().position(|arg| *arg == "--config") {
+        Some(i) => given.get(i + 1).map(|path| path.to_string()),
+        None => given.iter().find_map(|arg| arg.strip_prefix("--config=")).map(str::to_string),
+    };
+    let config = match path {
+        Some(path) => Config::load(&path)?,
+        None if std::path::Path::new(CONFIG_FILE).is_file() => Config::load(CONFIG_FILE)?,
+        None => return Ok(args),
+    };
+
+    let mut cli = Cli::command();
+    cli.build();
+    if let Some(unknown) = config.commands().find(|name| cli.find_subcommand(*name).is_none()) {
+        return Err(PepMatchError::Invalid(format!("{}: there's no {} subcommand", config.path(), unknown)));
+    }
+    let Some(position) = given.iter().position(|arg| cli.find_subcommand(*arg).is_some()) else {
+        return Ok(args);
+    };
+    let command = cli.find_subcommand(given[position]).unwrap_or(&cli);
+    let on_command_line = |arg: &clap::Arg| {
+        given.iter().any(|token| {
+            let long = arg.get_long().and_then(|long| token.strip_prefix("--")?.strip_prefix(long));
+            let short = arg.get_short().filter(|_| !token.starts_with("--")).and_then(|short| token.strip_prefix('-')?.strip_prefix(short));
+            long.is_some_and(|rest| rest.is_empty() || rest.starts_with('=')) || short.is_some()
+        })
+    };
+    let given_args: Vec<&clap::Arg> = command.get_arguments().filter(|arg| on_command_line(arg)).collect();
+
+    let mut added = Vec::new();
+    for (name, setting, own) in config.settings(command.get_name()) {
+        let Some(arg) = command.get_arguments().find(|arg| arg.get_long().is_some_and(|long| long.replace('_', "-") == name)) else {
+            if own {
+                return Err(PepMatchError::Invalid(format!(
+                    "{}: {} has no --{} option",
+                    config.path(),
+                    command.get_name(),
+                    name
+                )));
+            }
+            continue;
+        };
+        let conflicts = command.get_arg_conflicts_with(arg);
+        if on_command_line(arg)
+            || given_args.iter().any(|given| conflicts.contains(given) || command.get_arg_conflicts_with(given).contains(&arg))
+        {
+            continue;
+        }
+        let flag = format!("--{}", arg.get_long().unwrap_or_default());
+        match setting {
+            Setting::Flag(true) => added.push(flag),
+            Setting::Flag(false) => {}
+            Setting::Values(values) => {
+                for value in values {
+                    added.push(format!("{}={}", flag, value));
+                }
+            }
+        }
+    }
+    let added: Vec<OsString> = added.into_iter().map(OsString::from).collect();
+    args.splice(position + 1..position + 1, added);
+    Ok(args)
+}
+
+// log to stderr, with -v the time spent in each preprocessing and matching step is logged
+// as its span closes, and with -vv the spans of every protein and batch of peptides too
+fn init_logging(verbose: u8, format: LogFormat) {
+    let (level, spans) = match verbose {
+        0 => (Level::INFO, FmtSpan::NONE),
+        1 => (Level::DEBUG, FmtSpan::CLOSE),
+        _ => (Level::TRACE, FmtSpan::CLOSE),
+    };
+    let logger = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_span_events(spans)
+        .with_target(false);
+    match format {
+        // span timings are only kept with timestamps
+        LogFormat::Text if verbose == 0 => logger.without_time().init(),
+        LogFormat::Text => logger.init(),
+        LogFormat::Json => logger.json().init(),
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let progress = !cli.quiet;
+    match cli.command {
+        Command::Preprocess(args) => {
+            let min_len = match &args.peptide_lengths {
+                Some(lengths) => min_peptide_length(lengths)?,
+                None => 0,
+            };
+            let k = args.k.resolve(min_len, args.max_mismatches);
+            let mut preprocessor = Preprocessor::with_proteomes(&named_proteomes(&args.proteome)?, k)
+                .threads(args.threads)
+                .header_format(args.header.format()?)
+                .extra_k(&args.extra_k)
+                .treat_i_l_equivalent(args.treat_i_l_equivalent)
+                .equivalence_classes(args.equivalence_classes.clone())
+                .layout(args.layout)
+                .backend(args.backend)
+                .translate(args.translate)
+                .dedupe_sequences(args.dedupe_sequences)
+                .shards(args.shards)
+                .compression(args.compression)
+                .sqlite_cache_mb(args.sqlite_cache_mb)
+                .sqlite_page_size(args.sqlite_page_size)
+                .wal(args.wal)
+                .resume(args.resume)
+                .overwrite(args.overwrite)
+                .auto_rebuild(args.auto_rebuild)
+                .protein_filter(args.protein_filter.filter())
+                .progress(progress);
+            if let Some(db_path) = &args.db {
+                preprocessor = preprocessor.db_path(db_path);
+            }
+            if let Some(gene_priority) = &args.gene_priority {
+                preprocessor = preprocessor.gene_priority(gene_priority);
+            }
+            if args.dry_run {
+                let dry_run = preprocessor.dry_run()?;
+                println!("k\t{}", k);
+                println!("proteins\t{}", dry_run.proteins);
+                if args.dedupe_sequences {
+                    println!("synonyms\t{}", dry_run.synonyms);
+                }
+                println!("residues\t{}", dry_run.residues);
+                println!("locations\t{}", dry_run.locations);
+                println!("estimated_db_size\t{}", dry_run.estimated_bytes);
+                return Ok(());
+            }
+            preprocessor.preprocess()?;
+        }
+        Command::Match(args) => {
+            let started = std::time::Instant::now();
+            let mut taxon_filter = TaxonFilter::new().taxon_ids(&args.taxon_id).species(&args.species);
+            if let Some(taxonomy) = &args.taxonomy {
+                taxon_filter = taxon_filter.expand_descendants(taxonomy)?;
+            }
+            if let Some(chunk_size) = args.chunk_size {
+                return match_chunks(&args, taxon_filter, chunk_size, started, progress);
+            }
+
+            let mut reader = QueryReader::with_sources(
+                &args.query,
+                args.input_format,
+                args.peptide_column.as_deref(),
+                args.sheet.as_deref(),
+                &kept_columns(&args),
+                args.exclude_source.as_deref(),
+            )?;
+            let kept_names = reader.kept_columns().to_vec();
+            let query = reader.read_chunk(usize::MAX)?;
+            check_unmatched_rows(&args)?;
+            let Query { mut peptides, kept, sources } = validate_peptides(&args, query, validation_report(&args)?.as_mut())?;
+            let sources = args.exclude_source.is_some().then(|| source_filters(&peptides, &sources));
+            let distinct = distinct_peptides(&peptides);
+            if distinct.len() < peptides.len() {
+                info!(
+                    "Collapsed {} repeated peptides, each of the {} distinct ones is searched once",
+                    peptides.len() - distinct.len(),
+                    distinct.len()
+                );
+            }
+            // with columns kept, the hits of a peptide are joined to each of its rows, so it's
+            // only searched once
+            let rows = if kept.is_empty() { Vec::new() } else { std::mem::take(&mut peptides) };
+            if !kept.is_empty() {
+                peptides = distinct;
+            }
+            let mut hits = if args.no_index || args.discontinuous {
+                let mut hits = if args.no_index {
+                    scan(&args, &peptides, progress)?
                 } else {
-                    metadata_entry.push("".to_string());
+                    match_epitopes(&args, &peptides, progress)?
+                };
+                let protein_filter = args.protein_filter.filter();
+                let accession_filter = accession_filter(&args)?;
+                hits.retain(|hit| {
+                    taxon_filter.allows(&hit.taxon_id, &hit.species)
+                        && protein_filter.allows(hit.pe_level, &hit.db, hit.protein_length)
+                        && accession_filter.allows(&hit.protein_id)
+                        && sources.as_ref().and_then(|sources| sources.get(&hit.peptide)).is_none_or(|source| source.allows(&hit.protein_id))
+                });
+                if args.count_only {
+                    let mut counts: HashMap<&str, usize> = HashMap::new();
+                    for hit in &hits {
+                        *counts.entry(hit.peptide.as_str()).or_default() += 1;
+                    }
+                    let counts: Vec<usize> =
+                        peptides.iter().map(|peptide| counts.get(peptide.as_str()).copied().unwrap_or_default()).collect();
+                    return write_counts(&args, &peptides, counts);
+                }
+                hits
+            } else {
+                let matcher = matcher(&args, &peptides, taxon_filter, progress)?;
+                if args.count_only {
+                    return write_counts(&args, &peptides, matcher.count_matches(&peptides)?);
+                }
+                let mut hits = match &sources {
+                    Some(sources) => matcher.match_peptides_excluding_sources(&peptides, sources)?,
+                    None => matcher.match_peptides(&peptides)?,
+                };
+                if args.statistics {
+                    matcher.expected_hits(&matcher.composition()?, &mut hits);
+                }
+                if args.coverage {
+                    let out = Box::new(std::io::BufWriter::new(std::io::stdout()));
+                    return output::write_coverage(args.format, out, &matcher.coverage(&hits)?)
+                        .map_err(|source| PepMatchError::Io { path: "stdout".to_string(), source });
+                }
+                hits
+            };
+            sort_by_keys(&mut hits, &args.sort);
+
+            // every query row, with discontinuous epitopes written like their hits', e.g. A12, K15
+            let mut searched = if rows.is_empty() { peptides } else { rows };
+            if args.discontinuous {
+                searched = searched
+                    .iter()
+                    .map(|epitope| Ok(epitope.parse::<DiscontinuousEpitope>()?.to_string()))
+                    .collect::<Result<_>>()?;
+            }
+            let kept_values = if args.unmatched_rows { kept.clone() } else { Vec::new() };
+            let mut hits = if kept.is_empty() { hits } else { join_rows(hits, &searched, kept) };
+            if let Some(path) = &args.id_mapping {
+                IdMapping::load(path)?.annotate(&mut hits);
+            }
+            if let Some(path) = &args.mature_features {
+                MatureProteins::load(path)?.annotate(&mut hits);
+            }
+            let mut summary = Summary::default();
+            summary.add(&searched, &hits);
+
+            // hits in translated nucleotide sequences get their frame and bases reported too
+            let columns = Columns {
+                genomic: args.translate || hits.iter().any(|hit| hit.frame != 0),
+                context: args.context > 0,
+                score: args.min_score.is_some(),
+                n_terminus: args.met_cleavage,
+                mature: args.mature_features.is_some(),
+                region: args.best_region,
+                statistics: args.statistics,
+                id_mapping: args.id_mapping.is_some(),
+                protein: args.protein_properties,
+                kept: kept_names,
+                coordinates: args.coordinates.coordinates(),
+            };
+            let unmatched = args.unmatched_rows.then_some((searched.as_slice(), kept_values.as_slice()));
+            write_hits(hits, unmatched, columns, args.format, args.output_db.as_deref())?;
+            finish_summary(&args, &summary, started, progress)?;
+        }
+        Command::Decoy(args) => {
+            let name = proteome_name(&args.proteome);
+            let output = args.output.clone().unwrap_or_else(|| format!("{}_decoy.fasta", name));
+            let count = decoy::write_decoys(&args.proteome, &output, args.mode, args.seed)?;
+            if progress {
+                info!("Wrote {} decoys to {}", count, output);
+            }
+            if let Some(k) = args.k {
+                // the decoys get a proteome of their own so --only-proteome can tell them apart
+                let proteomes = [(name.clone(), args.proteome.clone()), (format!("{}_decoy", name), output)];
+                let mut preprocessor = Preprocessor::with_proteomes(&proteomes, k).progress(progress);
+                if let Some(db_path) = &args.db {
+                    preprocessor = preprocessor.db_path(db_path);
+                }
+                preprocessor.preprocess()?;
+            }
+        }
+        Command::Digest(args) => {
+            let digestion = match args.enzyme {
+                Some(protease) => Digestion::Protease {
+                    protease,
+                    missed_cleavages: args.missed_cleavages,
+                    min_length: args.min_length,
+                    max_length: args.max_length,
+                },
+                None => Digestion::Windows { length: args.length, overlap: args.overlap },
+            };
+            let (coordinates, output) = (args.coordinates.coordinates(), args.out.as_deref());
+            let count = digest_proteome(&args.proteome, &digestion, &args.header.format()?, coordinates, output)?;
+            if progress {
+                info!("Wrote {} peptides to {}", count, output.unwrap_or("stdout"));
+            }
+        }
+        Command::Extract(args) => {
+            let coordinates = args.coordinates.coordinates();
+            let count = extract_proteins(&args.matches, &args.db, &args.out, args.window, coordinates, progress)?;
+            if progress {
+                info!("Wrote {} sequences to {}", count, args.out);
+            }
+        }
+        Command::Export(args) => {
+            let rows = export(&args.db, args.what, args.format, args.out.as_deref(), progress)?;
+            if progress {
+                info!("Wrote {} rows to {}", rows, args.out.as_deref().unwrap_or("stdout"));
+            }
+        }
+        #[cfg(feature = "server")]
+        Command::Serve(args) => {
+            let matcher = Matcher::new(&args.db)?
+                .treat_i_l_equivalent(args.treat_i_l_equivalent)
+                .equivalence_classes(args.equivalence_classes.clone());
+            let mut options = ServeOptions::default();
+            if let Some(path) = &args.api_keys {
+                options = options.api_keys(read_api_keys(path)?);
+            }
+            if let Some(n) = args.rate_limit {
+                options = options.requests_per_minute(n);
+            }
+            if let Some(n) = args.max_peptides {
+                options = options.max_peptides(n);
+            }
+            pepmatch::server::serve(&matcher, &format!("{}:{}", args.host, args.port), args.threads, &options)?;
+        }
+        #[cfg(feature = "grpc")]
+        Command::Grpc(args) => {
+            let matcher = Matcher::new(&args.db)?
+                .treat_i_l_equivalent(args.treat_i_l_equivalent)
+                .equivalence_classes(args.equivalence_classes.clone());
+            pepmatch::grpc::serve(&matcher, &format!("{}:{}", args.host, args.port), args.threads)?;
+        }
+        Command::Motif(args) => {
+            let hits = scan_motifs(&args.db, &args.patterns, progress)?;
+            let columns = Columns { genomic: hits.iter().any(|hit| hit.frame != 0), ..Columns::default() };
+            write_hits(hits, None, columns, args.format, args.output_db.as_deref())?;
+        }
+        Command::Bench(args) => {
+            let mut benchmark = Benchmark::new(args.dataset).threads(args.threads).progress(progress);
+            if let Some(k) = args.k {
+                benchmark = benchmark.k(k);
+            }
+            if let Some(proteome) = &args.proteome {
+                benchmark = benchmark.proteome(proteome);
+            }
+            if let Some(db) = &args.db {
+                benchmark = benchmark.db(db);
+            }
+            let result = benchmark.run(&read_peptides(&args.query, None)?, args.expected.as_deref())?;
+            println!("dataset\t{}", result.dataset.name());
+            println!("k\t{}", result.k);
+            println!("peptides\t{}", result.peptides);
+            println!("hits\t{}", result.hits);
+            println!("index_seconds\t{:.3}", result.index_seconds);
+            println!("search_seconds\t{:.3}", result.search_seconds);
+            if let Some(peak_rss) = result.peak_rss {
+                println!("peak_rss_mb\t{:.1}", peak_rss as f64 / (1 << 20) as f64);
+            }
+            if let Some(recall) = result.recall {
+                println!("expected_hits\t{}", recall.expected);
+                println!("found_hits\t{}", recall.found);
+                println!("recall\t{:.4}", recall.fraction());
+            }
+        }
+        Command::Inspect(args) => {
+            let summary = inspect::summarize(&args.db)?;
+            println!("k\t{}", summary.k);
+            println!("proteins\t{}", summary.proteins);
+            println!("kmers\t{}", summary.kmers);
+            println!("locations\t{}", summary.locations);
+            println!("residues\t{}", summary.residues);
+            println!("db_size\t{}", summary.db_size);
+            if let Some(built_at) = summary.built_at {
+                println!("built_at\t{}", inspect::format_timestamp(built_at));
+            }
+            for (name, proteins) in summary.proteomes {
+                println!("proteome\t{}\t{}", name, proteins);
+            }
+            for (species, proteins) in summary.species {
+                println!("species\t{}\t{}", species, proteins);
+            }
+        }
+        Command::Stats(args) => {
+            let stats = statistics::proteome_stats(&args.db, args.k, args.top, progress)?;
+            let composition = &stats.composition;
+            println!("proteins\t{}", composition.proteins());
+            println!("residues\t{}", composition.residues());
+            println!("length_min\t{}", composition.length_quantile(0.0));
+            println!("length_q1\t{}", composition.length_quantile(0.25));
+            println!("length_median\t{}", composition.length_quantile(0.5));
+            println!("length_q3\t{}", composition.length_quantile(0.75));
+            println!("length_max\t{}", composition.length_quantile(1.0));
+            println!("length_mean\t{:.1}", composition.mean_length());
+            for (residue, count) in composition.residue_counts() {
+                println!("residue\t{}\t{}\t{:.4}", residue, count, composition.frequency(residue as u8));
+            }
+            println!("k\t{}", stats.k);
+            println!("kmers\t{}", stats.kmers);
+            println!("kmer_entropy\t{:.4}", stats.kmer_entropy);
+            for (kmer, count) in stats.repetitive {
+                println!("repetitive_kmer\t{}\t{}", kmer, count);
+            }
+        }
+        Command::Fetch(args) => {
+            let fetcher = Fetcher::new(&args.proteome_id)?
+                .dir(&args.output_dir)
+                .isoforms(args.isoforms)
+                .progress(progress);
+            let proteome = fetcher.fetch_proteome()?;
+            info!("Saved {}", proteome);
+            let gene_priority = if args.gene_priority { Some(fetcher.fetch_gene_priority()?) } else { None };
+            if let Some(gene_priority) = &gene_priority {
+                info!("Saved {}", gene_priority);
+            }
+
+            if let Some(k) = args.k {
+                let mut preprocessor = Preprocessor::new(&proteome, k).progress(progress);
+                if let Some(db_path) = &args.db {
+                    preprocessor = preprocessor.db_path(db_path);
+                }
+                if let Some(gene_priority) = &gene_priority {
+                    preprocessor = preprocessor.gene_priority(gene_priority);
                 }
+                preprocessor.preprocess()?;
             }
         }
+    }
+    Ok(())
+}
 
-        let metadata_tuple = (
-            metadata_entry[0].clone(),
-            metadata_entry[1].clone(),
-            metadata_entry[2].clone(),
-            metadata_entry[3].clone(),
-            metadata_entry[4].clone(),
-            metadata_entry[5].clone(),
-            metadata_entry[6].parse::<usize>().unwrap(),
-            metadata_entry[7].parse::<usize>().unwrap()
-        );
-        metadata.push(metadata_tuple);
-        i += 1;
+// write the number of hits of each peptide to stdout, repeated peptides are only
+// reported once
+fn write_counts(args: &MatchArgs, peptides: &[String], counts: Vec<usize>) -> Result<()> {
+    let mut seen = HashSet::new();
+    let counts: Vec<(String, usize)> = peptides
+        .iter()
+        .cloned()
+        .zip(counts)
+        .filter(|(peptide, _)| seen.insert(peptide.clone()))
+        .collect();
+    let out = Box::new(std::io::BufWriter::new(std::io::stdout()));
+    output::write_counts(args.format, out, &counts)
+        .map_err(|source| PepMatchError::Io { path: "stdout".to_string(), source })
+}
+
+// the proteins hits are reported in by the accessions of --include-proteins and --exclude-proteins
+fn accession_filter(args: &MatchArgs) -> Result<AccessionFilter> {
+    let mut filter = AccessionFilter::new();
+    if let Some(path) = &args.include_proteins {
+        filter = filter.include(&read_accessions(path)?);
+    }
+    if let Some(path) = &args.exclude_proteins {
+        filter = filter.exclude(&read_accessions(path)?);
     }
+    Ok(filter)
+}
 
-    (seqs, metadata)
+// the columns of the query to keep, with --join-on the ID column then every other one
+fn kept_columns(args: &MatchArgs) -> Vec<String> {
+    match &args.join_on {
+        Some(column) => vec![column.clone(), "*".to_string()],
+        None => args.keep_columns.clone(),
+    }
 }
 
-// split the peptide into k-mers with a window size of 1 and store also the index of that k-mer
-fn split_sequence(seq: &str, k: usize) -> Vec<(String, usize)> {
-    let mut kmers = Vec::new();
-    let mut i: usize = 0;
-    while i + k <= seq.len() {
-        kmers.push((seq[i..i + k].to_string(), i));
-        i += 1;
+// search the query a chunk of peptides at a time, writing out the hits of each chunk before the
+// next one is read, so memory stays flat with millions of peptides and the hits written so far
+// survive the search being stopped, with --async-io several chunks are searched at once while
+// the next are read and the hits of earlier ones written
+fn match_chunks(
+    args: &MatchArgs,
+    taxon_filter: TaxonFilter,
+    chunk_size: usize,
+    started: std::time::Instant,
+    progress: bool,
+) -> Result<()> {
+    if chunk_size == 0 {
+        return Err(PepMatchError::Invalid("--chunk-size has to be at least 1".to_string()));
     }
-    kmers
+    let mut reader = QueryReader::with_sources(
+        &args.query,
+        args.input_format,
+        args.peptide_column.as_deref(),
+        args.sheet.as_deref(),
+        &kept_columns(args),
+        args.exclude_source.as_deref(),
+    )?;
+    let kept_names = reader.kept_columns().to_vec();
+    check_unmatched_rows(args)?;
+    let mut report = validation_report(args)?;
+    let output_error = |source| PepMatchError::Io { path: args.output_db.as_deref().unwrap_or("stdout").to_string(), source };
+
+    // the chunks read after the first one, which has been read once the query is empty or a
+    // chunk comes up short, empty ones after the first are left out
+    let mut finished = false;
+    let mut read_chunk = |k: Option<usize>| -> Result<Option<Query>> {
+        if finished {
+            return Ok(None);
+        }
+        let chunk = reader.read_chunk(chunk_size)?;
+        finished = chunk.peptides.len() < chunk_size;
+        if chunk.peptides.is_empty() && k.is_some() {
+            return Ok(None);
+        }
+        let query = validate_peptides(args, chunk, report.as_mut())?;
+        if let Some(report) = &mut report {
+            report.flush().map_err(|source| PepMatchError::Io {
+                path: args.validation_report.clone().unwrap_or_default(),
+                source,
+            })?;
+        }
+        if let Some(k) = k.filter(|_| !args.best_match) {
+            warn_short_peptides(&query.peptides, k, args.max_mismatches);
+        }
+        Ok(Some(query))
+    };
+    // the matcher is set up for the first chunk's peptides and the columns follow its hits
+    let first = read_chunk(None)?.expect("the first chunk is always read");
+    let matcher = self::matcher(args, &first.peptides, taxon_filter, progress)?;
+    let matcher_k = matcher.k();
+    let mut next = Some(first);
+    let mut read_chunk = move || match next.take() {
+        Some(first) => Ok(Some(first)),
+        None => read_chunk(Some(matcher_k)),
+    };
+
+    let id_mapping = args.id_mapping.as_deref().map(IdMapping::load).transpose()?;
+    let mature = args.mature_features.as_deref().map(MatureProteins::load).transpose()?;
+    let composition = args.statistics.then(|| matcher.composition()).transpose()?;
+    let mut writer: Option<Box<dyn MatchWriter>> = None;
+    let mut summary = Summary::default();
+    let mut write_chunk = |query: Query, mut hits: Vec<Match>| -> Result<()> {
+        let Query { peptides, kept, .. } = query;
+        sort_by_keys(&mut hits, &args.sort);
+        let kept_values = if args.unmatched_rows { kept.clone() } else { Vec::new() };
+        let mut hits = if kept.is_empty() { hits } else { join_rows(hits, &peptides, kept) };
+        if let Some(composition) = &composition {
+            matcher.expected_hits(composition, &mut hits);
+        }
+        if let Some(mapping) = &id_mapping {
+            mapping.annotate(&mut hits);
+        }
+        if let Some(mature) = &mature {
+            mature.annotate(&mut hits);
+        }
+        summary.add(&peptides, &hits);
+
+        let out = match &mut writer {
+            Some(out) => out,
+            None => {
+                let columns = Columns {
+                    genomic: args.translate || hits.iter().any(|hit| hit.frame != 0),
+                    context: args.context > 0,
+                    score: args.min_score.is_some(),
+                    n_terminus: args.met_cleavage,
+                    mature: mature.is_some(),
+                    region: args.best_region,
+                    statistics: args.statistics,
+                    id_mapping: id_mapping.is_some(),
+                    protein: args.protein_properties,
+                    kept: kept_names.clone(),
+                    coordinates: args.coordinates.coordinates(),
+                };
+                writer.insert(open_writer(columns, args.format, args.output_db.as_deref())?)
+            }
+        };
+        let unmatched = args.unmatched_rows.then_some((peptides.as_slice(), kept_values.as_slice()));
+        write_rows(out.as_mut(), &hits, unmatched).map_err(output_error)?;
+        out.flush().map_err(output_error)?;
+        if progress && !peptides.is_empty() {
+            info!("Searched {} peptides", summary.peptides);
+        }
+        Ok(())
+    };
+    // rows of a query with kept columns search each of their peptides once
+    let searched = |query: &Query| if query.kept.is_empty() { query.peptides.clone() } else { distinct_peptides(&query.peptides) };
+    let search = |query: &Query| {
+        if args.exclude_source.is_none() {
+            return matcher.match_peptides(&searched(query));
+        }
+        matcher.match_peptides_excluding_sources(&searched(query), &source_filters(&query.peptides, &query.sources))
+    };
+
+    #[cfg(feature = "async-io")]
+    if args.async_io {
+        let read = || Ok(read_chunk()?.map(|query| (searched(&query), query)));
+        pepmatch::pipeline::match_pipelined(&matcher, CHUNKS_SEARCHED_AT_ONCE, read, &mut write_chunk)?;
+    }
+    // nothing's left to read once the pipeline's searched the query
+    while let Some(query) = read_chunk()? {
+        let hits = search(&query)?;
+        write_chunk(query, hits)?;
+    }
+    if let Some(mut writer) = writer {
+        writer.finish().map_err(output_error)?;
+    }
+    finish_summary(args, &summary, started, progress)
 }
 
-// connect to SQLite DB, call it proteome.db
-fn connect() -> rusqlite::Connection {
-    rusqlite::Connection::open("proteome.db").unwrap()
+// log what the search found and write out the peptides without hits if asked to
+fn finish_summary(args: &MatchArgs, summary: &Summary, started: std::time::Instant, progress: bool) -> Result<()> {
+    if progress {
+        for line in summary.report(started.elapsed()) {
+            info!("{}", line);
+        }
+    }
+    if let Some(path) = &args.unmatched_out {
+        summary.write_unmatched(path)?;
+        if progress {
+            info!("Wrote {} peptides without hits to {}", summary.unmatched.len(), path);
+        }
+    }
+    Ok(())
 }
 
-// create a kmers --> index table in the DB
-fn create_kmers_table(conn: &rusqlite::Connection) {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS kmers (
-            kmer             TEXT NOT NULL,
-            idx              INTEGER NOT NULL
-        )",
-        rusqlite::params![],
-    )
-    .unwrap();
+// each peptide once, in the order they first appear
+fn distinct_peptides(peptides: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    peptides.iter().filter(|peptide| seen.insert(peptide.as_str())).cloned().collect()
 }
 
-// create a protein metadata table in the DB
-fn create_metadata_table(conn: &rusqlite::Connection) {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS metadata (
-            protein_number   INTEGER NOT NULL,
-            protein_id       INTEGER NOT NULL,
-            protein_name     TEXT NOT NULL,
-            species          TEXT NOT NULL,
-            taxon_id         TEXT NOT NULL,
-            gene             TEXT NOT NULL,
-            pe_level         INTEGER NOT NULL,
-            sequence_version INTEGER NOT NULL
-        )",
-        rusqlite::params![],
-    )
-    .unwrap();
+// the hits of each row of the query with the values of its kept columns, in the order of the rows
+fn join_rows(hits: Vec<Match>, rows: &[String], kept: Vec<Vec<String>>) -> Vec<Match> {
+    let mut by_peptide: HashMap<String, Vec<Match>> = HashMap::new();
+    for hit in hits {
+        by_peptide.entry(hit.peptide.clone()).or_default().push(hit);
+    }
+    let mut joined = Vec::new();
+    for (peptide, values) in rows.iter().zip(kept) {
+        for hit in by_peptide.get(peptide).into_iter().flatten() {
+            joined.push(Match { kept: values.clone(), ..hit.clone() });
+        }
+    }
+    joined
 }
 
-// insert kmers into the table
-fn insert_kmers(conn: &mut rusqlite::Connection, kmers: &[(String, usize)], protein_count: &usize) {
-    // Disable synchronous mode for faster bulk inserts
-    conn.execute("PRAGMA synchronous = OFF", rusqlite::params![]).unwrap();
+// a writer of hits to stdout in a format, or into a results DB
+fn open_writer(columns: Columns, format: Format, output_db: Option<&str>) -> Result<Box<dyn MatchWriter>> {
+    match output_db {
+        Some(path) => {
+            output::db_writer(path, columns).map_err(|source| PepMatchError::Io { path: path.to_string(), source })
+        }
+        None => Ok(output::writer(format, Box::new(std::io::BufWriter::new(std::io::stdout())), columns)),
+    }
+}
 
-    let tx = conn.transaction().unwrap();
-    let mut stmt = tx
-        .prepare("INSERT INTO kmers (kmer, idx) VALUES (?1, ?2)")
-        .unwrap();
+// write hits to stdout in a format, or into a results DB, with the query's rows and their kept
+// values a row for each without hits too
+fn write_hits(
+    hits: Vec<Match>,
+    unmatched: Option<(&[String], &[Vec<String>])>,
+    columns: Columns,
+    format: Format,
+    output_db: Option<&str>,
+) -> Result<()> {
+    let output_error = |source| PepMatchError::Io { path: output_db.unwrap_or("stdout").to_string(), source };
+    let mut writer = open_writer(columns, format, output_db)?;
+    write_rows(writer.as_mut(), &hits, unmatched).map_err(output_error)?;
+    writer.finish().map_err(output_error)
+}
 
-    for kmer in kmers {
-        stmt.execute(rusqlite::params![kmer.0, (protein_count * 1000000) + kmer.1])
-            .unwrap();
+// write the hits, and given the query's rows a row for each without hits where its hits
+// would be, the hits of the rows come in their order
+fn write_rows(
+    writer: &mut dyn MatchWriter,
+    hits: &[Match],
+    unmatched: Option<(&[String], &[Vec<String>])>,
+) -> std::io::Result<()> {
+    let Some((rows, kept)) = unmatched else {
+        return hits.iter().try_for_each(|hit| writer.write_match(hit));
+    };
+    let with_hits: HashSet<&str> = hits.iter().map(|hit| hit.peptide.as_str()).collect();
+    let mut hits = hits.iter().peekable();
+    for (i, peptide) in rows.iter().enumerate() {
+        while let Some(hit) = hits.next_if(|hit| hit.peptide == *peptide) {
+            writer.write_match(hit)?;
+        }
+        if !with_hits.contains(peptide.as_str()) {
+            writer.write_unmatched(peptide, kept.get(i).map(Vec::as_slice).unwrap_or_default())?;
+        }
     }
+    hits.try_for_each(|hit| writer.write_match(hit))
+}
 
-    drop(stmt); // Explicitly drop stmt before committing the transaction
+// rows for peptides without hits are only written in formats of a row per hit
+fn check_unmatched_rows(args: &MatchArgs) -> Result<()> {
+    if args.unmatched_rows && !matches!(args.format, Format::Tsv | Format::Csv | Format::Json) {
+        return Err(PepMatchError::Invalid("--unmatched-rows can only be written as TSV, CSV or JSON".to_string()));
+    }
+    Ok(())
+}
 
-    tx.commit().unwrap();
+// exact hits of the peptides straight from the proteome FASTA files
+fn scan(args: &MatchArgs, peptides: &[String], progress: bool) -> Result<Vec<Match>> {
+    if args.proteome.is_empty() || args.max_mismatches > 0 {
+        return Err(PepMatchError::Invalid("--no-index needs --proteome and only finds exact matches".to_string()));
+    }
 
-    // Re-enable synchronous mode
-    conn.execute("PRAGMA synchronous = ON", rusqlite::params![]).unwrap();
+    // nothing is k-merized so k doesn't matter
+    scan_proteome(&read_proteomes(args, 1, progress)?, peptides, args.context)
 }
 
-// insert metadata into the table
-fn insert_metadata(conn: &mut rusqlite::Connection, metadata: &[(String, String, String, String, String, String, usize, usize)]) {
-    let tx = conn.transaction().unwrap();
-    let mut stmt = tx
-        .prepare("INSERT INTO metadata (protein_number, protein_id, protein_name, species, taxon_id, gene, pe_level, sequence_version) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")
-        .unwrap();
+// hits of discontinuous epitopes by residue position in the proteome FASTA files
+fn match_epitopes(args: &MatchArgs, epitopes: &[String], progress: bool) -> Result<Vec<Match>> {
+    if args.proteome.is_empty() {
+        return Err(PepMatchError::Invalid("--discontinuous needs --proteome".to_string()));
+    }
+    let epitopes = epitopes
+        .iter()
+        .map(|epitope| epitope.parse())
+        .collect::<Result<Vec<DiscontinuousEpitope>>>()?;
+    match_discontinuous(&read_proteomes(args, 1, progress)?, &epitopes, args.max_mismatches, args.tolerance)
+}
 
-    for data in metadata {
-        stmt.execute(rusqlite::params![data.0, data.1, data.2, data.3, data.4, data.5, data.6, data.7])
-            .unwrap();
+// how the --proteome files are read with the memory backend and --no-index
+fn read_proteomes(args: &MatchArgs, k: usize, progress: bool) -> Result<Preprocessor> {
+    let mut preprocessor = Preprocessor::with_proteomes(&named_proteomes(&args.proteome)?, k)
+        .treat_i_l_equivalent(args.treat_i_l_equivalent)
+        .equivalence_classes(args.equivalence_classes.clone())
+        .header_format(args.header.format()?)
+        .translate(args.translate)
+        .progress(progress);
+    if let Some(gene_priority) = &args.gene_priority {
+        preprocessor = preprocessor.gene_priority(gene_priority);
     }
-    drop(stmt); // explicitly drop stmt before committing the transaction
-    tx.commit().unwrap();
+    Ok(preprocessor)
 }
 
-// create indices on the kmers and metadata tables
-fn create_indices(conn: &mut rusqlite::Connection) {
-    let tx = conn.transaction().unwrap();
+// warn when proteome files a SQLite DB was built from changed since, or build it again
+// from them with --auto-rebuild
+fn check_sources(args: &MatchArgs, db_path: &str, progress: bool) -> Result<()> {
+    let info = BuildInfo::read(db_path)?;
+    let changed: Vec<&str> = info.changed_sources()?.iter().map(|source| source.path.as_str()).collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    if !args.auto_rebuild {
+        warn!(
+            "{} changed since {} was preprocessed, its hits may be out of date, preprocess it again or use --auto-rebuild",
+            changed.join(", "),
+            db_path
+        );
+        return Ok(());
+    }
+    info!("{} changed since {} was preprocessed, preprocessing it again", changed.join(", "), db_path);
+    info.preprocessor(db_path)?.header_format(args.header.format()?).threads(args.threads).progress(progress).preprocess()
+}
 
-    tx.execute("CREATE INDEX IF NOT EXISTS kmer_idx ON kmers (kmer)", rusqlite::params![])
-        .unwrap();
-    tx.execute("CREATE INDEX IF NOT EXISTS protein_number_idx ON metadata (protein_number)", rusqlite::params![])
-        .unwrap();
+// k given for the search, None when it's auto or left out
+fn fixed_k(k: Option<KValue>) -> Option<usize> {
+    match k {
+        Some(KValue::Fixed(k)) => Some(k),
+        _ => None,
+    }
+}
 
-    tx.commit().unwrap();
+// the backend to search with, a proteome given without a DB to search is indexed in memory
+// for the one search rather than preprocessed
+fn backend(args: &MatchArgs) -> Backend {
+    match args.backend {
+        Backend::Sqlite if args.db.is_none() && !args.proteome.is_empty() => Backend::Memory,
+        backend => backend,
+    }
 }
 
-fn main() {
-    let matches = App::new("Preprocess proteome.")
-        .arg(
-            Arg::with_name("proteome").short('p').long("proteome").value_name("FILE")
-                .help("Input FASTA file").takes_value(true).required(true)
-        )
-        .arg(
-            Arg::with_name("k").short('k').long("k_value").value_name("K")
-                .help("Value of k for k-mers").takes_value(true).required(true),
-        )
-        .get_matches();
-
-    let filename = matches.value_of("proteome").unwrap();
-    let k: usize = matches.value_of("k").unwrap().parse()
-        .unwrap_or_else(|_| {
-            eprintln!("Error: k must be an integer");
-            std::process::exit(1);
-        });
-    
-    // parse proteome file and connect to DB
-    let (seqs, metadata) = get_data_from_proteome(filename);
-    let mut conn = connect();
-
-    // create metadata table and insert metadata
-    create_metadata_table(&conn);
-    insert_metadata(&mut conn, &metadata);
-
-    // create kmers table and insert kmers
-    create_kmers_table(&conn);
-    for seq in seqs {
-        let kmers = split_sequence(&seq.0, k);
-        insert_kmers(&mut conn, &kmers, &seq.1);
-    }
-
-    // create indices
-    create_indices(&mut conn);
-}
\ No newline at end of file
+// a matcher for the peptides on a preprocessed DB or an index built in memory
+fn matcher(args: &MatchArgs, peptides: &[String], taxon_filter: TaxonFilter, progress: bool) -> Result<Matcher> {
+    let matcher = match backend(args) {
+        Backend::Memory => {
+            let min_len = peptides.iter().map(|peptide| peptide.len()).min().unwrap_or_default();
+            let k = args.k.unwrap_or(KValue::Auto).resolve(min_len, args.max_mismatches);
+            Matcher::in_memory(&read_proteomes(args, k, progress)?)?
+        }
+        Backend::Fm => {
+            // k only seeds best match, similarity and wildcard searches
+            let min_len = peptides.iter().map(|peptide| peptide.len()).min().unwrap_or_default();
+            let k = match args.k {
+                Some(k) => k.resolve(min_len, args.max_mismatches),
+                None => auto_k(min_len, args.max_mismatches),
+            };
+            Matcher::fm_index(&read_proteomes(args, k, progress)?)?
+        }
+        Backend::Sqlite => {
+            let db_path = args.db.as_deref().ok_or_else(|| {
+                PepMatchError::Invalid("--db is required with the sqlite backend".to_string())
+            })?;
+            check_sources(args, db_path, progress)?;
+            if !args.force {
+                let proteomes = named_proteomes(&args.proteome)?;
+                BuildInfo::read(db_path)?.validate(fixed_k(args.k), args.treat_i_l_equivalent, &args.equivalence_classes, &proteomes)?;
+            }
+            Matcher::new(db_path)?.treat_i_l_equivalent(args.treat_i_l_equivalent).equivalence_classes(args.equivalence_classes.clone())
+        }
+        Backend::Bin | Backend::Duckdb | Backend::Rocksdb | Backend::Lmdb => {
+            let name = match args.backend {
+                Backend::Duckdb => "duckdb",
+                Backend::Rocksdb => "rocksdb",
+                Backend::Lmdb => "lmdb",
+                _ => "bin",
+            };
+            #[cfg(not(feature = "duckdb"))]
+            if matches!(args.backend, Backend::Duckdb) {
+                return Err(PepMatchError::Invalid("the duckdb backend needs pepmatch built with the duckdb feature".to_string()));
+            }
+            let db_path = args.db.as_deref().ok_or_else(|| {
+                PepMatchError::Invalid(format!("--db is required with the {} backend", name))
+            })?;
+            let matcher = Matcher::new(db_path)?;
+            if !args.force {
+                // these indices keep their k and I/L flag themselves rather than in a meta table
+                let info = BuildInfo {
+                    k: Some(matcher.k()),
+                    extra_k: matcher.k_values().into_iter().skip(1).collect(),
+                    i_l_equivalent: matcher.index_i_l_equivalent(),
+                    ..BuildInfo::default()
+                };
+                let proteomes = named_proteomes(&args.proteome)?;
+                info.validate(fixed_k(args.k), args.treat_i_l_equivalent, &args.equivalence_classes, &proteomes)?;
+            }
+            matcher.treat_i_l_equivalent(args.treat_i_l_equivalent).equivalence_classes(args.equivalence_classes.clone())
+        }
+    };
+    // a k given for an index with k-mers of several pins its searches to that k
+    let matcher = match fixed_k(args.k) {
+        Some(k) if matcher.k_values().len() > 1 && matcher.k_values().contains(&k) => matcher.only_k(k)?,
+        _ => matcher,
+    };
+    let mut matcher = matcher
+        .max_mismatches(args.max_mismatches)
+        .best_match(args.best_match)
+        .best_region(args.best_region)
+        .taxon_filter(taxon_filter)
+        .protein_filter(args.protein_filter.filter())
+        .accession_filter(accession_filter(args)?)
+        .context(args.context)
+        .met_cleavage(args.met_cleavage)
+        .deamidation(args.chemical_equivalence.contains(&ChemicalEquivalence::Deamidation))
+        .threads(args.threads)
+        .one_match_per_protein(args.one_match_per_protein)
+        .expand_synonyms(args.expand_synonyms)
+        .progress(progress);
+    if let Some(limit) = args.limit {
+        matcher = matcher.limit(limit);
+    }
+    if let Some(min_score) = args.min_score {
+        matcher = matcher.similarity(ScoringMatrix::from_file(&args.matrix)?, min_score);
+    }
+    if !args.only_proteome.is_empty() {
+        matcher = matcher.only_proteomes(&args.only_proteome)?;
+    }
+    let cache_path = ResultCache::default_path()?;
+    if args.clear_cache {
+        ResultCache::clear(&cache_path)?;
+    }
+    if !args.no_cache && !matches!(backend(args), Backend::Memory | Backend::Fm) {
+        // searching doesn't need the cache, so one that can't be opened is only warned about
+        match ResultCache::open(&cache_path) {
+            Ok(cache) => matcher = matcher.cache(cache)?,
+            Err(e) => warn!("not caching results, {}", e),
+        }
+    }
+    if !args.best_match && !matches!(args.backend, Backend::Fm) {
+        warn_short_peptides(peptides, matcher.k(), args.max_mismatches);
+    }
+    Ok(matcher)
+}
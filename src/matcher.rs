@@ -0,0 +1,1309 @@
+use crate::accessions::AccessionFilter;
+#[cfg(feature = "sqlite")]
+use crate::cache::{self, ResultCache};
+use crate::coverage::{self, ProteinCoverage};
+use crate::equivalence::EquivalenceClasses;
+use crate::error::{PepMatchError, Result};
+use crate::hamming::mismatches_within;
+use crate::index::{
+    self, FilteredIndex, FmIndex, Location, MemoryIndex, PrefetchedIndex, ProteinMetadata, ProteomeIndex,
+    SharedIndex,
+};
+use crate::preprocessor::{canonicalize_i_l, check_k};
+use crate::progress;
+use crate::proteome::ProteinFilter;
+use crate::ranking;
+use crate::similarity::ScoringMatrix;
+use crate::statistics::Composition;
+use crate::taxonomy::TaxonFilter;
+use crate::translate::genomic_range;
+use crate::Preprocessor;
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+// number of peptides whose k-mers are looked up together in one batch
+const PEPTIDES_PER_BATCH: usize = 1000;
+
+// most k-mers a k-mer with wildcard residues is expanded into for seeding, one X or
+// up to four of B, Z and J, k-mers with more are masked and don't seed hits
+const MAX_KMER_VARIANTS: usize = 20;
+
+// the residues the X wildcard expands into
+const STANDARD_RESIDUES: &[u8] = b"ACDEFGHIKLMNPQRSTVWY";
+
+// a full-length hit of a query peptide in the proteome, positions are 1-based
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub peptide: String,
+    pub matched_sequence: String,
+    pub protein_number: usize, // number of the protein in the index it was found in
+    pub protein_id: String,
+    pub protein_name: String,
+    pub species: String,
+    pub taxon_id: String,
+    pub gene: String,
+    pub mismatches: usize,
+    pub mutated_positions: Vec<usize>, // positions in the peptide that differ from the protein
+    pub index_start: usize,
+    pub index_end: usize,
+    pub pe_level: usize, // protein existence level, 0 if missing from the header
+    pub gene_priority: bool, // the protein is in the gene priority proteome
+    pub db: String, // UniProt database of the protein, sp (Swiss-Prot) or tr (TrEMBL), empty for others
+    pub isoform: usize, // number of a non-canonical isoform, 0 for canonical proteins
+    pub protein_length: usize, // residues in the protein, 0 from DBs preprocessed before lengths were stored
+    pub molecular_weight: usize, // average mass of the protein in Da, 0 from DBs preprocessed before it was stored
+    pub discontinuous: bool, // the peptide and matched sequence are residue lists like A12, K15, R18
+    pub frame: i8, // reading frame of a hit in a translated nucleotide sequence, 0 for proteins
+    pub genomic_start: usize, // 1-based bases of the nucleotide sequence coding the hit, 0 for proteins
+    pub genomic_end: usize,
+    pub left_context: String, // residues of the protein flanking the hit, if asked for
+    pub right_context: String,
+    pub score: i32, // substitution matrix score of the hit in similarity searches, 0 otherwise
+    pub n_terminus: String, // protein or mature for hits at a protein's N-terminus, if asked for
+    pub mature_start: usize, // 1-based positions of the hit on the mature protein from its cleaved features, 0 for none
+    pub mature_end: usize,
+    pub query_start: usize, // 1-based positions in the peptide of a best region, reported for want of a full-length hit, 0 for hits
+    pub query_end: usize,
+    pub e_value: f64, // expected number of random hits of the peptide with its mismatches from the proteome's composition, if asked for
+    pub ensembl: String, // ;-separated IDs of the protein in Ensembl, RefSeq and HGNC from an ID mapping, if given
+    pub refseq: String,
+    pub hgnc: String,
+    pub kept: Vec<String>, // values of the query row's columns kept with the hit, in the order of Columns::kept
+}
+
+impl Match {
+    // each mismatch as position:query residue>protein residue, e.g. 3:A>V,
+    // or query residue>protein residue for discontinuous epitopes, e.g. R18>G18
+    pub fn substitutions(&self) -> Vec<String> {
+        if self.discontinuous {
+            let query: Vec<&str> = self.peptide.split(", ").collect();
+            let matched: Vec<&str> = self.matched_sequence.split(", ").collect();
+            return self.mutated_positions
+                .iter()
+                .map(|&i| format!("{}>{}", query[i - 1], matched[i - 1]))
+                .collect();
+        }
+        let query = self.peptide.as_bytes();
+        // the matched sequence of a best region starts at its query start
+        let matched = self.matched_sequence.as_bytes();
+        let offset = self.query_start.saturating_sub(1);
+        self.mutated_positions
+            .iter()
+            .map(|&position| {
+                format!("{}:{}>{}", position, query[position - 1] as char, matched[position - 1 - offset] as char)
+            })
+            .collect()
+    }
+}
+
+// searches peptides against a proteome DB written by the Preprocessor
+// or an index built in memory
+pub struct Matcher {
+    index: Box<dyn ProteomeIndex + Send>,
+    search: Search,
+    progress: bool,
+    threads: usize,
+    db_path: Option<String>, // the preprocessed DB searched, none for indices built in memory
+    only_proteomes: Vec<String>,
+    #[cfg(feature = "sqlite")]
+    cache: Option<ResultCache>,
+}
+
+// how peptides are searched, shared by the threads matching them
+#[derive(Clone)]
+struct Search {
+    k: usize,
+    k_values: Vec<usize>, // every k the index can be searched with, k first
+    any_k: bool,          // whether the index finds k-mers of any length
+    max_mismatches: usize,
+    best_match: bool,
+    best_region: bool,
+    i_l_equivalent: bool,
+    deamidation: bool,
+    equivalence_classes: EquivalenceClasses,
+    taxon_filter: TaxonFilter,
+    protein_filter: ProteinFilter,
+    accession_filter: AccessionFilter,
+    context: usize,
+    met_cleavage: bool,
+    similarity: Option<(ScoringMatrix, i32)>,
+    limit: Option<usize>,
+    one_per_protein: bool,
+    expand_synonyms: bool,
+}
+
+impl Matcher {
+    // open the preprocessed DB and get k from the stored k-mers, DBs preprocessed
+    // by the Python PEPMatch can be searched too
+    pub fn new(db_path: &str) -> Result<Matcher> {
+        let mut matcher = Matcher::with_index(index::open_db(db_path)?)?;
+        matcher.db_path = Some(db_path.to_string());
+        Ok(matcher)
+    }
+
+    // build the index the preprocessor would write to a DB in memory instead
+    pub fn in_memory(preprocessor: &Preprocessor) -> Result<Matcher> {
+        check_k(preprocessor.k)?;
+        for k in &preprocessor.extra_k {
+            check_k(*k)?;
+        }
+        Ok(Matcher::with_index(Box::new(SharedIndex::new(MemoryIndex::build(preprocessor)?)))?
+            .treat_i_l_equivalent(preprocessor.i_l_equivalent)
+            .equivalence_classes(preprocessor.equivalence_classes.clone())
+            .progress(preprocessor.progress))
+    }
+
+    // search an FM-index of the proteome built in memory, which looks peptides of any
+    // length up without k-mers, the preprocessor's k only seeds best match, similarity
+    // and wildcard searches
+    pub fn fm_index(preprocessor: &Preprocessor) -> Result<Matcher> {
+        check_k(preprocessor.k)?;
+        Ok(Matcher::with_index(Box::new(SharedIndex::new(FmIndex::build(preprocessor)?)))?
+            .treat_i_l_equivalent(preprocessor.i_l_equivalent)
+            .equivalence_classes(preprocessor.equivalence_classes.clone())
+            .progress(preprocessor.progress))
+    }
+
+    fn with_index(index: Box<dyn ProteomeIndex + Send>) -> Result<Matcher> {
+        let k = index.k();
+        check_k(k)?;
+        let k_values = index.k_values();
+        let any_k = index.any_k();
+        Ok(Matcher {
+            index,
+            search: Search {
+                k,
+                k_values,
+                any_k,
+                max_mismatches: 0,
+                best_match: false,
+                best_region: false,
+                i_l_equivalent: false,
+                deamidation: false,
+                equivalence_classes: EquivalenceClasses::default(),
+                taxon_filter: TaxonFilter::new(),
+                protein_filter: ProteinFilter::new(),
+                accession_filter: AccessionFilter::new(),
+                context: 0,
+                met_cleavage: false,
+                similarity: None,
+                limit: None,
+                one_per_protein: false,
+                expand_synonyms: false,
+            },
+            progress: false,
+            threads: 0,
+            db_path: None,
+            only_proteomes: Vec::new(),
+            #[cfg(feature = "sqlite")]
+            cache: None,
+        })
+    }
+
+    // allow up to n residue mismatches per hit, every mismatch needs another
+    // k-mer to seed with so peptides should be at least k * (n + 1) long
+    pub fn max_mismatches(mut self, n: usize) -> Matcher {
+        self.search.max_mismatches = n;
+        self
+    }
+
+    // only report the single best hit per peptide, raising the mismatch tolerance
+    // from 0 as far as the peptide's k-mers allow until something is found
+    pub fn best_match(mut self, best_match: bool) -> Matcher {
+        self.search.best_match = best_match;
+        self
+    }
+
+    // in best match mode, report the longest stretch of a peptide without a full-length hit
+    // that's found exactly in a protein instead, stretches shorter than k aren't looked for
+    pub fn best_region(mut self, best_region: bool) -> Matcher {
+        self.search.best_region = best_region;
+        self
+    }
+
+    // treat I and L as the same residue, the DB must have been preprocessed the same way
+    pub fn treat_i_l_equivalent(mut self, treat_i_l_equivalent: bool) -> Matcher {
+        self.search.i_l_equivalent = treat_i_l_equivalent;
+        self
+    }
+
+    // treat the residues of each class as the same residue, the DB must have been preprocessed
+    // with the same classes
+    pub fn equivalence_classes(mut self, equivalence_classes: EquivalenceClasses) -> Matcher {
+        self.search.equivalence_classes = equivalence_classes;
+        self
+    }
+
+    // treat N and D, and Q and E, as the same residue, for peptides from mass spectrometry
+    // that were deamidated, their k-mers seed with every residue they could stand for so
+    // k-mers with many of them are masked like wildcards
+    pub fn deamidation(mut self, deamidation: bool) -> Matcher {
+        self.search.deamidation = deamidation;
+        self
+    }
+
+    // show a progress bar on stderr while matching peptides
+    pub fn progress(mut self, progress: bool) -> Matcher {
+        self.progress = progress;
+        self
+    }
+
+    // match batches of peptides on this many threads, each with its own connection to
+    // a DB, 0 uses all cores, hits come out in the same order whatever the number
+    pub fn threads(mut self, threads: usize) -> Matcher {
+        self.threads = threads;
+        self
+    }
+
+    // only report hits in proteins of the filter's taxa, applied before picking best matches
+    pub fn taxon_filter(mut self, taxon_filter: TaxonFilter) -> Matcher {
+        self.search.taxon_filter = taxon_filter;
+        self
+    }
+
+    // only report hits in proteins the filter passes, by PE level or Swiss-Prot review,
+    // applied before picking best matches
+    pub fn protein_filter(mut self, protein_filter: ProteinFilter) -> Matcher {
+        self.search.protein_filter = protein_filter;
+        self
+    }
+
+    // only report hits in proteins the filter passes by accession, applied before picking
+    // best matches so a left out protein's hit gives way to the next best one
+    pub fn accession_filter(mut self, accession_filter: AccessionFilter) -> Matcher {
+        self.search.accession_filter = accession_filter;
+        self
+    }
+
+    // report up to n residues of the protein on either side of each hit
+    pub fn context(mut self, n: usize) -> Matcher {
+        self.search.context = n;
+        self
+    }
+
+    // flag hits at the N-terminus of their protein, and hits starting right after an initiator
+    // Met, which is cleaved off many mature proteins
+    pub fn met_cleavage(mut self, met_cleavage: bool) -> Matcher {
+        self.search.met_cleavage = met_cleavage;
+        self
+    }
+
+    // score hits with a substitution matrix and report those scoring at least min_score
+    // instead of counting mismatches, so conservative substitutions cost less than others,
+    // hits still need one of the peptide's k-mers intact to be found
+    pub fn similarity(mut self, matrix: ScoringMatrix, min_score: i32) -> Matcher {
+        self.search.similarity = Some((matrix, min_score));
+        self
+    }
+
+    // report at most n hits per peptide, the best ones, for peptides in repeats
+    // that hit thousands of places
+    pub fn limit(mut self, n: usize) -> Matcher {
+        self.search.limit = Some(n);
+        self
+    }
+
+    // only report the best hit of a peptide in each protein
+    pub fn one_match_per_protein(mut self, one_per_protein: bool) -> Matcher {
+        self.search.one_per_protein = one_per_protein;
+        self
+    }
+
+    // report a hit in a protein of a DB preprocessed with identical sequences deduplicated
+    // in every protein with its sequence too, each filtered on its own
+    pub fn expand_synonyms(mut self, expand_synonyms: bool) -> Matcher {
+        self.search.expand_synonyms = expand_synonyms;
+        self
+    }
+
+    // only search the proteins of the named proteomes of a multi-proteome index
+    pub fn only_proteomes(mut self, names: &[String]) -> Result<Matcher> {
+        let proteomes = self.index.proteomes()?;
+        for name in names {
+            if !proteomes.iter().any(|(proteome, _, _)| proteome == name) {
+                let known: Vec<String> = proteomes.into_iter().map(|(proteome, _, _)| proteome).collect();
+                return Err(PepMatchError::invalid(format!(
+                    "no proteome named {}, the index has: {}",
+                    name,
+                    known.join(", ")
+                )));
+            }
+        }
+
+        let selected = proteomes.into_iter().filter(|(proteome, _, _)| names.contains(proteome)).collect();
+        self.index = Box::new(FilteredIndex::new(self.index, selected));
+        self.only_proteomes.extend(names.iter().cloned());
+        Ok(self)
+    }
+
+    // look peptides up in a cache of earlier searches of the same DB with the same settings
+    // before searching them, then cache the hits of the rest, only DBs and binary indices
+    // can be cached as an index built in memory has nothing to tell it apart
+    #[cfg(feature = "sqlite")]
+    pub fn cache(mut self, cache: ResultCache) -> Result<Matcher> {
+        if self.db_path.is_none() {
+            return Err(PepMatchError::invalid("only searches of a preprocessed DB can be cached"));
+        }
+        self.cache = Some(cache);
+        Ok(self)
+    }
+
+    // a matcher on the same index with the default search settings, to search with other
+    // settings without opening or building the index again, an index in memory is shared
+    pub fn share(&self) -> Result<Matcher> {
+        let mut matcher = Matcher::with_index(self.index.worker()?)?
+            .treat_i_l_equivalent(self.search.i_l_equivalent)
+            .equivalence_classes(self.search.equivalence_classes.clone())
+            .threads(self.threads);
+        matcher.db_path = self.db_path.clone();
+        matcher.only_proteomes = self.only_proteomes.clone();
+        Ok(matcher)
+    }
+
+    // a matcher on another handle on the index with the same search settings and cache, to
+    // search from another thread at the same time as this one
+    pub fn fork(&self) -> Result<Matcher> {
+        Ok(Matcher {
+            index: self.index.worker()?,
+            search: self.search.clone(),
+            progress: false,
+            threads: self.threads,
+            db_path: self.db_path.clone(),
+            only_proteomes: self.only_proteomes.clone(),
+            #[cfg(feature = "sqlite")]
+            cache: self.cache.as_ref().map(ResultCache::reopen).transpose()?,
+        })
+    }
+
+    // a matcher on the DB opened again, to search it once it's been replaced, with the I/L
+    // setting, equivalence classes and proteomes of this one
+    pub fn reopen(&self) -> Result<Matcher> {
+        let db_path = self.db_path.as_deref().ok_or_else(|| PepMatchError::invalid("an index built in memory can't be reopened"))?;
+        let matcher = Matcher::new(db_path)?
+            .treat_i_l_equivalent(self.search.i_l_equivalent)
+            .equivalence_classes(self.search.equivalence_classes.clone())
+            .threads(self.threads);
+        if self.only_proteomes.is_empty() {
+            Ok(matcher)
+        } else {
+            matcher.only_proteomes(&self.only_proteomes)
+        }
+    }
+
+    pub fn k(&self) -> usize {
+        self.search.k
+    }
+
+    // every k the index has k-mers of, the index's own k first, each peptide is looked up
+    // with the one that suits its length and the mismatches searched for
+    pub fn k_values(&self) -> Vec<usize> {
+        self.search.k_values.clone()
+    }
+
+    // only look peptides up with one of the index's k
+    pub fn only_k(mut self, k: usize) -> Result<Matcher> {
+        if !self.search.k_values.contains(&k) {
+            let known: Vec<String> = self.search.k_values.iter().map(usize::to_string).collect();
+            return Err(PepMatchError::invalid(format!("the index has no k-mers of k = {}, only of {}", k, known.join(", "))));
+        }
+        self.search.k = k;
+        self.search.k_values = vec![k];
+        Ok(self)
+    }
+
+    // whether the index was built treating I and L as equivalent, None if it doesn't say
+    pub fn index_i_l_equivalent(&self) -> Option<bool> {
+        self.index.i_l_equivalent()
+    }
+
+    // names of the proteomes in the index
+    pub fn proteomes(&self) -> Result<Vec<String>> {
+        Ok(self.index.proteomes()?.into_iter().map(|(name, _, _)| name).collect())
+    }
+
+    // aggregate hits found by this matcher per protein with the coverage of the protein
+    pub fn coverage(&self, hits: &[Match]) -> Result<Vec<ProteinCoverage>> {
+        coverage::aggregate(hits, |protein_number| self.index.protein_sequence(protein_number))
+    }
+
+    // the residue composition and protein lengths of the proteins searched, with their residues
+    // the same as the search takes them
+    pub fn composition(&self) -> Result<Composition> {
+        let mut sequences = Vec::new();
+        for (_, first, last) in self.index.proteomes()? {
+            for protein_number in first..=last {
+                let sequence = self.index.protein_sequence(protein_number)?;
+                let sequence = self.search.equivalence_classes.canonicalize(&sequence);
+                sequences.push(if self.search.i_l_equivalent { canonicalize_i_l(&sequence) } else { sequence.into_owned() });
+            }
+        }
+        Ok(Composition::from_sequences(sequences.iter().map(String::as_str)))
+    }
+
+    // give hits the number of hits their peptides are expected to have by chance in a random
+    // proteome of the composition, with up to as many mismatches as each hit has
+    pub fn expected_hits(&self, composition: &Composition, hits: &mut [Match]) {
+        composition.annotate(hits, |peptide| self.search.query(peptide));
+    }
+
+    // find all matches of a single peptide within the mismatch tolerance,
+    // or only the best one in best match mode
+    pub fn match_peptide(&self, peptide: &str) -> Result<Vec<Match>> {
+        self.search.match_peptide(self.index.as_ref(), peptide, &AccessionFilter::new())
+    }
+
+    // find all matches of every peptide, in the order of the peptides
+    pub fn match_peptides(&self, peptides: &[String]) -> Result<Vec<Match>> {
+        #[cfg(feature = "sqlite")]
+        if let (Some(cache), Some(db_path)) = (&self.cache, &self.db_path) {
+            return self.match_cached(cache, db_path, peptides);
+        }
+        let hits = self.for_each_peptide(peptides, |search, index, peptide| {
+            search.match_peptide(index, peptide, &AccessionFilter::new())
+        })?;
+        Ok(hits.into_iter().flatten().collect())
+    }
+
+    // find all matches of every peptide like match_peptides, leaving out each peptide's hits in
+    // the proteins its source filter doesn't pass before picking best matches, the sources of
+    // peptides differ from query to query so these searches aren't cached
+    pub fn match_peptides_excluding_sources(
+        &self,
+        peptides: &[String],
+        sources: &HashMap<String, AccessionFilter>,
+    ) -> Result<Vec<Match>> {
+        let none = AccessionFilter::new();
+        let hits = self.for_each_peptide(peptides, |search, index, peptide| {
+            search.match_peptide(index, peptide, sources.get(peptide).unwrap_or(&none))
+        })?;
+        Ok(hits.into_iter().flatten().collect())
+    }
+
+    // look every peptide up in the cache, searching only the peptides that aren't
+    // cached yet, each once
+    #[cfg(feature = "sqlite")]
+    fn match_cached(&self, cache: &ResultCache, db_path: &str, peptides: &[String]) -> Result<Vec<Match>> {
+        let key = cache::digest(&format!(
+            "{} {} {} proteomes {:?} {}",
+            env!("CARGO_PKG_VERSION"),
+            db_path,
+            cache::index_fingerprint(db_path)?,
+            self.only_proteomes,
+            self.search.key()
+        ));
+        let mut cached = peptides
+            .iter()
+            .map(|peptide| cache.get(&key, peptide))
+            .collect::<Result<Vec<Option<Vec<Match>>>>>()?;
+        let mut seen = HashSet::new();
+        let missing: Vec<String> = peptides
+            .iter()
+            .zip(&cached)
+            .filter(|(peptide, hits)| hits.is_none() && seen.insert(peptide.as_str()))
+            .map(|(peptide, _)| peptide.clone())
+            .collect();
+        let found = self.for_each_peptide(&missing, |search, index, peptide| {
+            search.match_peptide(index, peptide, &AccessionFilter::new())
+        })?;
+        let results: Vec<(&str, &[Match])> =
+            missing.iter().map(String::as_str).zip(found.iter().map(Vec::as_slice)).collect();
+        cache.put(&key, &results)?;
+
+        let found: HashMap<&str, &[Match]> = results.into_iter().collect();
+        let mut hits = Vec::new();
+        for (peptide, cached) in peptides.iter().zip(&mut cached) {
+            match cached.take() {
+                Some(cached) => hits.extend(cached),
+                None => hits.extend_from_slice(found[peptide.as_str()]),
+            }
+        }
+        Ok(hits)
+    }
+
+    // the number of hits of every peptide, in the order of the peptides, without keeping them
+    pub fn count_matches(&self, peptides: &[String]) -> Result<Vec<usize>> {
+        self.for_each_peptide(peptides, |search, index, peptide| {
+            Ok(search.match_peptide(index, peptide, &AccessionFilter::new())?.len())
+        })
+    }
+
+    // search every peptide, in batches whose k-mers are looked up together to save
+    // round-trips to the index, returning what search_peptide gets for each in order,
+    // a peptide that's repeated is searched once and its repeats get copies of its results
+    fn for_each_peptide<T: Send + Clone>(
+        &self,
+        peptides: &[String],
+        search_peptide: impl Fn(&Search, &dyn ProteomeIndex, &str) -> Result<T> + Sync,
+    ) -> Result<Vec<T>> {
+        let (distinct, of_peptide) = distinct_peptides(peptides);
+        if distinct.len() == peptides.len() {
+            return self.for_each_distinct_peptide(peptides, search_peptide);
+        }
+        let results = self.for_each_distinct_peptide(&distinct, search_peptide)?;
+        Ok(of_peptide.into_iter().map(|i| results[i].clone()).collect())
+    }
+
+    fn for_each_distinct_peptide<T: Send>(
+        &self,
+        peptides: &[String],
+        search_peptide: impl Fn(&Search, &dyn ProteomeIndex, &str) -> Result<T> + Sync,
+    ) -> Result<Vec<T>> {
+        let span = tracing::info_span!("search", peptides = peptides.len());
+        let _entered = span.enter();
+        let bar = progress::peptides(self.progress, peptides.len());
+        let batches: Vec<&[String]> = peptides.chunks(PEPTIDES_PER_BATCH).collect();
+        let threads = if self.threads == 0 { rayon::current_num_threads() } else { self.threads };
+        let search = &self.search;
+        let search_batch = |index: &dyn ProteomeIndex, batch: &[String]| -> Result<Vec<T>> {
+            // batches searched on the pool's threads are still children of the search
+            let _span = tracing::debug_span!(parent: &span, "search_batch", peptides = batch.len()).entered();
+            let index = search.prefetch(index, batch)?;
+            batch.iter()
+                .map(|peptide| {
+                    let found = search_peptide(search, &index, peptide);
+                    bar.inc(1);
+                    found
+                })
+                .collect()
+        };
+
+        let results = if threads <= 1 || batches.len() <= 1 {
+            let mut results = Vec::new();
+            for batch in batches {
+                results.extend(search_batch(self.index.as_ref(), batch)?);
+            }
+            results
+        } else {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads.min(batches.len()))
+                .build()
+                .map_err(PepMatchError::invalid)?;
+            // one handle on the index per thread, each only ever used by its own thread
+            let workers = (0..pool.current_num_threads())
+                .map(|_| self.index.worker().map(Mutex::new))
+                .collect::<Result<Vec<_>>>()?;
+            let batch_results = pool.install(|| {
+                batches
+                    .par_iter()
+                    .map(|batch| {
+                        let worker = &workers[rayon::current_thread_index().unwrap_or_default()];
+                        let index = worker.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                        search_batch(index.as_ref(), batch)
+                    })
+                    .collect::<Result<Vec<Vec<T>>>>()
+            })?;
+            batch_results.into_iter().flatten().collect()
+        };
+        bar.finish_and_clear();
+        Ok(results)
+    }
+}
+
+impl Search {
+    // every setting that changes the hits of a peptide, for keying cached results
+    #[cfg(feature = "sqlite")]
+    fn key(&self) -> String {
+        let similarity = match &self.similarity {
+            Some((matrix, min_score)) => format!("{} >= {}", cache::digest(&matrix.key()), min_score),
+            None => "none".to_string(),
+        };
+        let k_values: Vec<String> = self.k_values.iter().map(usize::to_string).collect();
+        format!(
+            "k {} mismatches {} best {} region {} i/l {} deamidation {} classes {} taxa {} proteins {:?} accessions {} context {} met {} similarity {} limit {:?} per protein {} synonyms {}",
+            k_values.join(","),
+            self.max_mismatches,
+            self.best_match,
+            self.best_region,
+            self.i_l_equivalent,
+            self.deamidation,
+            self.equivalence_classes,
+            self.taxon_filter.key(),
+            self.protein_filter,
+            self.accession_filter.key(),
+            self.context,
+            self.met_cleavage,
+            similarity,
+            self.limit,
+            self.one_per_protein,
+            self.expand_synonyms
+        )
+    }
+
+    // the k a peptide is looked up with when searching for hits with up to max_mismatches,
+    // of an index with k-mers of several the largest that still leaves one of its k-mers
+    // intact, and the smallest if none does or the mismatches aren't bounded
+    fn k_for(&self, peptide: &str, max_mismatches: usize) -> usize {
+        if self.any_k {
+            // looked up whole, or by the largest k-mers that stay intact
+            if self.similarity.is_some() || has_wildcards(&self.query(peptide)) {
+                return self.k;
+            }
+            return auto_k(peptide.len(), max_mismatches);
+        }
+        if self.k_values.len() == 1 {
+            return self.k;
+        }
+        let smallest = self.k_values.iter().copied().min().unwrap_or(self.k);
+        if self.best_match || self.similarity.is_some() {
+            return smallest;
+        }
+        let largest_intact = auto_k(peptide.len(), max_mismatches);
+        self.k_values.iter().copied().filter(|k| *k <= largest_intact).max().unwrap_or(smallest)
+    }
+
+    // whether a peptide is shorter than every k it could be looked up with, so it has no
+    // k-mers and the protein sequences are scanned for it instead
+    fn scans(&self, peptide: &str) -> bool {
+        !self.any_k && peptide.len() < self.k_for(peptide, 0)
+    }
+
+    // fetch the k-mers of a batch of peptides from the index at once
+    fn prefetch<'a>(&self, index: &'a dyn ProteomeIndex, batch: &[String]) -> Result<PrefetchedIndex<'a>> {
+        // every search of a peptide looks up the same k-mers whatever the mismatches
+        let kmers = batch
+            .iter()
+            .map(|peptide| (peptide, self.k_for(peptide, self.max_mismatches)))
+            .filter(|(peptide, k)| peptide.len() >= *k)
+            .flat_map(|(peptide, k)| {
+                let query = self.query(peptide);
+                get_kmer_offsets(query.len(), k)
+                    .into_iter()
+                    .flat_map(move |offset| expand_wildcards(&query[offset..offset + k]))
+            })
+            .collect();
+        PrefetchedIndex::fetch(index, kmers)
+    }
+
+    // the hits of a peptide in the proteins its sources pass
+    fn match_peptide(&self, index: &dyn ProteomeIndex, peptide: &str, sources: &AccessionFilter) -> Result<Vec<Match>> {
+        if !self.best_match {
+            let hits = self.find_matches(index, peptide, self.max_mismatches, sources)?;
+            return Ok(ranking::limit_matches(hits, self.limit, self.one_per_protein));
+        }
+        if self.similarity.is_some() {
+            // the mismatch tolerance doesn't bound similarity searches
+            return Ok(ranking::best_match(self.find_matches(index, peptide, 0, sources)?).into_iter().collect());
+        }
+
+        // with n disjoint k-mers a hit with up to n - 1 mismatches keeps one of them intact,
+        // an index of any k or a scan tries up to half the residues mismatched
+        let limit = if self.any_k || self.scans(peptide) {
+            peptide.len() / 2
+        } else {
+            (peptide.len() / self.k_for(peptide, 0)).saturating_sub(1)
+        };
+        for max_mismatches in 0..=limit {
+            let hits = self.find_matches(index, peptide, max_mismatches, sources)?;
+            if let Some(best) = ranking::best_match(hits) {
+                return Ok(vec![best]);
+            }
+        }
+        if self.best_region {
+            return Ok(self.best_region(index, peptide, sources)?.into_iter().collect());
+        }
+        Ok(Vec::new())
+    }
+
+    // the best hit of the longest stretch of a peptide found exactly in a protein the filters
+    // pass, extended both ways from each location of each of its k-mers, so stretches shorter
+    // than the smallest k of the index aren't found
+    fn best_region(&self, index: &dyn ProteomeIndex, peptide: &str, sources: &AccessionFilter) -> Result<Option<Match>> {
+        let query = self.query(peptide).into_bytes();
+        let k = if self.any_k { self.k } else { self.k_values.iter().copied().min().unwrap_or(self.k) };
+        if query.len() < k {
+            return Ok(None);
+        }
+
+        let mut sequences: HashMap<usize, Vec<u8>> = HashMap::new();
+        let mut regions: HashSet<(Location, usize, usize)> = HashSet::new(); // protein start, query start and end
+        for offset in 0..=query.len() - k {
+            let kmer = String::from_utf8_lossy(&query[offset..offset + k]).to_string();
+            for variant in expand_wildcards(&kmer) {
+                for (protein_number, position) in index.lookup(&variant)? {
+                    let sequence = match sequences.entry(protein_number) {
+                        std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                        std::collections::hash_map::Entry::Vacant(entry) => {
+                            let sequence = index.protein_sequence(protein_number)?;
+                            entry.insert(self.equivalence_classes.canonicalize(&sequence).into_owned().into_bytes())
+                        }
+                    };
+                    let matches = |q: usize, p: usize| residues_match(query[q], sequence[p], self.i_l_equivalent);
+                    let left = (1..=offset.min(position)).take_while(|i| matches(offset - i, position - i)).count();
+                    let right = (offset + k..query.len())
+                        .take_while(|q| position + q - offset < sequence.len() && matches(*q, position + q - offset))
+                        .count();
+                    regions.insert(((protein_number, position - left), offset - left, offset + k + right));
+                }
+            }
+        }
+
+        // the longest regions first, falling back on shorter ones when the filters pass none
+        let mut regions: Vec<(Location, usize, usize)> = regions.into_iter().collect();
+        regions.sort_by_key(|(location, start, end)| (std::cmp::Reverse(end - start), *location, *start));
+        for longest in regions.chunk_by(|a, b| a.2 - a.1 == b.2 - b.1) {
+            let mut hits = Vec::new();
+            for &(location, start, end) in longest {
+                let metadata = index.protein_metadata(location.0)?;
+                if !self.taxon_filter.allows(&metadata.taxon_id, &metadata.species)
+                    || !self.protein_filter.allows(metadata.pe_level, &metadata.db, metadata.length)
+                    || !self.accession_filter.allows(&metadata.protein_id)
+                    || !sources.allows(&metadata.protein_id)
+                {
+                    continue;
+                }
+                let matched_sequence = index.protein_region(location, end - start)?;
+                let mut hit = build_match(&peptide[start..end], matched_sequence, location, metadata, self.i_l_equivalent);
+                hit.peptide = peptide.to_string();
+                // mismatches of equivalent residues are at their positions in the whole peptide
+                hit.mutated_positions.iter_mut().for_each(|position| *position += start);
+                (hit.query_start, hit.query_end) = (start + 1, end);
+                hits.push(hit);
+            }
+            if let Some(best) = ranking::best_match(hits) {
+                return Ok(Some(best));
+            }
+        }
+        Ok(None)
+    }
+
+    fn find_matches(
+        &self,
+        index: &dyn ProteomeIndex,
+        peptide: &str,
+        max_mismatches: usize,
+        sources: &AccessionFilter,
+    ) -> Result<Vec<Match>> {
+        let query = self.query(peptide);
+        let k = self.k_for(peptide, max_mismatches);
+        let classes = &self.equivalence_classes;
+
+        let hits = if self.scans(peptide) {
+            // every window of every protein is compared, similarity searches score them all
+            let max_mismatches = if self.similarity.is_some() { peptide.len() } else { max_mismatches };
+            scan_matches(index, &query, max_mismatches, self.i_l_equivalent, classes)?
+        } else if self.similarity.is_some() {
+            // every seeded candidate is scored below rather than counting its mismatches
+            find_mismatched_matches(index, &query, k, peptide.len(), self.i_l_equivalent, classes)?
+        } else if max_mismatches == 0 && !has_wildcards(&query) {
+            find_exact_matches(index, &query, k)?
+                .into_iter()
+                .map(|location| {
+                    // report the protein's own residues rather than the canonical ones
+                    let matched_sequence = if self.i_l_equivalent || !classes.is_empty() {
+                        index.protein_window(location, peptide.len())?.unwrap_or_default()
+                    } else {
+                        peptide.to_string()
+                    };
+                    Ok((location, matched_sequence))
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            find_mismatched_matches(index, &query, k, max_mismatches, self.i_l_equivalent, classes)?
+        };
+
+        let mut matches = Vec::new();
+        for (start, matched_sequence) in hits {
+            let score = match &self.similarity {
+                Some((matrix, min_score)) => {
+                    let score = matrix.score_alignment(peptide, &matched_sequence, |a, b| {
+                        residues_match(a, b, self.i_l_equivalent) || classes.equivalent(a, b)
+                    });
+                    if score < *min_score {
+                        continue;
+                    }
+                    score
+                }
+                None => 0,
+            };
+            let mut proteins = vec![(start.0, index.protein_metadata(start.0)?)];
+            if self.expand_synonyms {
+                // synonyms have the sequence of the protein they're synonyms of
+                let (length, molecular_weight) = (proteins[0].1.length, proteins[0].1.molecular_weight);
+                proteins.extend(index.synonyms(start.0)?.into_iter().map(|(number, metadata)| {
+                    (number, ProteinMetadata { length, molecular_weight, ..metadata })
+                }));
+            }
+            for (protein_number, metadata) in proteins {
+                if !self.taxon_filter.allows(&metadata.taxon_id, &metadata.species)
+                    || !self.protein_filter.allows(metadata.pe_level, &metadata.db, metadata.length)
+                    || !self.accession_filter.allows(&metadata.protein_id)
+                    || !sources.allows(&metadata.protein_id)
+                {
+                    continue;
+                }
+                let location = (protein_number, start.1);
+                let mut hit = build_match(peptide, matched_sequence.clone(), location, metadata, self.i_l_equivalent);
+                if self.deamidation || !classes.is_empty() {
+                    // deamidated and equivalent residues aren't mismatches
+                    let canonical = classes.canonicalize(&matched_sequence);
+                    hit.mutated_positions = get_mutated_positions(&query, &canonical, self.i_l_equivalent);
+                    hit.mismatches = hit.mutated_positions.len();
+                }
+                hit.score = score;
+                if self.context > 0 {
+                    let left = start.1.saturating_sub(self.context);
+                    hit.left_context = index.protein_region((start.0, left), start.1 - left)?;
+                    hit.right_context = index.protein_region((start.0, start.1 + peptide.len()), self.context)?;
+                }
+                if self.met_cleavage && start.1 <= 1 {
+                    hit.n_terminus = n_terminus(start.1, &index.protein_region((start.0, 0), 1)?).to_string();
+                }
+                matches.push(hit);
+            }
+        }
+        ranking::sort_matches(&mut matches);
+        Ok(matches)
+    }
+
+    // the peptide as it's looked up, the index stores canonical k-mers when I and L or the residues
+    // of equivalence classes are the same, and residues that could be deamidated are the wildcards
+    // for both forms
+    fn query(&self, peptide: &str) -> String {
+        let query = self.equivalence_classes.canonicalize(peptide);
+        let query = if self.i_l_equivalent { canonicalize_i_l(&query) } else { query.into_owned() };
+        if self.deamidation { deamidated(&query) } else { query }
+    }
+}
+
+// a hit of a peptide on the matched sequence of a protein starting at a location
+pub(crate) fn build_match(
+    peptide: &str,
+    matched_sequence: String,
+    (protein_number, position): Location,
+    metadata: ProteinMetadata,
+    i_l_equivalent: bool,
+) -> Match {
+    let mutated_positions = get_mutated_positions(peptide, &matched_sequence, i_l_equivalent);
+    let index_start = position + 1;
+    let (genomic_start, genomic_end) = match metadata.frame {
+        0 => (0, 0),
+        frame => genomic_range(frame, metadata.source_length, position, peptide.len()),
+    };
+    Match {
+        peptide: peptide.to_string(),
+        matched_sequence,
+        protein_number,
+        protein_id: metadata.protein_id,
+        protein_name: metadata.protein_name.trim().to_string(),
+        species: metadata.species.trim().to_string(),
+        taxon_id: metadata.taxon_id,
+        gene: metadata.gene,
+        mismatches: mutated_positions.len(),
+        mutated_positions,
+        index_start,
+        index_end: index_start + peptide.len() - 1,
+        pe_level: metadata.pe_level,
+        gene_priority: metadata.gene_priority,
+        db: metadata.db,
+        isoform: metadata.isoform,
+        protein_length: metadata.length,
+        molecular_weight: metadata.molecular_weight,
+        discontinuous: false,
+        frame: metadata.frame,
+        genomic_start,
+        genomic_end,
+        left_context: String::new(),
+        right_context: String::new(),
+        score: 0,
+        n_terminus: String::new(),
+        mature_start: 0,
+        mature_end: 0,
+        query_start: 0,
+        query_end: 0,
+        e_value: 0.0,
+        ensembl: String::new(),
+        refseq: String::new(),
+        hgnc: String::new(),
+        kept: Vec::new(),
+    }
+}
+
+// where a hit starting at a 0-based position is on the N-terminus of a protein starting with
+// first_residue, at the protein's first residue or the mature protein's after the initiator Met
+pub(crate) fn n_terminus(position: usize, first_residue: &str) -> &'static str {
+    match (position, first_residue) {
+        (0, _) => "protein",
+        (1, "M") => "mature",
+        _ => "",
+    }
+}
+
+// each peptide once in the order they first appear, and which of them each peptide is
+fn distinct_peptides(peptides: &[String]) -> (Vec<String>, Vec<usize>) {
+    let mut distinct = Vec::new();
+    let mut numbers: HashMap<&str, usize> = HashMap::new();
+    let of_peptide = peptides
+        .iter()
+        .map(|peptide| {
+            *numbers.entry(peptide.as_str()).or_insert_with(|| {
+                distinct.push(peptide.clone());
+                distinct.len() - 1
+            })
+        })
+        .collect();
+    (distinct, of_peptide)
+}
+
+// the largest k that finds every hit of a peptide of this length with up to max_mismatches
+// mismatches, one of its max_mismatches + 1 disjoint k-mers is always intact
+pub fn auto_k(peptide_len: usize, max_mismatches: usize) -> usize {
+    (peptide_len / (max_mismatches + 1)).max(1)
+}
+
+// offsets of every k-th k-mer of a peptide plus the last one so the whole peptide is covered
+fn get_kmer_offsets(peptide_len: usize, k: usize) -> Vec<usize> {
+    let last = peptide_len - k;
+    let mut offsets: Vec<usize> = (0..=last).step_by(k).collect();
+    if *offsets.last().unwrap() != last {
+        offsets.push(last);
+    }
+    offsets
+}
+
+// look up the locations of a k-mer, or every k-mer its wildcards expand into, and shift
+// them back by its offset in the peptide to get the candidate start locations of the whole peptide
+fn get_candidate_starts(index: &dyn ProteomeIndex, kmer: &str, offset: usize) -> Result<HashSet<Location>> {
+    let mut starts = HashSet::new();
+    for variant in expand_wildcards(kmer) {
+        starts.extend(index.lookup(&variant)?
+            .into_iter()
+            .filter(|(_, position)| *position >= offset) // k-mer can't start before the protein does
+            .map(|(protein_number, position)| (protein_number, position - offset)));
+    }
+    Ok(starts)
+}
+
+// the residues a wildcard in a query stands for: X for any, B for D or N,
+// Z for E or Q and J for I or L
+fn wildcard_residues(residue: u8) -> Option<&'static [u8]> {
+    match residue {
+        b'X' => Some(STANDARD_RESIDUES),
+        b'B' => Some(b"DN"),
+        b'Z' => Some(b"EQ"),
+        b'J' => Some(b"IL"),
+        _ => None,
+    }
+}
+
+// a peptide with its N and D as B, and Q and E as Z, so they match either residue
+fn deamidated(peptide: &str) -> String {
+    peptide
+        .chars()
+        .map(|residue| match residue {
+            'N' | 'D' => 'B',
+            'Q' | 'E' => 'Z',
+            residue => residue,
+        })
+        .collect()
+}
+
+fn has_wildcards(peptide: &str) -> bool {
+    peptide.bytes().any(|residue| wildcard_residues(residue).is_some())
+}
+
+// every k-mer the wildcards of a k-mer could stand for, none if there'd be more than
+// MAX_KMER_VARIANTS of them, k-mers without wildcards are only themselves
+fn expand_wildcards(kmer: &str) -> Vec<String> {
+    let choices: Vec<&[u8]> = kmer.as_bytes()
+        .iter()
+        .map(|residue| wildcard_residues(*residue).unwrap_or(std::slice::from_ref(residue)))
+        .collect();
+    if choices.iter().try_fold(1usize, |n, choice| n.checked_mul(choice.len())).is_none_or(|n| n > MAX_KMER_VARIANTS) {
+        return Vec::new();
+    }
+
+    let mut variants = vec![Vec::with_capacity(kmer.len())];
+    for choice in choices {
+        variants = variants
+            .into_iter()
+            .flat_map(|variant| {
+                choice.iter().map(move |residue| {
+                    let mut variant = variant.clone();
+                    variant.push(*residue);
+                    variant
+                })
+            })
+            .collect();
+    }
+    variants.into_iter().map(|variant| String::from_utf8(variant).unwrap_or_default()).collect()
+}
+
+// find exact matches of a peptide by looking up its k-mers and keeping only the
+// start locations where every k-mer lines up, returns the location of each match start
+fn find_exact_matches(index: &dyn ProteomeIndex, peptide: &str, k: usize) -> Result<Vec<Location>> {
+    if peptide.len() < k {
+        return Ok(Vec::new());
+    }
+
+    let mut hits: Option<HashSet<Location>> = None;
+    for offset in get_kmer_offsets(peptide.len(), k) {
+        let starts = get_candidate_starts(index, &peptide[offset..offset + k], offset)?;
+
+        let remaining: HashSet<Location> = match hits {
+            Some(previous) => previous.intersection(&starts).cloned().collect(),
+            None => starts,
+        };
+        if remaining.is_empty() {
+            return Ok(Vec::new());
+        }
+        hits = Some(remaining);
+    }
+
+    let mut hits: Vec<Location> = hits.unwrap_or_default().into_iter().collect();
+    hits.sort();
+    Ok(hits)
+}
+
+// find matches of a peptide with up to max_mismatches mismatches, any hit has at least one
+// of the peptide's k-mers intact so those seed candidate starts which are then verified
+// against the stored protein sequences, returns the location and sequence of each hit,
+// k-mers masked for having too many wildcards don't seed so such hits can be missed
+fn find_mismatched_matches(
+    index: &dyn ProteomeIndex,
+    peptide: &str,
+    k: usize,
+    max_mismatches: usize,
+    i_l_equivalent: bool,
+    equivalence_classes: &EquivalenceClasses,
+) -> Result<Vec<(Location, String)>> {
+    if peptide.len() < k {
+        return Ok(Vec::new());
+    }
+
+    let offsets = get_kmer_offsets(peptide.len(), k);
+    let mut candidates: HashSet<Location> = HashSet::new();
+    for offset in &offsets {
+        candidates.extend(get_candidate_starts(index, &peptide[*offset..*offset + k], *offset)?);
+    }
+
+    let mut candidates: Vec<Location> = candidates.into_iter().collect();
+    candidates.sort();
+
+    // wildcards only match a residue at a time
+    let wildcards = has_wildcards(peptide);
+    let within = |matched_sequence: &str| {
+        if wildcards {
+            get_mutated_positions(peptide, matched_sequence, i_l_equivalent).len() <= max_mismatches
+        } else {
+            mismatches_within(peptide.as_bytes(), matched_sequence.as_bytes(), max_mismatches, i_l_equivalent).is_some()
+        }
+    };
+    let mut hits = Vec::new();
+    for start in candidates {
+        if let Some(matched_sequence) = index.protein_window(start, peptide.len())? {
+            if within(&equivalence_classes.canonicalize(&matched_sequence)) {
+                hits.push((start, matched_sequence));
+            }
+        }
+    }
+    Ok(hits)
+}
+
+// find matches of a peptide too short to have k-mers by comparing it with every window of
+// every protein, which is slow but finds every hit, returns the location and sequence of each
+fn scan_matches(
+    index: &dyn ProteomeIndex,
+    peptide: &str,
+    max_mismatches: usize,
+    i_l_equivalent: bool,
+    equivalence_classes: &EquivalenceClasses,
+) -> Result<Vec<(Location, String)>> {
+    if peptide.is_empty() {
+        return Ok(Vec::new());
+    }
+    let query = peptide.as_bytes();
+    let mut hits = Vec::new();
+    for (_, first, last) in index.proteomes()? {
+        for protein_number in first..=last {
+            let sequence = index.protein_sequence(protein_number)?;
+            let canonical = equivalence_classes.canonicalize(&sequence);
+            for (position, window) in canonical.as_bytes().windows(query.len()).enumerate() {
+                let mismatches = query.iter().zip(window).filter(|(a, b)| !residues_match(**a, **b, i_l_equivalent));
+                if mismatches.take(max_mismatches + 1).count() <= max_mismatches {
+                    hits.push(((protein_number, position), sequence[position..position + query.len()].to_string()));
+                }
+            }
+        }
+    }
+    Ok(hits)
+}
+
+// 1-based positions where the peptide differs from the matched sequence
+fn get_mutated_positions(peptide: &str, matched_sequence: &str, i_l_equivalent: bool) -> Vec<usize> {
+    peptide.bytes()
+        .zip(matched_sequence.bytes())
+        .enumerate()
+        .filter(|(_, (a, b))| !residues_match(*a, *b, i_l_equivalent))
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+// whether a residue of the query matches one of the protein, wildcards in the query
+// match any of the residues they stand for
+fn residues_match(a: u8, b: u8, i_l_equivalent: bool) -> bool {
+    a == b
+        || (i_l_equivalent && matches!((a, b), (b'I', b'L') | (b'L', b'I')))
+        || wildcard_residues(a).is_some_and(|residues| residues.contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FASTA: &str = ">sp|P00001|ONE_HUMAN First protein OS=Homo sapiens OX=9606 GN=ONE PE=1 SV=2
+MKLVSTAAQWDEQ
+>sp|P00002|TWO_HUMAN Second protein OS=Homo sapiens OX=9606 GN=TWO PE=3 SV=1
+PESTAVQWLLK
+";
+
+    #[test]
+    fn wildcards_expand() {
+        assert_eq!(expand_wildcards("ACD"), vec!["ACD"]);
+        assert_eq!(expand_wildcards("ABJ"), vec!["ADI", "ADL", "ANI", "ANL"]);
+        assert_eq!(expand_wildcards("XA").len(), STANDARD_RESIDUES.len());
+    }
+
+    #[test]
+    fn too_many_wildcard_variants() {
+        assert!(expand_wildcards("XX").is_empty());
+        assert!(expand_wildcards("XBZ").is_empty());
+    }
+
+    #[test]
+    fn exact_and_mismatched_hits() {
+        let matcher = Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap().max_mismatches(1);
+        let hits = matcher.match_peptide("STAAQW").unwrap();
+        assert_eq!(hits.len(), 2);
+        let exact = hits.iter().find(|hit| hit.mismatches == 0).unwrap();
+        assert_eq!((exact.protein_id.as_str(), exact.index_start, exact.index_end), ("P00001", 5, 10));
+        let mismatched = hits.iter().find(|hit| hit.mismatches == 1).unwrap();
+        assert_eq!(mismatched.protein_id, "P00002");
+        assert_eq!(mismatched.substitutions(), vec!["4:A>V"]);
+    }
+
+    #[test]
+    fn best_region_mismatches_are_at_their_peptide_positions() {
+        let classes: EquivalenceClasses = "QE".parse().unwrap();
+        let preprocessor = Preprocessor::from_fasta("test", FASTA, 3).equivalence_classes(classes.clone());
+        let matcher = Matcher::in_memory(&preprocessor).unwrap().equivalence_classes(classes).best_match(true).best_region(true);
+        let hits = matcher.match_peptide("WWWWWWWWWWSTAAEW").unwrap();
+        assert_eq!(hits.len(), 1);
+        let hit = &hits[0];
+        assert_eq!((hit.query_start, hit.query_end, hit.matched_sequence.as_str()), (11, 16, "STAAQW"));
+        assert_eq!(hit.mutated_positions, vec![15]);
+        assert_eq!(hit.substitutions(), vec!["15:E>Q"]);
+    }
+
+    #[test]
+    fn each_peptide_gets_a_k_of_its_own() {
+        let preprocessor = Preprocessor::from_fasta("test", FASTA, 3).extra_k(&[2, 6]);
+        let matcher = Matcher::in_memory(&preprocessor).unwrap().max_mismatches(1);
+        assert_eq!(matcher.k_values(), vec![3, 2, 6]);
+        assert_eq!(matcher.search.k_for("STAAQW", 1), 3);
+        assert_eq!(matcher.search.k_for("MKLVSTAAQWDE", 1), 6);
+        assert_eq!(matcher.search.k_for("STA", 1), 2);
+
+        let single = Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap().max_mismatches(1);
+        let ids = |matcher: &Matcher, peptide| {
+            let mut hits: Vec<_> = matcher.match_peptide(peptide).unwrap().into_iter().map(|hit| (hit.protein_id, hit.index_start)).collect();
+            hits.sort();
+            hits
+        };
+        for peptide in ["STAAQW", "MKLVSTAAQWDE", "PESTAVQWLLK"] {
+            assert_eq!(ids(&matcher, peptide), ids(&single, peptide), "{}", peptide);
+        }
+        assert_eq!(matcher.only_k(6).unwrap().search.k_for("STAAQW", 1), 6);
+        assert!(Matcher::in_memory(&preprocessor).unwrap().only_k(4).is_err());
+    }
+
+    #[test]
+    fn fm_index_finds_what_kmers_do() {
+        let preprocessor = Preprocessor::from_fasta("test", FASTA, 3);
+        let ids = |matcher: &Matcher, peptide| {
+            let hits = matcher.match_peptide(peptide).unwrap();
+            let mut hits: Vec<_> = hits.into_iter().map(|hit| (hit.protein_id, hit.index_start, hit.mismatches)).collect();
+            hits.sort();
+            hits
+        };
+        for max_mismatches in 0..=2 {
+            let fm = Matcher::fm_index(&preprocessor).unwrap().max_mismatches(max_mismatches);
+            let kmers = Matcher::in_memory(&preprocessor).unwrap().max_mismatches(max_mismatches);
+            for peptide in ["STAAQW", "MKLVSTAAQWDE", "PESTAVQWLLK", "STBAQW"] {
+                assert_eq!(ids(&fm, peptide), ids(&kmers, peptide), "{} with {} mismatches", peptide, max_mismatches);
+            }
+        }
+        // peptides shorter than k are still looked up whole
+        let fm = Matcher::fm_index(&preprocessor).unwrap();
+        assert_eq!(ids(&fm, "WD"), vec![("P00001".to_string(), 10, 0)]);
+    }
+
+    #[test]
+    fn repeated_peptides_share_their_hits() {
+        let matcher = Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap().max_mismatches(1);
+        let peptides: Vec<String> = ["STAAQW", "WWWWWW", "STAAQW", "QWLLK", "STAAQW"].iter().map(|p| p.to_string()).collect();
+        let (distinct, of_peptide) = distinct_peptides(&peptides);
+        assert_eq!(distinct, vec!["STAAQW", "WWWWWW", "QWLLK"]);
+        assert_eq!(of_peptide, vec![0, 1, 0, 2, 0]);
+        assert_eq!(matcher.count_matches(&peptides).unwrap(), vec![2, 0, 2, 1, 2]);
+        let hits: Vec<String> = matcher.match_peptides(&peptides).unwrap().into_iter().map(|hit| hit.peptide).collect();
+        assert_eq!(hits.iter().filter(|peptide| *peptide == "STAAQW").count(), 6);
+        assert_eq!(hits.last().map(String::as_str), Some("STAAQW"));
+    }
+
+    #[test]
+    fn peptides_shorter_than_k_are_scanned_for() {
+        let ids = |matcher: &Matcher, peptide| {
+            let hits = matcher.match_peptide(peptide).unwrap();
+            let mut hits: Vec<_> = hits.into_iter().map(|hit| (hit.protein_id, hit.index_start, hit.mismatches)).collect();
+            hits.sort();
+            hits
+        };
+        let preprocessor = Preprocessor::from_fasta("test", FASTA, 6);
+        let scanned = Matcher::in_memory(&preprocessor).unwrap().max_mismatches(1);
+        let seeded = Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 2)).unwrap().max_mismatches(1);
+        for peptide in ["STA", "QW", "STBA", "WLLK"] {
+            assert!(scanned.search.scans(peptide));
+            assert_eq!(ids(&scanned, peptide), ids(&seeded, peptide), "{}", peptide);
+        }
+        assert!(!scanned.search.scans("STAAQW"));
+        assert_eq!(ids(&Matcher::in_memory(&preprocessor).unwrap(), "QW"), vec![
+            ("P00001".to_string(), 9, 0),
+            ("P00002".to_string(), 7, 0),
+        ]);
+        let best = Matcher::in_memory(&preprocessor).unwrap().best_match(true);
+        assert_eq!(best.match_peptide("STVA").unwrap()[0].mismatches, 1);
+        assert!(Matcher::in_memory(&preprocessor).unwrap().match_peptide("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn hits_after_the_initiator_met_are_flagged() {
+        let matcher = Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap().max_mismatches(1);
+        let n_terminus = |matcher: &Matcher, peptide| matcher.match_peptide(peptide).unwrap()[0].n_terminus.clone();
+        assert_eq!(n_terminus(&matcher, "MKLVST"), "");
+        let matcher = matcher.met_cleavage(true);
+        assert_eq!(n_terminus(&matcher, "MKLVST"), "protein");
+        assert_eq!(n_terminus(&matcher, "KLVSTA"), "mature");
+        assert_eq!(n_terminus(&matcher, "LVSTAA"), "");
+        assert_eq!(n_terminus(&matcher, "ESTAVQ"), "");
+        assert_eq!(n_terminus(&matcher, "PESTAVQ"), "protein");
+    }
+
+    #[test]
+    fn deamidated_residues_match_either_form() {
+        let matcher = Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap();
+        assert!(matcher.match_peptide("MKLVSTAAQWNEE").unwrap().is_empty());
+        let matcher = matcher.deamidation(true);
+        let hits = matcher.match_peptide("MKLVSTAAQWNEE").unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].matched_sequence.as_str(), hits[0].mismatches), ("MKLVSTAAQWDEQ", 0));
+        assert_eq!(hits[0].peptide, "MKLVSTAAQWNEE");
+        let matcher = matcher.max_mismatches(1);
+        assert_eq!(matcher.match_peptide("PQSTAVKWLLK").unwrap()[0].mutated_positions, vec![7]);
+    }
+
+    #[test]
+    fn k_of_zero_is_rejected() {
+        assert!(Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 0)).is_err());
+    }
+}
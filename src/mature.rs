@@ -0,0 +1,107 @@
+use crate::error::{PepMatchError, Result};
+use crate::proteome::open_proteome;
+use crate::Match;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+
+// UniProt feature types cleaved off the N-terminus of a precursor to give the mature protein
+const CLEAVED_FEATURES: [&str; 3] = ["SIGNAL", "PROPEP", "TRANSIT"];
+
+// where the mature proteins start in their precursors, from a UniProt TSV download with the
+// signal peptide, propeptide and transit peptide fields, e.g. from the REST API with
+// fields=accession,ft_signal,ft_propep,ft_transit&format=tsv, whose rows are an accession and
+// features like SIGNAL 1..22; /evidence="ECO:0000255"
+#[derive(Debug, Clone, Default)]
+pub struct MatureProteins {
+    cleaved: HashMap<String, usize>, // accession --> residues cleaved off its N-terminus
+}
+
+impl MatureProteins {
+    // read a feature table, gzipped or not, skipping its header and proteins without features
+    // cleaved off their N-terminus
+    pub fn load(path: &str) -> Result<MatureProteins> {
+        let mut proteins = MatureProteins::default();
+        for (i, line) in BufReader::new(open_proteome(path)?).lines().enumerate() {
+            let line = line.map_err(PepMatchError::io(path))?;
+            let mut fields = line.split('\t');
+            let accession = fields.next().unwrap_or_default().trim();
+            if (i == 0 && accession.eq_ignore_ascii_case("entry")) || accession.is_empty() {
+                continue;
+            }
+            let features = fields
+                .map(features)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| PepMatchError::invalid(format!("{}: line {}: can't read the features of {}", path, i + 1, accession)))?;
+            let cleaved = cleaved_residues(features.concat());
+            if cleaved > 0 {
+                proteins.cleaved.insert(accession.to_string(), cleaved);
+            }
+        }
+        Ok(proteins)
+    }
+
+    // give hits their 1-based positions on the mature protein, hits in proteins without
+    // cleaved features keep their precursor positions and hits overlapping a cleaved
+    // feature get none
+    pub fn annotate(&self, hits: &mut [Match]) {
+        for hit in hits {
+            let cleaved = self.cleaved.get(&hit.protein_id).copied().unwrap_or_default();
+            (hit.mature_start, hit.mature_end) = if hit.index_start > cleaved && hit.frame == 0 {
+                (hit.index_start - cleaved, hit.index_end - cleaved)
+            } else {
+                (0, 0)
+            };
+        }
+    }
+}
+
+// the (start, end) of the cleaved features in a field of features like SIGNAL 1..22; /note="..."
+// PROPEP 23..30, features at uncertain positions like ?..22 are left out, None if a feature's
+// positions aren't numbers at all
+fn features(field: &str) -> Option<Vec<(usize, usize)>> {
+    let mut features = Vec::new();
+    for part in field.split(';').map(str::trim) {
+        let Some((kind, range)) = part.split_once(' ') else { continue };
+        if !CLEAVED_FEATURES.contains(&kind) {
+            continue;
+        }
+        let (start, end) = range.trim().split_once("..")?;
+        let uncertain = |position: &str| position.starts_with(['?', '<', '>']);
+        if uncertain(start) || uncertain(end) {
+            continue;
+        }
+        features.push((start.parse().ok()?, end.parse().ok()?));
+    }
+    Some(features)
+}
+
+// how many residues of the N-terminus the features cleave off, following the features that
+// each start right after the last from residue 1, internal and C-terminal propeptides don't
+// move the mature protein's start
+fn cleaved_residues(mut features: Vec<(usize, usize)>) -> usize {
+    features.sort_unstable();
+    features.into_iter().fold(0, |cleaved, (start, end)| if start == cleaved + 1 { end } else { cleaved })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features_are_read_from_uniprot_fields() {
+        assert_eq!(features("SIGNAL 1..24; /evidence=\"ECO:0000269|PubMed:123\""), Some(vec![(1, 24)]));
+        assert_eq!(features("PROPEP 25..30; /note=\"Activation peptide\"; /id=\"PRO_0000028173\"; PROPEP 90..120"),
+            Some(vec![(25, 30), (90, 120)]));
+        assert_eq!(features("SIGNAL ?..24; /evidence=\"ECO:0000255\""), Some(vec![]));
+        assert_eq!(features(""), Some(vec![]));
+        assert_eq!(features("SIGNAL one..24"), None);
+    }
+
+    #[test]
+    fn only_n_terminal_features_are_cleaved() {
+        assert_eq!(cleaved_residues(vec![(25, 30), (1, 24)]), 30);
+        assert_eq!(cleaved_residues(vec![(1, 24), (57, 87)]), 24);
+        assert_eq!(cleaved_residues(vec![(57, 87)]), 0);
+        assert_eq!(cleaved_residues(vec![]), 0);
+    }
+}
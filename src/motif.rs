@@ -0,0 +1,160 @@
+use crate::error::{PepMatchError, Result};
+use crate::index;
+use crate::matcher::build_match;
+use crate::progress;
+use crate::Match;
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+// a PROSITE pattern like N-{P}-[ST]-{P}, elements joined by - are a residue, x for any,
+// [..] for any of and {..} for none of, with an optional repeat count like x(2) or x(2,4),
+// and < or > to anchor it to the N or C terminus of the protein
+#[derive(Debug, Clone)]
+pub struct Motif {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Motif {
+    // the occurrences of the motif in a sequence, overlapping ones included, as the
+    // 0-based position and residues of each
+    pub fn find_all<'a>(&self, sequence: &'a str) -> Vec<(usize, &'a str)> {
+        let mut occurrences = Vec::new();
+        let mut start = 0;
+        while let Some(found) = self.regex.find_at(sequence, start) {
+            occurrences.push((found.start(), found.as_str()));
+            start = found.start() + 1;
+            if start > sequence.len() {
+                break;
+            }
+        }
+        occurrences
+    }
+}
+
+impl FromStr for Motif {
+    type Err = PepMatchError;
+
+    fn from_str(s: &str) -> Result<Motif> {
+        let invalid = |reason: &str| PepMatchError::invalid(format!("invalid PROSITE pattern {}: {}", s, reason));
+        let pattern = s.trim().trim_end_matches('.');
+        let (pattern, n_terminal) = match pattern.strip_prefix('<') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        let (pattern, c_terminal) = match pattern.strip_suffix('>') {
+            Some(pattern) => (pattern, true),
+            None => (pattern, false),
+        };
+        if pattern.is_empty() {
+            return Err(invalid("no elements"));
+        }
+
+        let mut regex = String::from(if n_terminal { "^" } else { "" });
+        for element in pattern.split('-') {
+            regex.push_str(&element_regex(element.trim()).map_err(|reason| invalid(&reason))?);
+        }
+        if c_terminal {
+            regex.push('$');
+        }
+        Ok(Motif {
+            pattern: s.trim().to_string(),
+            regex: Regex::new(&regex).map_err(|e| invalid(&e.to_string()))?,
+        })
+    }
+}
+
+impl fmt::Display for Motif {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+// the regex of one element of a pattern, e.g. [ST](2,3) --> [ST]{2,3}
+fn element_regex(element: &str) -> std::result::Result<String, String> {
+    let (residues, repeat) = match element.find('(') {
+        Some(open) => {
+            let count = element[open..]
+                .strip_prefix('(')
+                .and_then(|count| count.strip_suffix(')'))
+                .ok_or_else(|| format!("unclosed repeat count in {}", element))?;
+            let bounds: Vec<&str> = count.split(',').map(str::trim).collect();
+            if bounds.len() > 2 || bounds.iter().any(|bound| bound.parse::<usize>().is_err()) {
+                return Err(format!("invalid repeat count in {}", element));
+            }
+            (&element[..open], format!("{{{}}}", bounds.join(",")))
+        }
+        None => (element, String::new()),
+    };
+
+    let letters = |residues: &str| -> std::result::Result<String, String> {
+        if !residues.is_empty() && residues.bytes().all(|residue| residue.is_ascii_alphabetic()) {
+            Ok(residues.to_ascii_uppercase())
+        } else {
+            Err(format!("invalid residues in {}", element))
+        }
+    };
+    let class = if residues.eq_ignore_ascii_case("x") {
+        ".".to_string()
+    } else if let Some(inner) = residues.strip_prefix('[').and_then(|inner| inner.strip_suffix(']')) {
+        format!("[{}]", letters(inner)?)
+    } else if let Some(inner) = residues.strip_prefix('{').and_then(|inner| inner.strip_suffix('}')) {
+        format!("[^{}]", letters(inner)?)
+    } else if residues.len() == 1 {
+        letters(residues)?
+    } else {
+        return Err(format!("invalid element {}", residues));
+    };
+    Ok(class + &repeat)
+}
+
+// find every occurrence of each motif in the protein sequences of a preprocessed DB,
+// hits come out in the order of the motifs with the motif as the query sequence
+pub fn scan_motifs(db_path: &str, motifs: &[Motif], show_progress: bool) -> Result<Vec<Match>> {
+    let index = index::open_db(db_path)?;
+    let proteomes = index.proteomes()?;
+    let total = proteomes.iter().map(|(_, first, last)| last + 1 - first).sum();
+
+    let mut hits: Vec<Vec<Match>> = vec![Vec::new(); motifs.len()];
+    let bar = progress::proteins(show_progress, total);
+    for protein_number in proteomes.iter().flat_map(|(_, first, last)| *first..=*last) {
+        let sequence = index.protein_sequence(protein_number)?;
+        for (motif, motif_hits) in motifs.iter().zip(hits.iter_mut()) {
+            for (position, occurrence) in motif.find_all(&sequence) {
+                let metadata = index.protein_metadata(protein_number)?;
+                let mut hit = build_match(occurrence, occurrence.to_string(), (protein_number, position), metadata, false);
+                hit.peptide = motif.to_string();
+                motif_hits.push(hit);
+            }
+        }
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+    Ok(hits.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_find_overlapping_and_anchored_occurrences() {
+        let motif: Motif = "N-{P}-[ST]-{P}.".parse().unwrap();
+        assert_eq!(motif.find_all("ANASANLTQ"), [(1, "NASA"), (5, "NLTQ")]);
+        assert!(motif.find_all("NPSA").is_empty());
+        let repeated: Motif = "K-x(1,2)-K".parse().unwrap();
+        assert_eq!(repeated.find_all("KAKAAK"), [(0, "KAK"), (2, "KAAK")]);
+        let anchored: Motif = "<M-K".parse().unwrap();
+        assert_eq!(anchored.find_all("MKMK"), [(0, "MK")]);
+        let c_terminal: Motif = "G-x>".parse().unwrap();
+        assert_eq!(c_terminal.find_all("GAGA"), [(2, "GA")]);
+    }
+
+    #[test]
+    fn malformed_patterns_are_rejected() {
+        for pattern in ["", "N-[P", "N-x(2", "N-x(a)", "N-PS", "N-[1]"] {
+            assert!(pattern.parse::<Motif>().is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,759 @@
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "duckdb")]
+mod duckdb;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use crate::coverage::ProteinCoverage;
+use crate::statistics::p_value;
+use crate::Match;
+use std::io::Write;
+use std::str::FromStr;
+
+// column names match the output of the Python PEPMatch
+const COLUMNS: [&str; 12] = [
+    "Query Sequence",
+    "Matched Sequence",
+    "Protein ID",
+    "Protein Name",
+    "Species",
+    "Taxon ID",
+    "Gene",
+    "Mismatches",
+    "Mutated Positions",
+    "Index start",
+    "Index end",
+    "Protein Existence Level",
+];
+
+// columns for where hits in translated nucleotide sequences are, added after COLUMNS
+const GENOMIC_COLUMNS: [&str; 3] = ["Frame", "Genomic Start", "Genomic End"];
+
+// columns for the protein residues flanking hits
+const CONTEXT_COLUMNS: [&str; 2] = ["Left Context", "Right Context"];
+
+// column for the substitution matrix score of hits in similarity searches
+const SCORE_COLUMN: &str = "Score";
+
+// columns for the positions of hits on the mature proteins, after their cleaved features
+const MATURE_COLUMNS: [&str; 2] = ["Mature Start", "Mature End"];
+
+// columns for the stretch of a peptide without a full-length hit that a best region hit is of
+const REGION_COLUMNS: [&str; 2] = ["Query Start", "Query End"];
+
+// columns for how many random hits each hit's peptide is expected to have and the chance of any
+const STATISTICS_COLUMNS: [&str; 2] = ["E-value", "P-value"];
+
+// column for whether hits start at their protein's N-terminus or after its initiator Met
+const N_TERMINUS_COLUMN: &str = "N-terminus";
+
+// columns for the IDs of the proteins in other databases, from an ID mapping
+const ID_MAPPING_COLUMNS: [&str; 3] = ["Ensembl", "RefSeq", "HGNC"];
+
+// columns for the length and molecular weight of the proteins of hits
+const PROTEIN_COLUMNS: [&str; 2] = ["Protein Length", "Molecular Weight"];
+
+// optional columns to write after the standard ones, and how positions are written in them
+#[derive(Debug, Clone, Default)]
+pub struct Columns {
+    pub genomic: bool,
+    pub context: bool,
+    pub score: bool,
+    pub n_terminus: bool,
+    pub mature: bool,
+    pub region: bool,
+    pub statistics: bool,
+    pub id_mapping: bool,
+    pub protein: bool,
+    pub kept: Vec<String>, // columns of the query rows written last, with their values in Match::kept
+    pub coordinates: Coordinates,
+}
+
+// how the positions of hits are written, 1-based with inclusive ends like the Python
+// PEPMatch by default, or 0-based or with exclusive ends for tools that count that way,
+// GFF3 and BED keep to their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coordinates {
+    pub base: usize,         // the position of a protein's first residue, 0 or 1
+    pub end_exclusive: bool, // whether ends are one past a hit's last residue
+}
+
+impl Default for Coordinates {
+    fn default() -> Coordinates {
+        Coordinates { base: 1, end_exclusive: false }
+    }
+}
+
+impl Coordinates {
+    // a hit's positions in this convention
+    pub fn record<'h>(&self, hit: &'h Match) -> MatchRecord<'h> {
+        // hits keep 1-based positions with inclusive ends
+        let (start, end) = self.interval(hit.index_start, hit.index_end);
+        let (genomic_start, genomic_end) = match hit.frame {
+            0 => (hit.genomic_start, hit.genomic_end),
+            _ => self.interval(hit.genomic_start, hit.genomic_end),
+        };
+        let mature = (hit.mature_start > 0).then(|| self.interval(hit.mature_start, hit.mature_end));
+        let region = (hit.query_start > 0).then(|| self.interval(hit.query_start, hit.query_end));
+        MatchRecord { hit, start, end, genomic_start, genomic_end, mature, region }
+    }
+
+    // the 1-based inclusive (start, end) of a hit's positions written in this convention,
+    // None if they can't be
+    pub fn one_based(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let start = (start + 1).checked_sub(self.base).filter(|start| *start > 0)?;
+        let end = (end + 1).checked_sub(self.base + usize::from(self.end_exclusive))?;
+        Some((start, end)).filter(|(start, end)| start <= end)
+    }
+
+    // 1-based inclusive positions written in this convention
+    pub(crate) fn interval(&self, start: usize, end: usize) -> (usize, usize) {
+        let shift = |position: usize| position + self.base - 1;
+        (shift(start), shift(end) + usize::from(self.end_exclusive))
+    }
+}
+
+// a hit with its positions on the protein, and on the nucleotide sequence it was translated
+// from, in the convention they're written in
+#[derive(Debug, Clone, Copy)]
+pub struct MatchRecord<'h> {
+    pub hit: &'h Match,
+    pub start: usize,
+    pub end: usize,
+    pub genomic_start: usize,
+    pub genomic_end: usize,
+    pub mature: Option<(usize, usize)>, // none for hits overlapping the features cleaved off their protein
+    pub region: Option<(usize, usize)>, // the positions in the peptide of a best region hit, none for other hits
+}
+
+impl Columns {
+    fn names(&self) -> Vec<&str> {
+        let mut names = COLUMNS.to_vec();
+        if self.genomic {
+            names.extend(GENOMIC_COLUMNS);
+        }
+        if self.context {
+            names.extend(CONTEXT_COLUMNS);
+        }
+        if self.score {
+            names.push(SCORE_COLUMN);
+        }
+        if self.n_terminus {
+            names.push(N_TERMINUS_COLUMN);
+        }
+        if self.mature {
+            names.extend(MATURE_COLUMNS);
+        }
+        if self.region {
+            names.extend(REGION_COLUMNS);
+        }
+        if self.statistics {
+            names.extend(STATISTICS_COLUMNS);
+        }
+        if self.id_mapping {
+            names.extend(ID_MAPPING_COLUMNS);
+        }
+        if self.protein {
+            names.extend(PROTEIN_COLUMNS);
+        }
+        names.extend(self.kept.iter().map(String::as_str));
+        names
+    }
+}
+
+// output formats for match results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Tsv,
+    Csv,
+    Json,
+    Gff3,
+    Bed,
+    #[cfg(feature = "arrow")]
+    Arrow,
+    #[cfg(feature = "arrow")]
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s.to_lowercase().as_str() {
+            "tsv" => Ok(Format::Tsv),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "gff3" | "gff" => Ok(Format::Gff3),
+            "bed" => Ok(Format::Bed),
+            #[cfg(feature = "arrow")]
+            "arrow" => Ok(Format::Arrow),
+            #[cfg(feature = "arrow")]
+            "parquet" => Ok(Format::Parquet),
+            #[cfg(not(feature = "arrow"))]
+            "arrow" | "parquet" => Err(format!("{} output needs pepmatch built with the arrow feature", s)),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+// writes match results out in some format, finish must be called once all hits are written
+pub trait MatchWriter {
+    fn write_match(&mut self, hit: &Match) -> std::io::Result<()>;
+    // write a row for a query peptide without hits, its hit fields empty and with the values
+    // of the query row's kept columns, only formats of a row per hit have them
+    fn write_unmatched(&mut self, _peptide: &str, _kept: &[String]) -> std::io::Result<()> {
+        Err(std::io::Error::other("rows for peptides without hits can only be written as TSV, CSV or JSON"))
+    }
+    // write out the hits so far, e.g. after each chunk of a query
+    fn flush(&mut self) -> std::io::Result<()>;
+    fn finish(&mut self) -> std::io::Result<()>;
+}
+
+// get a writer for the format that writes to out
+pub fn writer<'a>(format: Format, out: Box<dyn Write + Send + 'a>, columns: Columns) -> Box<dyn MatchWriter + 'a> {
+    match format {
+        Format::Tsv => Box::new(DelimitedWriter::new(out, b'\t', columns)),
+        Format::Csv => Box::new(DelimitedWriter::new(out, b',', columns)),
+        Format::Json => Box::new(JsonWriter::new(out, columns)),
+        Format::Gff3 => Box::new(IntervalWriter::new(out, Interval::Gff3)),
+        Format::Bed => Box::new(IntervalWriter::new(out, Interval::Bed)),
+        #[cfg(feature = "arrow")]
+        Format::Arrow => Box::new(arrow::ColumnarWriter::new(out, arrow::Columnar::Arrow, columns)),
+        #[cfg(feature = "arrow")]
+        Format::Parquet => Box::new(arrow::ColumnarWriter::new(out, arrow::Columnar::Parquet, columns)),
+    }
+}
+
+// get a writer that writes to a matches table of a results DB instead, a DuckDB file
+// when the path ends in .duckdb
+#[cfg(feature = "sqlite")]
+pub fn db_writer(path: &str, columns: Columns) -> std::io::Result<Box<dyn MatchWriter>> {
+    if path.ends_with(".duckdb") {
+        #[cfg(feature = "duckdb")]
+        return Ok(Box::new(self::duckdb::DuckdbWriter::create(path, columns)?));
+        #[cfg(not(feature = "duckdb"))]
+        return Err(std::io::Error::other("writing hits to DuckDB needs pepmatch built with the duckdb feature"));
+    }
+    Ok(Box::new(sqlite::SqliteWriter::create(path, columns)?))
+}
+
+// columns of a coverage report
+const COVERAGE_COLUMNS: [&str; 10] = [
+    "Protein ID",
+    "Protein Name",
+    "Species",
+    "Gene",
+    "Protein Length",
+    "Peptides",
+    "Covered Residues",
+    "Coverage",
+    "Intervals",
+    "Coverage Sequence",
+];
+
+// write hits aggregated per protein, as TSV, CSV or JSON
+pub fn write_coverage(format: Format, mut out: Box<dyn Write + '_>, coverages: &[ProteinCoverage]) -> std::io::Result<()> {
+    let fields = |coverage: &ProteinCoverage| {
+        [
+            coverage.protein_id.clone(),
+            coverage.protein_name.clone(),
+            coverage.species.clone(),
+            coverage.gene.clone(),
+            coverage.protein_length.to_string(),
+            coverage.peptides.to_string(),
+            coverage.covered_residues.to_string(),
+            format!("{:.4}", coverage.coverage()),
+            coverage.intervals_string(),
+            coverage.coverage_sequence.clone(),
+        ]
+    };
+    let delimiter = match format {
+        Format::Tsv => b'\t',
+        Format::Csv => b',',
+        Format::Json => {
+            let records: Vec<serde_json::Value> = coverages
+                .iter()
+                .map(|coverage| {
+                    serde_json::json!({
+                        COVERAGE_COLUMNS[0]: coverage.protein_id,
+                        COVERAGE_COLUMNS[1]: coverage.protein_name,
+                        COVERAGE_COLUMNS[2]: coverage.species,
+                        COVERAGE_COLUMNS[3]: coverage.gene,
+                        COVERAGE_COLUMNS[4]: coverage.protein_length,
+                        COVERAGE_COLUMNS[5]: coverage.peptides,
+                        COVERAGE_COLUMNS[6]: coverage.covered_residues,
+                        COVERAGE_COLUMNS[7]: coverage.coverage(),
+                        COVERAGE_COLUMNS[8]: coverage.intervals,
+                        COVERAGE_COLUMNS[9]: coverage.coverage_sequence,
+                    })
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut out, &records)?;
+            writeln!(out)?;
+            return out.flush();
+        }
+        _ => return Err(std::io::Error::other("coverage reports can only be written as tsv, csv or json")),
+    };
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(out);
+    writer.write_record(COVERAGE_COLUMNS)?;
+    for coverage in coverages {
+        writer.write_record(fields(coverage))?;
+    }
+    writer.flush()
+}
+
+// columns of a report of the number of hits per peptide
+const COUNT_COLUMNS: [&str; 2] = ["Query Sequence", "Matches"];
+
+// write the number of hits of each peptide, as TSV, CSV or JSON
+pub fn write_counts(format: Format, mut out: Box<dyn Write + '_>, counts: &[(String, usize)]) -> std::io::Result<()> {
+    let delimiter = match format {
+        Format::Tsv => b'\t',
+        Format::Csv => b',',
+        Format::Json => {
+            let records: Vec<serde_json::Value> = counts
+                .iter()
+                .map(|(peptide, count)| serde_json::json!({ COUNT_COLUMNS[0]: peptide, COUNT_COLUMNS[1]: count }))
+                .collect();
+            serde_json::to_writer_pretty(&mut out, &records)?;
+            writeln!(out)?;
+            return out.flush();
+        }
+        _ => return Err(std::io::Error::other("hit counts can only be written as tsv, csv or json")),
+    };
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(out);
+    writer.write_record(COUNT_COLUMNS)?;
+    for (peptide, count) in counts {
+        writer.write_record([peptide.clone(), count.to_string()])?;
+    }
+    writer.flush()
+}
+
+// the fields of a hit as strings, in the order of the columns
+fn fields(hit: &Match, columns: &Columns) -> Vec<String> {
+    let record = columns.coordinates.record(hit);
+    let mut fields = vec![
+        hit.peptide.clone(),
+        hit.matched_sequence.clone(),
+        hit.protein_id.clone(),
+        hit.protein_name.clone(),
+        hit.species.clone(),
+        hit.taxon_id.clone(),
+        hit.gene.clone(),
+        hit.mismatches.to_string(),
+        hit.substitutions().join(";"),
+        record.start.to_string(),
+        record.end.to_string(),
+        hit.pe_level.to_string(),
+    ];
+    if columns.genomic {
+        fields.extend([format!("{:+}", hit.frame), record.genomic_start.to_string(), record.genomic_end.to_string()]);
+    }
+    if columns.context {
+        fields.extend([hit.left_context.clone(), hit.right_context.clone()]);
+    }
+    if columns.score {
+        fields.push(hit.score.to_string());
+    }
+    if columns.n_terminus {
+        fields.push(hit.n_terminus.clone());
+    }
+    if columns.mature {
+        let (start, end) = record.mature.map(|(start, end)| (start.to_string(), end.to_string())).unwrap_or_default();
+        fields.extend([start, end]);
+    }
+    if columns.region {
+        let (start, end) = record.region.map(|(start, end)| (start.to_string(), end.to_string())).unwrap_or_default();
+        fields.extend([start, end]);
+    }
+    if columns.statistics {
+        fields.extend([format!("{:.3e}", hit.e_value), format!("{:.3e}", p_value(hit.e_value))]);
+    }
+    if columns.id_mapping {
+        fields.extend([hit.ensembl.clone(), hit.refseq.clone(), hit.hgnc.clone()]);
+    }
+    if columns.protein {
+        fields.extend([hit.protein_length.to_string(), hit.molecular_weight.to_string()]);
+    }
+    fields.extend(kept_values(hit, columns));
+    fields
+}
+
+// the fields of a row for a peptide without hits, only the peptide and kept values are filled in
+fn unmatched_fields(peptide: &str, kept: &[String], columns: &Columns) -> Vec<String> {
+    let mut fields = vec![peptide.to_string()];
+    fields.resize(columns.names().len() - columns.kept.len(), String::new());
+    fields.extend((0..columns.kept.len()).map(|i| kept.get(i).cloned().unwrap_or_default()));
+    fields
+}
+
+// a hit's values of the kept query columns, empty for any the hit has no value for
+fn kept_values<'h>(hit: &'h Match, columns: &Columns) -> impl Iterator<Item = String> + 'h {
+    let kept = columns.kept.len();
+    (0..kept).map(move |i| hit.kept.get(i).cloned().unwrap_or_default())
+}
+
+// TSV and CSV writer with a header row
+struct DelimitedWriter<'a> {
+    writer: csv::Writer<Box<dyn Write + 'a>>,
+    columns: Columns,
+    wrote_header: bool,
+}
+
+impl<'a> DelimitedWriter<'a> {
+    fn new(out: Box<dyn Write + 'a>, delimiter: u8, columns: Columns) -> DelimitedWriter<'a> {
+        DelimitedWriter {
+            writer: csv::WriterBuilder::new().delimiter(delimiter).from_writer(out),
+            columns,
+            wrote_header: false,
+        }
+    }
+}
+
+impl MatchWriter for DelimitedWriter<'_> {
+    fn write_match(&mut self, hit: &Match) -> std::io::Result<()> {
+        if !self.wrote_header {
+            self.writer.write_record(self.columns.names())?;
+            self.wrote_header = true;
+        }
+        self.writer.write_record(fields(hit, &self.columns))?;
+        Ok(())
+    }
+
+    fn write_unmatched(&mut self, peptide: &str, kept: &[String]) -> std::io::Result<()> {
+        if !self.wrote_header {
+            self.writer.write_record(self.columns.names())?;
+            self.wrote_header = true;
+        }
+        self.writer.write_record(unmatched_fields(peptide, kept, &self.columns))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        // still write the header if there were no hits
+        if !self.wrote_header {
+            self.writer.write_record(self.columns.names())?;
+            self.wrote_header = true;
+        }
+        self.writer.flush()
+    }
+}
+
+// writes a JSON array of objects keyed by the column names
+struct JsonWriter<'a> {
+    out: Box<dyn Write + 'a>,
+    columns: Columns,
+    count: usize,
+}
+
+impl<'a> JsonWriter<'a> {
+    fn new(out: Box<dyn Write + 'a>, columns: Columns) -> JsonWriter<'a> {
+        JsonWriter { out, columns, count: 0 }
+    }
+
+    fn write_record(&mut self, record: serde_json::Value) -> std::io::Result<()> {
+        write!(self.out, "{}\n  {}", if self.count == 0 { "[" } else { "," }, record)?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+impl MatchWriter for JsonWriter<'_> {
+    fn write_match(&mut self, hit: &Match) -> std::io::Result<()> {
+        let positions = self.columns.coordinates.record(hit);
+        let mut record = serde_json::json!({
+            COLUMNS[0]: hit.peptide,
+            COLUMNS[1]: hit.matched_sequence,
+            COLUMNS[2]: hit.protein_id,
+            COLUMNS[3]: hit.protein_name,
+            COLUMNS[4]: hit.species,
+            COLUMNS[5]: hit.taxon_id,
+            COLUMNS[6]: hit.gene,
+            COLUMNS[7]: hit.mismatches,
+            COLUMNS[8]: hit.substitutions(),
+            COLUMNS[9]: positions.start,
+            COLUMNS[10]: positions.end,
+            COLUMNS[11]: hit.pe_level,
+        });
+        if self.columns.genomic {
+            record[GENOMIC_COLUMNS[0]] = hit.frame.into();
+            record[GENOMIC_COLUMNS[1]] = positions.genomic_start.into();
+            record[GENOMIC_COLUMNS[2]] = positions.genomic_end.into();
+        }
+        if self.columns.context {
+            record[CONTEXT_COLUMNS[0]] = hit.left_context.clone().into();
+            record[CONTEXT_COLUMNS[1]] = hit.right_context.clone().into();
+        }
+        if self.columns.score {
+            record[SCORE_COLUMN] = hit.score.into();
+        }
+        if self.columns.n_terminus {
+            record[N_TERMINUS_COLUMN] = hit.n_terminus.clone().into();
+        }
+        if self.columns.mature {
+            record[MATURE_COLUMNS[0]] = positions.mature.map(|(start, _)| start).into();
+            record[MATURE_COLUMNS[1]] = positions.mature.map(|(_, end)| end).into();
+        }
+        if self.columns.region {
+            record[REGION_COLUMNS[0]] = positions.region.map(|(start, _)| start).into();
+            record[REGION_COLUMNS[1]] = positions.region.map(|(_, end)| end).into();
+        }
+        if self.columns.statistics {
+            record[STATISTICS_COLUMNS[0]] = hit.e_value.into();
+            record[STATISTICS_COLUMNS[1]] = p_value(hit.e_value).into();
+        }
+        if self.columns.id_mapping {
+            record[ID_MAPPING_COLUMNS[0]] = hit.ensembl.clone().into();
+            record[ID_MAPPING_COLUMNS[1]] = hit.refseq.clone().into();
+            record[ID_MAPPING_COLUMNS[2]] = hit.hgnc.clone().into();
+        }
+        if self.columns.protein {
+            record[PROTEIN_COLUMNS[0]] = hit.protein_length.into();
+            record[PROTEIN_COLUMNS[1]] = hit.molecular_weight.into();
+        }
+        for (name, value) in self.columns.kept.iter().zip(kept_values(hit, &self.columns)) {
+            record[name] = value.into();
+        }
+        self.write_record(record)
+    }
+
+    fn write_unmatched(&mut self, peptide: &str, kept: &[String]) -> std::io::Result<()> {
+        // the hit fields are nulls
+        let names = self.columns.names();
+        let mut record = serde_json::Map::new();
+        record.insert(names[0].to_string(), peptide.into());
+        for name in &names[1..names.len() - self.columns.kept.len()] {
+            record.insert(name.to_string(), serde_json::Value::Null);
+        }
+        for (i, name) in self.columns.kept.iter().enumerate() {
+            record.insert(name.clone(), kept.get(i).cloned().unwrap_or_default().into());
+        }
+        self.write_record(record.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.count == 0 {
+            writeln!(self.out, "[]")?;
+        } else {
+            writeln!(self.out, "\n]")?;
+        }
+        self.out.flush()
+    }
+}
+
+// interval formats that place hits on their proteins for genome and protein browsers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interval {
+    Gff3, // 1-based closed intervals with the hit's details as attributes
+    Bed,  // 0-based half-open intervals named after the peptide, scored by mismatches
+}
+
+// writes a GFF3 or BED record per hit, with the protein ID as the sequence the hit is on
+struct IntervalWriter<'a> {
+    out: Box<dyn Write + 'a>,
+    interval: Interval,
+    count: usize,
+}
+
+impl<'a> IntervalWriter<'a> {
+    fn new(out: Box<dyn Write + 'a>, interval: Interval) -> IntervalWriter<'a> {
+        IntervalWriter { out, interval, count: 0 }
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        if self.interval == Interval::Gff3 {
+            writeln!(self.out, "##gff-version 3")?;
+        }
+        Ok(())
+    }
+}
+
+impl MatchWriter for IntervalWriter<'_> {
+    fn write_match(&mut self, hit: &Match) -> std::io::Result<()> {
+        if self.count == 0 {
+            self.write_header()?;
+        }
+        self.count += 1;
+        match self.interval {
+            Interval::Gff3 => {
+                let mut attributes = vec![
+                    format!("ID=match{}", self.count),
+                    format!("Name={}", gff3_escape(&hit.peptide)),
+                    format!("matched_sequence={}", gff3_escape(&hit.matched_sequence)),
+                    format!("mismatches={}", hit.mismatches),
+                ];
+                if hit.mismatches > 0 {
+                    attributes.push(format!("mutated_positions={}", gff3_escape(&hit.substitutions().join(","))));
+                }
+                writeln!(
+                    self.out,
+                    "{}\tpepmatch\tpolypeptide_region\t{}\t{}\t.\t.\t.\t{}",
+                    gff3_escape(&hit.protein_id).replace(' ', "%20"),
+                    hit.index_start,
+                    hit.index_end,
+                    attributes.join(";")
+                )
+            }
+            Interval::Bed => writeln!(
+                self.out,
+                "{}\t{}\t{}\t{}\t{}\t.",
+                hit.protein_id.replace(char::is_whitespace, "_"),
+                hit.index_start - 1,
+                hit.index_end,
+                hit.peptide,
+                hit.mismatches
+            ),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        // still write the GFF3 header if there were no hits
+        if self.count == 0 {
+            self.write_header()?;
+        }
+        self.out.flush()
+    }
+}
+
+// percent-encode the characters GFF3 reserves in columns and attribute values
+fn gff3_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ';' | '=' | '&' | ',' | '%' | '\t' | '\n' | '\r' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit() -> Match {
+        Match {
+            peptide: "SIINFEKL".to_string(),
+            matched_sequence: "SIINFEKV".to_string(),
+            protein_number: 0,
+            protein_id: "P01012".to_string(),
+            protein_name: "Ovalbumin".to_string(),
+            species: "Gallus gallus".to_string(),
+            taxon_id: "9031".to_string(),
+            gene: "SERPINB14".to_string(),
+            mismatches: 1,
+            mutated_positions: vec![8],
+            index_start: 258,
+            index_end: 265,
+            pe_level: 1,
+            gene_priority: false,
+            db: "sp".to_string(),
+            isoform: 0,
+            protein_length: 0,
+            molecular_weight: 0,
+            discontinuous: false,
+            frame: 0,
+            genomic_start: 0,
+            genomic_end: 0,
+            left_context: String::new(),
+            right_context: String::new(),
+            score: 0,
+            n_terminus: String::new(),
+            mature_start: 0,
+            mature_end: 0,
+            query_start: 0,
+            query_end: 0,
+            e_value: 0.0,
+            ensembl: String::new(),
+            refseq: String::new(),
+            hgnc: String::new(),
+            kept: Vec::new(),
+        }
+    }
+
+    fn write(format: Format, hits: &[Match]) -> String {
+        let mut out = Vec::new();
+        let mut writer = writer(format, Box::new(&mut out), Columns::default());
+        for hit in hits {
+            writer.write_match(hit).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn gff3_records() {
+        assert_eq!(
+            write(Format::Gff3, &[hit()]),
+            "##gff-version 3\nP01012\tpepmatch\tpolypeptide_region\t258\t265\t.\t.\t.\t\
+             ID=match1;Name=SIINFEKL;matched_sequence=SIINFEKV;mismatches=1;mutated_positions=8:L>V\n"
+        );
+        assert_eq!(write(Format::Gff3, &[]), "##gff-version 3\n");
+    }
+
+    #[test]
+    fn unmatched_rows_keep_only_the_peptide() {
+        let columns = Columns { kept: vec!["id".to_string()], ..Columns::default() };
+        let write_unmatched = |format| {
+            let mut out = Vec::new();
+            let mut writer = writer(format, Box::new(&mut out), columns.clone());
+            writer.write_unmatched("WWWWWW", &["e1".to_string()]).unwrap();
+            writer.finish().unwrap();
+            drop(writer);
+            String::from_utf8(out).unwrap()
+        };
+        let tsv = write_unmatched(Format::Tsv);
+        assert_eq!(tsv.lines().nth(1), Some(format!("WWWWWW{}\te1", "\t".repeat(COLUMNS.len() - 1)).as_str()));
+        let json: serde_json::Value = serde_json::from_str(&write_unmatched(Format::Json)).unwrap();
+        assert_eq!(json[0]["Query Sequence"], "WWWWWW");
+        assert!(json[0]["Protein ID"].is_null());
+        assert_eq!(json[0]["id"], "e1");
+
+        let mut out = Vec::new();
+        assert!(writer(Format::Bed, Box::new(&mut out), columns.clone()).write_unmatched("WWWWWW", &[]).is_err());
+    }
+
+    #[test]
+    fn bed_records_are_half_open() {
+        assert_eq!(write(Format::Bed, &[hit()]), "P01012\t257\t265\tSIINFEKL\t1\t.\n");
+        assert_eq!(write(Format::Bed, &[]), "");
+    }
+
+    #[test]
+    fn coordinate_conventions() {
+        let hit = Match { frame: -2, genomic_start: 100, genomic_end: 123, ..hit() };
+        let positions = |base, end_exclusive| {
+            let record = Coordinates { base, end_exclusive }.record(&hit);
+            (record.start, record.end, record.genomic_start, record.genomic_end)
+        };
+        assert_eq!(positions(1, false), (258, 265, 100, 123));
+        assert_eq!(positions(0, false), (257, 264, 99, 122));
+        assert_eq!(positions(0, true), (257, 265, 99, 123));
+        assert_eq!(positions(1, true), (258, 266, 100, 124));
+        for (base, end_exclusive) in [(0, false), (0, true), (1, false), (1, true)] {
+            let (start, end, _, _) = positions(base, end_exclusive);
+            assert_eq!(Coordinates { base, end_exclusive }.one_based(start, end), Some((258, 265)));
+        }
+        assert_eq!(Coordinates::default().one_based(0, 5), None);
+        assert_eq!(Coordinates { base: 0, end_exclusive: true }.one_based(4, 4), None);
+    }
+
+    #[test]
+    fn gff3_reserved_characters() {
+        assert_eq!(gff3_escape("a;b=c,d&e%f\tg"), "a%3Bb%3Dc%2Cd%26e%25f%09g");
+    }
+}
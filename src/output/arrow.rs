@@ -0,0 +1,226 @@
+use super::{Columns, MatchRecord, MatchWriter};
+use crate::statistics::p_value;
+use crate::Match;
+use arrow::array::{ArrayRef, Float64Array, Int32Array, Int8Array, RecordBatch, StringArray, UInt64Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ipc::writer::FileWriter;
+use parquet::arrow::ArrowWriter;
+use std::io::Write;
+use std::sync::Arc;
+
+// number of hits buffered into each record batch
+const HITS_PER_BATCH: usize = 65536;
+
+// columnar file formats hits can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Columnar {
+    Arrow, // Arrow IPC file
+    Parquet,
+}
+
+enum Sink<'a> {
+    Arrow(FileWriter<Box<dyn Write + Send + 'a>>),
+    Parquet(ArrowWriter<Box<dyn Write + Send + 'a>>),
+}
+
+// writes hits as record batches of an Arrow IPC or Parquet file, with the same columns
+// as the other formats and the counts and positions as integers
+pub(crate) struct ColumnarWriter<'a> {
+    format: Columnar,
+    columns: Columns,
+    schema: SchemaRef,
+    out: Option<Box<dyn Write + Send + 'a>>, // until the sink is opened on the first batch
+    sink: Option<Sink<'a>>,
+    hits: Vec<Match>,
+}
+
+impl<'a> ColumnarWriter<'a> {
+    pub fn new(out: Box<dyn Write + Send + 'a>, format: Columnar, columns: Columns) -> ColumnarWriter<'a> {
+        ColumnarWriter {
+            format,
+            schema: Arc::new(schema(&columns)),
+            columns,
+            out: Some(out),
+            sink: None,
+            hits: Vec::new(),
+        }
+    }
+
+    fn sink(&mut self) -> std::io::Result<&mut Sink<'a>> {
+        if self.sink.is_none() {
+            let out = self.out.take().expect("the output is only taken once");
+            self.sink = Some(match self.format {
+                Columnar::Arrow => Sink::Arrow(FileWriter::try_new(out, &self.schema).map_err(std::io::Error::other)?),
+                Columnar::Parquet => {
+                    Sink::Parquet(ArrowWriter::try_new(out, self.schema.clone(), None).map_err(std::io::Error::other)?)
+                }
+            });
+        }
+        Ok(self.sink.as_mut().unwrap())
+    }
+
+    fn write_batch(&mut self) -> std::io::Result<()> {
+        let batch = record_batch(self.schema.clone(), &self.hits, &self.columns)?;
+        self.hits.clear();
+        match self.sink()? {
+            Sink::Arrow(writer) => writer.write(&batch).map_err(std::io::Error::other),
+            Sink::Parquet(writer) => writer.write(&batch).map_err(std::io::Error::other),
+        }
+    }
+}
+
+impl MatchWriter for ColumnarWriter<'_> {
+    fn write_match(&mut self, hit: &Match) -> std::io::Result<()> {
+        self.hits.push(hit.clone());
+        if self.hits.len() == HITS_PER_BATCH {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.hits.is_empty() {
+            self.write_batch()?;
+        }
+        match &mut self.sink {
+            Some(Sink::Arrow(writer)) => writer.flush().map_err(std::io::Error::other),
+            // closes the row group
+            Some(Sink::Parquet(writer)) => writer.flush().map_err(std::io::Error::other),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        // still write the schema if there were no hits
+        if !self.hits.is_empty() || self.sink.is_none() {
+            self.write_batch()?;
+        }
+        match self.sink.take() {
+            Some(Sink::Arrow(mut writer)) => {
+                writer.finish().map_err(std::io::Error::other)?;
+                writer.into_inner().map_err(std::io::Error::other)?.flush()
+            }
+            Some(Sink::Parquet(writer)) => writer.into_inner().map_err(std::io::Error::other)?.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn schema(columns: &Columns) -> Schema {
+    let names = columns.names();
+    let types = column_types(columns);
+    Schema::new(
+        names.into_iter()
+            .zip(types)
+            .map(|(name, data_type)| Field::new(name, data_type, false))
+            .collect::<Vec<Field>>(),
+    )
+}
+
+// types of the columns in the order of Columns::names
+fn column_types(columns: &Columns) -> Vec<DataType> {
+    let mut types = vec![DataType::Utf8; 7];
+    types.extend([DataType::UInt64, DataType::Utf8, DataType::UInt64, DataType::UInt64, DataType::UInt64]);
+    if columns.genomic {
+        types.extend([DataType::Int8, DataType::UInt64, DataType::UInt64]);
+    }
+    if columns.context {
+        types.extend([DataType::Utf8, DataType::Utf8]);
+    }
+    if columns.score {
+        types.push(DataType::Int32);
+    }
+    if columns.n_terminus {
+        types.push(DataType::Utf8);
+    }
+    if columns.mature {
+        types.extend([DataType::UInt64, DataType::UInt64]);
+    }
+    if columns.region {
+        types.extend([DataType::UInt64, DataType::UInt64]);
+    }
+    if columns.statistics {
+        types.extend([DataType::Float64, DataType::Float64]);
+    }
+    if columns.id_mapping {
+        types.extend([DataType::Utf8, DataType::Utf8, DataType::Utf8]);
+    }
+    if columns.protein {
+        types.extend([DataType::UInt64, DataType::UInt64]);
+    }
+    types.extend(columns.kept.iter().map(|_| DataType::Utf8));
+    types
+}
+
+fn record_batch(schema: SchemaRef, hits: &[Match], columns: &Columns) -> std::io::Result<RecordBatch> {
+    let text = |field: fn(&Match) -> String| -> ArrayRef { Arc::new(StringArray::from_iter_values(hits.iter().map(field))) };
+    let count = |field: fn(&Match) -> usize| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter_values(hits.iter().map(|hit| field(hit) as u64)))
+    };
+    let records: Vec<MatchRecord> = hits.iter().map(|hit| columns.coordinates.record(hit)).collect();
+    let position = |field: fn(&MatchRecord) -> usize| -> ArrayRef {
+        Arc::new(UInt64Array::from_iter_values(records.iter().map(|record| field(record) as u64)))
+    };
+
+    let mut arrays = vec![
+        text(|hit| hit.peptide.clone()),
+        text(|hit| hit.matched_sequence.clone()),
+        text(|hit| hit.protein_id.clone()),
+        text(|hit| hit.protein_name.clone()),
+        text(|hit| hit.species.clone()),
+        text(|hit| hit.taxon_id.clone()),
+        text(|hit| hit.gene.clone()),
+        count(|hit| hit.mismatches),
+        text(|hit| hit.substitutions().join(";")),
+        position(|record| record.start),
+        position(|record| record.end),
+        count(|hit| hit.pe_level),
+    ];
+    if columns.genomic {
+        arrays.push(Arc::new(Int8Array::from_iter_values(hits.iter().map(|hit| hit.frame))));
+        arrays.push(position(|record| record.genomic_start));
+        arrays.push(position(|record| record.genomic_end));
+    }
+    if columns.context {
+        arrays.push(text(|hit| hit.left_context.clone()));
+        arrays.push(text(|hit| hit.right_context.clone()));
+    }
+    if columns.score {
+        arrays.push(Arc::new(Int32Array::from_iter_values(hits.iter().map(|hit| hit.score))));
+    }
+    if columns.n_terminus {
+        arrays.push(text(|hit| hit.n_terminus.clone()));
+    }
+    if columns.mature {
+        let mature = |field: fn((usize, usize)) -> usize| -> ArrayRef {
+            Arc::new(UInt64Array::from_iter(records.iter().map(|record| record.mature.map(|mature| field(mature) as u64))))
+        };
+        arrays.push(mature(|(start, _)| start));
+        arrays.push(mature(|(_, end)| end));
+    }
+    if columns.region {
+        let region = |field: fn((usize, usize)) -> usize| -> ArrayRef {
+            Arc::new(UInt64Array::from_iter(records.iter().map(|record| record.region.map(|region| field(region) as u64))))
+        };
+        arrays.push(region(|(start, _)| start));
+        arrays.push(region(|(_, end)| end));
+    }
+    if columns.statistics {
+        arrays.push(Arc::new(Float64Array::from_iter_values(hits.iter().map(|hit| hit.e_value))));
+        arrays.push(Arc::new(Float64Array::from_iter_values(hits.iter().map(|hit| p_value(hit.e_value)))));
+    }
+    if columns.id_mapping {
+        arrays.push(text(|hit| hit.ensembl.clone()));
+        arrays.push(text(|hit| hit.refseq.clone()));
+        arrays.push(text(|hit| hit.hgnc.clone()));
+    }
+    if columns.protein {
+        arrays.push(count(|hit| hit.protein_length));
+        arrays.push(count(|hit| hit.molecular_weight));
+    }
+    for i in 0..columns.kept.len() {
+        let values = hits.iter().map(|hit| hit.kept.get(i).cloned().unwrap_or_default());
+        arrays.push(Arc::new(StringArray::from_iter_values(values)));
+    }
+    RecordBatch::try_new(schema, arrays).map_err(std::io::Error::other)
+}
@@ -0,0 +1,161 @@
+use super::{kept_values, Columns, MatchWriter};
+use crate::statistics;
+use crate::Match;
+use std::collections::HashSet;
+
+// writes hits to a matches table of a DuckDB results file, laid out like the SQLite results
+// DB's, hits are held until they're flushed and appended in one go as DuckDB takes single
+// rows slowly, the tables are replaced on every run
+pub(crate) struct DuckdbWriter {
+    conn: duckdb::Connection,
+    columns: Columns,
+    hits: Vec<Match>,
+    proteins: HashSet<usize>, // protein numbers whose metadata is written
+    match_id: usize,
+}
+
+impl DuckdbWriter {
+    pub fn create(path: &str, columns: Columns) -> std::io::Result<DuckdbWriter> {
+        // kept query columns are text columns of the matches table after the others
+        let kept: Vec<String> = columns.kept.iter().map(|name| format!("\"{}\"", name.replace('"', "\"\""))).collect();
+        let conn = duckdb::Connection::open(path)
+            .and_then(|conn| {
+                create_tables(&conn)?;
+                for name in &kept {
+                    conn.execute_batch(&format!("ALTER TABLE matches ADD COLUMN {} VARCHAR", name))?;
+                }
+                Ok(conn)
+            })
+            .map_err(error)?;
+        Ok(DuckdbWriter { conn, columns, hits: Vec::new(), proteins: HashSet::new(), match_id: 0 })
+    }
+
+    fn append(&mut self) -> duckdb::Result<()> {
+        let mut appender = self.conn.appender("metadata")?;
+        for hit in self.hits.iter().filter(|hit| self.proteins.insert(hit.protein_number)) {
+            // the IDs from an ID mapping are NULL without one
+            let (ensembl, refseq, hgnc) = if self.columns.id_mapping {
+                (Some(&hit.ensembl), Some(&hit.refseq), Some(&hit.hgnc))
+            } else {
+                (None, None, None)
+            };
+            appender.append_row(duckdb::params![
+                hit.protein_number as u32, hit.protein_id, hit.protein_name, hit.species,
+                hit.taxon_id, hit.gene, hit.pe_level as u8, hit.gene_priority,
+                hit.db, hit.isoform as u32, hit.protein_length as u32, hit.molecular_weight as u32, ensembl, refseq, hgnc
+            ])?;
+        }
+        appender.flush()?;
+
+        let mut appender = self.conn.appender("matches")?;
+        for hit in &self.hits {
+            self.match_id += 1;
+            let record = self.columns.coordinates.record(hit);
+            let (frame, genomic_start, genomic_end) = if self.columns.genomic {
+                (Some(hit.frame), Some(record.genomic_start as u64), Some(record.genomic_end as u64))
+            } else {
+                (None, None, None)
+            };
+            let (left_context, right_context) = if self.columns.context {
+                (Some(&hit.left_context), Some(&hit.right_context))
+            } else {
+                (None, None)
+            };
+            let score = self.columns.score.then_some(hit.score);
+            let n_terminus = self.columns.n_terminus.then_some(&hit.n_terminus);
+            let (mature_start, mature_end) = match record.mature.filter(|_| self.columns.mature) {
+                Some((start, end)) => (Some(start as u64), Some(end as u64)),
+                None => (None, None),
+            };
+            let (query_start, query_end) = match record.region.filter(|_| self.columns.region) {
+                Some((start, end)) => (Some(start as u64), Some(end as u64)),
+                None => (None, None),
+            };
+            let (e_value, p_value) = if self.columns.statistics {
+                (Some(hit.e_value), Some(statistics::p_value(hit.e_value)))
+            } else {
+                (None, None)
+            };
+            let (match_id, protein_number, mismatches) = (self.match_id as u64, hit.protein_number as u32, hit.mismatches as u32);
+            let (mutated_positions, index_start, index_end) =
+                (hit.substitutions().join(";"), record.start as u64, record.end as u64);
+            let kept: Vec<String> = kept_values(hit, &self.columns).collect();
+            let mut params: Vec<&dyn duckdb::ToSql> = vec![
+                &match_id, &hit.peptide, &hit.matched_sequence, &protein_number, &mismatches, &mutated_positions,
+                &index_start, &index_end, &frame, &genomic_start, &genomic_end, &left_context, &right_context, &score,
+                &n_terminus, &mature_start, &mature_end, &query_start, &query_end, &e_value, &p_value,
+            ];
+            params.extend(kept.iter().map(|value| value as &dyn duckdb::ToSql));
+            appender.append_row(params.as_slice())?;
+        }
+        appender.flush()?;
+        self.hits.clear();
+        Ok(())
+    }
+}
+
+impl MatchWriter for DuckdbWriter {
+    fn write_match(&mut self, hit: &Match) -> std::io::Result<()> {
+        self.hits.push(hit.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.append().map_err(error)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.append().and_then(|_| self.conn.execute_batch("CHECKPOINT")).map_err(error)
+    }
+}
+
+fn create_tables(conn: &duckdb::Connection) -> duckdb::Result<()> {
+    conn.execute_batch(
+        "DROP TABLE IF EXISTS matches;
+         DROP TABLE IF EXISTS metadata;
+         CREATE TABLE metadata (
+            protein_number   UINTEGER PRIMARY KEY,
+            protein_id       VARCHAR NOT NULL,
+            protein_name     VARCHAR NOT NULL,
+            species          VARCHAR NOT NULL,
+            taxon_id         VARCHAR NOT NULL,
+            gene             VARCHAR NOT NULL,
+            pe_level         UTINYINT NOT NULL,
+            gene_priority    BOOLEAN NOT NULL,
+            db               VARCHAR NOT NULL,
+            isoform          UINTEGER NOT NULL,
+            length           UINTEGER NOT NULL,
+            molecular_weight UINTEGER NOT NULL,
+            ensembl          VARCHAR,
+            refseq           VARCHAR,
+            hgnc             VARCHAR
+         );
+         CREATE TABLE matches (
+            match_id         UBIGINT PRIMARY KEY,
+            peptide          VARCHAR NOT NULL,
+            matched_sequence VARCHAR NOT NULL,
+            protein_number   UINTEGER NOT NULL REFERENCES metadata (protein_number),
+            mismatches       UINTEGER NOT NULL,
+            mutated_positions VARCHAR NOT NULL,
+            index_start      UBIGINT NOT NULL,
+            index_end        UBIGINT NOT NULL,
+            frame            TINYINT,
+            genomic_start    UBIGINT,
+            genomic_end      UBIGINT,
+            left_context     VARCHAR,
+            right_context    VARCHAR,
+            score            INTEGER,
+            n_terminus       VARCHAR,
+            mature_start     UBIGINT,
+            mature_end       UBIGINT,
+            query_start      UBIGINT,
+            query_end        UBIGINT,
+            e_value          DOUBLE,
+            p_value          DOUBLE
+         );",
+    )
+}
+
+fn error(e: duckdb::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
@@ -0,0 +1,171 @@
+use super::{kept_values, Columns, MatchWriter};
+use crate::statistics;
+use crate::Match;
+
+// writes hits to a matches table of a results DB, with the metadata of the proteins
+// they're in normalized into a metadata table the matches reference, the tables
+// are replaced on every run
+pub(crate) struct SqliteWriter {
+    conn: rusqlite::Connection,
+    columns: Columns,
+    insert_match: String,
+}
+
+impl SqliteWriter {
+    pub fn create(path: &str, columns: Columns) -> std::io::Result<SqliteWriter> {
+        // kept query columns are text columns of the matches table after the others
+        let kept: Vec<String> = columns.kept.iter().map(|name| format!("\"{}\"", name.replace('"', "\"\""))).collect();
+        let conn = rusqlite::Connection::open(path)
+            .and_then(|conn| {
+                create_tables(&conn)?;
+                for name in &kept {
+                    conn.execute(&format!("ALTER TABLE matches ADD COLUMN {} TEXT", name), rusqlite::params![])?;
+                }
+                conn.execute_batch("BEGIN")?;
+                Ok(conn)
+            })
+            .map_err(error)?;
+        let params: Vec<String> = (1..=20 + kept.len()).map(|i| format!("?{}", i)).collect();
+        let insert_match = format!(
+            "INSERT INTO matches (peptide, matched_sequence, protein_number, mismatches, mutated_positions,
+                index_start, index_end, frame, genomic_start, genomic_end, left_context, right_context, score, n_terminus,
+                mature_start, mature_end, query_start, query_end, e_value, p_value{})
+             VALUES ({})",
+            kept.iter().map(|name| format!(", {}", name)).collect::<String>(),
+            params.join(", ")
+        );
+        Ok(SqliteWriter { conn, columns, insert_match })
+    }
+
+    fn insert(&self, hit: &Match) -> rusqlite::Result<()> {
+        // the IDs from an ID mapping are NULL without one
+        let (ensembl, refseq, hgnc) = if self.columns.id_mapping {
+            (Some(&hit.ensembl), Some(&hit.refseq), Some(&hit.hgnc))
+        } else {
+            (None, None, None)
+        };
+        self.conn
+            .prepare_cached(
+                "INSERT OR IGNORE INTO metadata (protein_number, protein_id, protein_name, species, taxon_id, gene, pe_level, gene_priority, db, isoform,
+                    length, molecular_weight, ensembl, refseq, hgnc)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            )?
+            .execute(rusqlite::params![
+                hit.protein_number as i64, hit.protein_id, hit.protein_name, hit.species,
+                hit.taxon_id, hit.gene, hit.pe_level as i64, hit.gene_priority,
+                hit.db, hit.isoform as i64, hit.protein_length as i64, hit.molecular_weight as i64, ensembl, refseq, hgnc
+            ])?;
+
+        let record = self.columns.coordinates.record(hit);
+        let (frame, genomic_start, genomic_end) = if self.columns.genomic {
+            (Some(hit.frame), Some(record.genomic_start as i64), Some(record.genomic_end as i64))
+        } else {
+            (None, None, None)
+        };
+        let (left_context, right_context) = if self.columns.context {
+            (Some(&hit.left_context), Some(&hit.right_context))
+        } else {
+            (None, None)
+        };
+        let score = self.columns.score.then_some(hit.score);
+        let n_terminus = self.columns.n_terminus.then_some(&hit.n_terminus);
+        let (mature_start, mature_end) = match record.mature.filter(|_| self.columns.mature) {
+            Some((start, end)) => (Some(start as i64), Some(end as i64)),
+            None => (None, None),
+        };
+        let (query_start, query_end) = match record.region.filter(|_| self.columns.region) {
+            Some((start, end)) => (Some(start as i64), Some(end as i64)),
+            None => (None, None),
+        };
+        let (e_value, p_value) = if self.columns.statistics {
+            (Some(hit.e_value), Some(statistics::p_value(hit.e_value)))
+        } else {
+            (None, None)
+        };
+        let (protein_number, mismatches) = (hit.protein_number as i64, hit.mismatches as i64);
+        let (mutated_positions, index_start, index_end) =
+            (hit.substitutions().join(";"), record.start as i64, record.end as i64);
+        let kept: Vec<String> = kept_values(hit, &self.columns).collect();
+        let mut params: Vec<&dyn rusqlite::ToSql> = vec![
+            &hit.peptide, &hit.matched_sequence, &protein_number, &mismatches, &mutated_positions,
+            &index_start, &index_end, &frame, &genomic_start, &genomic_end, &left_context, &right_context, &score,
+            &n_terminus, &mature_start, &mature_end, &query_start, &query_end, &e_value, &p_value,
+        ];
+        params.extend(kept.iter().map(|value| value as &dyn rusqlite::ToSql));
+        self.conn.prepare_cached(&self.insert_match)?.execute(rusqlite::params_from_iter(params))?;
+        Ok(())
+    }
+}
+
+impl MatchWriter for SqliteWriter {
+    fn write_match(&mut self, hit: &Match) -> std::io::Result<()> {
+        self.insert(hit).map_err(error)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        // commit the hits so far, so they're kept if the search is stopped
+        self.conn.execute_batch("COMMIT; BEGIN").map_err(error)
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.conn
+            .execute_batch(
+                "COMMIT;
+                 CREATE INDEX IF NOT EXISTS matches_peptide_idx ON matches (peptide);
+                 CREATE INDEX IF NOT EXISTS matches_protein_number_idx ON matches (protein_number);",
+            )
+            .map_err(error)
+    }
+}
+
+fn create_tables(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "PRAGMA foreign_keys = ON;
+         DROP TABLE IF EXISTS matches;
+         DROP TABLE IF EXISTS metadata;
+         CREATE TABLE metadata (
+            protein_number   INTEGER PRIMARY KEY,
+            protein_id       TEXT NOT NULL,
+            protein_name     TEXT NOT NULL,
+            species          TEXT NOT NULL,
+            taxon_id         TEXT NOT NULL,
+            gene             TEXT NOT NULL,
+            pe_level         INTEGER NOT NULL,
+            gene_priority    INTEGER NOT NULL,
+            db               TEXT NOT NULL,
+            isoform          INTEGER NOT NULL,
+            length           INTEGER NOT NULL,
+            molecular_weight INTEGER NOT NULL,
+            ensembl          TEXT,
+            refseq           TEXT,
+            hgnc             TEXT
+         );
+         CREATE TABLE matches (
+            match_id         INTEGER PRIMARY KEY,
+            peptide          TEXT NOT NULL,
+            matched_sequence TEXT NOT NULL,
+            protein_number   INTEGER NOT NULL REFERENCES metadata (protein_number),
+            mismatches       INTEGER NOT NULL,
+            mutated_positions TEXT NOT NULL,
+            index_start      INTEGER NOT NULL,
+            index_end        INTEGER NOT NULL,
+            frame            INTEGER,
+            genomic_start    INTEGER,
+            genomic_end      INTEGER,
+            left_context     TEXT,
+            right_context    TEXT,
+            score            INTEGER,
+            n_terminus       TEXT,
+            mature_start     INTEGER,
+            mature_end       INTEGER,
+            query_start      INTEGER,
+            query_end        INTEGER,
+            e_value          REAL,
+            p_value          REAL
+         );",
+    )
+}
+
+fn error(e: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
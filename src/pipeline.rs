@@ -0,0 +1,84 @@
+use crate::error::{PepMatchError, Result};
+use crate::{Match, Matcher};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+// search a query as a pipeline on a tokio runtime: chunks of peptides are read with `read`
+// until it returns None and up to `depth` of them are searched at once on the runtime's
+// blocking threads, each with its own handle on the index, while the next are read and the
+// hits of earlier ones given to `write` in the order the chunks were read, so looking k-mers
+// up in the DB and verifying hits go on while the query is read and the hits written, each
+// chunk comes with what `write` needs of it besides its hits
+pub fn match_pipelined<C>(
+    matcher: &Matcher,
+    depth: usize,
+    mut read: impl FnMut() -> Result<Option<(Vec<String>, C)>>,
+    mut write: impl FnMut(C, Vec<Match>) -> Result<()>,
+) -> Result<()> {
+    let depth = depth.max(1);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .max_blocking_threads(depth)
+        .build()
+        .map_err(PepMatchError::io("the async runtime"))?;
+    let matchers = (0..depth).map(|_| matcher.fork()).collect::<Result<Vec<Matcher>>>()?;
+    let matchers = Arc::new(Mutex::new(matchers));
+
+    let mut searching = VecDeque::with_capacity(depth);
+    let mut reading = true;
+    loop {
+        while reading && searching.len() < depth {
+            let Some((peptides, chunk)) = read()? else {
+                reading = false;
+                break;
+            };
+            let matchers = Arc::clone(&matchers);
+            // a matcher is free for every search running, as no more than depth run at once
+            let search = runtime.spawn_blocking(move || {
+                let matcher = matchers.lock().expect("no search panics holding the matchers").pop();
+                let matcher = matcher.expect("a matcher for each search running");
+                let hits = matcher.match_peptides(&peptides);
+                matchers.lock().expect("no search panics holding the matchers").push(matcher);
+                hits
+            });
+            searching.push_back((chunk, search));
+        }
+        let Some((chunk, search)) = searching.pop_front() else {
+            return Ok(());
+        };
+        let hits = runtime.block_on(search).unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))?;
+        write(chunk, hits)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Preprocessor;
+
+    #[test]
+    fn chunks_are_written_in_the_order_they_are_read() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-pipeline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        Preprocessor::new(fasta, 3).db_path(db).preprocess().unwrap();
+        let matcher = Matcher::new(db).unwrap().max_mismatches(1);
+
+        let queries = ["MKLV", "STAA", "QWDE", "MKLA", "GGGG", "AAQW", "VSTA"];
+        let mut chunks = queries.iter().enumerate();
+        let read = || Ok(chunks.next().map(|(i, peptide)| (vec![peptide.to_string()], i)));
+        let mut written = Vec::new();
+        let write = |i, hits: Vec<Match>| {
+            written.push((i, hits.iter().map(|hit| hit.matched_sequence.clone()).collect::<Vec<_>>()));
+            Ok(())
+        };
+        match_pipelined(&matcher, 3, read, write).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let order: Vec<usize> = written.iter().map(|(i, _)| *i).collect();
+        assert_eq!(order, (0..queries.len()).collect::<Vec<_>>());
+        assert_eq!(written[3].1, vec!["MKLV".to_string()], "hits are found by the forked matchers' settings");
+        assert!(written[4].1.is_empty());
+    }
+}
@@ -0,0 +1,600 @@
+use crate::equivalence::EquivalenceClasses;
+use crate::error::{PepMatchError, Result};
+use crate::header::{self, HeaderFormat};
+use crate::index::{write_binary_index, MemoryIndex};
+#[cfg(feature = "lmdb")]
+use crate::index::LmdbStore;
+#[cfg(feature = "rocksdb")]
+use crate::index::RocksStore;
+use crate::progress;
+use crate::proteome::{count_proteins, read_protein_ids, ProteinFilter, ProteomeReader};
+use std::borrow::Cow;
+
+#[cfg(feature = "sqlite")]
+mod dedupe;
+#[cfg(feature = "duckdb")]
+mod duckdb;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+mod store;
+
+#[cfg(feature = "sqlite")]
+pub(crate) use sqlite::PROGRESS_TABLE;
+
+// number of proteins k-merized together and written in one transaction
+#[cfg(any(feature = "sqlite", feature = "duckdb", feature = "rocksdb", feature = "lmdb"))]
+const PROTEINS_PER_BATCH: usize = 500;
+
+// bytes of metadata, row and index overhead a protein takes in the DB beyond its sequence and
+// header fields, roughly
+const PROTEIN_BYTES: usize = 64;
+
+// what preprocessing a proteome would write, from a dry run that reads it without writing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DryRun {
+    pub proteins: usize, // proteins indexed
+    pub synonyms: usize, // proteins left out for having the sequence of an indexed one
+    pub residues: usize,
+    pub locations: usize, // k-mers the proteins are split into, of the extra k too
+    pub estimated_bytes: u64, // a rough estimate of the DB's size
+}
+
+// table layout of a preprocessed DB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DbLayout {
+    #[default]
+    Native, // one row of packed locations per k-mer plus a proteins table
+    Python, // the Python PEPMatch's tables, so either tool can search the DB
+}
+
+impl std::str::FromStr for DbLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<DbLayout, String> {
+        match s.to_lowercase().as_str() {
+            "native" => Ok(DbLayout::Native),
+            "python" => Ok(DbLayout::Python),
+            _ => Err(format!("unknown DB layout: {}", s)),
+        }
+    }
+}
+
+// how the locations of each k-mer of a SQLite DB are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None, // u32 (protein number, position) pairs
+    Zstd, // varints of the differences between locations, compressed with zstd where that's smaller
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Compression, String> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression: {}", s)),
+        }
+    }
+}
+
+// what a proteome is preprocessed into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexBackend {
+    #[default]
+    Sqlite, // a SQLite DB
+    Bin, // a binary index of sorted k-mers that's memory-mapped to search it
+    #[cfg(feature = "duckdb")]
+    Duckdb, // a DuckDB file
+    #[cfg(feature = "rocksdb")]
+    Rocksdb, // a RocksDB database directory
+    #[cfg(feature = "lmdb")]
+    Lmdb, // an LMDB environment directory
+}
+
+impl std::str::FromStr for IndexBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<IndexBackend, String> {
+        match s.to_lowercase().as_str() {
+            "sqlite" => Ok(IndexBackend::Sqlite),
+            "bin" => Ok(IndexBackend::Bin),
+            #[cfg(feature = "duckdb")]
+            "duckdb" => Ok(IndexBackend::Duckdb),
+            #[cfg(not(feature = "duckdb"))]
+            "duckdb" => Err("the duckdb backend needs pepmatch built with the duckdb feature".to_string()),
+            #[cfg(feature = "rocksdb")]
+            "rocksdb" => Ok(IndexBackend::Rocksdb),
+            #[cfg(not(feature = "rocksdb"))]
+            "rocksdb" => Err("the rocksdb backend needs pepmatch built with the rocksdb feature".to_string()),
+            #[cfg(feature = "lmdb")]
+            "lmdb" => Ok(IndexBackend::Lmdb),
+            #[cfg(not(feature = "lmdb"))]
+            "lmdb" => Err("the lmdb backend needs pepmatch built with the lmdb feature".to_string()),
+            _ => Err(format!("unknown index backend: {}", s)),
+        }
+    }
+}
+
+// preprocesses proteome FASTA files into k-mers and metadata tables in a SQLite DB,
+// its settings also describe how the Matcher builds an index in memory
+pub struct Preprocessor {
+    pub(crate) proteomes: Vec<(String, String)>, // (name, path) of each proteome
+    fasta: Option<String>, // the proteome itself when it's given as text rather than a path
+    pub(crate) k: usize,
+    pub(crate) extra_k: Vec<usize>, // other k the proteome is k-merized with too, without k
+    db_path: String,
+    threads: usize,
+    pub(crate) i_l_equivalent: bool,
+    pub(crate) equivalence_classes: EquivalenceClasses,
+    header_format: HeaderFormat,
+    pub(crate) progress: bool,
+    layout: DbLayout,
+    backend: IndexBackend,
+    gene_priority: Option<String>,
+    filter: ProteinFilter,
+    translate: bool,
+    dedupe_sequences: bool,
+    shards: usize, // files the k-mers are split across next to the DB, 0 keeps them in it
+    compression: Compression,
+    cache_mb: usize,
+    page_size: usize,
+    wal: bool,
+    resume: bool,
+    overwrite: bool,
+    auto_rebuild: bool,
+}
+
+impl Preprocessor {
+    // the DB is written to default_db_path unless db_path is set,
+    // the proteome is named after its file, e.g. human.fasta --> human
+    pub fn new(proteome: &str, k: usize) -> Preprocessor {
+        Preprocessor::with_proteomes(&[(proteome_name(proteome), proteome.to_string())], k)
+    }
+
+    // preprocess several (name, path) proteomes into one DB, e.g. human and viral
+    // proteomes, that can be told apart when matching, the DB is named after the first
+    pub fn with_proteomes(proteomes: &[(String, String)], k: usize) -> Preprocessor {
+        Preprocessor {
+            proteomes: proteomes.to_vec(),
+            fasta: None,
+            k,
+            extra_k: Vec::new(),
+            db_path: default_db_path(&proteomes[0].1, k),
+            threads: 0,
+            i_l_equivalent: false,
+            equivalence_classes: EquivalenceClasses::default(),
+            header_format: HeaderFormat::Auto,
+            progress: false,
+            layout: DbLayout::Native,
+            backend: IndexBackend::Sqlite,
+            gene_priority: None,
+            filter: ProteinFilter::new(),
+            translate: false,
+            dedupe_sequences: false,
+            shards: 0,
+            compression: Compression::None,
+            cache_mb: 256,
+            page_size: 16384,
+            wal: false,
+            resume: false,
+            overwrite: false,
+            auto_rebuild: false,
+        }
+    }
+
+    // a proteome given as FASTA text rather than a file, e.g. one pasted into a web page,
+    // it can only be built into an index in memory
+    pub fn from_fasta(name: &str, fasta: &str, k: usize) -> Preprocessor {
+        let mut preprocessor = Preprocessor::with_proteomes(&[(name.to_string(), name.to_string())], k);
+        preprocessor.fasta = Some(fasta.to_string());
+        preprocessor
+    }
+
+    pub fn db_path(mut self, db_path: &str) -> Preprocessor {
+        self.db_path = db_path.to_string();
+        self
+    }
+
+    // also k-merize the proteome with these k, each into k-mers of its own, so searches
+    // can look each peptide up with the k that suits it, only native SQLite DBs and
+    // indices in memory keep several
+    pub fn extra_k(mut self, k_values: &[usize]) -> Preprocessor {
+        self.extra_k = k_values.iter().copied().filter(|k| *k != self.k).collect();
+        self.extra_k.sort();
+        self.extra_k.dedup();
+        self
+    }
+
+    // cap the number of threads used to k-merize proteins, 0 uses all cores
+    pub fn threads(mut self, threads: usize) -> Preprocessor {
+        self.threads = threads;
+        self
+    }
+
+    // store k-mers with every I as an L so I/L-ambiguous peptides can still match,
+    // the proteins table keeps the original residues for reporting
+    pub fn treat_i_l_equivalent(mut self, treat_i_l_equivalent: bool) -> Preprocessor {
+        self.i_l_equivalent = treat_i_l_equivalent;
+        self
+    }
+
+    // store k-mers with every residue of a class as the first of its class, like I and L, so
+    // peptides match whichever of the class's residues the protein has, the proteins table
+    // keeps the original residues for reporting, only native SQLite DBs record them
+    pub fn equivalence_classes(mut self, equivalence_classes: EquivalenceClasses) -> Preprocessor {
+        self.equivalence_classes = equivalence_classes;
+        self
+    }
+
+    // how the FASTA headers are parsed for metadata, detected per header by default
+    pub fn header_format(mut self, header_format: HeaderFormat) -> Preprocessor {
+        self.header_format = header_format;
+        self
+    }
+
+    // show progress bars on stderr while preprocessing
+    pub fn progress(mut self, progress: bool) -> Preprocessor {
+        self.progress = progress;
+        self
+    }
+
+    // write the DB in the Python PEPMatch's layout instead, which holds a single proteome
+    pub fn layout(mut self, layout: DbLayout) -> Preprocessor {
+        self.layout = layout;
+        self
+    }
+
+    // write a binary index to be memory-mapped by the matcher instead of a SQLite DB,
+    // which is built in memory first, a DuckDB file or a RocksDB or LMDB directory, the default path
+    // gets the backend's extension, e.g. .bin
+    pub fn backend(mut self, backend: IndexBackend) -> Preprocessor {
+        let extension = match backend {
+            IndexBackend::Sqlite => None,
+            IndexBackend::Bin => Some("bin"),
+            #[cfg(feature = "duckdb")]
+            IndexBackend::Duckdb => Some("duckdb"),
+            #[cfg(feature = "rocksdb")]
+            IndexBackend::Rocksdb => Some("rocksdb"),
+            #[cfg(feature = "lmdb")]
+            IndexBackend::Lmdb => Some("lmdb"),
+        };
+        if let Some(extension) = extension.filter(|_| self.db_path == default_db_path(&self.proteomes[0].1, self.k)) {
+            self.db_path = format!("{}_k{}.{}", proteome_name(&self.proteomes[0].1), self.k, extension);
+        }
+        self.backend = backend;
+        self
+    }
+
+    // UniProt's gene priority proteome of one protein per gene, proteins with the same
+    // ID in the proteome are flagged and preferred in best match mode
+    pub fn gene_priority(mut self, gene_priority: &str) -> Preprocessor {
+        self.gene_priority = Some(gene_priority.to_string());
+        self
+    }
+
+    // leave out the proteins the filter doesn't pass, by PE level or Swiss-Prot review,
+    // so they're never indexed
+    pub fn protein_filter(mut self, filter: ProteinFilter) -> Preprocessor {
+        self.filter = filter;
+        self
+    }
+
+    // the proteome files are nucleotide FASTA to be translated in all six reading frames,
+    // hits report where they are in the nucleotide sequence
+    pub fn translate(mut self, translate: bool) -> Preprocessor {
+        self.translate = translate;
+        self
+    }
+
+    // index each sequence found more than once in a proteome under one of its proteins, the
+    // best evidenced, and keep the others as its synonyms, which searches can report too
+    pub fn dedupe_sequences(mut self, dedupe_sequences: bool) -> Preprocessor {
+        self.dedupe_sequences = dedupe_sequences;
+        self
+    }
+
+    // split the k-mers across this many SQLite files next to the DB by their first residues,
+    // for proteomes too large to index in one file, the DB keeps the rest, 0 or 1 don't split them
+    pub fn shards(mut self, shards: usize) -> Preprocessor {
+        self.shards = if shards > 1 { shards } else { 0 };
+        self
+    }
+
+    // how the locations of each k-mer are stored in a SQLite DB, zstd halves them or better
+    // for a little time decompressing them as they're looked up
+    pub fn compression(mut self, compression: Compression) -> Preprocessor {
+        self.compression = compression;
+        self
+    }
+
+    // size of SQLite's page cache while writing the DB
+    pub fn sqlite_cache_mb(mut self, cache_mb: usize) -> Preprocessor {
+        self.cache_mb = cache_mb;
+        self
+    }
+
+    // SQLite page size of a new DB in bytes, a power of 2 from 512 to 65536
+    pub fn sqlite_page_size(mut self, page_size: usize) -> Preprocessor {
+        self.page_size = page_size;
+        self
+    }
+
+    // write the DB in WAL journal mode, it's switched back once done so the DB stays one file
+    pub fn wal(mut self, wal: bool) -> Preprocessor {
+        self.wal = wal;
+        self
+    }
+
+    // continue preprocessing into a DB an earlier run was interrupted writing, from the
+    // proteins it hadn't committed, with the same proteomes and settings
+    pub fn resume(mut self, resume: bool) -> Preprocessor {
+        self.resume = resume;
+        self
+    }
+
+    // replace the DB if it already exists rather than refusing to write it
+    pub fn overwrite(mut self, overwrite: bool) -> Preprocessor {
+        self.overwrite = overwrite;
+        self
+    }
+
+    // rebuild a DB that already exists unless it's a SQLite DB built the same way from the
+    // proteome files as they are now, which is left as it is
+    pub fn auto_rebuild(mut self, auto_rebuild: bool) -> Preprocessor {
+        self.auto_rebuild = auto_rebuild;
+        self
+    }
+
+    // write the proteome's index to the DB path, as a SQLite DB, a binary index, a DuckDB
+    // file or a key-value store
+    pub fn preprocess(&self) -> Result<()> {
+        let _span = tracing::info_span!("preprocess", db = %self.db_path, k = self.k).entered();
+        self.check_settings()?;
+        if self.backend == IndexBackend::Sqlite {
+            return self.preprocess_sqlite();
+        }
+        self.replace_db()?;
+        match self.backend {
+            #[cfg(feature = "duckdb")]
+            IndexBackend::Duckdb => self.preprocess_duckdb(),
+            #[cfg(feature = "rocksdb")]
+            IndexBackend::Rocksdb => self.preprocess_store(&RocksStore::create(&self.db_path)?),
+            #[cfg(feature = "lmdb")]
+            IndexBackend::Lmdb => self.preprocess_store(&LmdbStore::create(&self.db_path)?),
+            _ => {
+                let index = MemoryIndex::build(self)?;
+                let _span = tracing::debug_span!("write_binary_index").entered();
+                let spinner = progress::spinner(self.progress, "writing binary index");
+                write_binary_index(&index, &self.db_path, self.i_l_equivalent)?;
+                spinner.finish_and_clear();
+                Ok(())
+            }
+        }
+    }
+
+    // read the proteome as preprocessing would and count what it would write, without writing
+    // anything, each record goes through the same reader, so a header or sequence preprocessing
+    // would fail on fails the dry run with its file and record, the DB's size is a rough
+    // estimate from the counts
+    pub fn dry_run(&self) -> Result<DryRun> {
+        self.check_settings()?;
+        let mut k_values = vec![self.k];
+        k_values.extend(&self.extra_k);
+        // identical sequences are picked representatives the way preprocessing picks them
+        #[cfg(feature = "sqlite")]
+        let representatives = match self.dedupe_sequences {
+            true => Some(dedupe::Representatives::choose(self.read_proteome()?)?),
+            false => None,
+        };
+        let mut dry_run = DryRun::default();
+        let mut bytes = 0;
+        for protein in self.read_proteome()? {
+            let protein = protein?;
+            let metadata = &protein.metadata;
+            bytes += metadata.protein_id.len()
+                + metadata.protein_name.len()
+                + metadata.species.len()
+                + metadata.taxon_id.len()
+                + metadata.gene.len()
+                + metadata.proteome.len()
+                + PROTEIN_BYTES;
+            // synonyms only take a row of metadata
+            #[cfg(feature = "sqlite")]
+            if representatives.as_ref().is_some_and(|representatives| !representatives.representative(&protein).1) {
+                dry_run.synonyms += 1;
+                continue;
+            }
+            dry_run.proteins += 1;
+            dry_run.residues += protein.sequence.len();
+            bytes += protein.sequence.len();
+            for k in &k_values {
+                let kmers = (protein.sequence.len() + 1).saturating_sub(*k);
+                dry_run.locations += kmers;
+                bytes += kmers * self.location_bytes(*k);
+            }
+        }
+        dry_run.estimated_bytes = bytes as u64;
+        Ok(dry_run)
+    }
+
+    // bytes a k-mer location takes in the DB, roughly, with its share of the k-mer's key and the
+    // page and index overhead, most k-mers of larger k having only a location or a few
+    fn location_bytes(&self, k: usize) -> usize {
+        match (self.backend, self.layout, self.compression) {
+            // a row of the k-mer and its location, and the k-mer again in the index on it
+            (IndexBackend::Sqlite, DbLayout::Python, _) => 2 * k + 22,
+            (IndexBackend::Sqlite, _, Compression::Zstd) => k + 14,
+            (IndexBackend::Sqlite, _, _) => k + 18,
+            #[cfg(feature = "duckdb")]
+            (IndexBackend::Duckdb, _, _) => k + 10,
+            _ => k + 15,
+        }
+    }
+
+    // settings the backend and layout have room for, checked before reading the proteome
+    fn check_settings(&self) -> Result<()> {
+        check_k(self.k)?;
+        for k in &self.extra_k {
+            check_k(*k)?;
+        }
+        // settings only the native SQLite layout has room for
+        let native_sqlite = self.backend == IndexBackend::Sqlite && self.layout == DbLayout::Native;
+        let only_native_sqlite = [
+            (!self.extra_k.is_empty(), "keep k-mers of several k"),
+            (self.dedupe_sequences, "keep the synonyms of deduplicated sequences"),
+            (self.shards > 0, "can be split into shards"),
+            (self.compression != Compression::None, "compress their k-mer locations"),
+            (!self.equivalence_classes.is_empty(), "record residue equivalence classes"),
+        ];
+        if let Some((_, what)) = only_native_sqlite.iter().find(|(set, _)| *set && !native_sqlite) {
+            return Err(PepMatchError::invalid(format!("only native SQLite DBs {}", what)));
+        }
+        if self.dedupe_sequences && self.translate {
+            return Err(PepMatchError::invalid("the frames of translated proteins can't be deduplicated"));
+        }
+        if self.fasta.is_some() {
+            return Err(PepMatchError::invalid("a proteome given as FASTA text can only be searched in memory"));
+        }
+        if self.layout == DbLayout::Python && self.backend != IndexBackend::Sqlite {
+            return Err(PepMatchError::invalid("the Python DB layout is a SQLite DB, it can't be written with another backend"));
+        }
+        if self.layout == DbLayout::Python && self.proteomes.len() > 1 {
+            return Err(PepMatchError::invalid("the Python DB layout holds a single proteome"));
+        }
+        if self.layout == DbLayout::Python && self.translate {
+            return Err(PepMatchError::invalid("the Python DB layout has no room for the frames of translated proteins"));
+        }
+        if self.backend == IndexBackend::Sqlite && (!self.page_size.is_power_of_two() || !(512..=65536).contains(&self.page_size)) {
+            return Err(PepMatchError::invalid(format!(
+                "SQLite page size must be a power of 2 from 512 to 65536, not {}",
+                self.page_size
+            )));
+        }
+        if self.resume && self.backend != IndexBackend::Sqlite {
+            return Err(PepMatchError::invalid("only SQLite DBs can be resumed, other indices are rebuilt"));
+        }
+        // an existing DB is only replaced when overwriting or rebuilding, or finished when resuming
+        if std::path::Path::new(&self.db_path).exists() && !self.overwrite && !self.auto_rebuild && !self.resume {
+            let resume = if self.backend == IndexBackend::Sqlite { " or --resume to finish preprocessing it" } else { "" };
+            return Err(PepMatchError::invalid(format!(
+                "{} already exists, use --overwrite to rebuild it{}",
+                self.db_path, resume
+            )));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn preprocess_sqlite(&self) -> Result<()> {
+        Err(PepMatchError::invalid("pepmatch was built without SQLite, preprocess to a binary index instead"))
+    }
+
+    // clear the way for a new DB at the DB path, the settings were checked first so an
+    // existing one is only deleted when overwriting or rebuilding
+    fn replace_db(&self) -> Result<()> {
+        if !std::path::Path::new(&self.db_path).exists() {
+            return Ok(());
+        }
+        if std::path::Path::new(&self.db_path).is_dir() {
+            return std::fs::remove_dir_all(&self.db_path).map_err(PepMatchError::io(&self.db_path));
+        }
+        #[cfg(feature = "sqlite")]
+        sqlite::remove_shards(&self.db_path)?;
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let path = format!("{}{}", self.db_path, suffix);
+            if std::path::Path::new(&path).exists() {
+                std::fs::remove_file(&path).map_err(PepMatchError::io(&path))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read_proteome(&self) -> Result<ProteomeReader> {
+        let reader = match &self.fasta {
+            Some(fasta) => ProteomeReader::from_fasta(&self.proteomes[0].0, fasta, header::parser(&self.header_format)),
+            None => ProteomeReader::open(&self.proteomes, header::parser(&self.header_format)),
+        };
+        let reader = reader
+            .protein_filter(self.filter)
+            .translate(self.translate);
+        match &self.gene_priority {
+            Some(path) => Ok(reader.gene_priority(read_protein_ids(path, header::parser(&self.header_format))?)),
+            None => Ok(reader),
+        }
+    }
+
+    // number of proteins to be read for a progress bar, only counted if it'll be shown
+    pub(crate) fn count_proteins(&self) -> Result<usize> {
+        match &self.fasta {
+            _ if !self.progress => Ok(0),
+            Some(fasta) => Ok(fasta.lines().filter(|line| line.starts_with('>')).count()),
+            None => count_proteins(&self.proteomes),
+        }
+    }
+
+    // k-merize a protein sequence into (kmer, position) pairs as they're stored in the index,
+    // with the index's k or one of its extra k
+    pub(crate) fn split_protein(&self, sequence: &str, k: usize) -> Vec<(String, usize)> {
+        split_sequence(&self.canonical(sequence), k)
+    }
+
+    // a protein sequence with the residues the index doesn't tell apart as one
+    pub(crate) fn canonical<'s>(&self, sequence: &'s str) -> Cow<'s, str> {
+        match (self.i_l_equivalent, self.equivalence_classes.canonicalize(sequence)) {
+            (true, canonical) => Cow::Owned(canonicalize_i_l(&canonical)),
+            (false, canonical) => canonical,
+        }
+    }
+}
+
+// k-mers have to have residues, the library takes any k unlike the command line
+pub(crate) fn check_k(k: usize) -> Result<()> {
+    if k == 0 {
+        return Err(PepMatchError::invalid("k must be at least 1"));
+    }
+    Ok(())
+}
+
+// default DB path for a proteome, e.g. human.fasta with k = 5 --> human_k5.db
+// in the current directory
+pub fn default_db_path(proteome: &str, k: usize) -> String {
+    format!("{}_k{}.db", proteome_name(proteome), k)
+}
+
+// name of a proteome going by its file name, e.g. data/human.fasta.gz --> human
+pub fn proteome_name(proteome: &str) -> String {
+    let mut name = std::path::Path::new(proteome)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| proteome.to_string());
+
+    if let Some(stripped) = name.strip_suffix(".gz") {
+        name = stripped.to_string();
+    }
+    for extension in [".fasta", ".fa", ".faa", ".fas"] {
+        if let Some(stripped) = name.strip_suffix(extension) {
+            name = stripped.to_string();
+            break;
+        }
+    }
+    name
+}
+
+// split the peptide into k-mers with a window size of 1 and store also the index of that k-mer
+pub(crate) fn split_sequence(seq: &str, k: usize) -> Vec<(String, usize)> {
+    let mut kmers = Vec::new();
+    let mut i: usize = 0;
+    while i + k <= seq.len() {
+        kmers.push((seq[i..i + k].to_string(), i));
+        i += 1;
+    }
+    kmers
+}
+
+// canonical form of a sequence where I and L are the same residue
+pub(crate) fn canonicalize_i_l(seq: &str) -> String {
+    seq.replace('I', "L")
+}
@@ -0,0 +1,143 @@
+use crate::error::Result;
+use crate::proteome::Protein;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// a protein's sequence and the proteome it's in, hashed so the sequences needn't be kept
+type SequenceKey = [u8; 16];
+
+// (PE level, not Swiss-Prot, read number) of a protein, lower is better
+type Rank = (usize, bool, usize);
+
+// the protein indexed for each sequence found more than once in a proteome, the others with
+// it are its synonyms, representatives are numbered again from 1 in the order they're read
+// and synonyms after them
+pub(super) struct Representatives {
+    chosen: HashMap<SequenceKey, Rank>,
+    read_numbers: Vec<usize>, // the read number of each representative, sorted
+}
+
+impl Representatives {
+    // pick the representative of each sequence in a proteome, the protein with the best evidence
+    // of existing, then a reviewed Swiss-Prot entry over a TrEMBL one, then the first read
+    pub fn choose(proteins: impl Iterator<Item = Result<Protein>>) -> Result<Representatives> {
+        let mut chosen: HashMap<SequenceKey, Rank> = HashMap::new();
+        for protein in proteins {
+            let protein = protein?;
+            let rank = rank(&protein);
+            chosen
+                .entry(sequence_key(&protein))
+                .and_modify(|best| *best = rank.min(*best))
+                .or_insert(rank);
+        }
+        let mut read_numbers: Vec<usize> = chosen.values().map(|(_, _, number)| *number).collect();
+        read_numbers.sort_unstable();
+        Ok(Representatives { chosen, read_numbers })
+    }
+
+    pub fn len(&self) -> usize {
+        self.read_numbers.len()
+    }
+
+    // the number of a protein's representative as it's indexed, and whether it's the protein itself
+    pub fn representative(&self, protein: &Protein) -> (usize, bool) {
+        let read_number = self.chosen[&sequence_key(protein)].2;
+        let number = self.read_numbers.partition_point(|number| *number < read_number) + 1;
+        (number, read_number == protein.number)
+    }
+
+    // the representatives of the proteins read, numbered as they're indexed
+    pub fn only<'a>(
+        &'a self,
+        proteins: impl Iterator<Item = Result<Protein>> + 'a,
+    ) -> impl Iterator<Item = Result<Protein>> + 'a {
+        proteins.filter_map(|protein| match protein {
+            Ok(mut protein) => {
+                let (number, is_representative) = self.representative(&protein);
+                protein.number = number;
+                is_representative.then_some(Ok(protein))
+            }
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    // the synonyms of the proteins read, numbered after the representatives, each with the
+    // number of its representative
+    pub fn synonyms<'a>(
+        &'a self,
+        proteins: impl Iterator<Item = Result<Protein>> + 'a,
+    ) -> impl Iterator<Item = Result<(Protein, usize)>> + 'a {
+        let mut next = self.len();
+        proteins.filter_map(move |protein| match protein {
+            Ok(mut protein) => {
+                let (representative, is_representative) = self.representative(&protein);
+                if is_representative {
+                    return None;
+                }
+                next += 1;
+                protein.number = next;
+                Some(Ok((protein, representative)))
+            }
+            Err(e) => Some(Err(e)),
+        })
+    }
+}
+
+fn sequence_key(protein: &Protein) -> SequenceKey {
+    let mut hasher = Sha256::new();
+    hasher.update(protein.metadata.proteome.as_bytes());
+    hasher.update([0]);
+    hasher.update(protein.sequence.as_bytes());
+    let mut key = SequenceKey::default();
+    key.copy_from_slice(&hasher.finalize()[..16]);
+    key
+}
+
+// proteins without a PE level rank below every level
+fn rank(protein: &Protein) -> Rank {
+    let pe_level = if protein.metadata.pe_level == 0 { 6 } else { protein.metadata.pe_level };
+    (pe_level, protein.metadata.db != "sp", protein.number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proteome::ProteinMetadata;
+
+    fn protein(number: usize, sequence: &str, proteome: &str, pe_level: usize, db: &str) -> Result<Protein> {
+        let metadata =
+            ProteinMetadata { pe_level, db: db.to_string(), proteome: proteome.to_string(), ..ProteinMetadata::default() };
+        Ok(Protein { number, sequence: sequence.to_string(), metadata })
+    }
+
+    fn proteins() -> Vec<Result<Protein>> {
+        vec![
+            protein(1, "MKLVST", "human", 3, "sp"),
+            protein(2, "PESTAV", "human", 0, "tr"),
+            protein(3, "MKLVST", "human", 1, "tr"),
+            protein(4, "MKLVST", "human", 1, "sp"),
+            protein(5, "PESTAV", "human", 0, "tr"),
+            protein(6, "MKLVST", "mouse", 2, "tr"),
+        ]
+    }
+
+    #[test]
+    fn the_best_evidenced_protein_represents_its_sequence() {
+        let representatives = Representatives::choose(proteins().into_iter()).unwrap();
+        assert_eq!(representatives.len(), 3);
+
+        let kept: Vec<usize> = representatives.only(proteins().into_iter()).map(|protein| protein.unwrap().number).collect();
+        assert_eq!(kept, vec![1, 2, 3]);
+        let representative = |i: usize| representatives.representative(proteins()[i].as_ref().unwrap());
+        assert_eq!(representative(3), (2, true), "PE 1 and Swiss-Prot beats PE 1 or Swiss-Prot");
+        assert_eq!(representative(0), (2, false));
+        assert_eq!(representative(1), (1, true), "no PE level ranks last and the first read wins ties");
+        assert_eq!(representative(5), (3, true), "proteomes are deduplicated on their own");
+
+        let synonyms: Vec<(usize, usize)> = representatives
+            .synonyms(proteins().into_iter())
+            .map(|synonym| synonym.map(|(protein, representative)| (protein.number, representative)).unwrap())
+            .collect();
+        assert_eq!(synonyms, vec![(4, 2), (5, 2), (6, 1)]);
+    }
+}
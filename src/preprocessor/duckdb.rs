@@ -0,0 +1,142 @@
+use super::{Preprocessor, PROTEINS_PER_BATCH};
+use crate::error::{PepMatchError, Result};
+use crate::progress;
+use crate::proteome::Protein;
+use crate::schema::SCHEMA_VERSION;
+use rayon::prelude::*;
+use tracing::debug_span;
+
+impl Preprocessor {
+    // stream the proteome into a DuckDB file in batches through its appenders, the k-mers
+    // are kept a row per location so they can be queried with plain SQL, and sorted by
+    // k-mer at the end so lookups only read the row groups a k-mer is in
+    pub(super) fn preprocess_duckdb(&self) -> Result<()> {
+        let conn = duckdb::Connection::open(&self.db_path).map_err(PepMatchError::duckdb(&self.db_path))?;
+        create_tables(&conn).map_err(PepMatchError::duckdb(&self.db_path))?;
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .map_err(PepMatchError::invalid)?;
+        let bar = progress::proteins(self.progress, self.count_proteins()?);
+        let mut kmers = 0;
+        let mut reader = self.read_proteome()?;
+        loop {
+            let proteins = reader.by_ref().take(PROTEINS_PER_BATCH).collect::<Result<Vec<Protein>>>()?;
+            if proteins.is_empty() {
+                break;
+            }
+            let _span = debug_span!("write_batch", proteins = proteins.len()).entered();
+            let split: Vec<Vec<(String, usize)>> =
+                pool.install(|| proteins.par_iter().map(|protein| self.split_protein(&protein.sequence, self.k)).collect());
+            kmers += split.iter().map(Vec::len).sum::<usize>();
+            insert_batch(&conn, &proteins, &split).map_err(PepMatchError::duckdb(&self.db_path))?;
+            bar.inc(proteins.len() as u64);
+            bar.set_message(format!("{} k-mers", kmers));
+        }
+        bar.finish();
+
+        let _span = debug_span!("sort_kmers").entered();
+        let spinner = progress::spinner(self.progress, "sorting k-mers");
+        let built_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let meta = [
+            ("k", self.k.to_string()),
+            ("i_l_equivalent", self.i_l_equivalent.to_string()),
+            ("translate", self.translate.to_string()),
+            ("version", env!("CARGO_PKG_VERSION").to_string()),
+            ("schema_version", SCHEMA_VERSION.to_string()),
+            ("built_at", built_at.to_string()), // seconds since the Unix epoch
+        ];
+        conn.execute_batch(
+            "CREATE TABLE kmers AS SELECT kmer, protein_number, position FROM kmer_rows ORDER BY kmer, protein_number, position;
+             DROP TABLE kmer_rows;
+             INSERT INTO proteomes
+             SELECT proteome, MIN(protein_number), MAX(protein_number) FROM metadata
+             GROUP BY proteome ORDER BY MIN(protein_number);",
+        )
+        .and_then(|_| {
+            let mut stmt = conn.prepare("INSERT INTO meta (key, value) VALUES (?, ?)")?;
+            for (key, value) in meta {
+                stmt.execute(duckdb::params![key, value])?;
+            }
+            Ok(())
+        })
+        .and_then(|_| conn.execute_batch("CHECKPOINT"))
+        .map_err(PepMatchError::duckdb(&self.db_path))?;
+        spinner.finish_and_clear();
+        Ok(())
+    }
+}
+
+// the meta table is written last, a DB without its k wasn't finished
+fn create_tables(conn: &duckdb::Connection) -> duckdb::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE meta (
+            key              VARCHAR PRIMARY KEY,
+            value            VARCHAR NOT NULL
+         );
+         CREATE TABLE metadata (
+            protein_number   UINTEGER PRIMARY KEY,
+            protein_id       VARCHAR NOT NULL,
+            protein_name     VARCHAR NOT NULL,
+            species          VARCHAR NOT NULL,
+            taxon_id         VARCHAR NOT NULL,
+            gene             VARCHAR NOT NULL,
+            pe_level         UTINYINT NOT NULL,
+            sequence_version UINTEGER NOT NULL,
+            proteome         VARCHAR NOT NULL,
+            gene_priority    BOOLEAN NOT NULL,
+            frame            TINYINT NOT NULL,
+            source_length    UINTEGER NOT NULL,
+            db               VARCHAR NOT NULL,
+            isoform          UINTEGER NOT NULL,
+            length           UINTEGER NOT NULL,
+            molecular_weight UINTEGER NOT NULL
+         );
+         CREATE TABLE proteins (
+            protein_number   UINTEGER PRIMARY KEY,
+            sequence         VARCHAR NOT NULL
+         );
+         CREATE TABLE kmer_rows (
+            kmer             VARCHAR NOT NULL,
+            protein_number   UINTEGER NOT NULL,
+            position         UINTEGER NOT NULL
+         );
+         CREATE TABLE proteomes (
+            proteome         VARCHAR NOT NULL,
+            first_protein    UINTEGER NOT NULL,
+            last_protein     UINTEGER NOT NULL
+         );",
+    )
+}
+
+fn insert_batch(conn: &duckdb::Connection, proteins: &[Protein], kmers: &[Vec<(String, usize)>]) -> duckdb::Result<()> {
+    let mut appender = conn.appender("metadata")?;
+    for protein in proteins {
+        let data = &protein.metadata;
+        appender.append_row(duckdb::params![
+            protein.number as u32, data.protein_id, data.protein_name, data.species,
+            data.taxon_id, data.gene, data.pe_level as u8, data.sequence_version as u32,
+            data.proteome, data.gene_priority, data.frame, data.source_length as u32,
+            data.db, data.isoform as u32, data.length as u32, data.molecular_weight as u32
+        ])?;
+    }
+    appender.flush()?;
+
+    let mut appender = conn.appender("proteins")?;
+    for protein in proteins {
+        appender.append_row(duckdb::params![protein.number as u32, protein.sequence])?;
+    }
+    appender.flush()?;
+
+    let mut appender = conn.appender("kmer_rows")?;
+    for (protein, protein_kmers) in proteins.iter().zip(kmers) {
+        for (kmer, position) in protein_kmers {
+            appender.append_row(duckdb::params![kmer, protein.number as u32, *position as u32])?;
+        }
+    }
+    appender.flush()
+}
@@ -0,0 +1,839 @@
+use super::dedupe::Representatives;
+use super::{Compression, DbLayout, Preprocessor, PROTEINS_PER_BATCH};
+use crate::build_info::{checksum, has_table, modified, BuildInfo};
+use crate::error::{PepMatchError, Result};
+use crate::index::{encode_kmer_locations, kmers_table, pack_idx, shard_of, shard_path, Location, PythonTables};
+use crate::progress;
+use crate::proteome::Protein;
+use crate::schema::SCHEMA_VERSION;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use tracing::{debug_span, trace_span};
+
+// (first, last) protein numbers of every batch written so far, only there while preprocessing,
+// a DB that has it was interrupted and can be resumed
+pub(crate) const PROGRESS_TABLE: &str = "preprocess_progress";
+
+// a batch of proteins and their (kmer, protein number, position) rows ready to be written
+struct Batch {
+    proteins: Vec<Protein>,
+    kmers: Vec<(String, usize, usize)>,
+    extra_kmers: Vec<Vec<(String, usize, usize)>>, // the rows of each extra k
+}
+
+// the table the k-mer rows of an extra k are written to before they're compacted
+fn kmer_rows_table(k: usize) -> String {
+    format!("kmer_rows_{}", k)
+}
+
+impl Preprocessor {
+    // stream the proteome in batches, k-merizing them in parallel and writing the
+    // k-mers, proteins and metadata tables from this thread, then index the tables
+    pub(super) fn preprocess_sqlite(&self) -> Result<()> {
+        let resuming = self.resume && std::path::Path::new(&self.db_path).exists();
+        if !resuming && self.auto_rebuild && self.up_to_date() {
+            tracing::info!("{} is up to date with its proteomes, leaving it as it is", self.db_path);
+            return Ok(());
+        }
+        if !resuming {
+            self.replace_db()?;
+        }
+        let mut conn = rusqlite::Connection::open(&self.db_path).map_err(PepMatchError::database(&self.db_path))?;
+        self.tune(&conn).map_err(PepMatchError::database(&self.db_path))?;
+        let written = if resuming {
+            self.written_proteins(&conn)?
+        } else {
+            match self.layout {
+                DbLayout::Native => create_tables(&conn, &self.extra_k),
+                DbLayout::Python => create_python_tables(&conn, &self.python_tables()),
+            }
+            .and_then(|_| create_progress_table(&conn))
+            .map_err(PepMatchError::database(&self.db_path))?;
+            self.write_meta(&conn)?;
+            Vec::new()
+        };
+        // the proteome is read once more first to pick the proteins identical sequences are indexed under
+        let representatives = if self.dedupe_sequences {
+            let _span = debug_span!("dedupe_sequences").entered();
+            let spinner = progress::spinner(self.progress, "finding identical sequences");
+            let representatives = Representatives::choose(self.read_proteome()?)?;
+            spinner.finish_and_clear();
+            Some(representatives)
+        } else {
+            None
+        };
+        let proteome: Box<dyn Iterator<Item = Result<Protein>> + Send + '_> = match &representatives {
+            Some(representatives) => Box::new(representatives.only(self.read_proteome()?)),
+            None => Box::new(self.read_proteome()?),
+        };
+
+        // only PROTEINS_PER_BATCH proteins are read in at a time so memory stays bounded,
+        // reading stops early if writing fails, proteins an interrupted run wrote are skipped
+        let stop = AtomicBool::new(false);
+        let mut reader = proteome.filter(|protein| match protein {
+            Ok(protein) => !written.iter().any(|(first, last)| (first..=last).contains(&&protein.number)),
+            Err(_) => true,
+        });
+        let proteins = std::iter::from_fn(|| {
+            if stop.load(Ordering::Relaxed) {
+                return None;
+            }
+            match reader.by_ref().take(PROTEINS_PER_BATCH).collect::<Result<Vec<Protein>>>() {
+                Ok(batch) if batch.is_empty() => None,
+                batch => Some(batch),
+            }
+        });
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .map_err(PepMatchError::invalid)?;
+        let read = match &representatives {
+            Some(representatives) => representatives.len(),
+            None => self.count_proteins()?,
+        };
+        let total = read.saturating_sub(written.iter().map(|(first, last)| last - first + 1).sum());
+        let (sender, receiver) = mpsc::sync_channel(pool.current_num_threads() * 2);
+        // the k-merizing threads' spans are children of this thread's
+        let span = tracing::Span::current();
+        let finished = std::thread::scope(|scope| {
+            scope.spawn(|| {
+                pool.install(|| {
+                    proteins.enumerate().par_bridge().for_each_with(sender, |sender, (number, proteins)| {
+                        let proteins_in_batch = proteins.as_ref().map_or(0, Vec::len);
+                        let _span = debug_span!(parent: &span, "kmerize_batch", proteins = proteins_in_batch).entered();
+                        let batch = proteins.map(|proteins| {
+                            let kmers = self.split_proteins(&proteins, self.k);
+                            let extra_kmers = self.extra_k.iter().map(|k| self.split_proteins(&proteins, *k)).collect();
+                            Batch { proteins, kmers, extra_kmers }
+                        });
+                        if sender.send((number, batch)).is_err() {
+                            stop.store(true, Ordering::Relaxed);
+                        }
+                    });
+                });
+            });
+            self.write_batches(&mut conn, receiver, total)
+        });
+        finished?;
+        if let Some(representatives) = &representatives {
+            self.write_synonyms(&mut conn, representatives)?;
+        }
+
+        // group the k-mer rows into one row per k-mer and create indices
+        let _span = debug_span!("index_tables").entered();
+        let spinner = progress::spinner(self.progress, "compacting k-mers and creating indices");
+        let mut shards = self.create_shards()?;
+        match self.layout {
+            DbLayout::Native => compact_kmers(&mut conn, "kmer_rows", "kmers", &mut shards, self.compression)
+                .and_then(|_| {
+                    self.extra_k.iter().try_for_each(|k| {
+                        compact_kmers(&mut conn, &kmer_rows_table(*k), &kmers_table(*k), &mut shards, self.compression)
+                    })
+                })
+                .and_then(|_| create_indices(&mut conn))
+                .and_then(|_| write_proteomes(&conn)),
+            DbLayout::Python => create_python_indices(&mut conn, &self.python_tables()),
+        }
+        .and_then(|_| conn.execute(&format!("DROP TABLE {}", PROGRESS_TABLE), rusqlite::params![]))
+        .map_err(PepMatchError::database(&self.db_path))?;
+        if self.wal {
+            conn.query_row("PRAGMA journal_mode = DELETE", rusqlite::params![], |_| Ok(()))
+                .map_err(PepMatchError::database(&self.db_path))?;
+        }
+        spinner.finish_and_clear();
+        Ok(())
+    }
+
+    // the (first, last) protein numbers of the batches an interrupted run committed,
+    // checking the DB is being preprocessed from the same proteomes with the same settings
+    fn written_proteins(&self, conn: &rusqlite::Connection) -> Result<Vec<(usize, usize)>> {
+        let cannot_resume = |reason: String| PepMatchError::invalid(format!("can't resume {}: {}", self.db_path, reason));
+        if !has_table(conn, PROGRESS_TABLE).map_err(PepMatchError::database(&self.db_path))? {
+            return Err(cannot_resume("it isn't partly preprocessed, there's nothing to resume".to_string()));
+        }
+        let kmer_table = match self.layout {
+            DbLayout::Native => "kmer_rows".to_string(),
+            DbLayout::Python => self.python_tables().kmers,
+        };
+        if !has_table(conn, &kmer_table).map_err(PepMatchError::database(&self.db_path))? {
+            return Err(cannot_resume(format!("it isn't being preprocessed in the {:?} layout", self.layout).to_lowercase()));
+        }
+
+        let info = BuildInfo::from_connection(conn).map_err(PepMatchError::database(&self.db_path))?;
+        if info.k != Some(self.k) || info.i_l_equivalent != Some(self.i_l_equivalent) {
+            return Err(cannot_resume(format!(
+                "it's being preprocessed with k = {} and I/L equivalence {}",
+                info.k.unwrap_or_default(),
+                if info.i_l_equivalent.unwrap_or_default() { "on" } else { "off" }
+            )));
+        }
+        if info.equivalence_classes != self.equivalence_classes {
+            return Err(cannot_resume(format!("it's being preprocessed with equivalence classes {}", info.equivalence_classes)));
+        }
+        if info.compression != self.compression {
+            return Err(cannot_resume(format!("it's being preprocessed with {:?} compression", info.compression).to_lowercase()));
+        }
+        if info.shards != self.shards {
+            return Err(cannot_resume(format!("it's being preprocessed into {} shards", info.shards)));
+        }
+        if info.dedupe_sequences != self.dedupe_sequences {
+            let deduplicated = if info.dedupe_sequences { "with" } else { "without" };
+            return Err(cannot_resume(format!("it's being preprocessed {} --dedupe-sequences", deduplicated)));
+        }
+        if info.extra_k != self.extra_k {
+            let extra_k: Vec<String> = info.extra_k.iter().map(usize::to_string).collect();
+            return Err(cannot_resume(format!("it's being preprocessed with extra k values {}", extra_k.join(", "))));
+        }
+        let names: Vec<&String> = info.sources.iter().map(|source| &source.proteome).collect();
+        if names != self.proteomes.iter().map(|(name, _)| name).collect::<Vec<_>>() {
+            return Err(cannot_resume("it's being preprocessed from other proteomes".to_string()));
+        }
+        for (source, (_, path)) in info.sources.iter().zip(&self.proteomes) {
+            if checksum(path)? != source.checksum {
+                return Err(cannot_resume(format!("{} changed since preprocessing started", path)));
+            }
+        }
+
+        conn.prepare(&format!("SELECT first_protein, last_protein FROM {}", PROGRESS_TABLE))
+            .and_then(|mut stmt| {
+                stmt.query_map(rusqlite::params![], |row| {
+                    Ok((row.get::<_, i64>(0)? as usize, row.get::<_, i64>(1)? as usize))
+                })?
+                .collect()
+            })
+            .map_err(PepMatchError::database(&self.db_path))
+    }
+
+    // whether the DB at the DB path is a finished one built with these settings from the
+    // proteome files as they are now
+    fn up_to_date(&self) -> bool {
+        let Ok(conn) = crate::index::open_read_only(&self.db_path) else {
+            return false;
+        };
+        let Ok(info) = BuildInfo::from_connection(&conn) else {
+            return false;
+        };
+        let same_settings = info.k == Some(self.k)
+            && info.extra_k == self.extra_k
+            && info.i_l_equivalent == Some(self.i_l_equivalent)
+            && info.equivalence_classes == self.equivalence_classes
+            && info.translate == Some(self.translate)
+            && info.dedupe_sequences == self.dedupe_sequences
+            && info.shards == self.shards
+            && info.compression == self.compression
+            && info.layout == Some(self.layout)
+            && info.gene_priority == self.gene_priority;
+        let same_sources = info.sources.iter().map(|source| (&source.proteome, &source.path)).eq(self.proteomes.iter().map(|(name, path)| (name, path)));
+        let finished = matches!(has_table(&conn, PROGRESS_TABLE), Ok(false));
+        same_settings && same_sources && finished && matches!(info.changed_sources(), Ok(changed) if changed.is_empty())
+    }
+
+    // set the pragmas for bulk loading, the page size only takes for a new DB
+    fn tune(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(&format!("PRAGMA page_size = {}", self.page_size), rusqlite::params![])?;
+        // so the pages of the k-mer rows can be given back once they're compacted
+        conn.execute("PRAGMA auto_vacuum = INCREMENTAL", rusqlite::params![])?;
+        // a negative cache size is in KiB rather than pages
+        conn.execute(&format!("PRAGMA cache_size = -{}", self.cache_mb * 1024), rusqlite::params![])?;
+        if self.wal {
+            // journal_mode returns the new mode as a row
+            conn.query_row("PRAGMA journal_mode = WAL", rusqlite::params![], |_| Ok(()))?;
+        }
+        Ok(())
+    }
+
+    // write the parameters the DB was built with as key/value rows of a meta table,
+    // and the files it was built from with their checksums to a build_info table
+    fn write_meta(&self, conn: &rusqlite::Connection) -> Result<()> {
+        let mut sources = Vec::new();
+        for (name, path) in &self.proteomes {
+            sources.push((name, path, checksum(path)?, modified(path)));
+        }
+        self.write_meta_tables(conn, &sources).map_err(PepMatchError::database(&self.db_path))
+    }
+
+    fn write_meta_tables(&self, conn: &rusqlite::Connection, sources: &[(&String, &String, String, Option<u64>)]) -> rusqlite::Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS meta (
+                key              TEXT PRIMARY KEY,
+                value            TEXT NOT NULL
+            )",
+            rusqlite::params![],
+        )?;
+        let built_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let extra_k: Vec<String> = self.extra_k.iter().map(usize::to_string).collect();
+        let meta = [
+            ("k", self.k.to_string()),
+            ("extra_k", extra_k.join(",")),
+            ("i_l_equivalent", self.i_l_equivalent.to_string()),
+            ("layout", format!("{:?}", self.layout).to_lowercase()),
+            ("translate", self.translate.to_string()),
+            ("dedupe_sequences", self.dedupe_sequences.to_string()),
+            ("shards", self.shards.to_string()),
+            ("compression", format!("{:?}", self.compression).to_lowercase()),
+            ("version", env!("CARGO_PKG_VERSION").to_string()),
+            ("schema_version", SCHEMA_VERSION.to_string()),
+            ("built_at", built_at.to_string()), // seconds since the Unix epoch
+        ];
+        let mut stmt = conn.prepare("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)")?;
+        for (key, value) in meta {
+            stmt.execute(rusqlite::params![key, value])?;
+        }
+        if let Some(gene_priority) = &self.gene_priority {
+            stmt.execute(rusqlite::params!["gene_priority", gene_priority])?;
+        }
+        if !self.equivalence_classes.is_empty() {
+            stmt.execute(rusqlite::params!["equivalence_classes", self.equivalence_classes.to_string()])?;
+        }
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS build_info (
+                proteome         TEXT NOT NULL,
+                source           TEXT NOT NULL,
+                checksum         TEXT NOT NULL,
+                modified         INTEGER
+            )",
+            rusqlite::params![],
+        )?;
+        let mut stmt =
+            conn.prepare("INSERT INTO build_info (proteome, source, checksum, modified) VALUES (?1, ?2, ?3, ?4)")?;
+        for (name, path, checksum, modified) in sources {
+            stmt.execute(rusqlite::params![name, path, checksum, modified.map(|modified| modified as i64)])?;
+        }
+        Ok(())
+    }
+
+    fn python_tables(&self) -> PythonTables {
+        PythonTables::new(&self.proteomes[0].0, self.k)
+    }
+
+    // write batches as they're k-merized until there are none left or one fails,
+    // dropping the receiver on failure stops the k-merizing threads
+    fn write_batches(
+        &self,
+        conn: &mut rusqlite::Connection,
+        receiver: mpsc::Receiver<(usize, Result<Batch>)>,
+        total: usize,
+    ) -> Result<()> {
+        // disable synchronous mode for faster bulk inserts
+        conn.execute("PRAGMA synchronous = OFF", rusqlite::params![]).map_err(PepMatchError::database(&self.db_path))?;
+        let bar = progress::proteins(self.progress, total);
+        let mut kmers = 0;
+        // batches are k-merized out of order but written in the order they were read,
+        // so preprocessing the same proteome always writes the same rows in the same order
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        for (number, batch) in receiver {
+            pending.insert(number, batch);
+            while let Some(batch) = pending.remove(&next) {
+                next += 1;
+                let batch = batch?;
+                let _span = debug_span!("write_batch", proteins = batch.proteins.len(), kmers = batch.kmers.len()).entered();
+                match self.layout {
+                    DbLayout::Native => insert_batch(conn, &batch, &self.extra_k),
+                    DbLayout::Python => insert_python_batch(conn, &self.python_tables(), &batch),
+                }
+                .map_err(PepMatchError::database(&self.db_path))?;
+                kmers += batch.kmers.len();
+                bar.inc(batch.proteins.len() as u64);
+                bar.set_message(format!("{} k-mers", kmers));
+            }
+        }
+        bar.finish();
+        conn.execute("PRAGMA synchronous = ON", rusqlite::params![]).map_err(PepMatchError::database(&self.db_path))?;
+        Ok(())
+    }
+
+    // create the shard files the k-mers are compacted into, replacing any an interrupted run
+    // left, none unless the DB is split into shards
+    fn create_shards(&self) -> Result<Vec<rusqlite::Connection>> {
+        remove_shards(&self.db_path)?;
+        (0..self.shards)
+            .map(|shard| {
+                let path = shard_path(&self.db_path, shard);
+                rusqlite::Connection::open(&path)
+                    .and_then(|conn| {
+                        conn.execute(&format!("PRAGMA page_size = {}", self.page_size), rusqlite::params![])?;
+                        conn.execute(&format!("PRAGMA cache_size = -{}", self.cache_mb * 1024), rusqlite::params![])?;
+                        create_kmers_table(&conn, "kmers")?;
+                        for k in &self.extra_k {
+                            create_kmers_table(&conn, &kmers_table(*k))?;
+                        }
+                        Ok(conn)
+                    })
+                    .map_err(PepMatchError::database(&path))
+            })
+            .collect()
+    }
+
+    // write the proteins that aren't indexed to a synonyms table with the number of the one
+    // indexed in their place, those an interrupted run wrote are written again
+    fn write_synonyms(&self, conn: &mut rusqlite::Connection, representatives: &Representatives) -> Result<()> {
+        let _span = debug_span!("write_synonyms").entered();
+        let tx = conn.transaction().map_err(PepMatchError::database(&self.db_path))?;
+        create_synonyms_table(&tx)
+            .and_then(|_| tx.execute("DELETE FROM synonyms", rusqlite::params![]))
+            .map_err(PepMatchError::database(&self.db_path))?;
+        for synonym in representatives.synonyms(self.read_proteome()?) {
+            let (protein, representative) = synonym?;
+            insert_synonym(&tx, &protein, representative).map_err(PepMatchError::database(&self.db_path))?;
+        }
+        tx.execute("CREATE INDEX IF NOT EXISTS synonym_representative_idx ON synonyms (representative)", rusqlite::params![])
+            .and_then(|_| tx.commit())
+            .map_err(PepMatchError::database(&self.db_path))
+    }
+
+    // k-merize a chunk of proteins into (kmer, protein number, position) rows
+    fn split_proteins(&self, proteins: &[Protein], k: usize) -> Vec<(String, usize, usize)> {
+        proteins.iter()
+            .flat_map(|protein| {
+                let _span = trace_span!("protein", number = protein.number, id = %protein.metadata.protein_id).entered();
+                self.split_protein(&protein.sequence, k)
+                    .into_iter()
+                    .map(move |(kmer, position)| (kmer, protein.number, position))
+            })
+            .collect()
+    }
+}
+
+fn create_tables(conn: &rusqlite::Connection, extra_k: &[usize]) -> rusqlite::Result<()> {
+    create_metadata_table(conn)?;
+    create_proteins_table(conn)?;
+    create_kmers_table(conn, "kmers")?;
+    create_kmer_rows_table(conn, "kmer_rows")?;
+    for k in extra_k {
+        create_kmers_table(conn, &kmers_table(*k))?;
+        create_kmer_rows_table(conn, &kmer_rows_table(*k))?;
+    }
+    Ok(())
+}
+
+// create a kmer --> locations table in the DB, with every (protein number, position)
+// of a k-mer packed into one blob
+fn create_kmers_table(conn: &rusqlite::Connection, table: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                kmer             TEXT PRIMARY KEY,
+                locations        BLOB NOT NULL
+            ) WITHOUT ROWID",
+            table
+        ),
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// create a (kmer, protein number, position) table the batches are written to before
+// they're compacted into the kmers table, it's kept in the DB so an interrupted run
+// can be resumed and dropped once compacted
+fn create_kmer_rows_table(conn: &rusqlite::Connection, table: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                kmer             TEXT NOT NULL,
+                protein_number   INTEGER NOT NULL,
+                position         INTEGER NOT NULL
+            )",
+            table
+        ),
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// create a protein metadata table in the DB
+fn create_metadata_table(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS metadata (
+            protein_number   INTEGER NOT NULL,
+            protein_id       TEXT NOT NULL,
+            protein_name     TEXT NOT NULL,
+            species          TEXT NOT NULL,
+            taxon_id         TEXT NOT NULL,
+            gene             TEXT NOT NULL,
+            pe_level         INTEGER NOT NULL,
+            sequence_version INTEGER NOT NULL,
+            proteome         TEXT NOT NULL,
+            gene_priority    INTEGER NOT NULL,
+            frame            INTEGER NOT NULL,
+            source_length    INTEGER NOT NULL,
+            db               TEXT NOT NULL,
+            isoform          INTEGER NOT NULL,
+            length           INTEGER NOT NULL,
+            molecular_weight INTEGER NOT NULL
+        )",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// create a table of the proteins left out for having the sequence of another, the
+// representative, with the metadata columns and a protein number after the indexed ones
+fn create_synonyms_table(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS synonyms (
+            protein_number   INTEGER NOT NULL,
+            representative   INTEGER NOT NULL,
+            protein_id       TEXT NOT NULL,
+            protein_name     TEXT NOT NULL,
+            species          TEXT NOT NULL,
+            taxon_id         TEXT NOT NULL,
+            gene             TEXT NOT NULL,
+            pe_level         INTEGER NOT NULL,
+            sequence_version INTEGER NOT NULL,
+            proteome         TEXT NOT NULL,
+            gene_priority    INTEGER NOT NULL,
+            db               TEXT NOT NULL,
+            isoform          INTEGER NOT NULL
+        )",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// create the table recording which proteins have been written
+fn create_progress_table(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                first_protein    INTEGER NOT NULL,
+                last_protein     INTEGER NOT NULL
+            )",
+            PROGRESS_TABLE
+        ),
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// create a protein number --> sequence table in the DB
+fn create_proteins_table(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS proteins (
+            protein_number   INTEGER NOT NULL,
+            sequence         TEXT NOT NULL
+        )",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// write a batch's metadata, sequences and k-mers in one transaction
+fn insert_batch(conn: &mut rusqlite::Connection, batch: &Batch, extra_k: &[usize]) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    insert_metadata(&tx, &batch.proteins)?;
+    insert_proteins(&tx, &batch.proteins)?;
+    insert_kmers(&tx, "kmer_rows", &batch.kmers)?;
+    for (k, kmers) in extra_k.iter().zip(&batch.extra_kmers) {
+        insert_kmers(&tx, &kmer_rows_table(*k), kmers)?;
+    }
+    insert_progress(&tx, &batch.proteins)?;
+    tx.commit()
+}
+
+// record a batch's proteins as written, in the transaction that writes them
+fn insert_progress(conn: &rusqlite::Connection, proteins: &[Protein]) -> rusqlite::Result<()> {
+    let first = proteins.iter().map(|protein| protein.number).min().unwrap_or_default();
+    let last = proteins.iter().map(|protein| protein.number).max().unwrap_or_default();
+    conn.prepare_cached(&format!("INSERT INTO {} (first_protein, last_protein) VALUES (?1, ?2)", PROGRESS_TABLE))?
+        .execute(rusqlite::params![first as i64, last as i64])?;
+    Ok(())
+}
+
+// insert (kmer, protein number, position) rows into the table
+fn insert_kmers(conn: &rusqlite::Connection, table: &str, kmers: &[(String, usize, usize)]) -> rusqlite::Result<()> {
+    let mut stmt = conn
+        .prepare_cached(&format!("INSERT INTO {} (kmer, protein_number, position) VALUES (?1, ?2, ?3)", table))?;
+
+    for kmer in kmers {
+        stmt.execute(rusqlite::params![kmer.0, kmer.1 as i64, kmer.2 as i64])?;
+    }
+    Ok(())
+}
+
+// insert full protein sequences into the table
+fn insert_proteins(conn: &rusqlite::Connection, proteins: &[Protein]) -> rusqlite::Result<()> {
+    let mut stmt = conn
+        .prepare_cached("INSERT INTO proteins (protein_number, sequence) VALUES (?1, ?2)")?;
+
+    for protein in proteins {
+        stmt.execute(rusqlite::params![protein.number as i64, protein.sequence])?;
+    }
+    Ok(())
+}
+
+// insert metadata into the table
+fn insert_metadata(conn: &rusqlite::Connection, proteins: &[Protein]) -> rusqlite::Result<()> {
+    let mut stmt = conn
+        .prepare_cached("INSERT INTO metadata (protein_number, protein_id, protein_name, species, taxon_id, gene, pe_level, sequence_version, proteome, gene_priority, frame, source_length, db, isoform, length, molecular_weight) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)")?;
+
+    for protein in proteins {
+        let data = &protein.metadata;
+        stmt.execute(rusqlite::params![
+                protein.number as i64, data.protein_id, data.protein_name, data.species,
+                data.taxon_id, data.gene, data.pe_level as i64, data.sequence_version as i64,
+                data.proteome, data.gene_priority, data.frame, data.source_length as i64,
+                data.db, data.isoform as i64, data.length as i64, data.molecular_weight as i64
+            ])?;
+    }
+    Ok(())
+}
+
+// insert a synonym and the number of its representative into the table
+fn insert_synonym(conn: &rusqlite::Connection, protein: &Protein, representative: usize) -> rusqlite::Result<()> {
+    let mut stmt = conn
+        .prepare_cached("INSERT INTO synonyms (protein_number, representative, protein_id, protein_name, species, taxon_id, gene, pe_level, sequence_version, proteome, gene_priority, db, isoform) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)")?;
+
+    let data = &protein.metadata;
+    stmt.execute(rusqlite::params![
+        protein.number as i64, representative as i64, data.protein_id, data.protein_name,
+        data.species, data.taxon_id, data.gene, data.pe_level as i64,
+        data.sequence_version as i64, data.proteome, data.gene_priority, data.db,
+        data.isoform as i64
+    ])?;
+    Ok(())
+}
+
+// delete the shard files of a DB, from the first on until one isn't there
+pub(super) fn remove_shards(db_path: &str) -> Result<()> {
+    for shard in 0.. {
+        let path = shard_path(db_path, shard);
+        if !std::path::Path::new(&path).exists() {
+            break;
+        }
+        std::fs::remove_file(&path).map_err(PepMatchError::io(&path))?;
+    }
+    Ok(())
+}
+
+// group the k-mer rows by k-mer and write each k-mer's sorted locations as one row, to the
+// k-mer's shard file if there are any, proteins are split across batches so a k-mer's rows
+// can't be grouped any earlier, the rows are only dropped once every shard is written
+fn compact_kmers(
+    conn: &mut rusqlite::Connection,
+    rows_table: &str,
+    kmers_table: &str,
+    shards: &mut [rusqlite::Connection],
+    compression: Compression,
+) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    let shard_txs = shards.iter_mut().map(|shard| shard.transaction()).collect::<rusqlite::Result<Vec<_>>>()?;
+    {
+        let mut select = tx.prepare(&format!(
+            "SELECT kmer, protein_number, position FROM {} ORDER BY kmer, protein_number, position",
+            rows_table
+        ))?;
+        let insert_sql = format!("INSERT INTO {} (kmer, locations) VALUES (?1, ?2)", kmers_table);
+        let mut inserts = match shard_txs.is_empty() {
+            true => vec![tx.prepare(&insert_sql)?],
+            false => shard_txs.iter().map(|shard| shard.prepare(&insert_sql)).collect::<rusqlite::Result<Vec<_>>>()?,
+        };
+        let mut insert = |kmer: String, locations: &[Location]| {
+            let shard = if inserts.len() > 1 { shard_of(&kmer, inserts.len()) } else { 0 };
+            let blob = encode_kmer_locations(locations, compression)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+            inserts[shard].execute(rusqlite::params![kmer, blob]).map(|_| ())
+        };
+
+        let mut rows = select.query(rusqlite::params![])?;
+        let mut current: Option<(String, Vec<Location>)> = None;
+        while let Some(row) = rows.next()? {
+            let kmer: String = row.get(0)?;
+            let location = (row.get::<_, i64>(1)? as usize, row.get::<_, i64>(2)? as usize);
+
+            match &mut current {
+                Some((previous, locations)) if *previous == kmer => locations.push(location),
+                _ => {
+                    if let Some((previous, locations)) = current.replace((kmer, vec![location])) {
+                        insert(previous, &locations)?;
+                    }
+                }
+            }
+        }
+        if let Some((kmer, locations)) = current {
+            insert(kmer, &locations)?;
+        }
+    }
+    for shard in shard_txs {
+        shard.commit()?;
+    }
+    tx.execute(&format!("DROP TABLE {}", rows_table), rusqlite::params![])?;
+    tx.commit()?;
+    // the pragma frees a page per step
+    let mut vacuum = conn.prepare("PRAGMA incremental_vacuum")?;
+    let mut rows = vacuum.query(rusqlite::params![])?;
+    while rows.next()?.is_some() {}
+    Ok(())
+}
+
+// create indices on the proteins and metadata tables, kmers is keyed by k-mer already
+fn create_indices(conn: &mut rusqlite::Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+
+    tx.execute("CREATE INDEX IF NOT EXISTS protein_sequence_idx ON proteins (protein_number)", rusqlite::params![])?;
+    tx.execute("CREATE INDEX IF NOT EXISTS protein_number_idx ON metadata (protein_number)", rusqlite::params![])?;
+
+    tx.commit()
+}
+
+// write the range of protein numbers each proteome's proteins were numbered with,
+// proteomes are read one after the other so their proteins are numbered contiguously
+fn write_proteomes(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS proteomes (
+            proteome         TEXT NOT NULL,
+            first_protein    INTEGER NOT NULL,
+            last_protein     INTEGER NOT NULL
+        )",
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        "INSERT INTO proteomes (proteome, first_protein, last_protein)
+         SELECT proteome, MIN(protein_number), MAX(protein_number) FROM metadata
+         GROUP BY proteome ORDER BY MIN(protein_number)",
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// create the Python PEPMatch's k-mers and metadata tables, the k-mers table has a row
+// per location with the protein number and position packed into idx
+fn create_python_tables(conn: &rusqlite::Connection, tables: &PythonTables) -> rusqlite::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS \"{}\" (
+                kmer             TEXT NOT NULL,
+                idx              INTEGER NOT NULL
+            )",
+            tables.kmers
+        ),
+        rusqlite::params![],
+    )?;
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS \"{}\" (
+                protein_number   INTEGER NOT NULL,
+                protein_id       TEXT NOT NULL,
+                protein_name     TEXT NOT NULL,
+                species          TEXT NOT NULL,
+                taxon_id         TEXT NOT NULL,
+                gene             TEXT NOT NULL,
+                pe_level         INTEGER NOT NULL,
+                sequence_version INTEGER NOT NULL,
+                gene_priority    INTEGER NOT NULL
+            )",
+            tables.metadata
+        ),
+        rusqlite::params![],
+    )?;
+    Ok(())
+}
+
+// write a batch's metadata and k-mers to the Python PEPMatch's tables in one transaction
+fn insert_python_batch(conn: &mut rusqlite::Connection, tables: &PythonTables, batch: &Batch) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(&format!(
+            "INSERT INTO \"{}\" (protein_number, protein_id, protein_name, species, taxon_id, gene, pe_level, sequence_version, gene_priority) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            tables.metadata
+        ))?;
+        for protein in &batch.proteins {
+            let data = &protein.metadata;
+            stmt.execute(rusqlite::params![
+                protein.number as i64, data.protein_id, data.protein_name, data.species,
+                data.taxon_id, data.gene, data.pe_level as i64, data.sequence_version as i64,
+                data.gene_priority
+            ])?;
+        }
+
+        let mut stmt = tx.prepare_cached(&format!("INSERT INTO \"{}\" (kmer, idx) VALUES (?1, ?2)", tables.kmers))?;
+        for (kmer, protein_number, position) in &batch.kmers {
+            stmt.execute(rusqlite::params![kmer, pack_idx((*protein_number, *position)) as i64])?;
+        }
+        insert_progress(&tx, &batch.proteins)?;
+    }
+    tx.commit()
+}
+
+// create the indices the Python PEPMatch looks k-mers and metadata up by
+fn create_python_indices(conn: &mut rusqlite::Connection, tables: &PythonTables) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        &format!("CREATE INDEX IF NOT EXISTS \"{0}_kmer_idx\" ON \"{0}\" (kmer)", tables.kmers),
+        rusqlite::params![],
+    )?;
+    tx.execute(
+        &format!("CREATE INDEX IF NOT EXISTS \"{0}_protein_number_idx\" ON \"{0}\" (protein_number)", tables.metadata),
+        rusqlite::params![],
+    )?;
+
+    tx.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Matcher;
+
+    #[test]
+    fn interrupted_runs_are_resumed() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-resume-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db, whole) = (dir.join("p.fasta"), dir.join("p.db"), dir.join("whole.db"));
+        let (fasta, db, whole) = (fasta.to_str().unwrap(), db.to_str().unwrap(), whole.to_str().unwrap());
+        std::fs::write(
+            fasta,
+            ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n\
+             >sp|P00002|TWO_HUMAN Second OS=Homo sapiens OX=9606 PE=3 SV=1\nPESTAVQWLLKDEQ\n",
+        )
+        .unwrap();
+
+        // write the first protein's batch and stop as an interrupted run would
+        let preprocessor = Preprocessor::new(fasta, 3).db_path(db);
+        preprocessor.replace_db().unwrap();
+        let mut conn = rusqlite::Connection::open(db).unwrap();
+        create_tables(&conn, &[]).and_then(|_| create_progress_table(&conn)).unwrap();
+        preprocessor.write_meta(&conn).unwrap();
+        let proteins = vec![preprocessor.read_proteome().unwrap().next().unwrap().unwrap()];
+        let kmers = preprocessor.split_proteins(&proteins, 3);
+        let (sender, receiver) = mpsc::channel();
+        sender.send((0, Ok(Batch { proteins, kmers, extra_kmers: Vec::new() }))).unwrap();
+        drop(sender);
+        preprocessor.write_batches(&mut conn, receiver, 1).unwrap();
+        drop(conn);
+
+        let partly = Matcher::new(db).err().unwrap().to_string();
+        let fresh = Preprocessor::new(fasta, 3).db_path(db).preprocess().unwrap_err().to_string();
+        preprocessor.resume(true).preprocess().unwrap();
+        Preprocessor::new(fasta, 3).db_path(whole).preprocess().unwrap();
+        let hits = |db| {
+            let mut hits: Vec<_> = Matcher::new(db)
+                .unwrap()
+                .max_mismatches(1)
+                .match_peptides(&["STAAQW".to_string(), "WDEQ".to_string()])
+                .unwrap()
+                .into_iter()
+                .map(|hit| (hit.peptide, hit.protein_id, hit.index_start, hit.mismatches))
+                .collect();
+            hits.sort();
+            hits
+        };
+        let (resumed, whole) = (hits(db), hits(whole));
+        let again = Preprocessor::new(fasta, 3).db_path(db).resume(true).preprocess().unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(partly.contains("didn't finish"), "{}", partly);
+        assert!(fresh.contains("--resume"), "{}", fresh);
+        assert_eq!(resumed, whole);
+        assert_eq!(resumed.len(), 4);
+        assert!(again.contains("nothing to resume"), "{}", again);
+    }
+}
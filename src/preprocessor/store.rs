@@ -0,0 +1,61 @@
+use super::{Preprocessor, PROTEINS_PER_BATCH};
+use crate::error::Result;
+use crate::index::{binary, encode_locations, store, KmerStore, Location, ProteomeRange};
+use crate::progress;
+use crate::proteome::Protein;
+use fxhash::FxHashMap;
+use rayon::prelude::*;
+use tracing::debug_span;
+
+impl Preprocessor {
+    // stream the proteome into a key-value store in batches, each batch's k-mers get keys
+    // of their own so nothing is read back while writing, the meta entry is written last
+    // so a store preprocessing didn't finish is told apart
+    pub(super) fn preprocess_store(&self, store: &dyn KmerStore) -> Result<()> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()
+            .map_err(crate::error::PepMatchError::invalid)?;
+        let bar = progress::proteins(self.progress, self.count_proteins()?);
+        let mut proteomes: Vec<ProteomeRange> = Vec::new();
+        let mut reader = self.read_proteome()?;
+        for batch_number in 0u32.. {
+            let proteins = reader.by_ref().take(PROTEINS_PER_BATCH).collect::<Result<Vec<Protein>>>()?;
+            if proteins.is_empty() {
+                break;
+            }
+            let _span = debug_span!("write_batch", proteins = proteins.len()).entered();
+            let split: Vec<Vec<(String, usize)>> =
+                pool.install(|| proteins.par_iter().map(|protein| self.split_protein(&protein.sequence, self.k)).collect());
+            let mut kmers: FxHashMap<String, Vec<Location>> = FxHashMap::default();
+            for (protein, protein_kmers) in proteins.iter().zip(split) {
+                for (kmer, position) in protein_kmers {
+                    kmers.entry(kmer).or_default().push((protein.number, position));
+                }
+            }
+
+            let mut entries: Vec<(Vec<u8>, Vec<u8>)> = kmers
+                .iter()
+                .map(|(kmer, locations)| (store::kmer_key(kmer, batch_number), encode_locations(locations)))
+                .collect();
+            for protein in &proteins {
+                let metadata = binary::metadata_json(protein.number, &protein.metadata).to_string();
+                entries.push((store::metadata_key(protein.number), metadata.into_bytes()));
+                entries.push((store::sequence_key(protein.number), protein.sequence.clone().into_bytes()));
+                // proteins are numbered contiguously one proteome after the other
+                match proteomes.last_mut() {
+                    Some((name, _, last)) if *name == protein.metadata.proteome => *last = protein.number,
+                    _ => proteomes.push((protein.metadata.proteome.clone(), protein.number, protein.number)),
+                }
+            }
+            // the stores take their batches sorted fastest
+            entries.sort_unstable();
+            store.put(&entries)?;
+            bar.inc(proteins.len() as u64);
+        }
+        bar.finish_and_clear();
+
+        let meta = serde_json::json!({ "k": self.k, "i_l_equivalent": self.i_l_equivalent, "proteomes": proteomes });
+        store.put(&[(store::META_KEY.to_vec(), meta.to_string().into_bytes())])
+    }
+}
@@ -0,0 +1,41 @@
+use indicatif::{ProgressBar, ProgressStyle};
+
+// bar of proteins read out of a total, with the number of k-mers as its message
+pub(crate) fn proteins(enabled: bool, total: usize) -> ProgressBar {
+    bar(enabled, total, "{elapsed_precise} [{bar:40}] {pos}/{len} proteins {msg} (eta {eta})")
+}
+
+// bar of peptides searched out of a total
+pub(crate) fn peptides(enabled: bool, total: usize) -> ProgressBar {
+    bar(enabled, total, "{elapsed_precise} [{bar:40}] {pos}/{len} peptides (eta {eta})")
+}
+
+// bar of bytes downloaded to a file, a spinner if the total isn't known
+pub(crate) fn bytes(enabled: bool, total: Option<u64>, path: &str) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+    let (bar, template) = match total {
+        Some(total) => (ProgressBar::new(total), "{elapsed_precise} [{bar:40}] {bytes}/{total_bytes} {msg} (eta {eta})"),
+        None => (ProgressBar::new_spinner(), "{elapsed_precise} {spinner} {bytes} {msg}"),
+    };
+    bar.with_style(ProgressStyle::with_template(template).unwrap().progress_chars("=> "))
+        .with_message(path.to_string())
+}
+
+// spinner for a step with no measurable progress
+pub(crate) fn spinner(enabled: bool, message: &'static str) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner().with_message(message);
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+    spinner
+}
+
+fn bar(enabled: bool, total: usize, template: &str) -> ProgressBar {
+    if !enabled {
+        return ProgressBar::hidden();
+    }
+    ProgressBar::new(total as u64).with_style(ProgressStyle::with_template(template).unwrap().progress_chars("=> "))
+}
@@ -0,0 +1,306 @@
+use crate::error::{PepMatchError, Result};
+use crate::header::HeaderParser;
+use crate::translate::six_frames;
+use bio::io::fasta;
+use flate2::read::MultiGzDecoder;
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Cursor, Read};
+
+type Records = fasta::Records<BufReader<Box<dyn Read + Send>>>;
+
+// metadata of a protein parsed from its FASTA header
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProteinMetadata {
+    pub protein_id: String,
+    pub protein_name: String,
+    pub species: String,
+    pub taxon_id: String,
+    pub gene: String,
+    pub pe_level: usize,
+    pub sequence_version: usize,
+    pub proteome: String, // name of the proteome the protein is from in a multi-proteome DB
+    pub gene_priority: bool, // in the gene priority proteome of one protein per gene
+    pub frame: i8, // reading frame a protein was translated in, +1 to +3 or -1 to -3, 0 if it wasn't
+    pub source_length: usize, // length of the nucleotide sequence a protein was translated from
+    pub db: String, // UniProt database of the entry, sp for reviewed Swiss-Prot or tr for TrEMBL, empty otherwise
+    pub isoform: usize, // number of a non-canonical UniProt isoform, e.g. 2 for P04637-2, 0 for canonical entries
+    pub length: usize, // residues in the sequence, 0 in DBs preprocessed before lengths were stored
+    pub molecular_weight: usize, // average mass of the sequence in Da, 0 in DBs preprocessed before it was stored
+}
+
+// restricts proteins to those with evidence of existing at or below a protein existence
+// level, to reviewed Swiss-Prot entries or to those of a length, an empty filter passes everything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProteinFilter {
+    max_pe_level: Option<usize>,
+    swissprot_only: bool,
+    min_length: Option<usize>,
+}
+
+impl ProteinFilter {
+    pub fn new() -> ProteinFilter {
+        ProteinFilter::default()
+    }
+
+    // PE levels run from 1 (evidence at protein level) to 5 (uncertain), proteins
+    // without a PE level in their header don't pass
+    pub fn max_pe_level(mut self, max_pe_level: usize) -> ProteinFilter {
+        self.max_pe_level = Some(max_pe_level);
+        self
+    }
+
+    pub fn swissprot_only(mut self, swissprot_only: bool) -> ProteinFilter {
+        self.swissprot_only = swissprot_only;
+        self
+    }
+
+    // proteins without a length, from DBs preprocessed before lengths were stored, don't pass
+    pub fn min_length(mut self, min_length: usize) -> ProteinFilter {
+        self.min_length = Some(min_length);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_pe_level.is_none() && !self.swissprot_only && self.min_length.is_none()
+    }
+
+    // db is the UniProt database of the protein, sp for Swiss-Prot
+    pub fn allows(&self, pe_level: usize, db: &str, length: usize) -> bool {
+        let pe_level_allowed = self.max_pe_level.is_none_or(|max| (1..=max).contains(&pe_level));
+        let length_allowed = self.min_length.is_none_or(|min| length > 0 && length >= min);
+        pe_level_allowed && (db == "sp" || !self.swissprot_only) && length_allowed
+    }
+}
+
+// a protein read from proteome FASTA files, numbered from 1 in file order and
+// continuing across files so every protein in a DB has a unique number
+pub(crate) struct Protein {
+    pub number: usize,
+    pub sequence: String,
+    pub metadata: ProteinMetadata,
+}
+
+// streams the proteins of (name, path) proteome FASTA files one record at a time,
+// parsing the metadata out of each header, it stops after the first error
+pub(crate) struct ProteomeReader {
+    proteomes: VecDeque<(String, String)>,
+    current: Option<OpenProteome>,
+    parser: Box<dyn HeaderParser>,
+    gene_priority: HashSet<String>,
+    filter: ProteinFilter,
+    translate: bool,
+    frames: VecDeque<Protein>, // translated frames of the last record still to be read
+    count: usize,
+    failed: bool,
+}
+
+// the file being read and how many of its records have been read
+struct OpenProteome {
+    name: String,
+    path: String,
+    records: Records,
+    record: usize,
+}
+
+impl ProteomeReader {
+    pub fn open(proteomes: &[(String, String)], parser: Box<dyn HeaderParser>) -> ProteomeReader {
+        ProteomeReader {
+            proteomes: proteomes.iter().cloned().collect(),
+            current: None,
+            parser,
+            gene_priority: HashSet::new(),
+            filter: ProteinFilter::new(),
+            translate: false,
+            frames: VecDeque::new(),
+            count: 0,
+            failed: false,
+        }
+    }
+
+    // read the proteins of a proteome given as FASTA text
+    pub fn from_fasta(name: &str, fasta: &str, parser: Box<dyn HeaderParser>) -> ProteomeReader {
+        let mut reader = ProteomeReader::open(&[], parser);
+        let text: Box<dyn Read + Send> = Box::new(Cursor::new(fasta.as_bytes().to_vec()));
+        reader.current = Some(OpenProteome {
+            name: name.to_string(),
+            path: name.to_string(),
+            records: fasta::Reader::new(text).records(),
+            record: 0,
+        });
+        reader
+    }
+
+    // flag the proteins with these IDs as being in the gene priority proteome
+    pub fn gene_priority(mut self, protein_ids: HashSet<String>) -> ProteomeReader {
+        self.gene_priority = protein_ids;
+        self
+    }
+
+    // skip the proteins the filter doesn't pass, they aren't numbered
+    pub fn protein_filter(mut self, filter: ProteinFilter) -> ProteomeReader {
+        self.filter = filter;
+        self
+    }
+
+    // read nucleotide FASTA files, translating every record in six frames, each
+    // frame becomes a protein with the frame appended to the record's ID, e.g. chr1_frame-2
+    pub fn translate(mut self, translate: bool) -> ProteomeReader {
+        self.translate = translate;
+        self
+    }
+
+    fn read_next(&mut self) -> Option<Result<Protein>> {
+        if let Some(frame) = self.frames.pop_front() {
+            return Some(Ok(frame));
+        }
+        loop {
+            if let Some(proteome) = &mut self.current {
+                if let Some(record) = proteome.records.next() {
+                    proteome.record += 1;
+                    let mut protein = read_protein(proteome, record, self.count + 1, self.parser.as_ref());
+                    if let Ok(protein) = &mut protein {
+                        let metadata = &protein.metadata;
+                        if !self.filter.allows(metadata.pe_level, &metadata.db, metadata.length) {
+                            continue;
+                        }
+                        protein.metadata.gene_priority = self.gene_priority.contains(&protein.metadata.protein_id);
+                    }
+                    if self.translate {
+                        return Some(protein.map(|protein| self.split_frames(protein)));
+                    }
+                    self.count += 1;
+                    return Some(protein);
+                }
+            }
+
+            // move on to the next file once this one runs out
+            let (name, path) = self.proteomes.pop_front()?;
+            let records = match open_proteome(&path) {
+                Ok(reader) => fasta::Reader::new(reader).records(),
+                Err(e) => return Some(Err(e)),
+            };
+            self.current = Some(OpenProteome { name, path, records, record: 0 });
+        }
+    }
+}
+
+impl ProteomeReader {
+    // number the six frames of a nucleotide record as proteins, returning the first
+    // and queueing the rest
+    fn split_frames(&mut self, record: Protein) -> Protein {
+        for (frame, sequence) in six_frames(record.sequence.as_bytes()) {
+            self.count += 1;
+            let mut metadata = record.metadata.clone();
+            metadata.protein_id = format!("{}_frame{:+}", metadata.protein_id, frame);
+            metadata.frame = frame;
+            metadata.source_length = record.sequence.len();
+            (metadata.length, metadata.molecular_weight) = (sequence.len(), molecular_weight(&sequence));
+            self.frames.push_back(Protein { number: self.count, sequence, metadata });
+        }
+        self.frames.pop_front().unwrap()
+    }
+}
+
+impl Iterator for ProteomeReader {
+    type Item = Result<Protein>;
+
+    fn next(&mut self) -> Option<Result<Protein>> {
+        if self.failed {
+            return None;
+        }
+        let protein = self.read_next();
+        self.failed = matches!(protein, Some(Err(_)));
+        protein
+    }
+}
+
+// check a FASTA record and parse it into a protein
+fn read_protein(
+    proteome: &OpenProteome,
+    record: std::io::Result<fasta::Record>,
+    number: usize,
+    parser: &dyn HeaderParser,
+) -> Result<Protein> {
+    let error = |message: String| PepMatchError::Fasta {
+        path: proteome.path.clone(),
+        record: proteome.record,
+        message,
+    };
+    let record = record.map_err(|e| error(e.to_string()))?;
+    record.check().map_err(|e| error(e.to_string()))?;
+
+    let mut metadata = parser.parse(record.id(), record.desc().unwrap_or(""));
+    metadata.proteome = proteome.name.clone();
+    let sequence = String::from_utf8_lossy(record.seq()).to_string();
+    (metadata.length, metadata.molecular_weight) = (sequence.len(), molecular_weight(&sequence));
+    Ok(Protein { number, sequence, metadata })
+}
+
+// the average mass of a protein in Da, rounded, from the average masses of its residues and a
+// water, ambiguous residues weigh what the ones they stand for do on average
+pub(crate) fn molecular_weight(sequence: &str) -> usize {
+    let residues: f64 = sequence
+        .bytes()
+        .map(|residue| match residue.to_ascii_uppercase() {
+            b'A' => 71.0788,
+            b'R' => 156.1875,
+            b'N' => 114.1038,
+            b'D' => 115.0886,
+            b'C' => 103.1388,
+            b'E' => 129.1155,
+            b'Q' => 128.1307,
+            b'G' => 57.0519,
+            b'H' => 137.1411,
+            b'I' | b'L' | b'J' => 113.1594,
+            b'K' => 128.1741,
+            b'M' => 131.1926,
+            b'F' => 147.1766,
+            b'P' => 97.1167,
+            b'S' => 87.0782,
+            b'T' => 101.1051,
+            b'W' => 186.2132,
+            b'Y' => 163.1760,
+            b'V' => 99.1326,
+            b'U' => 150.0388,
+            b'O' => 237.3018,
+            b'B' => 114.5962,
+            b'Z' => 128.6231,
+            b'*' => 0.0,
+            _ => 110.0,
+        })
+        .sum();
+    if sequence.is_empty() { 0 } else { (residues + 18.0153).round() as usize }
+}
+
+// IDs of the proteins in a proteome FASTA file, e.g. UniProt's gene priority proteome
+pub(crate) fn read_protein_ids(path: &str, parser: Box<dyn HeaderParser>) -> Result<HashSet<String>> {
+    ProteomeReader::open(&[(String::new(), path.to_string())], parser)
+        .map(|protein| protein.map(|protein| protein.metadata.protein_id))
+        .collect()
+}
+
+// count the records in (name, path) proteome FASTA files without parsing them
+pub(crate) fn count_proteins(proteomes: &[(String, String)]) -> Result<usize> {
+    let mut count = 0;
+    for (_, path) in proteomes {
+        for line in BufReader::new(open_proteome(path)?).split(b'\n') {
+            if line.map_err(PepMatchError::io(path))?.starts_with(b">") {
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+// open a proteome FASTA file for reading, decompressing it on the fly if it's gzipped
+pub(crate) fn open_proteome(filename: &str) -> Result<Box<dyn Read + Send>> {
+    let file = std::fs::File::open(filename).map_err(PepMatchError::io(filename))?;
+    let mut reader = BufReader::new(file);
+
+    // check for the gzip magic bytes rather than trusting the extension
+    if reader.fill_buf().map_err(PepMatchError::io(filename))?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
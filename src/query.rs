@@ -0,0 +1,312 @@
+use crate::discontinuous::DiscontinuousEpitope;
+use crate::error::{PepMatchError, Result};
+use bio::io::fasta;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read};
+
+// column names a peptide column is picked up by when none is given, in order of preference
+const PEPTIDE_COLUMNS: [&str; 4] = ["peptide", "sequence", "query sequence", "epitope"];
+
+// extensions of the spreadsheets collaborators send peptide lists in
+const SPREADSHEET_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+// formats of a query file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    #[default]
+    Auto, // FASTA if it starts with a header, a table if its first line has a delimiter, otherwise text
+    Fasta,
+    Text, // one peptide per line
+    Csv,
+    Tsv,
+    #[cfg(feature = "xlsx")]
+    Excel, // a sheet of a spreadsheet, read like a table
+}
+
+impl std::str::FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<InputFormat, String> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(InputFormat::Auto),
+            "fasta" => Ok(InputFormat::Fasta),
+            "text" | "txt" => Ok(InputFormat::Text),
+            "csv" => Ok(InputFormat::Csv),
+            "tsv" => Ok(InputFormat::Tsv),
+            #[cfg(feature = "xlsx")]
+            "xlsx" | "xls" | "excel" => Ok(InputFormat::Excel),
+            #[cfg(not(feature = "xlsx"))]
+            "xlsx" | "xls" | "excel" => Err(format!("{} input needs pepmatch built with the xlsx feature", s)),
+            _ => Err(format!("unknown input format: {}", s)),
+        }
+    }
+}
+
+// the peptides of a query file and the values of the columns kept from each of its rows
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub peptides: Vec<String>,
+    pub kept: Vec<Vec<String>>, // a row of values per peptide, empty unless columns are kept
+    pub sources: Vec<String>, // accessions of the protein each peptide comes from, empty unless a source column is read
+}
+
+// read in peptides from a file, or stdin with "-", detecting whether it's FASTA,
+// a CSV/TSV table or plain text with one peptide per line
+pub fn read_peptides(source: &str, column: Option<&str>) -> Result<Vec<String>> {
+    Ok(read_query(source, InputFormat::Auto, column, None, &[])?.peptides)
+}
+
+// read in all the peptides of a query file in a format, see QueryReader::open
+pub fn read_query(
+    source: &str,
+    format: InputFormat,
+    column: Option<&str>,
+    sheet: Option<&str>,
+    keep: &[String],
+) -> Result<Query> {
+    QueryReader::open(source, format, column, sheet, keep)?.read_chunk(usize::MAX)
+}
+
+// a peptide, the values of the columns kept from its row and its source protein's accessions
+type Row = (String, Vec<String>, String);
+
+// reads the peptides of a query file a chunk at a time, so a file of millions of them
+// doesn't have to be loaded whole
+pub struct QueryReader {
+    rows: Box<dyn Iterator<Item = Result<Row>>>,
+    keep: bool,
+    kept_columns: Vec<String>, // names of the kept columns, to write them under
+    sources: bool,
+}
+
+impl QueryReader {
+    // open a query file in a format, or stdin with "-", keeping the values of other columns
+    // of a table to report with each peptide's hits, e.g. the IDs of IEDB epitopes, * keeps
+    // every column that isn't the peptides' or kept already, tables in spreadsheets are read
+    // from a sheet, the first by default
+    pub fn open(
+        source: &str,
+        format: InputFormat,
+        column: Option<&str>,
+        sheet: Option<&str>,
+        keep: &[String],
+    ) -> Result<QueryReader> {
+        QueryReader::with_sources(source, format, column, sheet, keep, None)
+    }
+
+    // open a query file like open, also reading the accessions of the protein each peptide
+    // comes from out of a column of a table
+    pub fn with_sources(
+        source: &str,
+        format: InputFormat,
+        column: Option<&str>,
+        sheet: Option<&str>,
+        keep: &[String],
+        sources: Option<&str>,
+    ) -> Result<QueryReader> {
+        let spreadsheet = std::path::Path::new(source)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| SPREADSHEET_EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+        #[cfg(feature = "xlsx")]
+        if format == InputFormat::Excel || (format == InputFormat::Auto && spreadsheet) {
+            let rows = read_spreadsheet(source, sheet)?;
+            return QueryReader::table(source, Box::new(rows.into_iter().map(Ok)), column, keep, sources);
+        }
+        #[cfg(not(feature = "xlsx"))]
+        if format == InputFormat::Auto && spreadsheet {
+            return Err(PepMatchError::invalid(format!("{}: spreadsheets need pepmatch built with the xlsx feature", source)));
+        }
+        if sheet.is_some() {
+            return Err(PepMatchError::invalid(format!("{}: only spreadsheets have sheets", source)));
+        }
+
+        let mut input: Box<dyn BufRead> = if source == "-" {
+            Box::new(std::io::stdin().lock())
+        } else {
+            Box::new(BufReader::new(File::open(source).map_err(PepMatchError::io(source))?))
+        };
+        // the format is detected from the first line that isn't blank, which is read ahead
+        let mut first = String::new();
+        while first.trim().is_empty() {
+            if input.read_line(&mut first).map_err(PepMatchError::io(source))? == 0 {
+                break;
+            }
+        }
+        let header = first.trim_start().lines().next().unwrap_or_default().to_string();
+        let input = Cursor::new(first).chain(input);
+
+        let format = match format {
+            InputFormat::Auto if header.starts_with('>') => InputFormat::Fasta,
+            InputFormat::Auto => match delimiter(&header, column.or(keep.first().map(String::as_str)).or(sources)) {
+                Some(b'\t') => InputFormat::Tsv,
+                Some(_) => InputFormat::Csv,
+                None => InputFormat::Text,
+            },
+            format => format,
+        };
+        if !keep.is_empty() && !matches!(format, InputFormat::Csv | InputFormat::Tsv) {
+            return Err(PepMatchError::invalid(format!("{}: only columns of a table can be kept", source)));
+        }
+        if sources.is_some() && !matches!(format, InputFormat::Csv | InputFormat::Tsv) {
+            return Err(PepMatchError::invalid(format!("{}: only a table has a column of source proteins", source)));
+        }
+
+        let path = source.to_string();
+        let rows: Box<dyn Iterator<Item = Result<Row>>> = match format {
+            InputFormat::Fasta => Box::new(fasta::Reader::from_bufread(input).records().enumerate().map(
+                move |(i, result)| {
+                    let record = result.map_err(|e| PepMatchError::Fasta {
+                        path: path.clone(),
+                        record: i + 1,
+                        message: e.to_string(),
+                    })?;
+                    Ok((String::from_utf8_lossy(record.seq()).to_string(), Vec::new(), String::new()))
+                },
+            )),
+            InputFormat::Csv | InputFormat::Tsv => {
+                let reader = csv::ReaderBuilder::new()
+                    .delimiter(if format == InputFormat::Tsv { b'\t' } else { b',' })
+                    .flexible(true)
+                    .has_headers(false)
+                    .from_reader(input);
+                let rows = reader.into_records().map(|record| {
+                    record.map(|record| record.iter().map(str::to_string).collect()).map_err(|e| e.to_string())
+                });
+                return QueryReader::table(source, Box::new(rows), column, keep, sources);
+            }
+            _ => Box::new(
+                input.lines()
+                    .map(move |line| {
+                        let line = line.map_err(PepMatchError::io(&path))?;
+                        Ok((line.trim().to_string(), Vec::new(), String::new()))
+                    })
+                    .filter(|row| !matches!(row, Ok((line, _, _)) if line.is_empty())),
+            ),
+        };
+        Ok(QueryReader { rows, keep: false, kept_columns: Vec::new(), sources: false })
+    }
+
+    // read the peptides in a table's column, the selected one or the first with a
+    // known peptide column name, otherwise the first column, and the kept and source columns of
+    // their rows, the first row being the header
+    fn table(
+        source: &str,
+        mut rows: Box<dyn Iterator<Item = std::result::Result<Vec<String>, String>>>,
+        column: Option<&str>,
+        keep: &[String],
+        sources: Option<&str>,
+    ) -> Result<QueryReader> {
+        let invalid = |e: String| PepMatchError::invalid(format!("{}: {}", source, e));
+        let names: Vec<String> = rows.next()
+            .transpose()
+            .map_err(invalid)?
+            .unwrap_or_default()
+            .iter()
+            .map(|name| name.trim().to_string())
+            .collect();
+        let headers: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+
+        let find = |column: &str| {
+            headers.iter()
+                .position(|name| *name == column.trim().to_lowercase())
+                .ok_or_else(|| invalid(format!("no column named {}", column)))
+        };
+        let position = match column {
+            Some(column) => find(column)?,
+            None => PEPTIDE_COLUMNS.iter()
+                .find_map(|known| headers.iter().position(|name| name == known))
+                .unwrap_or(0),
+        };
+        let named = keep
+            .iter()
+            .filter(|column| column.trim() != "*")
+            .map(|column| find(column))
+            .collect::<Result<Vec<usize>>>()?;
+        // columns named are reported as they're given and the others as the header has them
+        let mut kept = Vec::new();
+        let mut kept_columns = Vec::new();
+        for column in keep {
+            if column.trim() == "*" {
+                let others: Vec<usize> =
+                    (0..headers.len()).filter(|i| *i != position && !named.contains(i) && !kept.contains(i)).collect();
+                kept_columns.extend(others.iter().map(|&i| names[i].clone()));
+                kept.extend(others);
+            } else {
+                kept.push(find(column)?);
+                kept_columns.push(column.trim().to_string());
+            }
+        }
+
+        let source_position = sources.map(find).transpose()?;
+
+        let source = source.to_string();
+        let keep = !kept.is_empty();
+        let rows = rows.filter_map(move |record| {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => return Some(Err(PepMatchError::invalid(format!("{}: {}", source, e)))),
+            };
+            let peptide = record.get(position).map(|peptide| peptide.trim()).filter(|peptide| !peptide.is_empty())?;
+            let values = kept.iter().map(|&i| record.get(i).map_or("", |value| value.trim()).to_string());
+            let accessions = source_position.and_then(|i| record.get(i)).map_or("", |value| value.trim());
+            Some(Ok((peptide.to_string(), values.collect(), accessions.to_string())))
+        });
+        Ok(QueryReader { rows: Box::new(rows), keep, kept_columns, sources: source_position.is_some() })
+    }
+
+    // the names of the columns kept
+    pub fn kept_columns(&self) -> &[String] {
+        &self.kept_columns
+    }
+
+    // read the next chunk of up to size peptides, which is empty once they've all been read
+    pub fn read_chunk(&mut self, size: usize) -> Result<Query> {
+        let mut query = Query::default();
+        for row in self.rows.by_ref().take(size) {
+            let (peptide, values, accessions) = row?;
+            query.peptides.push(peptide);
+            if self.keep {
+                query.kept.push(values);
+            }
+            if self.sources {
+                query.sources.push(accessions);
+            }
+        }
+        Ok(query)
+    }
+}
+
+// the delimiter of a table going by its header line, a selected column means the input
+// has to be a table so fall back to a comma for single column files, commas between
+// the residues of discontinuous epitopes don't make a table
+fn delimiter(header: &str, column: Option<&str>) -> Option<u8> {
+    if column.is_none() && DiscontinuousEpitope::is_discontinuous(header) {
+        None
+    } else if header.contains('\t') {
+        Some(b'\t')
+    } else if header.contains(',') || column.is_some() {
+        Some(b',')
+    } else {
+        None
+    }
+}
+
+// the rows of a sheet of a spreadsheet, picked by its name or its number counting from 1
+#[cfg(feature = "xlsx")]
+fn read_spreadsheet(source: &str, sheet: Option<&str>) -> Result<Vec<Vec<String>>> {
+    use calamine::Reader;
+    let invalid = |e: String| PepMatchError::invalid(format!("{}: {}", source, e));
+    let mut workbook = calamine::open_workbook_auto(source).map_err(|e| invalid(e.to_string()))?;
+
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        None => names.first(),
+        Some(sheet) => names.iter().find(|name| name.eq_ignore_ascii_case(sheet.trim())).or_else(|| {
+            sheet.trim().parse::<usize>().ok().and_then(|number| names.get(number.checked_sub(1)?))
+        }),
+    };
+    let name = name.ok_or_else(|| invalid(format!("no sheet {}", sheet.unwrap_or_default())))?.clone();
+    let range = workbook.worksheet_range(&name).map_err(|e| invalid(e.to_string()))?;
+    Ok(range.rows().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect())
+}
@@ -0,0 +1,106 @@
+use crate::Match;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+// order hits from best to worst: highest similarity score when scored, exact matches and then fewest mismatches, proteins
+// in the gene priority proteome, lowest PE level, reviewed Swiss-Prot entries over TrEMBL ones and canonical
+// isoforms over the other isoforms, ties are broken by protein ID and position so the same hit always wins
+pub fn compare_matches(a: &Match, b: &Match) -> Ordering {
+    b.score.cmp(&a.score)
+        .then_with(|| a.mismatches.cmp(&b.mismatches))
+        .then_with(|| b.gene_priority.cmp(&a.gene_priority))
+        .then_with(|| pe_level_rank(a).cmp(&pe_level_rank(b)))
+        .then_with(|| (b.db == "sp").cmp(&(a.db == "sp")))
+        .then_with(|| is_isoform(a).cmp(&is_isoform(b)))
+        .then_with(|| a.protein_id.cmp(&b.protein_id))
+        .then_with(|| a.index_start.cmp(&b.index_start))
+}
+
+// order a peptide's hits by protein ID and then position, so they come out the same from
+// run to run and from one DB of a proteome to another however its proteins were numbered
+pub fn sort_matches(hits: &mut [Match]) {
+    hits.sort_by(|a, b| a.protein_id.cmp(&b.protein_id).then_with(|| a.index_start.cmp(&b.index_start)));
+}
+
+// what hits can be sorted by, keys after the first break its ties
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Mismatches, // fewest first
+    Position,   // by where they start in their proteins
+    ProteinId,
+    PeLevel, // lowest first, hits without one last
+    Score,   // highest first
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortKey, String> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "mismatches" => Ok(SortKey::Mismatches),
+            "position" => Ok(SortKey::Position),
+            "protein_id" => Ok(SortKey::ProteinId),
+            "pe_level" => Ok(SortKey::PeLevel),
+            "score" => Ok(SortKey::Score),
+            _ => Err(format!("unknown sort key: {}, expected mismatches, position, protein_id, pe_level or score", s)),
+        }
+    }
+}
+
+impl SortKey {
+    fn compare(&self, a: &Match, b: &Match) -> Ordering {
+        match self {
+            SortKey::Mismatches => a.mismatches.cmp(&b.mismatches),
+            SortKey::Position => a.index_start.cmp(&b.index_start),
+            SortKey::ProteinId => a.protein_id.cmp(&b.protein_id),
+            SortKey::PeLevel => pe_level_rank(a).cmp(&pe_level_rank(b)),
+            SortKey::Score => b.score.cmp(&a.score),
+        }
+    }
+}
+
+// order the hits of each peptide by the keys, leaving the peptides where they are, a peptide's
+// hits being next to each other, the sort is stable so hits the keys can't tell apart keep
+// their order
+pub fn sort_by_keys(hits: &mut [Match], keys: &[SortKey]) {
+    let compare = |a: &Match, b: &Match| keys.iter().fold(Ordering::Equal, |order, key| order.then_with(|| key.compare(a, b)));
+    for peptide_hits in hits.chunk_by_mut(|a, b| a.peptide == b.peptide) {
+        peptide_hits.sort_by(compare);
+    }
+}
+
+// pick the single best hit out of a peptide's hits
+pub fn best_match(hits: Vec<Match>) -> Option<Match> {
+    hits.into_iter().min_by(compare_matches)
+}
+
+// keep only the best hit in each protein and then at most limit hits, the best ones,
+// left in the order they were found
+pub fn limit_matches(hits: Vec<Match>, limit: Option<usize>, one_per_protein: bool) -> Vec<Match> {
+    let mut ranked: Vec<usize> = (0..hits.len()).collect();
+    ranked.sort_by(|&a, &b| compare_matches(&hits[a], &hits[b]));
+    let mut proteins = HashSet::new();
+    let mut kept: Vec<usize> = ranked
+        .into_iter()
+        .filter(|&i| !one_per_protein || proteins.insert(hits[i].protein_number))
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    kept.sort_unstable();
+    let mut kept = kept.into_iter().peekable();
+    hits.into_iter()
+        .enumerate()
+        .filter(|(i, _)| kept.next_if_eq(i).is_some())
+        .map(|(_, hit)| hit)
+        .collect()
+}
+
+// a missing PE level (0) ranks below every real one
+fn pe_level_rank(hit: &Match) -> usize {
+    if hit.pe_level == 0 { usize::MAX } else { hit.pe_level }
+}
+
+// non-canonical isoforms have a suffix on the accession, e.g. P04637-2, which is
+// all there is to go by in DBs preprocessed before isoforms were stored
+fn is_isoform(hit: &Match) -> bool {
+    hit.isoform > 0 || hit.protein_id.contains('-')
+}
@@ -0,0 +1,220 @@
+use crate::error::{PepMatchError, Result};
+use crate::output::Columns;
+use crate::proteome::ProteinFilter;
+use crate::similarity::ScoringMatrix;
+use crate::taxonomy::TaxonFilter;
+use crate::validate::{check_peptides, PeptideStatus};
+use crate::{Match, Matcher};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// JSON match requests of the HTTP API and the C interface, an object of the peptides
+// and search settings, e.g. {"peptides": ["SLQETFSDLW"], "max_mismatches": 1}, and the
+// hits they find
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchRequest {
+    pub peptides: Vec<String>,
+    pub max_mismatches: usize,
+    pub best_match: bool,
+    pub taxon_ids: Vec<String>,
+    pub species: Vec<String>,
+    pub max_pe_level: Option<usize>,
+    pub swissprot_only: bool,
+    pub context: usize, // residues either side of each hit returned
+    pub min_score: Option<i32>, // BLOSUM62 score of a similarity search
+    pub limit: Option<usize>,
+    pub one_match_per_protein: bool,
+    pub proteomes: Vec<String>,
+}
+
+// a hit keyed like the JSON output format, with the genomic, context and score columns
+// only when the search has them
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(utoipa::ToSchema))]
+pub struct MatchHit {
+    #[serde(rename = "Query Sequence")]
+    pub query_sequence: String,
+    #[serde(rename = "Matched Sequence")]
+    pub matched_sequence: String,
+    #[serde(rename = "Protein ID")]
+    pub protein_id: String,
+    #[serde(rename = "Protein Name")]
+    pub protein_name: String,
+    #[serde(rename = "Species")]
+    pub species: String,
+    #[serde(rename = "Taxon ID")]
+    pub taxon_id: String,
+    #[serde(rename = "Gene")]
+    pub gene: String,
+    #[serde(rename = "Mismatches")]
+    pub mismatches: usize,
+    #[serde(rename = "Mutated Positions")]
+    pub mutated_positions: Vec<String>, // each mismatch as position:query residue>protein residue
+    #[serde(rename = "Index start")]
+    pub index_start: usize, // 1-based with an inclusive end
+    #[serde(rename = "Index end")]
+    pub index_end: usize,
+    #[serde(rename = "Protein Existence Level")]
+    pub protein_existence_level: usize,
+    #[serde(rename = "Frame", skip_serializing_if = "Option::is_none", default)]
+    pub frame: Option<i8>,
+    #[serde(rename = "Genomic Start", skip_serializing_if = "Option::is_none", default)]
+    pub genomic_start: Option<usize>,
+    #[serde(rename = "Genomic End", skip_serializing_if = "Option::is_none", default)]
+    pub genomic_end: Option<usize>,
+    #[serde(rename = "Left Context", skip_serializing_if = "Option::is_none", default)]
+    pub left_context: Option<String>,
+    #[serde(rename = "Right Context", skip_serializing_if = "Option::is_none", default)]
+    pub right_context: Option<String>,
+    #[serde(rename = "Score", skip_serializing_if = "Option::is_none", default)]
+    pub score: Option<i32>,
+}
+
+// the hits of a match request, in the order they're found
+pub type MatchResponse = Vec<MatchHit>;
+
+impl MatchHit {
+    fn new(hit: &Match, columns: &Columns) -> MatchHit {
+        let genomic = |value| columns.genomic.then_some(value);
+        let context = |value: &String| columns.context.then(|| value.clone());
+        MatchHit {
+            query_sequence: hit.peptide.clone(),
+            matched_sequence: hit.matched_sequence.clone(),
+            protein_id: hit.protein_id.clone(),
+            protein_name: hit.protein_name.clone(),
+            species: hit.species.clone(),
+            taxon_id: hit.taxon_id.clone(),
+            gene: hit.gene.clone(),
+            mismatches: hit.mismatches,
+            mutated_positions: hit.substitutions(),
+            index_start: hit.index_start,
+            index_end: hit.index_end,
+            protein_existence_level: hit.pe_level,
+            frame: columns.genomic.then_some(hit.frame),
+            genomic_start: genomic(hit.genomic_start),
+            genomic_end: genomic(hit.genomic_end),
+            left_context: context(&hit.left_context),
+            right_context: context(&hit.right_context),
+            score: columns.score.then_some(hit.score),
+        }
+    }
+}
+
+// search the peptides of a match request as JSON with its settings on a matcher's index
+pub(crate) fn match_json(base: &Matcher, request: &Value) -> Result<String> {
+    let request = MatchRequest::deserialize(request)
+        .map_err(|e| PepMatchError::invalid(format!("the request isn't a valid match request: {}", e)))?;
+    let hits = match_request(base, &request)?;
+    serde_json::to_string(&hits).map_err(|e| PepMatchError::io("response")(e.into()))
+}
+
+// search the peptides of a match request with its settings on a matcher's index
+pub fn match_request(base: &Matcher, request: &MatchRequest) -> Result<MatchResponse> {
+    let peptides: Vec<String> =
+        request.peptides.iter().filter(|peptide| !peptide.trim().is_empty()).cloned().collect();
+    if peptides.is_empty() {
+        return Err(PepMatchError::invalid("peptides must be a non-empty array of peptides"));
+    }
+    let checks = check_peptides(&peptides);
+    for check in &checks {
+        if let PeptideStatus::Invalid(problem) = &check.status {
+            return Err(PepMatchError::invalid(format!("peptide {} is invalid: {}", check.input, problem)));
+        }
+    }
+    let peptides: Vec<String> = checks.into_iter().map(|check| check.peptide).collect();
+
+    let mut protein_filter = ProteinFilter::new().swissprot_only(request.swissprot_only);
+    if let Some(max_pe_level) = request.max_pe_level {
+        protein_filter = protein_filter.max_pe_level(max_pe_level);
+    }
+    let mut matcher = base
+        .share()?
+        // servers search a request per thread already
+        .threads(1)
+        .max_mismatches(request.max_mismatches)
+        .best_match(request.best_match)
+        .taxon_filter(TaxonFilter::new().taxon_ids(&request.taxon_ids).species(&request.species))
+        .protein_filter(protein_filter)
+        .context(request.context)
+        .one_match_per_protein(request.one_match_per_protein);
+    if let Some(limit) = request.limit {
+        matcher = matcher.limit(limit);
+    }
+    if let Some(min_score) = request.min_score {
+        matcher = matcher.similarity(ScoringMatrix::blosum62(), min_score);
+    }
+    if !request.proteomes.is_empty() {
+        matcher = matcher.only_proteomes(&request.proteomes)?;
+    }
+
+    let hits = matcher.match_peptides(&peptides)?;
+    let columns = Columns {
+        genomic: hits.iter().any(|hit| hit.frame != 0),
+        context: request.context > 0,
+        score: request.min_score.is_some(),
+        ..Columns::default()
+    };
+    Ok(hits.iter().map(|hit| MatchHit::new(hit, &columns)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_default_the_settings_they_leave_out() {
+        let request: MatchRequest = serde_json::from_str(r#"{"peptides": ["SLQETFSDLW"], "max_mismatches": 1}"#).unwrap();
+        let expected = MatchRequest { peptides: vec!["SLQETFSDLW".to_string()], max_mismatches: 1, ..MatchRequest::default() };
+        assert_eq!(request, expected);
+        assert!(serde_json::from_str::<MatchRequest>(r#"{"peptides": ["SLQETFSDLW"], "mismatches": 1}"#).is_err());
+    }
+
+    #[test]
+    fn hits_only_have_the_columns_searched_for() {
+        let hit = Match {
+            peptide: "SLQETFSDLW".to_string(),
+            matched_sequence: "SLQETFSDLW".to_string(),
+            protein_number: 1,
+            protein_id: "P04637".to_string(),
+            protein_name: String::new(),
+            species: String::new(),
+            taxon_id: String::new(),
+            gene: String::new(),
+            mismatches: 0,
+            mutated_positions: Vec::new(),
+            index_start: 17,
+            index_end: 26,
+            pe_level: 1,
+            gene_priority: false,
+            db: String::new(),
+            isoform: 0,
+            protein_length: 0,
+            molecular_weight: 0,
+            discontinuous: false,
+            frame: 0,
+            genomic_start: 0,
+            genomic_end: 0,
+            left_context: "PL".to_string(),
+            right_context: "KL".to_string(),
+            score: 0,
+            n_terminus: String::new(),
+            mature_start: 0,
+            mature_end: 0,
+            query_start: 0,
+            query_end: 0,
+            e_value: 0.0,
+            ensembl: String::new(),
+            refseq: String::new(),
+            hgnc: String::new(),
+            kept: Vec::new(),
+        };
+        let plain = serde_json::to_value(MatchHit::new(&hit, &Columns::default())).unwrap();
+        assert_eq!(plain["Index start"], 17);
+        assert!(plain.get("Left Context").is_none() && plain.get("Frame").is_none());
+        let columns = Columns { context: true, ..Columns::default() };
+        let with_context = serde_json::to_value(MatchHit::new(&hit, &columns)).unwrap();
+        assert_eq!((&with_context["Left Context"], &with_context["Right Context"]), (&"PL".into(), &"KL".into()));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::index::region;
+use crate::matcher::{build_match, n_terminus};
+use crate::progress;
+use crate::ranking;
+use crate::error::{PepMatchError, Result};
+use crate::{Match, Preprocessor};
+use aho_corasick::AhoCorasick;
+use fxhash::FxHashMap;
+
+// find every exact hit of the peptides by streaming the proteome once through an
+// Aho-Corasick automaton of the peptides, nothing is indexed so it suits a few
+// peptides against a proteome that won't be searched again, hits come out in
+// the order of the peptides like Matcher::match_peptides, with up to context residues
+// of the protein on either side of each hit
+pub fn scan_proteome(preprocessor: &Preprocessor, peptides: &[String], context: usize) -> Result<Vec<Match>> {
+    let canonical = |sequence: &str| preprocessor.canonical(sequence).into_owned();
+
+    // one pattern per distinct peptide, repeated peptides share its hits
+    let mut patterns: Vec<String> = Vec::new();
+    let mut pattern_ids: FxHashMap<String, usize> = FxHashMap::default();
+    for peptide in peptides.iter().filter(|peptide| !peptide.is_empty()) {
+        pattern_ids.entry(canonical(peptide)).or_insert_with_key(|pattern| {
+            patterns.push(pattern.clone());
+            patterns.len() - 1
+        });
+    }
+    let automaton = AhoCorasick::new(&patterns).map_err(PepMatchError::invalid)?;
+
+    let mut hits: Vec<Vec<Match>> = vec![Vec::new(); patterns.len()];
+    let bar = progress::proteins(preprocessor.progress, preprocessor.count_proteins()?);
+    for protein in preprocessor.read_proteome()? {
+        let protein = protein?;
+        bar.inc(1);
+        let sequence = canonical(&protein.sequence);
+        for found in automaton.find_overlapping_iter(&sequence) {
+            let start = found.start();
+            let matched_sequence = protein.sequence[start..found.end()].to_string();
+            let mut hit = build_match(
+                &patterns[found.pattern().as_usize()],
+                matched_sequence,
+                (protein.number, start),
+                protein.metadata.clone(),
+                preprocessor.i_l_equivalent,
+            );
+            if context > 0 {
+                let left = start.saturating_sub(context);
+                hit.left_context = region(&protein.sequence, left, start - left);
+                hit.right_context = region(&protein.sequence, found.end(), context);
+            }
+            if !preprocessor.equivalence_classes.is_empty() {
+                // hits are exact up to the equivalent residues
+                hit.mutated_positions.clear();
+                hit.mismatches = 0;
+            }
+            hit.n_terminus = n_terminus(start, region(&protein.sequence, 0, 1).as_str()).to_string();
+            hits[found.pattern().as_usize()].push(hit);
+        }
+    }
+
+    bar.finish_and_clear();
+    for hits in &mut hits {
+        ranking::sort_matches(hits);
+    }
+
+    // report the peptides as they were given rather than their canonical form
+    Ok(peptides.iter()
+        .filter_map(|peptide| pattern_ids.get(&canonical(peptide)).map(|&id| (peptide, id)))
+        .flat_map(|(peptide, id)| {
+            hits[id].iter().cloned().map(move |mut hit| {
+                hit.peptide = peptide.clone();
+                hit
+            })
+        })
+        .collect())
+}
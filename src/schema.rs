@@ -0,0 +1,227 @@
+#[cfg(feature = "sqlite")]
+use crate::build_info::{has_column, has_table};
+use crate::error::{PepMatchError, Result};
+#[cfg(feature = "sqlite")]
+use crate::proteome::molecular_weight;
+#[cfg(feature = "sqlite")]
+use rusqlite::{OpenFlags, OptionalExtension};
+
+// the version of the tables pepmatch writes to SQLite and DuckDB DBs, kept in their meta table
+// and bumped with every column or table added, SQLite DBs of older versions are brought up to
+// it by the migrations after theirs, DuckDB ones have to be preprocessed again
+pub(crate) const SCHEMA_VERSION: usize = 3;
+
+// DBs preprocessed before the version was kept
+const UNVERSIONED: usize = 1;
+
+// a change to the tables of SQLite DBs, bringing those of the version before up to `version`,
+// migrations check what's there first since unversioned DBs can have some of it already
+#[cfg(feature = "sqlite")]
+struct Migration {
+    version: usize,
+    apply: fn(&rusqlite::Transaction) -> rusqlite::Result<()>,
+}
+
+#[cfg(feature = "sqlite")]
+const MIGRATIONS: [Migration; 2] = [
+    // the gene priority, frame, source length, database and isoform of proteins
+    Migration { version: 2, apply: add_protein_columns },
+    // the lengths and molecular weights of proteins
+    Migration { version: 3, apply: add_protein_properties },
+];
+
+// the schema version of a DB from its meta table
+#[cfg(feature = "sqlite")]
+pub(crate) fn schema_version(conn: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    if !has_table(conn, "meta")? {
+        return Ok(UNVERSIONED);
+    }
+    let version: Option<String> = conn
+        .query_row("SELECT value FROM meta WHERE key = 'schema_version'", rusqlite::params![], |row| row.get(0))
+        .optional()?;
+    Ok(version.and_then(|version| version.parse().ok()).unwrap_or(UNVERSIONED))
+}
+
+// the version of a SQLite DB older than this pepmatch's, None if it's up to date, DBs from a
+// newer pepmatch can't be read
+#[cfg(feature = "sqlite")]
+pub(crate) fn outdated(conn: &rusqlite::Connection, db_path: &str) -> Result<Option<usize>> {
+    let version = schema_version(conn).map_err(PepMatchError::database(db_path))?;
+    if version > SCHEMA_VERSION {
+        return Err(PepMatchError::invalid(format!(
+            "{}: was preprocessed by a newer pepmatch with schema version {}, this one reads up to {}, \
+             upgrade pepmatch or preprocess it again",
+            db_path, version, SCHEMA_VERSION
+        )));
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(None);
+    }
+    if let Some(lacking) = lacking(conn).map_err(PepMatchError::database(db_path))? {
+        return Err(PepMatchError::invalid(format!(
+            "{}: was preprocessed by an older pepmatch without {}, it can't be upgraded, preprocess the proteome again",
+            db_path, lacking
+        )));
+    }
+    Ok(Some(version))
+}
+
+// what the migrations build on that a DB lacks, the layouts of DBs preprocessed before k-mers'
+// locations were packed, proteomes were told apart or sequences were kept can't be upgraded
+#[cfg(feature = "sqlite")]
+fn lacking(conn: &rusqlite::Connection) -> rusqlite::Result<Option<&'static str>> {
+    let layout = [
+        (has_column(conn, "kmers", "locations")?, "a row of packed locations per k-mer"),
+        (has_column(conn, "metadata", "proteome")?, "the proteome of each protein"),
+        (has_table(conn, "proteomes")?, "a proteomes table"),
+        (has_table(conn, "proteins")?, "the proteins' sequences"),
+    ];
+    Ok(layout.into_iter().find(|(has, _)| !has).map(|(_, what)| what))
+}
+
+// bring a SQLite DB up to the current schema version by applying the migrations after its
+// own, in one transaction so a DB that can't be upgraded is left as it was, one whose layout
+// the migrations don't build on isn't touched
+#[cfg(feature = "sqlite")]
+pub(crate) fn upgrade(db_path: &str) -> Result<()> {
+    let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let mut conn = rusqlite::Connection::open_with_flags(db_path, flags).map_err(PepMatchError::database(db_path))?;
+    if outdated(&conn, db_path)?.is_none() {
+        return Ok(());
+    }
+    let upgrade = |conn: &mut rusqlite::Connection| -> rusqlite::Result<()> {
+        let tx = conn.transaction()?;
+        let version = schema_version(&tx)?;
+        for migration in MIGRATIONS.iter().filter(|migration| migration.version > version) {
+            (migration.apply)(&tx)?;
+        }
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS meta (
+                key              TEXT PRIMARY KEY,
+                value            TEXT NOT NULL
+            )",
+            rusqlite::params![],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('schema_version', ?1)",
+            rusqlite::params![SCHEMA_VERSION.to_string()],
+        )?;
+        tx.commit()
+    };
+    upgrade(&mut conn).map_err(PepMatchError::database(db_path))
+}
+
+// a DuckDB DB has to be of the current version, there are no migrations for them
+#[cfg(feature = "duckdb")]
+pub(crate) fn check_duckdb(version: Option<usize>, db_path: &str) -> Result<()> {
+    let version = version.unwrap_or(UNVERSIONED);
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+    Err(PepMatchError::invalid(format!(
+        "{}: was preprocessed with schema version {}, this pepmatch reads version {}, rebuild it with --overwrite",
+        db_path, version, SCHEMA_VERSION
+    )))
+}
+
+#[cfg(feature = "sqlite")]
+fn add_column(tx: &rusqlite::Transaction, table: &str, column: &str, definition: &str) -> rusqlite::Result<()> {
+    if !has_column(tx, table, column)? {
+        tx.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), rusqlite::params![])?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn add_protein_columns(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+    for column in ["gene_priority", "frame", "source_length", "isoform"] {
+        add_column(tx, "metadata", column, "INTEGER NOT NULL DEFAULT 0")?;
+    }
+    add_column(tx, "metadata", "db", "TEXT NOT NULL DEFAULT ''")
+}
+
+// the columns are filled in from the sequences, DBs without a proteins table keep them 0
+#[cfg(feature = "sqlite")]
+fn add_protein_properties(tx: &rusqlite::Transaction) -> rusqlite::Result<()> {
+    let missing = !has_column(tx, "metadata", "length")?;
+    add_column(tx, "metadata", "length", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(tx, "metadata", "molecular_weight", "INTEGER NOT NULL DEFAULT 0")?;
+    if !missing || !has_table(tx, "proteins")? {
+        return Ok(());
+    }
+    let mut select = tx.prepare("SELECT protein_number, sequence FROM proteins")?;
+    let mut update = tx.prepare("UPDATE metadata SET length = ?2, molecular_weight = ?3 WHERE protein_number = ?1")?;
+    let mut rows = select.query(rusqlite::params![])?;
+    while let Some(row) = rows.next()? {
+        let (protein_number, sequence): (i64, String) = (row.get(0)?, row.get(1)?);
+        update.execute(rusqlite::params![protein_number, sequence.len() as i64, molecular_weight(&sequence) as i64])?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::preprocessor::Preprocessor;
+
+    #[test]
+    fn older_dbs_are_upgraded() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-schema-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        Preprocessor::new(fasta, 3).db_path(db).preprocess().unwrap();
+
+        // take the DB back to before protein lengths and the schema version were kept
+        let conn = rusqlite::Connection::open(db).unwrap();
+        assert_eq!(outdated(&conn, db).unwrap(), None);
+        conn.execute_batch(
+            "ALTER TABLE metadata DROP COLUMN length;
+             ALTER TABLE metadata DROP COLUMN molecular_weight;
+             DELETE FROM meta WHERE key = 'schema_version';",
+        )
+        .unwrap();
+        assert_eq!(outdated(&conn, db).unwrap(), Some(UNVERSIONED));
+        upgrade(db).unwrap();
+        assert_eq!(outdated(&conn, db).unwrap(), None);
+        let (length, weight): (i64, i64) = conn
+            .query_row("SELECT length, molecular_weight FROM metadata", rusqlite::params![], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((length, weight as usize), (13, molecular_weight("MKLVSTAAQWDEQ")));
+
+        conn.execute("UPDATE meta SET value = '99' WHERE key = 'schema_version'", rusqlite::params![]).unwrap();
+        let error = outdated(&conn, db).unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(error.contains("newer pepmatch"), "{}", error);
+    }
+
+    #[test]
+    fn dbs_of_the_first_layout_are_left_as_they_are() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-schema-first-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("proteome.db");
+        let db = db.to_str().unwrap();
+        // a k-mer row per location and no proteins or proteomes tables
+        let conn = rusqlite::Connection::open(db).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE kmers (kmer TEXT NOT NULL, idx INTEGER NOT NULL);
+             CREATE TABLE metadata (
+                protein_number INTEGER NOT NULL, protein_id INTEGER NOT NULL, protein_name TEXT NOT NULL,
+                species TEXT NOT NULL, taxon_id TEXT NOT NULL, gene TEXT NOT NULL, pe_level INTEGER NOT NULL,
+                sequence_version INTEGER NOT NULL
+             );
+             INSERT INTO kmers VALUES ('MKL', 0);
+             INSERT INTO metadata VALUES (0, 1, 'First', 'Homo sapiens', '9606', 'ONE', 1, 1);",
+        )
+        .unwrap();
+
+        let error = crate::index::open_db(db).err().unwrap().to_string();
+        let upgrade_error = upgrade(db).unwrap_err().to_string();
+        let untouched = !has_table(&conn, "meta").unwrap() && !has_column(&conn, "metadata", "length").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(error.contains("preprocess the proteome again"), "{}", error);
+        assert!(upgrade_error.contains("preprocess the proteome again"), "{}", upgrade_error);
+        assert!(untouched);
+    }
+}
@@ -0,0 +1,388 @@
+mod access;
+mod openapi;
+
+pub use access::read_api_keys;
+
+use crate::error::{PepMatchError, Result};
+use crate::{match_request, MatchHit, MatchRequest, MatchResponse, Matcher};
+use access::{ApiKeys, RateLimiter};
+use openapi::{ErrorBody, Health, Info};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tiny_http::{Header, Method, Request, Response};
+
+// paths requests are let in to without an API key or counting against the rate limit
+const OPEN_PATHS: [&str; 2] = ["/health", "/openapi.json"];
+
+// most bytes of a request body that are read, about a million peptides
+const MAX_BODY_BYTES: u64 = 16 << 20;
+
+// who the server lets in and how much they can ask of it, by default anyone and anything
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    api_keys: Vec<String>,
+    requests_per_minute: Option<u32>,
+    max_peptides: Option<usize>,
+}
+
+impl ServeOptions {
+    // only let in requests with one of these keys, as `Authorization: Bearer KEY` or
+    // `X-API-Key: KEY`, but for /health
+    pub fn api_keys(mut self, keys: Vec<String>) -> Self {
+        self.api_keys = keys;
+        self
+    }
+
+    // the requests each API key, or each address without keys, can make a minute
+    pub fn requests_per_minute(mut self, n: u32) -> Self {
+        self.requests_per_minute = Some(n);
+        self
+    }
+
+    // most peptides of a match request
+    pub fn max_peptides(mut self, n: usize) -> Self {
+        self.max_peptides = Some(n);
+        self
+    }
+}
+
+// the options as the threads check requests against them
+struct Access {
+    api_keys: ApiKeys,
+    limiter: Option<RateLimiter>,
+    max_peptides: Option<usize>,
+}
+
+impl Access {
+    fn new(options: &ServeOptions) -> Result<Access> {
+        if options.requests_per_minute == Some(0) {
+            return Err(PepMatchError::invalid("the rate limit must be at least a request a minute"));
+        }
+        Ok(Access {
+            api_keys: ApiKeys::new(&options.api_keys),
+            limiter: options.requests_per_minute.map(RateLimiter::new),
+            max_peptides: options.max_peptides,
+        })
+    }
+
+    // the response to a request that isn't let in, its client counted against the rate limit
+    fn refuse(&self, request: &Request) -> Option<(u16, String, Option<Header>)> {
+        let key = request
+            .headers()
+            .iter()
+            .find_map(|header| {
+                let value = header.value.as_str();
+                if header.field.equiv("Authorization") {
+                    value.strip_prefix("Bearer ")
+                } else if header.field.equiv("X-API-Key") {
+                    Some(value)
+                } else {
+                    None
+                }
+            })
+            .map(str::trim);
+        let client = if self.api_keys.is_empty() {
+            request.remote_addr().map(|address| address.ip().to_string()).unwrap_or_default()
+        } else {
+            match key {
+                Some(key) if self.api_keys.allows(key) => key.to_string(),
+                _ => {
+                    let challenge = Header::from_bytes("WWW-Authenticate", "Bearer").expect("a valid header");
+                    return Some((401, json!({ "error": "a valid API key is needed" }).to_string(), Some(challenge)));
+                }
+            }
+        };
+        let wait = self.limiter.as_ref()?.take(&client, Instant::now()).err()?;
+        let seconds = wait.as_secs() + 1;
+        let retry = Header::from_bytes("Retry-After", seconds.to_string()).expect("a valid header");
+        let message = format!("too many requests, retry in {} seconds", seconds);
+        Some((429, json!({ "error": message }).to_string(), Some(retry)))
+    }
+
+    fn check_size(&self, request: &MatchRequest) -> Result<()> {
+        let peptides = request.peptides.len();
+        match self.max_peptides {
+            Some(max) if peptides > max => Err(PepMatchError::invalid(format!(
+                "{} peptides is more than the {} a request can search",
+                peptides, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+// serve searches of a matcher's index over HTTP until the process is stopped, on threads
+// that each handle a request at a time with their own handle on the index:
+//   GET  /health  {"status": "ok"}
+//   GET  /info    k of the index and its proteomes
+//   POST /match   {"peptides": [...], "max_mismatches": 1, ...} --> the hits as the JSON output format
+//   POST /reload  open the DB again once it's been replaced --> its k and proteomes
+//   GET  /openapi.json  the OpenAPI document of these
+// requests but for /health and /openapi.json are refused without an API key or over the rate limit of the options
+pub fn serve(matcher: &Matcher, address: &str, threads: usize, options: &ServeOptions) -> Result<()> {
+    let server = tiny_http::Server::http(address)
+        .map_err(|e| PepMatchError::invalid(format!("can't listen on {}: {}", address, e)))?;
+    let server = Arc::new(server);
+    let served = Arc::new(Served::new(matcher.share()?));
+    let access = Arc::new(Access::new(options)?);
+    let threads = if threads == 0 { std::thread::available_parallelism().map_or(1, usize::from) } else { threads };
+    tracing::info!("Listening on http://{}", address);
+
+    let handles = (0..threads)
+        .map(|_| {
+            let mut current = served.share()?;
+            let server = Arc::clone(&server);
+            let served = Arc::clone(&served);
+            let access = Arc::clone(&access);
+            Ok(std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    if served.reloaded_since(current.0) {
+                        match served.share() {
+                            Ok(reloaded) => current = reloaded,
+                            Err(e) => tracing::warn!("Searching the index opened before, can't open the reloaded one: {}", e),
+                        }
+                    }
+                    handle(&current.1, &served, &access, request);
+                }
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for handle in handles {
+        let _ = handle.join();
+    }
+    Ok(())
+}
+
+// the index served and the number of times it's been reloaded, the threads take their own
+// handle on it before a request when it's been reloaded since their last one, so requests
+// being searched finish on the index they started on
+struct Served {
+    current: Mutex<(usize, Matcher)>,
+}
+
+impl Served {
+    fn new(matcher: Matcher) -> Served {
+        Served { current: Mutex::new((0, matcher)) }
+    }
+
+    fn reloaded_since(&self, generation: usize) -> bool {
+        self.current.lock().expect("no thread panics holding the index").0 != generation
+    }
+
+    fn share(&self) -> Result<(usize, Matcher)> {
+        let current = self.current.lock().expect("no thread panics holding the index");
+        Ok((current.0, current.1.share()?))
+    }
+
+    // swap in the DB opened again, keeping the one served if it can't be opened
+    fn reload(&self) -> Result<Info> {
+        let mut current = self.current.lock().expect("no thread panics holding the index");
+        let matcher = current.1.reopen()?;
+        let info = info(&matcher)?;
+        *current = (current.0 + 1, matcher);
+        tracing::info!("Reloaded the index");
+        Ok(info)
+    }
+}
+
+fn handle(matcher: &Matcher, served: &Served, access: &Access, mut request: Request) {
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let content_type = Header::from_bytes("Content-Type", "application/json").expect("a valid header");
+    if !OPEN_PATHS.contains(&path.as_str()) {
+        if let Some((status, body, header)) = access.refuse(&request) {
+            let mut response = Response::from_string(body).with_status_code(status).with_header(content_type);
+            if let Some(header) = header {
+                response = response.with_header(header);
+            }
+            let _ = request.respond(response);
+            return;
+        }
+    }
+    let (status, body) = match (request.method(), path.as_str()) {
+        (Method::Get, "/health") => respond(Ok(health())),
+        (Method::Get, "/info") => respond(info(matcher)),
+        (Method::Post, "/match") => match read_body(&mut request) {
+            Ok(body) => match access.check_size(&body) {
+                Err(e) => (413, json!({ "error": e.to_string() }).to_string()),
+                Ok(()) => respond(match_peptides(matcher, &body)),
+            },
+            Err(e) => error(&e),
+        },
+        (Method::Post, "/reload") => respond(reload(served)),
+        (Method::Get, "/openapi.json") => (200, openapi::document()),
+        (_, "/health" | "/info" | "/match" | "/reload" | "/openapi.json") => {
+            (405, json!({ "error": "method not allowed" }).to_string())
+        }
+        _ => (404, json!({ "error": "not found" }).to_string()),
+    };
+    // the client hanging up before the response is sent isn't the server's problem
+    let _ = request.respond(Response::from_string(body).with_status_code(status).with_header(content_type));
+}
+
+// the endpoints, as the OpenAPI document has them
+
+#[utoipa::path(get, path = "/health", tag = "pepmatch", responses((status = 200, description = "The server is up", body = Health)))]
+fn health() -> Health {
+    Health { status: "ok".to_string() }
+}
+
+#[utoipa::path(
+    get,
+    path = "/info",
+    tag = "pepmatch",
+    responses(
+        (status = 200, description = "k of the index and its proteomes", body = Info),
+        (status = 401, description = "No valid API key was given", body = ErrorBody),
+        (status = 429, description = "The key or address is over the rate limit", body = ErrorBody),
+    ),
+    security((), ("api_key" = []))
+)]
+fn info(matcher: &Matcher) -> Result<Info> {
+    Ok(Info { k: matcher.k(), proteomes: matcher.proteomes()? })
+}
+
+#[utoipa::path(
+    post,
+    path = "/match",
+    tag = "pepmatch",
+    request_body = MatchRequest,
+    responses(
+        (status = 200, description = "The hits of the peptides", body = [MatchHit]),
+        (status = 400, description = "The request is invalid", body = ErrorBody),
+        (status = 401, description = "No valid API key was given", body = ErrorBody),
+        (status = 413, description = "The request has more peptides than the server searches at once", body = ErrorBody),
+        (status = 429, description = "The key or address is over the rate limit", body = ErrorBody),
+        (status = 500, description = "Searching the index failed", body = ErrorBody),
+    ),
+    security((), ("api_key" = []))
+)]
+fn match_peptides(matcher: &Matcher, request: &MatchRequest) -> Result<MatchResponse> {
+    match_request(matcher, request)
+}
+
+#[utoipa::path(
+    post,
+    path = "/reload",
+    tag = "pepmatch",
+    responses(
+        (status = 200, description = "The DB was opened again, with its k and proteomes", body = Info),
+        (status = 401, description = "No valid API key was given", body = ErrorBody),
+        (status = 429, description = "The key or address is over the rate limit", body = ErrorBody),
+        (status = 500, description = "The DB can't be opened, the index served before is kept", body = ErrorBody),
+    ),
+    security((), ("api_key" = []))
+)]
+fn reload(served: &Served) -> Result<Info> {
+    served.reload()
+}
+
+fn respond(response: Result<impl Serialize>) -> (u16, String) {
+    let body = response.and_then(|body| serde_json::to_string(&body).map_err(|e| PepMatchError::io("response")(e.into())));
+    match body {
+        Ok(body) => (200, body),
+        Err(e) => error(&e),
+    }
+}
+
+// 400 for bad requests and 500 for anything that went wrong searching
+fn error(e: &PepMatchError) -> (u16, String) {
+    let status = if matches!(e, PepMatchError::Invalid(_)) { 400 } else { 500 };
+    (status, json!({ "error": e.to_string() }).to_string())
+}
+
+fn read_body<T: DeserializeOwned>(request: &mut Request) -> Result<T> {
+    let mut body = String::new();
+    std::io::Read::read_to_string(&mut std::io::Read::take(request.as_reader(), MAX_BODY_BYTES), &mut body)
+        .map_err(|e| PepMatchError::invalid(format!("can't read the request: {}", e)))?;
+    serde_json::from_str(&body).map_err(|e| PepMatchError::invalid(format!("the request isn't valid: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Preprocessor;
+
+    #[test]
+    fn reloading_swaps_in_the_replaced_db() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-server-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        Preprocessor::new(fasta, 3).db_path(db).preprocess().unwrap();
+        let served = Served::new(Matcher::new(db).unwrap());
+        let (generation, before) = served.share().unwrap();
+
+        let replacement = dir.join("new.db");
+        Preprocessor::new(fasta, 4).db_path(replacement.to_str().unwrap()).preprocess().unwrap();
+        std::fs::rename(&replacement, db).unwrap();
+        assert!(!served.reloaded_since(generation));
+        assert_eq!(served.reload().unwrap().k, 4);
+        assert!(served.reloaded_since(generation));
+        let (generation, after) = served.share().unwrap();
+        assert_eq!((before.k(), after.k()), (3, 4));
+        assert_eq!(before.proteomes().unwrap().len(), 1, "the index served before can still be searched");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(served.reload().is_err());
+        assert!(!served.reloaded_since(generation), "a DB that can't be opened isn't swapped in");
+    }
+
+    #[test]
+    fn requests_are_answered_over_http() {
+        let dir = std::env::temp_dir().join(format!("pepmatch-http-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (fasta, db) = (dir.join("p.fasta"), dir.join("p.db"));
+        let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+        std::fs::write(fasta, ">sp|P00001|ONE_HUMAN First OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQWDEQ\n").unwrap();
+        Preprocessor::new(fasta, 3).db_path(db).preprocess().unwrap();
+        let matcher = Matcher::new(db).unwrap();
+
+        // a free port, the server runs until the tests are done
+        let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let options = ServeOptions::default().api_keys(vec!["secret".to_string()]).max_peptides(2);
+        let listening = address.clone();
+        std::thread::spawn(move || serve(&matcher, &listening, 1, &options));
+        let send = |request: String| {
+            let mut stream = (0..100)
+                .find_map(|_| {
+                    std::net::TcpStream::connect(&address)
+                        .map_err(|_| std::thread::sleep(std::time::Duration::from_millis(20)))
+                        .ok()
+                })
+                .expect("the server to be listening");
+            std::io::Write::write_all(&mut stream, request.as_bytes()).unwrap();
+            let mut response = String::new();
+            std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+            let status: u16 = response.split(' ').nth(1).unwrap().parse().unwrap();
+            let body = response.split("\r\n\r\n").nth(1).unwrap_or_default().to_string();
+            (status, body)
+        };
+        let get = |path: &str| send(format!("GET {} HTTP/1.1\r\nX-API-Key: secret\r\nConnection: close\r\n\r\n", path));
+        let post = |key: &str, body: &str| {
+            send(format!(
+                "POST /match HTTP/1.1\r\nX-API-Key: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                key,
+                body.len(),
+                body
+            ))
+        };
+
+        assert_eq!(send("GET /health HTTP/1.1\r\nConnection: close\r\n\r\n".to_string()), (200, r#"{"status":"ok"}"#.to_string()));
+        let (status, body) = post("secret", r#"{"peptides": ["STAAQW", "WWWWWW"], "max_mismatches": 1}"#);
+        assert_eq!(status, 200, "{}", body);
+        let hits: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((&hits[0]["Protein ID"], &hits[0]["Index start"]), (&json!("P00001"), &json!(5)));
+        assert_eq!(post("wrong", r#"{"peptides": ["STAAQW"]}"#).0, 401);
+        assert_eq!(post("secret", r#"{"peptides": ["STAAQW", "DEQ", "MKL"]}"#).0, 413);
+        assert_eq!(post("secret", r#"{"peptides": "STAAQW"}"#).0, 400);
+        assert_eq!(get("/info").0, 200);
+        assert_eq!(get("/match").0, 405);
+        assert_eq!(get("/nowhere").0, 404);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
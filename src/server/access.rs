@@ -0,0 +1,128 @@
+use crate::error::{PepMatchError, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// clients whose buckets are kept before the full ones are forgotten, and then those seen
+// least recently
+const MAX_CLIENTS: usize = 10_000;
+
+// read API keys from a file, one per line, skipping blank lines and # comments
+pub fn read_api_keys(path: &str) -> Result<Vec<String>> {
+    let keys = std::fs::read_to_string(path).map_err(PepMatchError::io(path))?;
+    let keys: Vec<String> = keys
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if keys.is_empty() {
+        return Err(PepMatchError::invalid(format!("{} has no API keys", path)));
+    }
+    Ok(keys)
+}
+
+// the API keys requests are let in with, kept hashed so that checking a key takes as long
+// whatever it has in common with them
+pub(super) struct ApiKeys(HashSet<[u8; 32]>);
+
+impl ApiKeys {
+    pub fn new(keys: &[String]) -> ApiKeys {
+        ApiKeys(keys.iter().map(|key| hash(key)).collect())
+    }
+
+    // every request is let in without keys
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn allows(&self, key: &str) -> bool {
+        self.0.contains(&hash(key))
+    }
+}
+
+fn hash(key: &str) -> [u8; 32] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+// a bucket of requests for each client that fills back up at a steady rate, so a client can
+// make a burst of up to a minute's requests then carry on at the rate
+pub(super) struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, (f64, Instant)>>, // requests left and when they were counted
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> RateLimiter {
+        RateLimiter { per_minute, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    // take a request from the client's bucket, or how long until it has one
+    pub fn take(&self, client: &str, now: Instant) -> std::result::Result<(), Duration> {
+        let capacity = f64::from(self.per_minute);
+        let per_second = capacity / 60.0;
+        let mut buckets = self.buckets.lock().expect("no thread panics holding the buckets");
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(client) {
+            buckets.retain(|_, (left, counted)| *left + now.duration_since(*counted).as_secs_f64() * per_second < capacity);
+        }
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(client) {
+            // a tenth go at once so a flood of new clients doesn't sort the buckets for each
+            let mut seen: Vec<(Instant, String)> = buckets.iter().map(|(client, (_, counted))| (*counted, client.clone())).collect();
+            seen.sort_unstable();
+            for (_, client) in seen.into_iter().take(MAX_CLIENTS / 10) {
+                buckets.remove(&client);
+            }
+        }
+        let (left, counted) = buckets.entry(client.to_string()).or_insert((capacity, now));
+        *left = (*left + now.duration_since(*counted).as_secs_f64() * per_second).min(capacity);
+        *counted = now;
+        if *left >= 1.0 {
+            *left -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - *left) / per_second))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_known_keys_are_allowed() {
+        let keys = ApiKeys::new(&["s3cret".to_string()]);
+        assert!(keys.allows("s3cret"));
+        assert!(!keys.allows("s3cre"));
+        assert!(!keys.is_empty());
+        assert!(ApiKeys::new(&[]).is_empty());
+    }
+
+    #[test]
+    fn buckets_refill_at_the_rate() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.take("a", start).is_ok());
+        assert!(limiter.take("a", start).is_ok());
+        let wait = limiter.take("a", start).unwrap_err();
+        assert_eq!(wait.as_secs(), 30, "a request every 30 seconds at 2 a minute");
+        assert!(limiter.take("b", start).is_ok(), "clients have buckets of their own");
+        assert!(limiter.take("a", start + Duration::from_secs(29)).is_err());
+        assert!(limiter.take("a", start + Duration::from_secs(31)).is_ok());
+    }
+
+    #[test]
+    fn clients_seen_least_recently_are_forgotten() {
+        let limiter = RateLimiter::new(1);
+        let start = Instant::now();
+        for client in 0..MAX_CLIENTS {
+            assert!(limiter.take(&client.to_string(), start + Duration::from_millis(client as u64)).is_ok());
+        }
+        let now = start + Duration::from_secs(20);
+        assert!(limiter.take("new", now).is_ok());
+        assert!(limiter.buckets.lock().unwrap().len() < MAX_CLIENTS);
+        assert!(limiter.take("0", now).is_ok(), "the first client's empty bucket was forgotten");
+        assert!(limiter.take(&(MAX_CLIENTS - 1).to_string(), now).is_err(), "the last client's is kept");
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{MatchHit, MatchRequest};
+use serde::Serialize;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+// the OpenAPI document of the HTTP API, served at /openapi.json for generating clients
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "pepmatch", description = "Searches of peptides against a preprocessed proteome"),
+    paths(super::health, super::info, super::match_peptides, super::reload),
+    components(schemas(MatchRequest, MatchHit, Health, Info, ErrorBody)),
+    modifiers(&ApiKey)
+)]
+pub(super) struct ApiDoc;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct Health {
+    pub status: String,
+}
+
+// k of the index served and its proteomes
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct Info {
+    pub k: usize,
+    pub proteomes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct ErrorBody {
+    pub error: String,
+}
+
+// the API key of servers started with keys, as a bearer token
+struct ApiKey;
+
+impl Modify for ApiKey {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let scheme = HttpBuilder::new().scheme(HttpAuthScheme::Bearer).description(Some("An API key")).build();
+        openapi.components.get_or_insert_with(Default::default).add_security_scheme("api_key", SecurityScheme::Http(scheme));
+    }
+}
+
+pub(super) fn document() -> String {
+    let mut document = ApiDoc::openapi();
+    // pepmatch-rs has no license to name
+    document.info.license = None;
+    document.to_json().expect("the OpenAPI document is serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_document_has_every_endpoint_and_schema() {
+        let document: serde_json::Value = serde_json::from_str(&document()).unwrap();
+        for path in ["/health", "/info", "/match", "/reload"] {
+            assert!(document["paths"][path].is_object(), "{} is documented", path);
+        }
+        let schemas = &document["components"]["schemas"];
+        assert!(schemas["MatchRequest"]["properties"]["max_mismatches"].is_object());
+        assert!(schemas["MatchHit"]["properties"]["Query Sequence"].is_object());
+        let body = &document["paths"]["/match"]["post"]["requestBody"]["content"]["application/json"];
+        assert_eq!(body["schema"]["$ref"], "#/components/schemas/MatchRequest");
+    }
+}
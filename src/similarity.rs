@@ -0,0 +1,113 @@
+use crate::error::{PepMatchError, Result};
+
+// BLOSUM62 in the NCBI matrix format
+const BLOSUM62: &str = "
+   A  R  N  D  C  Q  E  G  H  I  L  K  M  F  P  S  T  W  Y  V  B  Z  X  *
+A  4 -1 -2 -2  0 -1 -1  0 -2 -1 -1 -1 -1 -2 -1  1  0 -3 -2  0 -2 -1  0 -4
+R -1  5  0 -2 -3  1  0 -2  0 -3 -2  2 -1 -3 -2 -1 -1 -3 -2 -3 -1  0 -1 -4
+N -2  0  6  1 -3  0  0  0  1 -3 -3  0 -2 -3 -2  1  0 -4 -2 -3  3  0 -1 -4
+D -2 -2  1  6 -3  0  2 -1 -1 -3 -4 -1 -3 -3 -1  0 -1 -4 -3 -3  4  1 -1 -4
+C  0 -3 -3 -3  9 -3 -4 -3 -3 -1 -1 -3 -1 -2 -3 -1 -1 -2 -2 -1 -3 -3 -2 -4
+Q -1  1  0  0 -3  5  2 -2  0 -3 -2  1  0 -3 -1  0 -1 -2 -1 -2  0  3 -1 -4
+E -1  0  0  2 -4  2  5 -2  0 -3 -3  1 -2 -3 -1  0 -1 -3 -2 -2  1  4 -1 -4
+G  0 -2  0 -1 -3 -2 -2  6 -2 -4 -4 -2 -3 -3 -2  0 -2 -2 -3 -3 -1 -2 -1 -4
+H -2  0  1 -1 -3  0  0 -2  8 -3 -3 -1 -2 -1 -2 -1 -2 -2  2 -3  0  0 -1 -4
+I -1 -3 -3 -3 -1 -3 -3 -4 -3  4  2 -3  1  0 -3 -2 -1 -3 -1  3 -3 -3 -1 -4
+L -1 -2 -3 -4 -1 -2 -3 -4 -3  2  4 -2  2  0 -3 -2 -1 -2 -1  1 -4 -3 -1 -4
+K -1  2  0 -1 -3  1  1 -2 -1 -3 -2  5 -1 -3 -1  0 -1 -3 -2 -2  0  1 -1 -4
+M -1 -1 -2 -3 -1  0 -2 -3 -2  1  2 -1  5  0 -2 -1 -1 -1 -1  1 -3 -1 -1 -4
+F -2 -3 -3 -3 -2 -3 -3 -3 -1  0  0 -3  0  6 -4 -2 -2  1  3 -1 -3 -3 -1 -4
+P -1 -2 -2 -1 -3 -1 -1 -2 -2 -3 -3 -1 -2 -4  7 -1 -1 -4 -3 -2 -2 -1 -2 -4
+S  1 -1  1  0 -1  0  0  0 -1 -2 -2  0 -1 -2 -1  4  1 -3 -2 -2  0  0  0 -4
+T  0 -1  0 -1 -1 -1 -1 -2 -2 -1 -1 -1 -1 -2 -1  1  5 -2 -2  0 -1 -1  0 -4
+W -3 -3 -4 -4 -2 -2 -3 -2 -2 -3 -2 -3 -1  1 -4 -3 -2 11  2 -3 -4 -3 -2 -4
+Y -2 -2 -2 -3 -2 -1 -2 -3  2 -1 -1 -2 -1  3 -3 -2 -2  2  7 -1 -3 -2 -1 -4
+V  0 -3 -3 -3 -1 -2 -2 -3 -3  3  1 -2  1 -1 -2 -2  0 -3 -1  4 -3 -2 -1 -4
+B -2 -1  3  4 -3  0  1 -1  0 -3 -4  0 -3 -3 -2  0 -1 -4 -3 -3  4  1 -1 -4
+Z -1  0  0  1 -3  3  4 -2  0 -3 -3  1 -1 -3 -1  0 -1 -3 -2 -2  1  4 -1 -4
+X  0 -1 -1 -1 -2 -1 -1 -1 -1 -1 -1 -1 -1 -1 -2  0  0 -2 -1 -1 -1 -1 -1 -4
+* -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4  1
+";
+
+// substitution scores between residues, residues missing from the matrix
+// score as low as its lowest score
+#[derive(Debug, Clone)]
+pub struct ScoringMatrix {
+    scores: Vec<i32>, // 128 x 128 by ASCII residue
+}
+
+impl ScoringMatrix {
+    pub fn blosum62() -> ScoringMatrix {
+        ScoringMatrix::parse(BLOSUM62).expect("BLOSUM62 is a valid matrix")
+    }
+
+    // read a matrix in the NCBI format, like the BLOSUM and PAM files BLAST uses,
+    // or blosum62 for the built in one
+    pub fn from_file(path: &str) -> Result<ScoringMatrix> {
+        if path.eq_ignore_ascii_case("blosum62") {
+            return Ok(ScoringMatrix::blosum62());
+        }
+        let contents = std::fs::read_to_string(path).map_err(PepMatchError::io(path))?;
+        ScoringMatrix::parse(&contents).map_err(|e| PepMatchError::invalid(format!("{}: {}", path, e)))
+    }
+
+    // a header row of residues then a row of scores per residue, # starts a comment
+    fn parse(contents: &str) -> std::result::Result<ScoringMatrix, String> {
+        let mut lines = contents.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let columns = lines.next()
+            .ok_or("no header row of residues")?
+            .split_whitespace()
+            .map(residue)
+            .collect::<std::result::Result<Vec<u8>, String>>()?;
+
+        let mut rows = Vec::new();
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let row = residue(fields.next().unwrap_or_default())?;
+            let scores = fields
+                .map(|score| score.parse::<i32>().map_err(|_| format!("invalid score for {}: {}", row as char, score)))
+                .collect::<std::result::Result<Vec<i32>, String>>()?;
+            if scores.len() != columns.len() {
+                return Err(format!("row {} has {} scores for {} residues", row as char, scores.len(), columns.len()));
+            }
+            rows.push((row, scores));
+        }
+
+        let lowest = rows.iter().flat_map(|(_, scores)| scores.iter().copied()).min().ok_or("no rows of scores")?;
+        let mut scores = vec![lowest; 128 * 128];
+        for (row, row_scores) in rows {
+            for (&column, score) in columns.iter().zip(row_scores) {
+                scores[row as usize * 128 + column as usize] = score;
+            }
+        }
+        Ok(ScoringMatrix { scores })
+    }
+
+    // the scores as a string, the same for the same matrix
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn key(&self) -> String {
+        self.scores.iter().map(i32::to_string).collect::<Vec<_>>().join(",")
+    }
+
+    pub fn score(&self, a: u8, b: u8) -> i32 {
+        self.scores[(a & 0x7f) as usize * 128 + (b & 0x7f) as usize]
+    }
+
+    // total score of a peptide aligned without gaps to a sequence of the same length,
+    // residues counted as the same (like I and L when equivalent) score as identical
+    pub fn score_alignment(&self, peptide: &str, sequence: &str, same: impl Fn(u8, u8) -> bool) -> i32 {
+        peptide.bytes()
+            .zip(sequence.bytes())
+            .map(|(a, b)| if same(a, b) { self.score(a, a) } else { self.score(a, b) })
+            .sum()
+    }
+}
+
+fn residue(field: &str) -> std::result::Result<u8, String> {
+    match field.as_bytes() {
+        [residue] if residue.is_ascii() => Ok(residue.to_ascii_uppercase()),
+        _ => Err(format!("not a residue: {}", field)),
+    }
+}
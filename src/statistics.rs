@@ -0,0 +1,218 @@
+use crate::error::Result;
+use crate::{index, progress, Match};
+use std::collections::HashMap;
+
+// residues a wildcard in a query stands for, like the matcher's
+const WILDCARDS: [(u8, &[u8]); 3] = [(b'B', b"DN"), (b'Z', b"EQ"), (b'J', b"IL")];
+
+// the residue frequencies and protein lengths of a proteome, for how many times a peptide
+// would be found in a random proteome of the same size and composition, generated one
+// residue at a time at those frequencies
+#[derive(Debug, Clone, Default)]
+pub struct Composition {
+    counts: [u64; 26], // residues A to Z, anything else isn't counted
+    lengths: Vec<usize>, // of the proteins, ascending
+    longer_sums: Vec<u64>, // for each protein the sum of the lengths of it and the proteins longer than it
+}
+
+impl Composition {
+    pub fn from_sequences<'s>(sequences: impl IntoIterator<Item = &'s str>) -> Composition {
+        let mut composition = Composition::default();
+        for sequence in sequences {
+            for residue in sequence.bytes().filter(u8::is_ascii_uppercase) {
+                composition.counts[(residue - b'A') as usize] += 1;
+            }
+            composition.lengths.push(sequence.len());
+        }
+        composition.lengths.sort_unstable();
+        let mut sum = 0;
+        composition.longer_sums = composition.lengths.iter().rev().map(|length| { sum += *length as u64; sum }).collect();
+        composition.longer_sums.reverse();
+        composition
+    }
+
+    pub fn residues(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn proteins(&self) -> usize {
+        self.lengths.len()
+    }
+
+    // the length of the protein a fraction of the way through the proteins from the shortest,
+    // 0.5 for the median, 0 without proteins
+    pub fn length_quantile(&self, quantile: f64) -> usize {
+        let last = self.lengths.len().saturating_sub(1);
+        self.lengths.get((quantile.clamp(0.0, 1.0) * last as f64).round() as usize).copied().unwrap_or_default()
+    }
+
+    pub fn mean_length(&self) -> f64 {
+        if self.lengths.is_empty() { 0.0 } else { self.longer_sums[0] as f64 / self.lengths.len() as f64 }
+    }
+
+    // the residues with how many of each there are, in alphabetical order, leaving out
+    // those that aren't in the proteome
+    pub fn residue_counts(&self) -> impl Iterator<Item = (char, u64)> + '_ {
+        (b'A'..=b'Z').map(|residue| (residue as char, self.counts[(residue - b'A') as usize])).filter(|(_, count)| *count > 0)
+    }
+
+    // the frequency of a residue in the proteome, or of any the wildcards X, B, Z and J stand for
+    pub fn frequency(&self, residue: u8) -> f64 {
+        let residues = self.residues();
+        if residues == 0 {
+            return 0.0;
+        }
+        let count = |residue: u8| if residue.is_ascii_uppercase() { self.counts[(residue - b'A') as usize] } else { 0 };
+        let count = match residue {
+            b'X' => residues,
+            _ => match WILDCARDS.iter().find(|(wildcard, _)| *wildcard == residue) {
+                Some((_, residues)) => residues.iter().map(|residue| count(*residue)).sum(),
+                None => count(residue),
+            },
+        };
+        count as f64 / residues as f64
+    }
+
+    // the number of places a peptide of a length could start in the proteins
+    pub fn windows(&self, length: usize) -> u64 {
+        if length == 0 {
+            return 0;
+        }
+        // the proteins at least as long as the peptide each have length - peptide length + 1
+        let first = self.lengths.partition_point(|protein| *protein < length);
+        let longer = (self.lengths.len() - first) as u64;
+        self.longer_sums.get(first).map_or(0, |sum| sum - longer * (length as u64 - 1))
+    }
+
+    // the chance of a random window matching a peptide with up to max_mismatches mismatches
+    pub fn match_probability(&self, peptide: &str, max_mismatches: usize) -> f64 {
+        // the chances of each number of mismatches over the residues so far
+        let mut mismatches = vec![0.0; max_mismatches + 1];
+        mismatches[0] = 1.0;
+        for residue in peptide.bytes() {
+            let matches = self.frequency(residue);
+            for n in (0..=max_mismatches).rev() {
+                let mismatched = if n > 0 { mismatches[n - 1] * (1.0 - matches) } else { 0.0 };
+                mismatches[n] = mismatches[n] * matches + mismatched;
+            }
+        }
+        mismatches.iter().sum::<f64>().min(1.0)
+    }
+
+    // how many hits with up to max_mismatches mismatches a peptide would have by chance, its E-value
+    pub fn expected_hits(&self, peptide: &str, max_mismatches: usize) -> f64 {
+        self.windows(peptide.len()) as f64 * self.match_probability(peptide, max_mismatches)
+    }
+
+    // give each hit the expected number of random hits of its query, searched as query, with up
+    // to as many mismatches as the hit has
+    pub fn annotate(&self, hits: &mut [Match], query: impl Fn(&str) -> String) {
+        for hit in hits.iter_mut().filter(|hit| !hit.discontinuous) {
+            hit.e_value = self.expected_hits(&query(&hit.peptide), hit.mismatches);
+        }
+    }
+}
+
+// the composition and k-mers of a preprocessed proteome
+#[derive(Debug, Clone)]
+pub struct ProteomeStats {
+    pub composition: Composition,
+    pub k: usize,
+    pub kmers: usize,        // distinct k-mers
+    pub kmer_entropy: f64,   // Shannon entropy of the k-mer occurrences in bits, log2 of the number of k-mers if all were as common
+    pub repetitive: Vec<(String, usize)>, // the most frequent k-mers with their occurrences, most first
+}
+
+// count the residues, protein lengths and k-mers of a preprocessed DB or binary index, with the
+// k it was built with unless given one, keeping the top most frequent k-mers
+pub fn proteome_stats(db_path: &str, k: Option<usize>, top: usize, show_progress: bool) -> Result<ProteomeStats> {
+    let index = index::open_db(db_path)?;
+    let k = k.unwrap_or_else(|| index.k());
+    let proteomes = index.proteomes()?;
+    let total = proteomes.iter().map(|(_, first, last)| last + 1 - first).sum();
+
+    let bar = progress::proteins(show_progress, total);
+    let mut sequences = Vec::with_capacity(total);
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+    for protein_number in proteomes.iter().flat_map(|(_, first, last)| *first..=*last) {
+        let sequence = index.protein_sequence(protein_number)?;
+        if k > 0 {
+            for kmer in sequence.as_bytes().windows(k) {
+                *counts.entry(kmer.to_vec()).or_default() += 1;
+            }
+        }
+        sequences.push(sequence);
+        bar.inc(1);
+    }
+    bar.finish_and_clear();
+
+    let kmer_entropy = entropy(counts.values().copied());
+    let mut repetitive: Vec<(String, usize)> =
+        counts.iter().map(|(kmer, count)| (String::from_utf8_lossy(kmer).into_owned(), *count)).collect();
+    // most frequent first, ties alphabetical so the top is the same every time
+    repetitive.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    repetitive.truncate(top);
+    Ok(ProteomeStats {
+        composition: Composition::from_sequences(sequences.iter().map(String::as_str)),
+        k,
+        kmers: counts.len(),
+        kmer_entropy,
+        repetitive,
+    })
+}
+
+// the Shannon entropy in bits of things occurring as many times as the counts
+fn entropy(counts: impl Iterator<Item = usize> + Clone) -> f64 {
+    let total: usize = counts.clone().sum();
+    let entropy: f64 = counts.map(|count| count as f64 / total as f64).map(|frequency| -frequency * frequency.log2()).sum();
+    entropy.max(0.0)
+}
+
+// the chance of at least one random hit when E-value of them are expected
+pub fn p_value(e_value: f64) -> f64 {
+    -(-e_value).exp_m1()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_are_counted_in_proteins_long_enough() {
+        let composition = Composition::from_sequences(["ACDE", "AC", "ACDEFG"]);
+        assert_eq!(composition.windows(1), 12);
+        assert_eq!(composition.windows(2), 9);
+        assert_eq!(composition.windows(4), 4);
+        assert_eq!(composition.windows(6), 1);
+        assert_eq!(composition.windows(7), 0);
+        assert_eq!(composition.residues(), 12);
+        assert_eq!(composition.residue_counts().next(), Some(('A', 3)));
+        assert_eq!(composition.length_quantile(0.0), 2);
+        assert_eq!(composition.length_quantile(0.5), 4);
+        assert_eq!(composition.length_quantile(1.0), 6);
+        assert_eq!(composition.mean_length(), 4.0);
+        assert_eq!(Composition::default().length_quantile(0.5), 0);
+    }
+
+    #[test]
+    fn entropy_is_in_bits() {
+        assert_eq!(entropy([5, 5, 5, 5].into_iter()), 2.0);
+        assert_eq!(entropy([8].into_iter()), 0.0);
+        assert!((entropy([3, 1].into_iter()) - 0.811278).abs() < 1e-6);
+        assert_eq!(entropy(std::iter::empty()), 0.0);
+    }
+
+    #[test]
+    fn random_hits_follow_the_residue_frequencies() {
+        let composition = Composition::from_sequences(["AAAC", "AAAC"]);
+        assert_eq!(composition.frequency(b'A'), 0.75);
+        assert_eq!(composition.frequency(b'X'), 1.0);
+        assert_eq!(composition.frequency(b'W'), 0.0);
+        assert!((composition.match_probability("AC", 0) - 0.1875).abs() < 1e-12);
+        // AC, or one of them mismatched: 0.1875 + 0.75 * 0.75 + 0.25 * 0.25
+        assert!((composition.match_probability("AC", 1) - 0.8125).abs() < 1e-12);
+        assert_eq!(composition.match_probability("AC", 2), 1.0);
+        assert!((composition.expected_hits("AC", 0) - 6.0 * 0.1875).abs() < 1e-12);
+        assert!((p_value(composition.expected_hits("AC", 0)) - (1.0 - (-1.125f64).exp())).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::error::{PepMatchError, Result};
+use crate::Match;
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::Duration;
+
+// what a search found, added to a batch of peptides at a time and reported when it's done
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub peptides: usize,
+    pub matched: usize, // peptides with at least one hit
+    pub exact_hits: usize,
+    pub mismatched_hits: usize,
+    pub region_hits: usize, // best regions of peptides without full-length hits, which count as unmatched
+    pub unmatched: Vec<String>, // peptides without hits, each once in the order they were searched
+    seen: HashSet<String>,
+}
+
+impl Summary {
+    // add a batch of searched peptides and their hits
+    pub fn add(&mut self, peptides: &[String], hits: &[Match]) {
+        let (regions, hits): (Vec<&Match>, Vec<&Match>) = hits.iter().partition(|hit| hit.query_start > 0);
+        let with_hits: HashSet<&str> = hits.iter().map(|hit| hit.peptide.as_str()).collect();
+        self.peptides += peptides.len();
+        for peptide in peptides {
+            if with_hits.contains(peptide.as_str()) {
+                self.matched += 1;
+            } else if self.seen.insert(peptide.clone()) {
+                self.unmatched.push(peptide.clone());
+            }
+        }
+        let exact = hits.iter().filter(|hit| hit.mismatches == 0).count();
+        self.exact_hits += exact;
+        self.mismatched_hits += hits.len() - exact;
+        self.region_hits += regions.len();
+    }
+
+    // the summary's lines, with how long the search took and how fast it went
+    pub fn report(&self, elapsed: Duration) -> Vec<String> {
+        let seconds = elapsed.as_secs_f64();
+        let rate = if seconds > 0.0 { self.peptides as f64 / seconds } else { 0.0 };
+        let regions = if self.region_hits > 0 { format!(", {} best regions", self.region_hits) } else { String::new() };
+        vec![
+            format!("Peptides searched: {}", self.peptides),
+            format!("Peptides with hits: {} ({})", self.matched, percent(self.matched, self.peptides)),
+            format!("Peptides without hits: {}", self.peptides - self.matched),
+            format!("Hits: {} exact, {} with mismatches{}", self.exact_hits, self.mismatched_hits, regions),
+            format!("Elapsed: {:.2}s, {:.0} peptides/s", seconds, rate),
+        ]
+    }
+
+    // the peptides without hits a line each, to search them again with other settings
+    pub fn write_unmatched(&self, path: &str) -> Result<()> {
+        let file = std::fs::File::create(path).map_err(PepMatchError::io(path))?;
+        let mut out = std::io::BufWriter::new(file);
+        for peptide in &self.unmatched {
+            writeln!(out, "{}", peptide).map_err(PepMatchError::io(path))?;
+        }
+        out.flush().map_err(PepMatchError::io(path))
+    }
+}
+
+fn percent(n: usize, total: usize) -> String {
+    if total == 0 {
+        return "0%".to_string();
+    }
+    format!("{:.1}%", 100.0 * n as f64 / total as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Matcher, Preprocessor};
+
+    const FASTA: &str = ">sp|P00001|ONE_HUMAN First protein OS=Homo sapiens OX=9606 GN=ONE PE=1 SV=2
+MKLVSTAAQWDEQ
+>sp|P00002|TWO_HUMAN Second protein OS=Homo sapiens OX=9606 GN=TWO PE=3 SV=1
+PESTAVQWLLK
+";
+
+    #[test]
+    fn batches_add_up() {
+        let matcher = Matcher::in_memory(&Preprocessor::from_fasta("test", FASTA, 3)).unwrap().max_mismatches(1);
+        let mut summary = Summary::default();
+        for batch in [vec!["STAAQW", "WWWWWW"], vec!["WWWWWW", "QWLLK", "YYYYYY"]] {
+            let peptides: Vec<String> = batch.iter().map(|peptide| peptide.to_string()).collect();
+            summary.add(&peptides, &matcher.match_peptides(&peptides).unwrap());
+        }
+        assert_eq!((summary.peptides, summary.matched), (5, 2));
+        assert_eq!((summary.exact_hits, summary.mismatched_hits), (2, 1));
+        assert_eq!(summary.unmatched, vec!["WWWWWW", "YYYYYY"]);
+
+        let report = summary.report(Duration::from_millis(2500));
+        assert_eq!(report[1], "Peptides with hits: 2 (40.0%)");
+        assert_eq!(report[2], "Peptides without hits: 3");
+        assert_eq!(report[4], "Elapsed: 2.50s, 2 peptides/s");
+        assert_eq!(Summary::default().report(Duration::ZERO)[1], "Peptides with hits: 0 (0%)");
+    }
+}
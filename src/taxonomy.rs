@@ -0,0 +1,126 @@
+use crate::error::{PepMatchError, Result};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+
+// restricts hits to proteins of some taxa, by taxon ID or a substring of the species
+// name, a protein passes if it matches any of them and an empty filter passes everything
+#[derive(Debug, Clone, Default)]
+pub struct TaxonFilter {
+    taxon_ids: HashSet<String>,
+    species: Vec<String>, // lowercase
+}
+
+impl TaxonFilter {
+    pub fn new() -> TaxonFilter {
+        TaxonFilter::default()
+    }
+
+    pub fn taxon_ids(mut self, taxon_ids: &[String]) -> TaxonFilter {
+        self.taxon_ids.extend(taxon_ids.iter().map(|id| id.trim().to_string()));
+        self
+    }
+
+    // case-insensitive substrings of species names, e.g. "sapiens"
+    pub fn species(mut self, species: &[String]) -> TaxonFilter {
+        self.species.extend(species.iter().map(|name| name.to_lowercase()));
+        self
+    }
+
+    // add every descendant of the filter's taxon IDs going by the nodes.dmp of an NCBI
+    // taxonomy dump, given as the file itself or the directory it was extracted to,
+    // e.g. 9604 (Hominidae) then also matches 9606 (Homo sapiens)
+    pub fn expand_descendants(mut self, taxonomy: &str) -> Result<TaxonFilter> {
+        let children = read_children(taxonomy)?;
+
+        let mut pending: Vec<String> = self.taxon_ids.iter().cloned().collect();
+        while let Some(taxon_id) = pending.pop() {
+            for child in children.get(&taxon_id).into_iter().flatten() {
+                if self.taxon_ids.insert(child.clone()) {
+                    pending.push(child.clone());
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.taxon_ids.is_empty() && self.species.is_empty()
+    }
+
+    // the taxon IDs and species in a fixed order, the same for the same filter
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn key(&self) -> String {
+        let mut taxon_ids: Vec<&String> = self.taxon_ids.iter().collect();
+        taxon_ids.sort();
+        format!("{:?} {:?}", taxon_ids, self.species)
+    }
+
+    pub fn allows(&self, taxon_id: &str, species: &str) -> bool {
+        if self.is_empty() || self.taxon_ids.contains(taxon_id) {
+            return true;
+        }
+        let species = species.to_lowercase();
+        self.species.iter().any(|name| species.contains(name.as_str()))
+    }
+}
+
+// parent taxon ID --> child taxon IDs from the "tax_id | parent tax_id | rank | ..." rows of nodes.dmp
+fn read_children(taxonomy: &str) -> Result<HashMap<String, Vec<String>>> {
+    let path = match std::path::Path::new(taxonomy).is_dir() {
+        true => std::path::Path::new(taxonomy).join("nodes.dmp").to_string_lossy().to_string(),
+        false => taxonomy.to_string(),
+    };
+    let file = std::fs::File::open(&path).map_err(PepMatchError::io(&path))?;
+
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(PepMatchError::io(&path))?;
+        let mut fields = line.split('|').map(str::trim);
+        match (fields.next(), fields.next()) {
+            (Some(taxon_id), Some(parent)) if !taxon_id.is_empty() => {
+                // the root is its own parent
+                if taxon_id != parent {
+                    children.entry(parent.to_string()).or_default().push(taxon_id.to_string());
+                }
+            }
+            _ => {
+                return Err(PepMatchError::invalid(format!("{}: line {}: expected tax_id | parent tax_id", path, i + 1)))
+            }
+        }
+    }
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taxa_and_species_pass_their_proteins() {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect::<Vec<String>>();
+        let filter = TaxonFilter::new().taxon_ids(&strings(&["10090"])).species(&strings(&["Sapiens"]));
+        assert!(filter.allows("10090", "Mus musculus"));
+        assert!(filter.allows("9606", "Homo sapiens"));
+        assert!(!filter.allows("9598", "Pan troglodytes"));
+        assert!(TaxonFilter::new().allows("9598", "Pan troglodytes"));
+    }
+
+    #[test]
+    fn descendants_of_taxa_pass_too() {
+        let nodes = std::env::temp_dir().join(format!("pepmatch-nodes-{}.dmp", std::process::id()));
+        let nodes = nodes.to_str().unwrap();
+        // root, Hominidae, Homo, Homo sapiens, and Mus musculus under the root
+        std::fs::write(
+            nodes,
+            "1\t|\t1\t|\tno rank\t|\n9604\t|\t1\t|\tfamily\t|\n9605\t|\t9604\t|\tgenus\t|\n\
+             9606\t|\t9605\t|\tspecies\t|\n10090\t|\t1\t|\tspecies\t|\n",
+        )
+        .unwrap();
+        let filter = TaxonFilter::new().taxon_ids(&["9604".to_string()]).expand_descendants(nodes);
+        std::fs::remove_file(nodes).unwrap();
+        let filter = filter.unwrap();
+        assert!(filter.allows("9606", "Homo sapiens"));
+        assert!(filter.allows("9605", ""));
+        assert!(!filter.allows("10090", "Mus musculus"));
+    }
+}
@@ -0,0 +1,85 @@
+// six-frame translation of nucleotide sequences with the standard genetic code
+
+// amino acids of the 64 codons with bases in TCAG order, e.g. TTT, TTC, TTA, TTG, TCT, ...
+const CODONS: &[u8; 64] = b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+// the protein translations of the three forward frames (+1, +2, +3) and the three of the
+// reverse complement (-1, -2, -3), stop codons are * and codons with other bases than
+// ACGT/U are X, each frame n starts at base |n| of its strand
+pub(crate) fn six_frames(sequence: &[u8]) -> Vec<(i8, String)> {
+    let reverse = reverse_complement(sequence);
+    let mut frames = Vec::with_capacity(6);
+    for (strand, bases) in [(1, sequence), (-1, &reverse[..])] {
+        for offset in 0..3 {
+            frames.push((strand * (offset as i8 + 1), translate(bases.get(offset..).unwrap_or_default())));
+        }
+    }
+    frames
+}
+
+fn translate(bases: &[u8]) -> String {
+    bases.chunks_exact(3)
+        .map(|codon| {
+            codon.iter()
+                .try_fold(0, |index, base| base_index(*base).map(|base| index * 4 + base))
+                .map_or('X', |index| CODONS[index] as char)
+        })
+        .collect()
+}
+
+fn base_index(base: u8) -> Option<usize> {
+    match base.to_ascii_uppercase() {
+        b'T' | b'U' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    }
+}
+
+fn reverse_complement(sequence: &[u8]) -> Vec<u8> {
+    sequence.iter()
+        .rev()
+        .map(|base| match base.to_ascii_uppercase() {
+            b'A' => b'T',
+            b'T' | b'U' => b'A',
+            b'C' => b'G',
+            b'G' => b'C',
+            _ => b'N',
+        })
+        .collect()
+}
+
+// 1-based (start, end) of the bases coding a stretch of a frame's translation, from
+// its 0-based position and length in residues, on the forward strand either way
+pub(crate) fn genomic_range(frame: i8, source_length: usize, position: usize, len: usize) -> (usize, usize) {
+    let offset = frame.unsigned_abs() as usize - 1;
+    let start = offset + 3 * position; // 0-based on the frame's strand
+    let end = start + 3 * len;
+    if frame > 0 {
+        (start + 1, end)
+    } else {
+        (source_length - end + 1, source_length - start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_frames() {
+        assert_eq!(genomic_range(1, 30, 0, 3), (1, 9));
+        assert_eq!(genomic_range(2, 30, 0, 3), (2, 10));
+        assert_eq!(genomic_range(3, 30, 2, 1), (9, 11));
+    }
+
+    #[test]
+    fn reverse_frames_count_from_the_end() {
+        // frame -1 starts at the last base, so its first codon is bases 28-30
+        assert_eq!(genomic_range(-1, 30, 0, 1), (28, 30));
+        assert_eq!(genomic_range(-1, 30, 0, 3), (22, 30));
+        assert_eq!(genomic_range(-2, 30, 0, 3), (21, 29));
+        assert_eq!(genomic_range(-3, 30, 1, 2), (20, 25));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::discontinuous::DiscontinuousEpitope;
+use regex::Regex;
+use std::sync::LazyLock;
+
+// residues a peptide can be made of: the standard 20, selenocysteine (U) and pyrrolysine (O),
+// which UniProt writes into the sequences of the few proteins that have them, and the
+// wildcards X, B, Z and J
+const RESIDUES: &[u8] = b"ACDEFGHIKLMNPQRSTVWYUOXBZJ";
+
+// carbamidomethylated cysteines as search engines write them, with the mass shift, the
+// UniMod accession or the name in brackets or parentheses, e.g. C[+57.021], C(UniMod:4), C[CAM]
+static CARBAMIDOMETHYL: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)C(\[(\+57(\.\d*)?|unimod:4|carbamidomethyl|cam)\]|\((\+57(\.\d*)?|unimod:4|carbamidomethyl|cam)\))")
+        .expect("the pattern is valid")
+});
+
+// what checking a peptide found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeptideStatus {
+    Valid,
+    Normalized,      // searched as the normalized peptide
+    Invalid(String), // not searched, with why
+}
+
+impl PeptideStatus {
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, PeptideStatus::Invalid(_))
+    }
+}
+
+// a peptide as it was given, as it's searched and what was wrong with it
+#[derive(Debug, Clone)]
+pub struct PeptideCheck {
+    pub input: String,
+    pub peptide: String,
+    pub status: PeptideStatus,
+}
+
+// normalize a peptide into the residues it's searched as, uppercased with whitespace, gaps (-)
+// and stops (*) taken out, it's invalid if that leaves anything that isn't a residue or
+// nothing at all, discontinuous epitopes like A12, K15 are parsed later and pass as they are
+pub fn check_peptide(input: &str) -> PeptideCheck {
+    if DiscontinuousEpitope::is_discontinuous(input) {
+        return PeptideCheck { input: input.to_string(), peptide: input.trim().to_string(), status: PeptideStatus::Valid };
+    }
+    let peptide: String = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-' && *c != '*')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    let mut invalid: Vec<char> = Vec::new();
+    for c in peptide.chars().filter(|c| !c.is_ascii() || !RESIDUES.contains(&(*c as u8))) {
+        if !invalid.contains(&c) {
+            invalid.push(c);
+        }
+    }
+    let status = if peptide.is_empty() {
+        PeptideStatus::Invalid("no residues".to_string())
+    } else if !invalid.is_empty() {
+        let invalid: Vec<String> = invalid.iter().map(|c| c.to_string()).collect();
+        PeptideStatus::Invalid(format!("not amino acids: {}", invalid.join(" ")))
+    } else if peptide != input {
+        PeptideStatus::Normalized
+    } else {
+        PeptideStatus::Valid
+    };
+    PeptideCheck { input: input.to_string(), peptide, status }
+}
+
+pub fn check_peptides(peptides: &[String]) -> Vec<PeptideCheck> {
+    peptides.iter().map(|peptide| check_peptide(peptide)).collect()
+}
+
+// check peptides like check_peptides, reading carbamidomethylated cysteines as plain C
+pub fn check_carbamidomethylated_peptides(peptides: &[String]) -> Vec<PeptideCheck> {
+    peptides
+        .iter()
+        .map(|input| {
+            let mut check = check_peptide(&CARBAMIDOMETHYL.replace_all(input, "C"));
+            if check.status == PeptideStatus::Valid && check.input != *input {
+                check.status = PeptideStatus::Normalized;
+            }
+            check.input = input.clone();
+            check
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carbamidomethylated_cysteines_are_read_as_c() {
+        let peptides: Vec<String> = ["AC[+57.021]DK", "AC(UniMod:4)DC[CAM]K", "ACDK", "AC[+16]DK"].iter().map(|p| p.to_string()).collect();
+        let checks = check_carbamidomethylated_peptides(&peptides);
+        let searched: Vec<(&str, &PeptideStatus)> = checks.iter().map(|check| (check.peptide.as_str(), &check.status)).collect();
+        assert_eq!(searched[..3], [
+            ("ACDK", &PeptideStatus::Normalized),
+            ("ACDCK", &PeptideStatus::Normalized),
+            ("ACDK", &PeptideStatus::Valid),
+        ]);
+        assert!(checks[3].status.is_invalid());
+        assert_eq!(checks[0].input, "AC[+57.021]DK");
+    }
+}
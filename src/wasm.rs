@@ -0,0 +1,41 @@
+// wasm-bindgen API for searching small proteomes client-side in a web tool, built with
+//   cargo build --target wasm32-unknown-unknown --no-default-features --features wasm
+// the proteome is given as FASTA text and indexed in memory as there's no filesystem
+use crate::error::PepMatchError;
+use crate::request;
+use crate::{Matcher, Preprocessor};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct Proteome {
+    matcher: Matcher,
+}
+
+#[wasm_bindgen]
+impl Proteome {
+    // index the proteins of a proteome's FASTA text by their k-mers
+    #[wasm_bindgen(constructor)]
+    pub fn new(name: &str, fasta: &str, k: usize, treat_i_l_equivalent: bool) -> Result<Proteome, JsError> {
+        let preprocessor = Preprocessor::from_fasta(name, fasta, k).treat_i_l_equivalent(treat_i_l_equivalent);
+        // a browser has no threads to search with
+        let matcher = Matcher::in_memory(&preprocessor).map_err(js_error)?.threads(1);
+        Ok(Proteome { matcher })
+    }
+
+    pub fn k(&self) -> usize {
+        self.matcher.k()
+    }
+
+    // search peptides with a JSON request like the body of a POST /match to pepmatch serve,
+    // returning the hits as a JSON array
+    #[wasm_bindgen(js_name = matchJson)]
+    pub fn match_json(&self, request: &str) -> Result<String, JsError> {
+        let request = serde_json::from_str(request)
+            .map_err(|e| JsError::new(&format!("the request isn't valid JSON: {}", e)))?;
+        request::match_json(&self.matcher, &request).map_err(js_error)
+    }
+}
+
+fn js_error(e: PepMatchError) -> JsError {
+    JsError::new(&e.to_string())
+}
@@ -0,0 +1,865 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+fn data(name: &str) -> String {
+    format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn pepmatch(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_pepmatch")).arg("--quiet").args(args).output().unwrap();
+    assert!(output.status.success(), "pepmatch {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pepmatch-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// the rows of a TSV search result after its header, as (query, protein ID, mismatches, start)
+fn hits(tsv: &str) -> Vec<(String, String, String, String)> {
+    let mut hits: Vec<_> = tsv
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            (fields[0].to_string(), fields[2].to_string(), fields[7].to_string(), fields[9].to_string())
+        })
+        .collect();
+    hits.sort();
+    hits
+}
+
+fn expected() -> Vec<(String, String, String, String)> {
+    let mut expected: Vec<_> = [
+        ("DEQRRPLN", "P00001", "0", "11"),
+        ("DEQRRPLN", "P00002", "0", "12"),
+        ("STAAQW", "P00001", "0", "5"),
+        ("STAAQW", "P00002", "1", "3"),
+    ]
+    .iter()
+    .map(|(a, b, c, d)| (a.to_string(), b.to_string(), c.to_string(), d.to_string()))
+    .collect();
+    expected.sort();
+    expected
+}
+
+#[test]
+fn preprocess_then_match() {
+    let dir = scratch("sqlite");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(tsv.starts_with("Query Sequence\tMatched Sequence\tProtein ID"));
+    assert_eq!(hits(&tsv), expected());
+}
+
+#[test]
+fn stats_report_composition_and_kmers() {
+    let dir = scratch("stats");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--backend", "bin"]);
+    let stats = pepmatch(&["stats", "-d", db, "--top", "3"]);
+    let stats_k2 = pepmatch(&["stats", "-d", db, "-k", "2", "--top", "1"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let value = |stats: &str, name: &str| -> Vec<String> {
+        let line = stats.lines().find(|line| line.split('\t').next() == Some(name)).unwrap_or_else(|| panic!("{}", stats));
+        line.split('\t').skip(1).map(str::to_string).collect()
+    };
+    assert_eq!(value(&stats, "proteins"), ["3"]);
+    assert_eq!(value(&stats, "residues"), ["57"]);
+    assert_eq!(value(&stats, "length_median"), ["19"]);
+    assert_eq!(value(&stats, "residue"), ["A", "3", "0.0526"]);
+    assert_eq!(value(&stats, "k"), ["3"]);
+    // DEQ, EQR, PLN, QRR, RPL and RRP are in both P00001 and P00002
+    let repetitive: Vec<&str> = stats.lines().filter(|line| line.starts_with("repetitive_kmer\t")).collect();
+    assert_eq!(repetitive, ["repetitive_kmer\tDEQ\t2", "repetitive_kmer\tEQR\t2", "repetitive_kmer\tPLN\t2"]);
+    assert_eq!(value(&stats_k2, "k"), ["2"]);
+    assert_eq!(stats_k2.lines().filter(|line| line.starts_with("repetitive_kmer\t")).count(), 1);
+}
+
+#[test]
+fn equivalence_classes_are_indexed_and_checked() {
+    let dir = scratch("equivalence-classes");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    let query = dir.join("peptides.txt");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "AQWNEQ\nPQSTAVEWLLK\n").unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--equivalence-classes", "ND;QE"]);
+    let tsv = pepmatch(&["match", "-q", query, "-d", db, "--equivalence-classes", "ND;QE"]);
+    let scanned = pepmatch(&["match", "-q", query, "-p", &data("proteome.fasta"), "--no-index", "--equivalence-classes", "ND;QE"]);
+    let refused = Command::new(env!("CARGO_BIN_EXE_pepmatch")).args(["--quiet", "match", "-q", query, "-d", db]).output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(tsv, scanned);
+    let rows: Vec<Vec<&str>> = tsv.lines().skip(1).map(|line| line.split('\t').collect()).collect();
+    let found: Vec<(&str, &str, &str)> = rows.iter().map(|row| (row[0], row[1], row[7])).collect();
+    assert_eq!(found, [("AQWNEQ", "AQWDEQ", "0"), ("PQSTAVEWLLK", "PESTAVQWLLK", "0")]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("--equivalence-classes"));
+}
+
+#[test]
+fn binary_index_matches_like_sqlite() {
+    let dir = scratch("bin");
+    let db = dir.join("proteome.bin");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--backend", "bin"]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--backend", "bin"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv), expected());
+}
+
+#[test]
+fn match_straight_from_the_proteome() {
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "-k", "3", "-m", "1", "--backend", "memory"]);
+    assert_eq!(hits(&tsv), expected());
+    // without a DB or k the proteome is indexed in memory with k picked from the peptides
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "-m", "1"]);
+    assert_eq!(hits(&tsv), expected());
+}
+
+#[test]
+fn several_k_in_one_db() {
+    let dir = scratch("extra-k");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "--extra-k", "2,6", "-d", db]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1"]);
+    let pinned = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "-k", "2"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv), expected());
+    assert_eq!(hits(&pinned), expected());
+}
+
+#[test]
+fn sharded_db_matches_like_one_file() {
+    let dir = scratch("shards");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "--extra-k", "2", "-d", db, "--shards", "4"]);
+    let shards = (0..4).filter(|shard| std::path::Path::new(&format!("{}.shard-{}", db, shard)).exists()).count();
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1"]);
+    let pinned = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "-k", "2"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(shards, 4);
+    assert_eq!(hits(&tsv), expected());
+    assert_eq!(hits(&pinned), expected());
+}
+
+#[cfg(feature = "async-io")]
+#[test]
+fn async_io_pipeline_matches_like_chunks_in_turn() {
+    let dir = scratch("async-io");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db]);
+    let search = |args: &[&str]| pepmatch(&[&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1"], args].concat());
+    let chunked = search(&["--chunk-size", "1"]);
+    let pipelined = search(&["--chunk-size", "1", "--async-io"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(pipelined, chunked, "hits come out in the order of the query");
+    assert_eq!(hits(&pipelined), expected());
+}
+
+#[test]
+fn compressed_db_matches_like_one_without() {
+    let dir = scratch("zstd");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--compression", "zstd"]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1"]);
+    let summary = pepmatch(&["inspect", db]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv), expected());
+    assert!(summary.lines().any(|line| line.starts_with("locations\t") && line != "locations\t0"));
+}
+
+#[test]
+fn dry_run_counts_without_writing() {
+    let dir = scratch("dry-run");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    let report = pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--dry-run"]);
+    let written = std::path::Path::new(db).exists();
+    // identical sequences are counted once
+    let fasta = dir.join("duplicated.fasta");
+    let fasta = fasta.to_str().unwrap();
+    let proteome = std::fs::read_to_string(data("proteome.fasta")).unwrap();
+    std::fs::write(fasta, format!("{}>sp|P00003|THREE_HUMAN Third protein OS=Homo sapiens OX=9606 PE=1 SV=1\nPESTAVQWLLKDEQRRPLN\n", proteome)).unwrap();
+    let deduped = pepmatch(&["preprocess", "-p", fasta, "-k", "3", "-d", db, "--dedupe-sequences", "--dry-run"]);
+    // a dry run refuses what preprocessing would, like writing over an existing DB
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db]);
+    let refused = Command::new(env!("CARGO_BIN_EXE_pepmatch"))
+        .args(["--quiet", "preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--dry-run"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!written);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("already exists"));
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[..4], ["k\t3", "proteins\t3", "residues\t57", "locations\t51"]);
+    assert!(lines[4].starts_with("estimated_db_size\t"));
+    let lines: Vec<&str> = deduped.lines().collect();
+    assert_eq!(lines[..5], ["k\t3", "proteins\t3", "synonyms\t1", "residues\t57", "locations\t51"]);
+}
+
+#[test]
+fn dry_run_rejects_malformed_records() {
+    let dir = scratch("dry-run-malformed");
+    let fasta = dir.join("malformed.fasta");
+    let fasta = fasta.to_str().unwrap();
+    std::fs::write(
+        fasta,
+        ">sp|P00001|ONE_HUMAN First protein OS=Homo sapiens OX=9606 PE=1 SV=1\nMKLVSTAAQW\n\
+         >sp|P00002|TWO_HUMAN Second protein OS=Homo sapiens OX=9606 PE=1 SV=1\nPEST4VQW?LK\n",
+    )
+    .unwrap();
+    let db = dir.join("malformed.db");
+    let output = Command::new(env!("CARGO_BIN_EXE_pepmatch"))
+        .args(["--quiet", "preprocess", "-p", fasta, "-k", "3", "-d", db.to_str().unwrap(), "--dry-run"])
+        .output()
+        .unwrap();
+    let written = db.exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!output.status.success());
+    assert!(!written);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("malformed.fasta: record 2"), "{}", stderr);
+}
+
+#[test]
+fn export_dumps_every_table() {
+    let dir = scratch("export");
+    let plain = dir.join("plain.db");
+    let plain = plain.to_str().unwrap();
+    let packed = dir.join("packed.db");
+    let packed = packed.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", plain]);
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", packed, "--shards", "2", "--compression", "zstd"]);
+    let metadata = pepmatch(&["export", "-d", plain, "--what", "metadata"]);
+    let proteins = pepmatch(&["export", "-d", plain, "--what", "proteins", "-f", "jsonl"]);
+    let sorted = |db: &str| {
+        let mut rows: Vec<String> = pepmatch(&["export", "-d", db, "--what", "kmers"]).lines().map(str::to_string).collect();
+        rows.sort();
+        rows
+    };
+    let (kmers, packed_kmers) = (sorted(plain), sorted(packed));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let fasta = std::fs::read_to_string(data("proteome.fasta")).unwrap();
+    let records = fasta.matches('>').count();
+    assert!(metadata.starts_with("protein_number\tprotein_id\tprotein_name\t"));
+    assert_eq!(metadata.lines().count(), records + 1);
+    assert_eq!(proteins.lines().count(), records);
+    assert!(proteins.starts_with("{\"protein_number\":1,\"sequence\":\"M"));
+    let residues: usize = fasta.lines().filter(|line| !line.starts_with('>')).map(str::len).sum();
+    assert_eq!(kmers.len(), residues - 2 * records + 1, "a row per 3-mer and the header");
+    assert_eq!(kmers, packed_kmers);
+}
+
+#[test]
+fn config_file_defaults() {
+    let dir = scratch("config");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db]);
+    let config = dir.join("pepmatch.toml");
+    std::fs::write(&config, format!("db = {:?}\n\n[match]\nmax-mismatches = 1\nformat = \"csv\"\n", db)).unwrap();
+    let config = config.to_str().unwrap();
+    let search = |args: &[&str]| pepmatch(&[&["--config", config, "match", "-q", &data("peptides.txt")], args].concat());
+    let csv = search(&[]);
+    let tsv = search(&["-f", "tsv"]);
+    let exact = search(&["-f", "tsv", "-m", "0"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(csv.starts_with("Query Sequence,Matched Sequence"));
+    assert_eq!(hits(&tsv), expected());
+    assert_eq!(hits(&exact).len(), 3);
+}
+
+#[test]
+fn peptides_without_hits_are_written_out() {
+    let dir = scratch("unmatched");
+    let unmatched = dir.join("unmatched.txt");
+    let unmatched = unmatched.to_str().unwrap();
+    let (proteome, query) = (data("proteome.fasta"), data("peptides.txt"));
+    let tsv = pepmatch(&["match", "-q", &query, "-p", &proteome, "-k", "3", "--backend", "memory", "--unmatched-out", unmatched]);
+    let written = std::fs::read_to_string(unmatched).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv).len(), 3);
+    assert_eq!(written, "WWWWWW\n");
+}
+
+#[test]
+fn unmatched_rows_keep_the_query_order() {
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "--unmatched-rows"]);
+    let rows: Vec<Vec<&str>> = tsv.lines().skip(1).map(|line| line.split('\t').collect()).collect();
+    let last = rows.last().unwrap();
+
+    assert_eq!(rows.len(), 4);
+    assert_eq!(last[0], "WWWWWW");
+    assert!(last[1..].iter().all(|field| field.is_empty()));
+}
+
+#[test]
+fn met_cleavage_flags_the_mature_n_terminus() {
+    let dir = scratch("met-cleavage");
+    let query = dir.join("peptides.txt");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "MKLVSTAA\nKLVSTAAQ\nLVSTAAQW\nPESTAVQW\n").unwrap();
+    let tsv = pepmatch(&["match", "-q", query, "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "--met-cleavage"]);
+    let scanned = pepmatch(&["match", "-q", query, "-p", &data("proteome.fasta"), "--no-index", "--met-cleavage"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(tsv, scanned);
+    let flags: Vec<(&str, &str)> = tsv
+        .lines()
+        .map(|line| line.split('\t').collect::<Vec<_>>())
+        .map(|row| (row[0], row[row.len() - 1]))
+        .collect();
+    assert_eq!(flags, [
+        ("Query Sequence", "N-terminus"),
+        ("MKLVSTAA", "protein"),
+        ("KLVSTAAQ", "mature"),
+        ("LVSTAAQW", ""),
+        ("PESTAVQW", "protein"),
+    ]);
+}
+
+#[test]
+fn statistics_give_the_expected_random_hits() {
+    let args = ["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "-m", "1", "--statistics"];
+    let tsv = pepmatch(&args);
+    let chunked = pepmatch(&[&args[..], &["--chunk-size", "1"]].concat());
+
+    assert_eq!(tsv, chunked);
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][rows[0].len() - 2..], ["E-value", "P-value"]);
+    let e_value = |sequence: &str| -> f64 {
+        let row = rows.iter().find(|row| row[1] == sequence).unwrap();
+        row[row.len() - 2].parse().unwrap()
+    };
+    // 42 windows of 6 residues, each STAAQW by chance with S, T, A, Q and W at 2, 3, 3, 6 and 2 of 57 residues
+    let expected = 42.0 * (2.0 * 3.0 * 3.0 * 3.0 * 6.0 * 2.0) / 57f64.powi(6);
+    assert!((e_value("STAAQW") - expected).abs() < 1e-9, "{}", tsv);
+    assert!(e_value("STAVQW") > e_value("STAAQW"));
+}
+
+#[test]
+fn mature_features_give_mature_positions() {
+    let dir = scratch("mature-features");
+    let features = dir.join("features.tsv");
+    let features = features.to_str().unwrap();
+    std::fs::write(features, "Entry\tSignal peptide\tPropeptide\nP00001\tSIGNAL 1..3; /evidence=\"ECO:0000255\"\t\n").unwrap();
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "--mature-features", features]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][rows[0].len() - 2..], ["Mature Start", "Mature End"]);
+    for row in &rows[1..] {
+        let (start, end): (usize, usize) = (row[9].parse().unwrap(), row[10].parse().unwrap());
+        let mature = match row[2] {
+            "P00001" if start > 3 => [(start - 3).to_string(), (end - 3).to_string()],
+            "P00001" => [String::new(), String::new()],
+            _ => [start.to_string(), end.to_string()],
+        };
+        assert_eq!(row[row.len() - 2..], mature, "{:?}", row);
+    }
+}
+
+#[test]
+fn chemical_equivalence_tolerates_deamidation_and_carbamidomethylation() {
+    let dir = scratch("chemical-equivalence");
+    let query = dir.join("peptides.txt");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "STAANW\nPESTAVEWLLK\nVSTAC[+57.021]QW\n").unwrap();
+    let search = |equivalence: &str| {
+        let tsv = pepmatch(&["match", "-q", query, "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "-m", "1", equivalence]);
+        tsv.lines().skip(1).map(|line| line.split('\t').map(str::to_string).collect::<Vec<_>>()).collect::<Vec<_>>()
+    };
+    let plain = search("--invalid-peptides=skip");
+    let deamidated = search("--chemical-equivalence");
+    let modified = search("--chemical-equivalence=deamidation,carbamidomethyl");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mismatches = |rows: &[Vec<String>], peptide: &str| -> Vec<String> {
+        rows.iter().filter(|row| row[0] == peptide).map(|row| row[7].clone()).collect()
+    };
+    assert_eq!(mismatches(&plain, "STAANW"), ["1"]);
+    assert_eq!(mismatches(&deamidated, "STAANW"), ["1"]);
+    assert_eq!(mismatches(&plain, "PESTAVEWLLK"), ["1"]);
+    assert_eq!(mismatches(&deamidated, "PESTAVEWLLK"), ["0"]);
+    assert!(mismatches(&deamidated, "VSTACQW").is_empty());
+    assert_eq!(mismatches(&modified, "VSTACQW"), ["1"]);
+}
+
+#[test]
+fn join_on_reports_every_query_column() {
+    let dir = scratch("join-on");
+    let query = dir.join("epitopes.csv");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "Epitope ID,Assay,Sequence,Host\n1,T cell,STAAQW,human\n2,B cell,WWWWWW,mouse\n").unwrap();
+    let tsv = pepmatch(&["match", "-q", query, "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "--join-on", "epitope id", "--unmatched-rows"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][rows[0].len() - 3..], ["epitope id", "Assay", "Host"]);
+    assert!(rows[1..].iter().any(|row| row[0] == "STAAQW" && row[row.len() - 3..] == ["1", "T cell", "human"]));
+    assert_eq!(rows.last().unwrap()[rows[0].len() - 3..], ["2", "B cell", "mouse"]);
+}
+
+#[test]
+fn identical_sequences_are_indexed_once() {
+    let dir = scratch("dedupe");
+    let (fasta, peptides, db) = (dir.join("proteome.fasta"), dir.join("peptides.txt"), dir.join("proteome.db"));
+    let (fasta, peptides, db) = (fasta.to_str().unwrap(), peptides.to_str().unwrap(), db.to_str().unwrap());
+    std::fs::write(peptides, "STAAQW\n").unwrap();
+    std::fs::write(
+        fasta,
+        ">tr|Q00001|ONE_HUMAN Unreviewed copy OS=Homo sapiens OX=9606 GN=ONE PE=2 SV=1
+MKLVSTAAQWDEQ
+>sp|P00001|ONE_HUMAN First protein OS=Homo sapiens OX=9606 GN=ONE PE=1 SV=2
+MKLVSTAAQWDEQ
+>sp|P00002|TWO_HUMAN Second protein OS=Homo sapiens OX=9606 GN=TWO PE=3 SV=1
+PESTAVQWLLK
+",
+    )
+    .unwrap();
+    pepmatch(&["preprocess", "-p", fasta, "-k", "3", "-d", db, "--dedupe-sequences"]);
+    let proteins = |tsv: &str| -> Vec<String> { hits(tsv).into_iter().map(|(_, protein_id, _, _)| protein_id).collect() };
+    let representative = pepmatch(&["match", "-q", peptides, "-d", db]);
+    let expanded = pepmatch(&["match", "-q", peptides, "-d", db, "--expand-synonyms"]);
+    let reviewed = pepmatch(&["match", "-q", peptides, "-d", db, "--expand-synonyms", "--max-pe-level", "1"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(proteins(&representative), vec!["P00001"]);
+    assert_eq!(proteins(&expanded), vec!["P00001", "Q00001"]);
+    assert_eq!(proteins(&reviewed), vec!["P00001"]);
+}
+
+#[test]
+fn accession_files_include_and_exclude_proteins() {
+    let dir = scratch("accessions");
+    let (include, exclude) = (dir.join("include.txt"), dir.join("exclude.txt"));
+    let (include, exclude) = (include.to_str().unwrap(), exclude.to_str().unwrap());
+    std::fs::write(include, "# the second protein\nP00002\n").unwrap();
+    std::fs::write(exclude, "P00001\n").unwrap();
+    let memory = ["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory"];
+    let excluded = pepmatch(&[&memory[..], &["-m", "1", "--exclude-proteins", exclude]].concat());
+    let best = pepmatch(&[&memory[..], &["--best-match", "--exclude-proteins", exclude]].concat());
+    let included = pepmatch(&["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "--no-index", "--include-proteins", include]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let in_p00002: Vec<_> = expected().into_iter().filter(|hit| hit.1 == "P00002").collect();
+    assert_eq!(hits(&excluded), in_p00002);
+    // the best match gives way to the best one in the proteins left
+    assert_eq!(hits(&best), in_p00002);
+    assert_eq!(hits(&included), [in_p00002[0].clone()]);
+}
+
+#[test]
+fn exclude_source_drops_hits_in_the_peptides_own_proteins() {
+    let dir = scratch("exclude-source");
+    let query = dir.join("epitopes.csv");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "peptide,source\nSTAAQW,P00001\nDEQRRPLN,http://purl.uniprot.org/uniprot/P00002\nWWWWWW,\n").unwrap();
+    let memory = ["match", "-q", query, "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "--exclude-source", "source"];
+    let tsv = pepmatch(&[&memory[..], &["-m", "1"]].concat());
+    let best = pepmatch(&[&memory[..], &["--best-match"]].concat());
+    let chunked = pepmatch(&[&memory[..], &["-m", "1", "--chunk-size", "1"]].concat());
+    let scanned = pepmatch(&["match", "-q", query, "-p", &data("proteome.fasta"), "--no-index", "--exclude-source", "source"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let others: Vec<_> = expected()
+        .into_iter()
+        .filter(|hit| (hit.0.as_str(), hit.1.as_str()) != ("STAAQW", "P00001") && (hit.0.as_str(), hit.1.as_str()) != ("DEQRRPLN", "P00002"))
+        .collect();
+    assert_eq!(hits(&tsv), others);
+    assert_eq!(hits(&best), others);
+    assert_eq!(tsv, chunked);
+    assert_eq!(hits(&scanned), others.into_iter().filter(|hit| hit.2 == "0").collect::<Vec<_>>());
+}
+
+#[test]
+fn best_region_stands_in_for_peptides_without_hits() {
+    let dir = scratch("best-region");
+    let query = dir.join("peptides.txt");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "STAAQW\nYYYYQRRPLNGHYYYYYYYY\nWWWWWW\n").unwrap();
+    let tsv = pepmatch(&["match", "-q", query, "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "--best-match", "--best-region"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][rows[0].len() - 2..], ["Query Start", "Query End"]);
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[1][..2], ["STAAQW", "STAAQW"]);
+    assert_eq!(rows[1][rows[1].len() - 2..], ["", ""]);
+    // the longest stretch found is in P00001, where QRRPLN goes on to GH
+    assert_eq!(rows[2][..3], ["YYYYQRRPLNGHYYYYYYYY", "QRRPLNGH", "P00001"]);
+    assert_eq!(rows[2][rows[2].len() - 2..], ["5", "12"]);
+}
+
+#[test]
+fn digest_cuts_overlapping_peptides() {
+    let dir = scratch("digest");
+    let digest = dir.join("digest.tsv");
+    let digest = digest.to_str().unwrap();
+    pepmatch(&["digest", "-p", &data("proteome.fasta"), "--length", "8", "--overlap", "4", "-o", digest]);
+    let tsv = std::fs::read_to_string(digest).unwrap();
+    let matched = pepmatch(&["match", "-q", digest, "-p", &data("proteome.fasta"), "--no-index"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0], ["Peptide", "Protein ID", "Start", "End"]);
+    let p00001: Vec<_> = rows.iter().filter(|row| row[1] == "P00001").map(|row| (row[0], row[2], row[3])).collect();
+    assert_eq!(
+        p00001,
+        [("MKLVSTAA", "1", "8"), ("STAAQWDE", "5", "12"), ("QWDEQRRP", "9", "16"), ("QRRPLNGH", "13", "20"), ("RPLNGHTK", "15", "22")]
+    );
+    // every peptide is found back where it came from
+    for row in &rows[1..] {
+        assert!(hits(&matched).contains(&(row[0].to_string(), row[1].to_string(), "0".to_string(), row[2].to_string())));
+    }
+}
+
+#[test]
+fn digest_with_a_protease() {
+    let tsv = pepmatch(&["digest", "-p", &data("proteome.fasta"), "--enzyme", "trypsin", "--min-length", "8", "--max-length", "12"]);
+    let peptides: Vec<(&str, &str)> = tsv.lines().skip(1).map(|line| line.split('\t').collect::<Vec<_>>()).map(|row| (row[0], row[1])).collect();
+    let expected = [
+        ("LVSTAAQWDEQR", "P00001"),
+        ("RPLNGHTK", "P00001"),
+        ("PESTAVQWLLK", "P00002"),
+        ("LLMMNNPPQQ", "A0A001"),
+    ];
+    assert_eq!(peptides, expected);
+}
+
+#[test]
+fn sort_orders_each_peptides_hits() {
+    let memory = ["match", "-q", &data("peptides.txt"), "-p", &data("proteome.fasta"), "-k", "3", "--backend", "memory", "-m", "1"];
+    let rows = |tsv: &str| -> Vec<(String, String)> {
+        tsv.lines().skip(1).map(|line| line.split('\t').collect::<Vec<_>>()).map(|row| (row[0].to_string(), row[2].to_string())).collect()
+    };
+    let by_position = rows(&pepmatch(&[&memory[..], &["--sort", "position"]].concat()));
+    let by_level = rows(&pepmatch(&[&memory[..], &["--sort", "pe_level,position"]].concat()));
+    let by_default = rows(&pepmatch(&memory));
+
+    let pairs = |pairs: &[(&str, &str)]| pairs.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect::<Vec<_>>();
+    assert_eq!(by_position, pairs(&[("STAAQW", "P00002"), ("STAAQW", "P00001"), ("DEQRRPLN", "P00001"), ("DEQRRPLN", "P00002")]));
+    assert_eq!(by_level, pairs(&[("STAAQW", "P00001"), ("STAAQW", "P00002"), ("DEQRRPLN", "P00001"), ("DEQRRPLN", "P00002")]));
+    assert_eq!(by_default, by_level);
+}
+
+#[test]
+fn protein_length_and_weight_are_stored_and_filtered_on() {
+    let dir = scratch("protein-properties");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--protein-properties"]);
+    let long = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--min-protein-length", "20"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][rows[0].len() - 2..], ["Protein Length", "Molecular Weight"]);
+    let properties = |protein_id: &str| {
+        let row = rows.iter().find(|row| row[2] == protein_id).unwrap();
+        (row[row.len() - 2].to_string(), row[row.len() - 1].to_string())
+    };
+    assert_eq!(properties("P00001"), ("22".to_string(), "2567".to_string()));
+    assert_eq!(properties("P00002").0, "19");
+    // P00002 is 19 residues long
+    assert_eq!(hits(&long), expected().into_iter().filter(|hit| hit.1 == "P00001").collect::<Vec<_>>());
+}
+
+#[test]
+fn python_layout_matches_like_native() {
+    let dir = scratch("python");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--layout", "python"]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1"]);
+    let both = format!("other={}", data("proteome.fasta"));
+    let refused = Command::new(env!("CARGO_BIN_EXE_pepmatch"))
+        .args(["--quiet", "preprocess", "-p", &data("proteome.fasta"), "-p", &both, "-k", "3", "--layout", "python"])
+        .args(["-d", &dir.join("both.db").to_string_lossy()])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv), expected());
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("single proteome"));
+}
+
+#[test]
+fn gene_priority_proteins_win_best_match_ties() {
+    let dir = scratch("gene-priority");
+    let (plain, flagged) = (dir.join("plain.db"), dir.join("flagged.db"));
+    let (plain, flagged) = (plain.to_str().unwrap(), flagged.to_str().unwrap());
+    let gene_priority = dir.join("gene_priority.fasta");
+    let gene_priority = gene_priority.to_str().unwrap();
+    std::fs::write(gene_priority, ">sp|P00002|TWO_HUMAN Second protein OS=Homo sapiens OX=9606 GN=TWO PE=3 SV=1\nPESTAVQWLLKDEQRRPLN\n").unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", plain]);
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", flagged, "--gene-priority", gene_priority]);
+    let query = dir.join("query.txt");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "DEQRRPLN\n").unwrap();
+    let best = |db| pepmatch(&["match", "-q", query, "-d", db, "--best-match"]);
+    let (plain, flagged) = (best(plain), best(flagged));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // both proteins have the peptide, the better evidenced one wins unless the other has gene priority
+    let protein = |tsv: &str| tsv.lines().nth(1).unwrap().split('\t').nth(2).unwrap().to_string();
+    assert_eq!(protein(&plain), "P00001");
+    assert_eq!(protein(&flagged), "P00002");
+}
+
+#[test]
+fn discontinuous_epitopes_match_by_residue_position() {
+    let dir = scratch("discontinuous");
+    let query = dir.join("epitopes.txt");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "M1, L3, S5\nX99\n").unwrap();
+    let proteome = data("proteome.fasta");
+    let match_epitopes = |extra: &[&str]| {
+        let mut args = vec!["match", "-q", query, "-p", &proteome, "--discontinuous"];
+        args.extend(extra);
+        pepmatch(&args)
+    };
+    let exact = match_epitopes(&[]);
+    let shifted = match_epitopes(&["-m", "1"]);
+    std::fs::write(query, "K3\n").unwrap();
+    let tolerant = match_epitopes(&["--tolerance", "1"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let found = |tsv: &str| -> Vec<(String, String, String)> {
+        tsv.lines()
+            .skip(1)
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .filter(|row| !row[2].is_empty())
+            .map(|row| (row[0].to_string(), row[1].to_string(), row[2].to_string()))
+            .collect()
+    };
+    let m1 = ("M1, L3, S5".to_string(), "M1, L3, S5".to_string(), "P00001".to_string());
+    assert_eq!(found(&exact), std::slice::from_ref(&m1));
+    // a residue past the end of every protein doesn't match as a mismatch
+    assert!(found(&shifted).iter().all(|hit| hit.0 != "X99"), "{}", shifted);
+    assert!(found(&shifted).contains(&m1));
+    // P00001 has its K one position before
+    assert_eq!(found(&tolerant), [("K3".to_string(), "K2".to_string(), "P00001".to_string())]);
+}
+
+#[test]
+fn motifs_are_found_in_the_db_proteins() {
+    let dir = scratch("motif");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db]);
+    let tsv = pepmatch(&["motif", "D-E-Q-[KR]", "<G-G", "-d", db]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let found: Vec<_> = hits(&tsv).into_iter().map(|(motif, protein_id, _, start)| (motif, protein_id, start)).collect();
+    let expected: Vec<_> = [("<G-G", "A0A001", "1"), ("D-E-Q-[KR]", "P00001", "11"), ("D-E-Q-[KR]", "P00002", "12")]
+        .iter()
+        .map(|(motif, protein_id, start)| (motif.to_string(), protein_id.to_string(), start.to_string()))
+        .collect();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn decoys_are_preprocessed_with_their_targets() {
+    let dir = scratch("decoy");
+    let (fasta, db) = (dir.join("proteome_decoy.fasta"), dir.join("proteome.db"));
+    let (fasta, db) = (fasta.to_str().unwrap(), db.to_str().unwrap());
+    pepmatch(&["decoy", "-p", &data("proteome.fasta"), "-o", fasta, "-k", "3", "-d", db]);
+    let query = dir.join("query.txt");
+    let query = query.to_str().unwrap();
+    // DEQRRPLN reversed
+    std::fs::write(query, "NLPRRQED\n").unwrap();
+    let tsv = pepmatch(&["match", "-q", query, "-d", db]);
+    let decoys = std::fs::read_to_string(fasta).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(decoys.matches('>').count(), 3);
+    let found: Vec<_> = hits(&tsv).into_iter().map(|(_, protein_id, _, start)| (protein_id, start)).collect();
+    assert_eq!(found, [("DECOY_P00001".to_string(), "5".to_string()), ("DECOY_P00002".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn coverage_merges_the_hits_in_each_protein() {
+    let dir = scratch("coverage");
+    let db = dir.join("proteome.db");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--coverage"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][0], "Protein ID");
+    // STAAQW and DEQRRPLN are next to each other in P00001 and apart in P00002
+    assert_eq!(rows[1][..1], ["P00001"]);
+    assert_eq!(rows[1][4..], ["22", "2", "14", "0.6364", "5-18", "mklvSTAAQWDEQRRPLNghtk"]);
+    assert_eq!(rows[2][..1], ["P00002"]);
+    assert_eq!(rows[2][4..], ["19", "2", "14", "0.7368", "3-8;12-19", "peSTAVQWllkDEQRRPLN"]);
+    assert_eq!(rows.len(), 3);
+}
+
+#[test]
+fn limit_and_count_only_cut_down_the_hits() {
+    let proteome = data("proteome.fasta");
+    let args = ["match", "-q", &data("peptides.txt"), "-p", &proteome, "-k", "3", "-m", "1"];
+    let limited = pepmatch(&[&args[..], &["--limit", "1"]].concat());
+    let counts = pepmatch(&[&args[..], &["--count-only"]].concat());
+
+    // the exact hit of STAAQW and the better evidenced protein of DEQRRPLN
+    let best: Vec<_> = expected().into_iter().filter(|hit| hit.1 == "P00001").collect();
+    assert_eq!(hits(&limited), best);
+    assert_eq!(counts, "Query Sequence\tMatches\nSTAAQW\t2\nDEQRRPLN\t2\nWWWWWW\t0\n");
+}
+
+#[test]
+fn pe_level_and_swissprot_filters_leave_out_proteins() {
+    let dir = scratch("pe-filters");
+    let db = dir.join("swissprot.db");
+    let db = db.to_str().unwrap();
+    let query = dir.join("query.txt");
+    let query = query.to_str().unwrap();
+    // HHKKLL is only in the TrEMBL protein
+    std::fs::write(query, "STAAQW\nDEQRRPLN\nHHKKLL\n").unwrap();
+    let proteome = data("proteome.fasta");
+    pepmatch(&["preprocess", "-p", &proteome, "-k", "3", "-d", db, "--swissprot-only"]);
+    let swissprot = pepmatch(&["match", "-q", query, "-d", db, "-m", "1"]);
+    let evidenced = pepmatch(&["match", "-q", query, "-p", &proteome, "-k", "3", "-m", "1", "--max-pe-level", "2"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // P00002 is at PE level 3 and A0A001 at 4
+    assert_eq!(hits(&swissprot), expected());
+    assert_eq!(hits(&evidenced), expected().into_iter().filter(|hit| hit.1 == "P00001").collect::<Vec<_>>());
+}
+
+#[test]
+fn kept_columns_follow_their_rows_hits() {
+    let dir = scratch("keep-columns");
+    let query = dir.join("epitopes.csv");
+    let query = query.to_str().unwrap();
+    std::fs::write(query, "id,epitope,assay\ne1,STAAQW,elispot\ne2,DEQRRPLN,\"tetramer, HLA-A2\"\n").unwrap();
+    let proteome = data("proteome.fasta");
+    let args = ["match", "-q", query, "--peptide-column", "epitope", "-p", &proteome, "-k", "3", "-m", "1"];
+    let tsv = pepmatch(&[&args[..], &["--keep-columns", "id,assay"]].concat());
+    let json = pepmatch(&[&args[..], &["--keep-columns", "assay", "-f", "json"]].concat());
+    let missing = Command::new(env!("CARGO_BIN_EXE_pepmatch"))
+        .args(["--quiet"])
+        .args(args)
+        .args(["--keep-columns", "allele"])
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let rows: Vec<Vec<&str>> = tsv.lines().map(|line| line.split('\t').collect()).collect();
+    assert_eq!(rows[0][rows[0].len() - 2..], ["id", "assay"]);
+    for row in &rows[1..] {
+        let kept = if row[0] == "STAAQW" { ["e1", "elispot"] } else { ["e2", "tetramer, HLA-A2"] };
+        assert_eq!(row[row.len() - 2..], kept);
+    }
+    assert_eq!(hits(&tsv), expected());
+    let records: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+    assert_eq!(records.len(), 4);
+    assert!(records.iter().all(|record| record["assay"] == if record["Query Sequence"] == "STAAQW" { "elispot" } else { "tetramer, HLA-A2" }));
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("allele"));
+}
+
+#[test]
+fn bench_reports_the_hits_and_recall_of_a_dataset() {
+    let dir = scratch("bench");
+    let expected_hits = dir.join("expected.csv");
+    let expected_hits = expected_hits.to_str().unwrap();
+    // as the Python PEPMatch writes them, with float positions and empty rows for peptides without hits
+    std::fs::write(
+        expected_hits,
+        "Query Sequence,Protein ID,Index start\nSTAAQW,P00001,5.0\nDEQRRPLN,P00002,12.0\nWWWWWW,,\nGGHHKK,P00009,1.0\n",
+    )
+    .unwrap();
+    let proteome = data("proteome.fasta");
+    let report = pepmatch(&["bench", "neoepitopes", "-p", &proteome, "-q", &data("peptides.txt"), "-k", "3", "-e", expected_hits]);
+    let hits = pepmatch(&["match", "-q", &data("peptides.txt"), "-p", &proteome, "-k", "3", "-m", "3"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let report: std::collections::HashMap<&str, &str> = report.lines().filter_map(|line| line.split_once('\t')).collect();
+    assert_eq!(report["dataset"], "neoepitopes");
+    assert_eq!(report["k"], "3");
+    assert_eq!(report["peptides"], "3");
+    assert_eq!(report["hits"], (hits.lines().count() - 1).to_string());
+    assert_eq!((report["expected_hits"], report["found_hits"], report["recall"]), ("3", "2", "0.6667"));
+}
+
+#[cfg(feature = "rocksdb")]
+#[test]
+fn rocksdb_store_matches_like_sqlite() {
+    let dir = scratch("rocksdb");
+    let db = dir.join("proteome.rocksdb");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--backend", "rocksdb"]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--backend", "rocksdb"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv), expected());
+}
+
+#[cfg(feature = "lmdb")]
+#[test]
+fn lmdb_store_matches_like_sqlite() {
+    let dir = scratch("lmdb");
+    let db = dir.join("proteome.lmdb");
+    let db = db.to_str().unwrap();
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--backend", "lmdb"]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--backend", "lmdb"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv), expected());
+}
+
+#[cfg(feature = "duckdb")]
+#[test]
+fn duckdb_index_matches_like_sqlite_and_takes_the_hits() {
+    let dir = scratch("duckdb");
+    let (db, results) = (dir.join("proteome.duckdb"), dir.join("hits.duckdb"));
+    let (db, results) = (db.to_str().unwrap(), results.to_str().unwrap());
+    pepmatch(&["preprocess", "-p", &data("proteome.fasta"), "-k", "3", "-d", db, "--backend", "duckdb"]);
+    let tsv = pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--backend", "duckdb"]);
+    pepmatch(&["match", "-q", &data("peptides.txt"), "-d", db, "-m", "1", "--backend", "duckdb", "--output-db", results]);
+    let conn = duckdb::Connection::open(results).unwrap();
+    let mut rows: Vec<(String, String)> = conn
+        .prepare("SELECT peptide, protein_id FROM matches JOIN metadata USING (protein_number)")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    drop(conn);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hits(&tsv), expected());
+    rows.sort();
+    assert_eq!(rows, expected().into_iter().map(|hit| (hit.0, hit.1)).collect::<Vec<_>>());
+}